@@ -0,0 +1,126 @@
+--
+-- Copyright 2022 The Blaze Authors
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+--
+
+--
+-- Expected values of the murmur3/xxhash64 tests in
+-- native-engine/datafusion-ext-commons/src/spark_hash.rs.
+--
+-- hash() and xxhash64() are Spark's Murmur3Hash and XxHash64 with seed 42, the
+-- same functions used by HashPartitioning. Run with vanilla Spark (without the
+-- blaze extension) so that the values are computed by Spark itself:
+--
+--   spark-sql --conf spark.sql.extensions= -f dev/spark-hash-vectors.sql
+--
+-- Each query outputs rows in the same order as the arrays built in the test.
+--
+
+-- test_i8
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST(1 AS TINYINT)), (CAST(0 AS TINYINT)), (CAST(-1 AS TINYINT)),
+  (CAST(127 AS TINYINT)), (CAST(-128 AS TINYINT)) AS t(v);
+
+-- test_i32
+SELECT hash(v), xxhash64(v) FROM VALUES (1), (2), (3), (4) AS t(v);
+
+-- test_i64
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (1L), (0L), (-1L), (9223372036854775807L), (-9223372036854775808L) AS t(v);
+
+-- test_str
+SELECT hash(v), xxhash64(v) FROM VALUES ('hello'), ('bar'), (''), ('😁'), ('天地') AS t(v);
+
+-- test_null_keeps_seed
+SELECT hash(a), xxhash64(a) FROM VALUES (1), (CAST(NULL AS INT)) AS t(a);
+SELECT hash(b, a), xxhash64(b, a) FROM VALUES
+  (CAST(NULL AS INT), 1), (CAST(NULL AS INT), CAST(NULL AS INT)) AS t(b, a);
+
+-- test_float_normalization
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST('0.0' AS FLOAT)), (CAST('-0.0' AS FLOAT)), (CAST('NaN' AS FLOAT)),
+  (-CAST('NaN' AS FLOAT)), (CAST('1.5' AS FLOAT)) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST('0.0' AS DOUBLE)), (CAST('-0.0' AS DOUBLE)), (CAST('NaN' AS DOUBLE)) AS t(v);
+
+-- test_decimal
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST(123.45 AS DECIMAL(10, 2))), (CAST(-1.00 AS DECIMAL(10, 2))),
+  (CAST(NULL AS DECIMAL(10, 2))) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST(123.45 AS DECIMAL(20, 2))), (CAST(-100000000000000000.00 AS DECIMAL(20, 2))),
+  (CAST(0 AS DECIMAL(20, 2))) AS t(v);
+
+-- test_decimal_precisions
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST(1 AS DECIMAL(1, 0))), (CAST(-9 AS DECIMAL(1, 0))), (CAST(0 AS DECIMAL(1, 0))) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST(999999999999999999 AS DECIMAL(18, 0))), (CAST(-999999999999999999 AS DECIMAL(18, 0))),
+  (CAST(1 AS DECIMAL(18, 0))) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST(1 AS DECIMAL(19, 0))), (CAST(-1 AS DECIMAL(19, 0))),
+  (CAST(9999999999999999999 AS DECIMAL(19, 0))), (CAST(-9999999999999999999 AS DECIMAL(19, 0))),
+  (CAST(0 AS DECIMAL(19, 0))) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST(127 AS DECIMAL(28, 10))), (CAST(-128 AS DECIMAL(28, 10))),
+  (CAST(255 AS DECIMAL(28, 10))) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (CAST('99999999999999999999999999999999999999' AS DECIMAL(38, 0))),
+  (CAST('-99999999999999999999999999999999999999' AS DECIMAL(38, 0))),
+  (CAST(-1 AS DECIMAL(38, 0))) AS t(v);
+
+-- test_nested_list
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (array(1, 2, 3)), (CAST(array() AS ARRAY<INT>)), (CAST(NULL AS ARRAY<INT>)),
+  (array(4, NULL)) AS t(v);
+
+-- test_nested_struct
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (named_struct('a', 1, 'b', 'a')), (named_struct('a', CAST(NULL AS INT), 'b', 'b')),
+  (CAST(NULL AS STRUCT<a: INT, b: STRING>)) AS t(v);
+
+-- test_nested_map
+SELECT hash(v), xxhash64(v) FROM VALUES (map('k', 1)), (map('x', 1, 'y', 2)) AS t(v);
+
+-- test_deeply_nested
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (array(
+    named_struct('a', 1, 'b', array(2L, 3L)),
+    named_struct('a', CAST(NULL AS INT), 'b', CAST(NULL AS ARRAY<BIGINT>)))),
+  (CAST(array() AS ARRAY<STRUCT<a: INT, b: ARRAY<BIGINT>>>)) AS t(v);
+
+-- test_nested_nulls
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (array(CAST(NULL AS INT))), (array(CAST(NULL AS INT), CAST(NULL AS INT))),
+  (CAST(NULL AS ARRAY<INT>)) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (array(array(1, NULL), NULL, CAST(array() AS ARRAY<INT>))),
+  (array(array(CAST(NULL AS INT)))),
+  (array(CAST(NULL AS ARRAY<INT>))) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (named_struct('a', CAST(NULL AS INT), 'b', CAST(NULL AS STRING))),
+  (CAST(NULL AS STRUCT<a: INT, b: STRING>)) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (named_struct('a', named_struct('p', CAST(NULL AS INT), 'q', 1), 'b', CAST(NULL AS INT))),
+  (named_struct('a', CAST(NULL AS STRUCT<p: INT, q: INT>), 'b', 2)),
+  (named_struct('a', named_struct('p', CAST(NULL AS INT), 'q', CAST(NULL AS INT)), 'b', CAST(NULL AS INT)))
+  AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (named_struct('a', array(NULL, 2), 'b', 'x')),
+  (named_struct('a', CAST(NULL AS ARRAY<INT>), 'b', CAST(NULL AS STRING))) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (map(1, CAST(NULL AS INT))), (map(1, CAST(NULL AS INT), 2, 3)) AS t(v);
+SELECT hash(v), xxhash64(v) FROM VALUES
+  (map(1, CAST(NULL AS ARRAY<INT>), 2, array(NULL, 3))), (map(1, CAST(array() AS ARRAY<INT>)))
+  AS t(v);
@@ -30,6 +30,7 @@ macro_rules! define_conf {
 
 define_conf!(IntConf, BATCH_SIZE);
 define_conf!(DoubleConf, MEMORY_FRACTION);
+define_conf!(StringConf, MEMMGR_POLICY);
 define_conf!(BooleanConf, SMJ_INEQUALITY_JOIN_ENABLE);
 define_conf!(BooleanConf, CASE_CONVERT_FUNCTIONS_ENABLE);
 define_conf!(BooleanConf, INPUT_BATCH_STATISTICS_ENABLE);
@@ -41,15 +42,42 @@ define_conf!(BooleanConf, PARTIAL_AGG_SKIPPING_SKIP_SPILL);
 define_conf!(BooleanConf, PARQUET_ENABLE_PAGE_FILTERING);
 define_conf!(BooleanConf, PARQUET_ENABLE_BLOOM_FILTER);
 define_conf!(StringConf, SPARK_IO_COMPRESSION_CODEC);
+define_conf!(IntConf, SPARK_IO_COMPRESSION_ZSTD_LEVEL);
+define_conf!(StringConf, SHUFFLE_COMPRESSION_CODEC);
+define_conf!(BooleanConf, ZSTD_DICT_TRAINING_ENABLE);
+define_conf!(BooleanConf, SHUFFLE_ARROW_IPC_FORMAT_ENABLE);
+define_conf!(BooleanConf, COLUMN_ENCODING_ENABLE);
+define_conf!(IntConf, SHUFFLE_SKEW_SAMPLE_ROWS);
+define_conf!(DoubleConf, SHUFFLE_SKEW_HOT_KEY_MIN_RATIO);
+define_conf!(IntConf, SHUFFLE_SKEW_MAX_HOT_KEYS);
+define_conf!(IntConf, SHUFFLE_BYPASS_MERGE_THRESHOLD);
+define_conf!(BooleanConf, SPARK_FILE_TRANSFER_TO);
+define_conf!(IntConf, SHUFFLE_RSS_PUSH_BATCH_SIZE);
+define_conf!(IntConf, SHUFFLE_LOCAL_READ_MMAP_THRESHOLD);
 define_conf!(IntConf, TOKIO_WORKER_THREADS_PER_CPU);
 define_conf!(IntConf, SPARK_TASK_CPUS);
 define_conf!(StringConf, SPILL_COMPRESSION_CODEC);
+define_conf!(IntConf, SPILL_ASYNC_MAX_INFLIGHT);
+define_conf!(StringConf, SPILL_LOCAL_DIRS);
+define_conf!(IntConf, SPILL_DISK_QUOTA_PER_TASK_MB);
+define_conf!(IntConf, SPILL_DISK_QUOTA_PER_EXECUTOR_MB);
+define_conf!(IntConf, SPILL_MMAP_READ_THRESHOLD);
+define_conf!(IntConf, SPILL_MERGE_MAX_FAN_IN);
+define_conf!(IntConf, LIMIT_MAX_OPERATOR_OUTPUT_ROWS);
+define_conf!(IntConf, LIMIT_MAX_TASK_SPILL_MB);
+define_conf!(IntConf, LIMIT_TASK_DEADLINE_SECS);
+define_conf!(BooleanConf, TRACE_ENABLE);
+define_conf!(StringConf, TRACE_DIR);
 define_conf!(BooleanConf, SMJ_FALLBACK_ENABLE);
 define_conf!(IntConf, SMJ_FALLBACK_ROWS_THRESHOLD);
 define_conf!(IntConf, SMJ_FALLBACK_MEM_SIZE_THRESHOLD);
 define_conf!(IntConf, SUGGESTED_BATCH_MEM_SIZE);
 define_conf!(IntConf, SUGGESTED_BATCH_MEM_SIZE_KWAY_MERGE);
 define_conf!(BooleanConf, ORC_FORCE_POSITIONAL_EVOLUTION);
+define_conf!(BooleanConf, UDF_FUSION_ENABLE);
+define_conf!(StringConf, WASM_UDF_DIR);
+define_conf!(IntConf, WASM_UDF_MEMORY_LIMIT);
+define_conf!(IntConf, WASM_UDF_FUEL_PER_ROW);
 
 pub trait BooleanConf {
     fn key(&self) -> &'static str;
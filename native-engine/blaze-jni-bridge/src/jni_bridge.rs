@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::error::Error;
+use std::{error::Error, sync::atomic::AtomicUsize};
 
 pub use datafusion;
 pub use jni::{
@@ -26,6 +26,9 @@ pub use jni::{
 use once_cell::sync::OnceCell;
 pub use paste::paste;
 
+/// number of java methods called by jni_call!/jni_call_static!
+pub static JNI_CALL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
 thread_local! {
     pub static THREAD_JNIENV: once_cell::unsync::Lazy<JNIEnv<'static>> =
         once_cell::unsync::Lazy::new(|| {
@@ -286,6 +289,7 @@ macro_rules! jni_call {
         })
     }};
     ($env:expr, $clsname:ident($obj:expr).$method:ident($($args:expr),* $(,)?)) => {{
+        $crate::jni_bridge::JNI_CALL_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         log::trace!("jni_call!: {}({:?}).{}({:?})",
             stringify!($clsname),
             $obj,
@@ -323,6 +327,7 @@ macro_rules! jni_call_static {
         })
     }};
     ($env:expr, $clsname:ident.$method:ident($($args:expr),* $(,)?)) => {{
+        $crate::jni_bridge::JNI_CALL_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         log::trace!("jni_call_static!: {}.{}({:?})",
             stringify!($clsname),
             stringify!($method),
@@ -440,8 +445,10 @@ pub struct JavaClasses<'a> {
     pub cSparkSQLMetric: SparkSQLMetric<'a>,
     pub cSparkMetricNode: SparkMetricNode<'a>,
     pub cSparkUDFWrapperContext: SparkUDFWrapperContext<'a>,
+    pub cSparkFusedUDFWrapperContext: SparkFusedUDFWrapperContext<'a>,
     pub cSparkUDAFWrapperContext: SparkUDAFWrapperContext<'a>,
     pub cSparkUDTFWrapperContext: SparkUDTFWrapperContext<'a>,
+    pub cSparkPythonEvalWrapperContext: SparkPythonEvalWrapperContext<'a>,
     pub cBlazeConf: BlazeConf<'a>,
     pub cBlazeRssPartitionWriterBase: BlazeRssPartitionWriterBase<'a>,
     pub cBlazeCallNativeWrapper: BlazeCallNativeWrapper<'a>,
@@ -502,8 +509,10 @@ impl JavaClasses<'static> {
                 cSparkSQLMetric: SparkSQLMetric::new(env)?,
                 cSparkMetricNode: SparkMetricNode::new(env)?,
                 cSparkUDFWrapperContext: SparkUDFWrapperContext::new(env)?,
+                cSparkFusedUDFWrapperContext: SparkFusedUDFWrapperContext::new(env)?,
                 cSparkUDAFWrapperContext: SparkUDAFWrapperContext::new(env)?,
                 cSparkUDTFWrapperContext: SparkUDTFWrapperContext::new(env)?,
+                cSparkPythonEvalWrapperContext: SparkPythonEvalWrapperContext::new(env)?,
                 cBlazeConf: BlazeConf::new(env)?,
                 cBlazeRssPartitionWriterBase: BlazeRssPartitionWriterBase::new(env)?,
                 cBlazeCallNativeWrapper: BlazeCallNativeWrapper::new(env)?,
@@ -1156,6 +1165,8 @@ pub struct BlazeRssPartitionWriterBase<'a> {
     pub class: JClass<'a>,
     pub method_write: JMethodID,
     pub method_write_ret: ReturnType,
+    pub method_pushBatch: JMethodID,
+    pub method_pushBatch_ret: ReturnType,
     pub method_flush: JMethodID,
     pub method_flush_ret: ReturnType,
     pub method_close: JMethodID,
@@ -1172,6 +1183,12 @@ impl<'a> BlazeRssPartitionWriterBase<'_> {
             class,
             method_write: env.get_method_id(class, "write", "(ILjava/nio/ByteBuffer;)V")?,
             method_write_ret: ReturnType::Primitive(Primitive::Void),
+            method_pushBatch: env.get_method_id(
+                class,
+                "pushBatch",
+                "([I[ILjava/nio/ByteBuffer;)V",
+            )?,
+            method_pushBatch_ret: ReturnType::Primitive(Primitive::Void),
             method_flush: env.get_method_id(class, "flush", "()V")?,
             method_flush_ret: ReturnType::Primitive(Primitive::Void),
             method_close: env.get_method_id(class, "close", "()V")?,
@@ -1201,6 +1218,27 @@ impl<'a> SparkUDFWrapperContext<'a> {
     }
 }
 
+#[allow(non_snake_case)]
+pub struct SparkFusedUDFWrapperContext<'a> {
+    pub class: JClass<'a>,
+    pub ctor: JMethodID,
+    pub method_eval: JMethodID,
+    pub method_eval_ret: ReturnType,
+}
+impl<'a> SparkFusedUDFWrapperContext<'a> {
+    pub const SIG_TYPE: &'static str = "org/apache/spark/sql/blaze/SparkFusedUDFWrapperContext";
+
+    pub fn new(env: &JNIEnv<'a>) -> JniResult<SparkFusedUDFWrapperContext<'a>> {
+        let class = get_global_jclass(env, Self::SIG_TYPE)?;
+        Ok(SparkFusedUDFWrapperContext {
+            class,
+            ctor: env.get_method_id(class, "<init>", "(Ljava/nio/ByteBuffer;)V")?,
+            method_eval: env.get_method_id(class, "eval", "(JJ)V")?,
+            method_eval_ret: ReturnType::Primitive(Primitive::Void),
+        })
+    }
+}
+
 #[allow(non_snake_case)]
 pub struct SparkUDAFWrapperContext<'a> {
     pub class: JClass<'a>,
@@ -1307,6 +1345,35 @@ impl<'a> SparkUDTFWrapperContext<'a> {
     }
 }
 
+#[allow(non_snake_case)]
+pub struct SparkPythonEvalWrapperContext<'a> {
+    pub class: JClass<'a>,
+    pub ctor: JMethodID,
+    pub method_feed: JMethodID,
+    pub method_feed_ret: ReturnType,
+    pub method_finishInput: JMethodID,
+    pub method_finishInput_ret: ReturnType,
+    pub method_nextResult: JMethodID,
+    pub method_nextResult_ret: ReturnType,
+}
+impl<'a> SparkPythonEvalWrapperContext<'a> {
+    pub const SIG_TYPE: &'static str = "org/apache/spark/sql/blaze/SparkPythonEvalWrapperContext";
+
+    pub fn new(env: &JNIEnv<'a>) -> JniResult<SparkPythonEvalWrapperContext<'a>> {
+        let class = get_global_jclass(env, Self::SIG_TYPE)?;
+        Ok(SparkPythonEvalWrapperContext {
+            class,
+            ctor: env.get_method_id(class, "<init>", "(Ljava/nio/ByteBuffer;)V")?,
+            method_feed: env.get_method_id(class, "feed", "(J)V")?,
+            method_feed_ret: ReturnType::Primitive(Primitive::Void),
+            method_finishInput: env.get_method_id(class, "finishInput", "()V")?,
+            method_finishInput_ret: ReturnType::Primitive(Primitive::Void),
+            method_nextResult: env.get_method_id(class, "nextResult", "(JZ)Z")?,
+            method_nextResult_ret: ReturnType::Primitive(Primitive::Boolean),
+        })
+    }
+}
+
 #[allow(non_snake_case)]
 pub struct BlazeCallNativeWrapper<'a> {
     pub class: JClass<'a>,
@@ -1446,6 +1513,18 @@ pub struct BlazeBlockObject<'a> {
     pub method_getByteBuffer_ret: ReturnType,
     pub method_getChannel: JMethodID,
     pub method_getChannel_ret: ReturnType,
+    pub method_hasShuffleFiles: JMethodID,
+    pub method_hasShuffleFiles_ret: ReturnType,
+    pub method_getDataFilePath: JMethodID,
+    pub method_getDataFilePath_ret: ReturnType,
+    pub method_getIndexFilePath: JMethodID,
+    pub method_getIndexFilePath_ret: ReturnType,
+    pub method_getStartPartition: JMethodID,
+    pub method_getStartPartition_ret: ReturnType,
+    pub method_getEndPartition: JMethodID,
+    pub method_getEndPartition_ret: ReturnType,
+    pub method_throwFetchFailed: JMethodID,
+    pub method_throwFetchFailed_ret: ReturnType,
 }
 
 impl<'a> BlazeBlockObject<'a> {
@@ -1477,6 +1556,30 @@ impl<'a> BlazeBlockObject<'a> {
                 "()Ljava/nio/channels/ReadableByteChannel;",
             )?,
             method_getChannel_ret: ReturnType::Object,
+            method_hasShuffleFiles: env.get_method_id(class, "hasShuffleFiles", "()Z")?,
+            method_hasShuffleFiles_ret: ReturnType::Primitive(Primitive::Boolean),
+            method_getDataFilePath: env.get_method_id(
+                class,
+                "getDataFilePath",
+                "()Ljava/lang/String;",
+            )?,
+            method_getDataFilePath_ret: ReturnType::Object,
+            method_getIndexFilePath: env.get_method_id(
+                class,
+                "getIndexFilePath",
+                "()Ljava/lang/String;",
+            )?,
+            method_getIndexFilePath_ret: ReturnType::Object,
+            method_getStartPartition: env.get_method_id(class, "getStartPartition", "()I")?,
+            method_getStartPartition_ret: ReturnType::Primitive(Primitive::Int),
+            method_getEndPartition: env.get_method_id(class, "getEndPartition", "()I")?,
+            method_getEndPartition_ret: ReturnType::Primitive(Primitive::Int),
+            method_throwFetchFailed: env.get_method_id(
+                class,
+                "throwFetchFailed",
+                "(Ljava/lang/String;)V",
+            )?,
+            method_throwFetchFailed_ret: ReturnType::Primitive(Primitive::Void),
         })
     }
 }
@@ -22,7 +22,7 @@ use std::{
 use arrow::{
     array::{ArrayRef, RecordBatch},
     compute::SortOptions,
-    datatypes::{Field, FieldRef, SchemaRef},
+    datatypes::{DataType, Field, FieldRef, SchemaRef},
     row::{RowConverter, SortField},
 };
 use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
@@ -80,9 +80,16 @@ use datafusion_ext_plans::{
     parquet_exec::ParquetExec,
     parquet_sink_exec::ParquetSinkExec,
     project_exec::ProjectExec,
+    python_eval_exec::PythonEvalExec,
+    range_bounds_exec::RangeBoundsExec,
+    range_sample_exec::RangeSampleExec,
     rename_columns_exec::RenameColumnsExec,
     rss_shuffle_writer_exec::RssShuffleWriterExec,
-    shuffle::Partitioning,
+    shuffle::{
+        checksum::{ShuffleChecksumAlgorithm, ShuffleChecksumOutput},
+        skew::HotKeySplitting,
+        Partitioning,
+    },
     shuffle_writer_exec::ShuffleWriterExec,
     sort_exec::SortExec,
     sort_merge_join_exec::SortMergeJoinExec,
@@ -256,11 +263,27 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     shuffle_writer.output_partitioning.as_ref(),
                 )?;
 
+                let sort_exprs =
+                    try_parse_physical_sort_exprs(&shuffle_writer.sort_expr, &input.schema())?;
+
+                let checksum_output = if !shuffle_writer.output_checksum_file.is_empty() {
+                    Some(ShuffleChecksumOutput {
+                        checksum_file: shuffle_writer.output_checksum_file.clone(),
+                        algorithm: ShuffleChecksumAlgorithm::try_from_name(
+                            &shuffle_writer.checksum_algorithm,
+                        )?,
+                    })
+                } else {
+                    None
+                };
+
                 Ok(Arc::new(ShuffleWriterExec::try_new(
                     input,
                     output_partitioning.unwrap(),
+                    sort_exprs,
                     shuffle_writer.output_data_file.clone(),
                     shuffle_writer.output_index_file.clone(),
+                    checksum_output,
                 )?))
             }
             PhysicalPlanType::RssShuffleWriter(rss_shuffle_writer) => {
@@ -287,10 +310,12 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
             }
             PhysicalPlanType::IpcReader(ipc_reader) => {
                 let schema = Arc::new(convert_required!(ipc_reader.schema)?);
+                let sort_exprs = try_parse_physical_sort_exprs(&ipc_reader.sort_expr, &schema)?;
                 Ok(Arc::new(IpcReaderExec::new(
                     ipc_reader.num_partitions as usize,
                     ipc_reader.ipc_provider_resource_id.clone(),
                     schema,
+                    sort_exprs,
                 )))
             }
             PhysicalPlanType::Debug(debug) => {
@@ -701,6 +726,42 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     props,
                 )))
             }
+            PhysicalPlanType::RangeSample(range_sample) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(range_sample.input)?;
+                let sort_exprs =
+                    try_parse_physical_sort_exprs(&range_sample.sort_expr, &input.schema())?;
+                Ok(Arc::new(RangeSampleExec::try_new(
+                    input,
+                    sort_exprs,
+                    range_sample.sample_size as usize,
+                )?))
+            }
+            PhysicalPlanType::RangeBounds(range_bounds) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(range_bounds.input)?;
+                let sort_exprs =
+                    try_parse_physical_sort_exprs(&range_bounds.sort_expr, &input.schema())?;
+                Ok(Arc::new(RangeBoundsExec::try_new(
+                    input,
+                    sort_exprs,
+                    range_bounds.partition_count as usize,
+                )?))
+            }
+            PhysicalPlanType::PythonEval(python_eval) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(python_eval.input)?;
+                let input_schema = input.schema();
+                let args = python_eval
+                    .arg
+                    .iter()
+                    .map(|expr| try_parse_physical_expr(expr, &input_schema))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let result_schema = Arc::new(convert_required!(python_eval.result_schema)?);
+                Ok(Arc::new(PythonEvalExec::try_new(
+                    input,
+                    python_eval.serialized.clone(),
+                    args,
+                    result_schema,
+                )?))
+            }
         }
     }
 }
@@ -910,13 +971,17 @@ fn try_parse_physical_expr(
 
                 let scalar_udf = if scalar_function == protobuf::ScalarFunction::SparkExtFunctions {
                     let fun_name = &e.name;
-                    let fun = datafusion_ext_functions::create_spark_ext_function(fun_name)?;
+                    let return_type: DataType = convert_required!(e.return_type)?;
+                    let fun = datafusion_ext_functions::create_spark_ext_function(
+                        fun_name,
+                        &return_type,
+                    )?;
                     Arc::new(create_udf(
                         &format!("spark_ext_function_{}", fun_name),
                         args.iter()
                             .map(|e| e.data_type(input_schema))
                             .collect::<Result<Vec<_>, _>>()?,
-                        Arc::new(convert_required!(e.return_type)?),
+                        Arc::new(return_type),
                         Volatility::Volatile,
                         fun,
                     ))
@@ -1073,6 +1138,36 @@ fn try_parse_physical_sort_expr(
     Some(pyhsical_sort_expr)
 }
 
+fn try_parse_physical_sort_exprs(
+    exprs: &[protobuf::PhysicalExprNode],
+    input_schema: &SchemaRef,
+) -> Result<Vec<PhysicalSortExpr>, PlanSerDeError> {
+    exprs
+        .iter()
+        .map(|expr| {
+            let Some(ExprType::Sort(sort_expr)) = &expr.expr_type else {
+                return Err(proto_error(format!(
+                    "physical_plan::from_proto() Unexpected sort expr {:?}",
+                    expr
+                )));
+            };
+            let expr = sort_expr.expr.as_ref().ok_or_else(|| {
+                proto_error(format!(
+                    "physical_plan::from_proto() Unexpected sort expr {:?}",
+                    sort_expr
+                ))
+            })?;
+            Ok(PhysicalSortExpr {
+                expr: try_parse_physical_expr(expr, input_schema)?,
+                options: SortOptions {
+                    descending: !sort_expr.asc,
+                    nulls_first: sort_expr.nulls_first,
+                },
+            })
+        })
+        .collect()
+}
+
 pub fn parse_protobuf_partitioning(
     input: Arc<dyn ExecutionPlan>,
     partitioning: Option<&Box<PhysicalRepartition>>,
@@ -1093,10 +1188,17 @@ pub fn parse_protobuf_partitioning(
                     .iter()
                     .map(|e| try_parse_physical_expr(e, &input.schema()))
                     .collect::<Result<Vec<Arc<dyn PhysicalExpr>>, _>>()?;
-                Ok(Some(Partitioning::HashPartitioning(
-                    expr,
-                    hash_part.partition_count.try_into().unwrap(),
-                )))
+                let partition_count = hash_part.partition_count.try_into().unwrap();
+                if hash_part.hot_key_splits > 1 && partition_count > 1 {
+                    let splitting =
+                        HotKeySplitting::try_new_with_conf(hash_part.hot_key_splits as usize)?;
+                    return Ok(Some(Partitioning::SkewedHashPartitioning(
+                        expr,
+                        partition_count,
+                        Arc::new(splitting),
+                    )));
+                }
+                Ok(Some(Partitioning::HashPartitioning(expr, partition_count)))
             }
 
             RepartitionType::RoundRobinRepartition(round_robin_part) => {
@@ -0,0 +1,221 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! debug utility dumping shuffle/spill files as arrow batches.
+//!
+//! usage: ipc_dump <data-file> [--index <index-file>] [--schema <fields>]
+//!        [--spill [--codec <codec>]]
+//!
+//! * `--index`: committed shuffle index file, batches are dumped by partitions
+//! * `--schema`: comma-separated `name:DataType` fields (e.g. `id:Int64,
+//!   name:Utf8`), required for files in blaze ipc format. files written in
+//!   arrow ipc format are self-described, but every arrow ipc stream is
+//!   wrapped in a block header, so this is the supported way to read them.
+//! * `--spill`: reads a spill file, which is a raw compressed stream split into
+//!   checksum frames. frames are verified and decompressed with `--codec` (lz4
+//!   by default). with `--schema`, the stream is decoded as serialized batches,
+//!   spills with other data between batches (like sorted keys of sort spills)
+//!   can only be verified.
+
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    os::unix::fs::FileExt,
+    str::FromStr,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{RecordBatch, RecordBatchOptions},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    util::pretty::pretty_format_batches,
+};
+use datafusion::common::Result;
+use datafusion_ext_commons::{
+    df_execution_err,
+    io::{read_one_batch, recover_named_batch},
+};
+use datafusion_ext_plans::{
+    common::ipc_compression::{
+        is_arrow_ipc_block_header, IoCompressionCodec, IoCompressionReader, IpcCompressionReader,
+        BLOCK_HEADER_LEN,
+    },
+    memmgr::spill::SpillChecksumReader,
+    shuffle::local_reader::read_partition_range,
+};
+
+const USAGE: &str = "usage: ipc_dump <data-file> [--index <index-file>] [--schema <fields>] \
+                     [--spill [--codec <codec>]]";
+
+fn main() -> Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let mut data_file_path = None;
+    let mut index_file_path = None;
+    let mut schema = None;
+    let mut spill = false;
+    let mut codec = IoCompressionCodec::Lz4;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--index" if i + 1 < args.len() => {
+                index_file_path = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--schema" if i + 1 < args.len() => {
+                schema = Some(parse_schema(&args[i + 1])?);
+                i += 1;
+            }
+            "--spill" => {
+                spill = true;
+            }
+            "--codec" if i + 1 < args.len() => {
+                codec = IoCompressionCodec::try_from_name(&args[i + 1])?;
+                i += 1;
+            }
+            arg if data_file_path.is_none() && !arg.starts_with("--") => {
+                data_file_path = Some(arg.to_string());
+            }
+            arg => return df_execution_err!("unexpected argument: {arg}"),
+        }
+        i += 1;
+    }
+    let Some(data_file_path) = data_file_path else {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    };
+
+    if spill {
+        return dump_spill(&data_file_path, codec, schema);
+    }
+
+    // without index file, the whole file is dumped as one segment
+    let segments = match &index_file_path {
+        Some(index_file_path) => {
+            let index_file = File::open(index_file_path)?;
+            let num_partitions = (index_file.metadata()?.len() / 8).saturating_sub(1) as usize;
+            (0..num_partitions)
+                .map(|p| {
+                    let (start, end) = read_partition_range(&index_file, p, p + 1)?;
+                    Ok((Some(p), start, end))
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+        None => vec![(None, 0, File::open(&data_file_path)?.metadata()?.len())],
+    };
+
+    for (partition, start, end) in segments {
+        if start == end {
+            continue;
+        }
+        if let Some(partition) = partition {
+            println!(
+                "partition {partition}: offset={start}, length={}",
+                end - start
+            );
+        }
+        dump_segment(&data_file_path, start, end, schema.clone())?;
+    }
+    Ok(())
+}
+
+fn dump_segment(path: &str, start: u64, end: u64, schema: Option<SchemaRef>) -> Result<()> {
+    let mut file = File::open(path)?;
+
+    // blaze ipc blocks are not self-described, so the schema is required
+    let mut header = [0u8; BLOCK_HEADER_LEN];
+    file.read_exact_at(&mut header, start)?;
+    if !is_arrow_ipc_block_header(&header) && schema.is_none() {
+        return df_execution_err!("--schema is required for files in blaze ipc format");
+    }
+    file.seek(SeekFrom::Start(start))?;
+    let segment = BufReader::new(file.take(end - start));
+
+    let read_schema = schema.clone().unwrap_or_else(|| Arc::new(Schema::empty()));
+    let mut reader = IpcCompressionReader::new(segment);
+    let mut batch_idx = 0;
+    while let Some((num_rows, cols)) = reader.read_batch(&read_schema)? {
+        let batch = match &schema {
+            Some(schema) => recover_named_batch(num_rows, &cols, schema.clone())?,
+            None => {
+                let fields = cols
+                    .iter()
+                    .enumerate()
+                    .map(|(i, col)| Field::new(format!("c{i}"), col.data_type().clone(), true))
+                    .collect::<Vec<_>>();
+                RecordBatch::try_new_with_options(
+                    Arc::new(Schema::new(fields)),
+                    cols,
+                    &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+                )?
+            }
+        };
+        println!("batch {batch_idx}: num_rows={num_rows}");
+        println!("{}", pretty_format_batches(&[batch])?);
+        batch_idx += 1;
+    }
+    Ok(())
+}
+
+fn dump_spill(path: &str, codec: IoCompressionCodec, schema: Option<SchemaRef>) -> Result<()> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+
+    // checksums of all frames are verified while decompressing
+    let mut data = vec![];
+    IoCompressionReader::try_new_with_codec(codec, SpillChecksumReader::new(BufReader::new(file)))?
+        .read_to_end(&mut data)?;
+    println!(
+        "spill: file_size={file_len}, uncompressed_size={}, checksums verified",
+        data.len()
+    );
+
+    let Some(schema) = schema else {
+        return Ok(());
+    };
+    let mut input = Cursor::new(data);
+    let mut batch_idx = 0;
+    while let Some((num_rows, cols)) = read_one_batch(&mut input, &schema)? {
+        let batch = recover_named_batch(num_rows, &cols, schema.clone())?;
+        println!("batch {batch_idx}: num_rows={num_rows}");
+        println!("{}", pretty_format_batches(&[batch])?);
+        batch_idx += 1;
+    }
+    Ok(())
+}
+
+// parses comma-separated name:DataType fields, commas inside data types (like
+// Decimal128(10, 2)) are not treated as separators
+fn parse_schema(s: &str) -> Result<SchemaRef> {
+    let mut fields = vec![];
+    let mut depth = 0;
+    let mut field_start = 0;
+    for (i, c) in s.char_indices().chain([(s.len(), ',')]) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                let field = s[field_start..i].trim();
+                let Some((name, data_type)) = field.split_once(':') else {
+                    return df_execution_err!("invalid schema field: {field}");
+                };
+                let data_type = DataType::from_str(data_type.trim())?;
+                fields.push(Field::new(name.trim(), data_type, true));
+                field_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
@@ -0,0 +1,68 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use datafusion_ext_plans::memmgr::{MemManager, MemManagerSnapshot};
+use poem::{handler, http::StatusCode, web::Json, RouteMethod};
+
+use crate::http::Handler;
+
+#[handler]
+async fn mem_status_handler() -> poem::Result<Json<MemManagerSnapshot>> {
+    if !MemManager::initialized() {
+        return Err(poem::Error::from_string(
+            "mem manager not initialized",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    }
+    Ok(Json(MemManager::get().snapshot()))
+}
+
+/// reports memory used by all registered consumers in json, including
+/// partitions, spills and peaks of each consumer
+#[derive(Default)]
+pub struct MemStatusHandler;
+
+impl Handler for MemStatusHandler {
+    fn get_route_method(&self) -> RouteMethod {
+        RouteMethod::new().get(mem_status_handler)
+    }
+
+    fn get_route_path(&self) -> String {
+        "/debug/memory/status".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, Route};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_router() {
+        let handler = MemStatusHandler::default();
+        let app = Route::new().at(handler.get_route_path(), handler.get_route_method());
+        let cli = TestClient::new(app);
+
+        MemManager::init(1 << 30);
+        let resp = cli.get("/debug/memory/status").send().await;
+        resp.assert_status_is_ok();
+
+        let json = resp.json().await;
+        let status = json.value().object();
+        status.get("total").assert_i64(1 << 30);
+        status.get("consumers").array();
+        status.get("finished_consumers").array();
+    }
+}
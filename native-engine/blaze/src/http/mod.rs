@@ -12,10 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod mem_status;
 #[cfg(feature = "jemalloc-pprof")]
 mod memory_profiling;
 #[cfg(feature = "jemalloc-pprof")]
 mod pprof;
+mod prometheus;
 
 use std::sync::Mutex;
 
@@ -94,6 +96,13 @@ pub struct HttpService {
 impl HttpService {
     pub fn init() -> Self {
         let server = Box::new(DefaultHTTPServer::new());
+        {
+            use crate::http::mem_status::MemStatusHandler;
+            server.register_handler(Box::new(MemStatusHandler::default()));
+
+            use crate::http::prometheus::MetricsHandler;
+            server.register_handler(Box::new(MetricsHandler::default()));
+        }
         #[cfg(feature = "jemalloc-pprof")]
         {
             use crate::http::pprof::PProfHandler;
@@ -0,0 +1,224 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fmt::{Display, Write},
+    sync::atomic::Ordering::Relaxed,
+};
+
+use blaze_jni_bridge::jni_bridge::JNI_CALL_COUNT;
+use datafusion_ext_plans::{
+    memmgr::{disk_spill::DiskSpillManager, MemManager},
+    shuffle::shuffle_bytes_written,
+};
+use poem::{handler, web::WithContentType, IntoResponse, RouteMethod};
+
+use crate::{http::Handler, rt::tokio_stats};
+
+/// writes metrics in prometheus text exposition format
+#[derive(Default)]
+struct PrometheusWriter(String);
+
+impl PrometheusWriter {
+    fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+        self.write(name, help, "gauge", value);
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: impl Display) {
+        self.write(name, help, "counter", value);
+    }
+
+    fn write(&mut self, name: &str, help: &str, metric_type: &str, value: impl Display) {
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+        let _ = writeln!(self.0, "# TYPE {name} {metric_type}");
+        let _ = writeln!(self.0, "{name} {value}");
+    }
+}
+
+fn collect_metrics() -> String {
+    let mut w = PrometheusWriter::default();
+
+    if MemManager::initialized() {
+        let mm = MemManager::get().snapshot();
+        w.gauge(
+            "blaze_mem_total_bytes",
+            "Memory managed by mem manager",
+            mm.total,
+        );
+        w.gauge(
+            "blaze_mem_used_bytes",
+            "Memory used by all consumers",
+            mm.total_used,
+        );
+        w.gauge(
+            "blaze_mem_peak_used_bytes",
+            "Peak memory used by all consumers",
+            mm.peak_total_used,
+        );
+        w.gauge(
+            "blaze_mem_spillable_bytes",
+            "Memory used by spillable consumers",
+            mm.mem_spillables,
+        );
+        w.gauge(
+            "blaze_mem_jvm_direct_used_bytes",
+            "JVM direct memory used",
+            mm.jvm_direct_used,
+        );
+        w.gauge(
+            "blaze_mem_consumers",
+            "Number of registered consumers",
+            mm.consumers.len(),
+        );
+        w.counter(
+            "blaze_spills_total",
+            "Number of spills written by mem consumers",
+            mm.num_spills,
+        );
+        w.counter(
+            "blaze_spilled_mem_bytes_total",
+            "Length of spill data written by mem consumers",
+            mm.spilled_mem,
+        );
+    }
+    w.gauge(
+        "blaze_spill_disk_used_bytes",
+        "Disk space used by native spill files",
+        DiskSpillManager::get().executor_used(),
+    );
+
+    let rt_stats = tokio_stats();
+    w.gauge(
+        "blaze_native_runtimes_active",
+        "Number of running native runtimes",
+        rt_stats.num_runtimes,
+    );
+    w.gauge(
+        "blaze_tokio_workers",
+        "Number of tokio worker threads",
+        rt_stats.num_workers,
+    );
+    w.gauge(
+        "blaze_tokio_alive_tasks",
+        "Number of alive tokio tasks",
+        rt_stats.num_alive_tasks,
+    );
+    w.gauge(
+        "blaze_tokio_global_queue_depth",
+        "Number of tasks in tokio global queues",
+        rt_stats.global_queue_depth,
+    );
+    w.counter(
+        "blaze_tokio_worker_busy_seconds_total",
+        "Time tokio workers spent on executing tasks",
+        rt_stats.worker_busy_duration.as_secs_f64(),
+    );
+
+    #[cfg(feature = "jemalloc-pprof")]
+    {
+        use tikv_jemalloc_ctl::{epoch, stats};
+
+        // jemalloc stats are cached until epoch is advanced
+        if epoch::advance().is_ok() {
+            let jemalloc_stats = [
+                ("allocated", stats::allocated::read()),
+                ("active", stats::active::read()),
+                ("resident", stats::resident::read()),
+                ("mapped", stats::mapped::read()),
+                ("retained", stats::retained::read()),
+            ];
+            for (name, value) in jemalloc_stats {
+                if let Ok(value) = value {
+                    w.gauge(
+                        &format!("blaze_jemalloc_{name}_bytes"),
+                        &format!("Bytes {name} by jemalloc"),
+                        value,
+                    );
+                }
+            }
+        }
+    }
+
+    w.counter(
+        "blaze_shuffle_bytes_written_total",
+        "Bytes written to shuffle outputs",
+        shuffle_bytes_written(),
+    );
+    w.counter(
+        "blaze_jni_calls_total",
+        "Number of java methods called from native",
+        JNI_CALL_COUNT.load(Relaxed),
+    );
+    w.0
+}
+
+#[handler]
+async fn metrics_handler() -> WithContentType<String> {
+    collect_metrics().with_content_type("text/plain; version=0.0.4")
+}
+
+/// exports process-wide metrics of the native engine in prometheus text
+/// format, which can be scraped by cluster monitoring
+#[derive(Default)]
+pub struct MetricsHandler;
+
+impl Handler for MetricsHandler {
+    fn get_route_method(&self) -> RouteMethod {
+        RouteMethod::new().get(metrics_handler)
+    }
+
+    fn get_route_path(&self) -> String {
+        "/metrics".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{test::TestClient, Route};
+
+    use super::*;
+
+    #[test]
+    fn test_prometheus_writer() {
+        let mut w = PrometheusWriter::default();
+        w.gauge("test_gauge", "A test gauge", 1);
+        w.counter("test_counter", "A test counter", 2.5);
+        assert_eq!(
+            w.0,
+            "# HELP test_gauge A test gauge\n\
+             # TYPE test_gauge gauge\n\
+             test_gauge 1\n\
+             # HELP test_counter A test counter\n\
+             # TYPE test_counter counter\n\
+             test_counter 2.5\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let handler = MetricsHandler::default();
+        let app = Route::new().at(handler.get_route_path(), handler.get_route_method());
+        let cli = TestClient::new(app);
+
+        MemManager::init(1 << 30);
+        let resp = cli.get("/metrics").send().await;
+        resp.assert_status_is_ok();
+        resp.assert_content_type("text/plain; version=0.0.4");
+
+        let body = resp.0.into_body().into_string().await.unwrap();
+        assert!(body.contains(&format!("blaze_mem_total_bytes {}\n", 1 << 30)));
+        assert!(body.contains("# TYPE blaze_jni_calls_total counter\n"));
+        assert!(body.contains("blaze_native_runtimes_active 0\n"));
+    }
+}
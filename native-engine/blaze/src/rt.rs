@@ -13,9 +13,15 @@
 // limitations under the License.
 
 use std::{
+    collections::HashMap,
     error::Error,
     panic::AssertUnwindSafe,
-    sync::{mpsc::Receiver, Arc},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+        mpsc::Receiver,
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use arrow::{
@@ -39,17 +45,26 @@ use datafusion::{
         displayable, empty::EmptyExec, metrics::ExecutionPlanMetricsSet, ExecutionPlan,
     },
 };
-use datafusion_ext_commons::{df_execution_err, downcast_any};
+use datafusion_ext_commons::{
+    cancellation::set_thread_cancel_token, df_execution_err, downcast_any,
+};
 use datafusion_ext_plans::{
-    common::execution_context::{cancel_all_tasks, ExecutionContext},
+    common::{
+        execution_context::{cancel_all_tasks, ExecutionContext},
+        trace::trace_dir,
+    },
     ipc_writer_exec::IpcWriterExec,
     parquet_sink_exec::ParquetSinkExec,
     shuffle_writer_exec::ShuffleWriterExec,
 };
 use futures::{FutureExt, StreamExt};
 use jni::objects::{GlobalRef, JObject};
+use once_cell::sync::OnceCell;
 use prost::Message;
-use tokio::{runtime::Runtime, task::JoinHandle};
+use tokio::{
+    runtime::{Handle, Runtime},
+    task::JoinHandle,
+};
 
 use crate::{
     handle_unwinded_scope,
@@ -59,6 +74,8 @@ use crate::{
 
 pub struct NativeExecutionRuntime {
     exec_ctx: Arc<ExecutionContext>,
+    stage_id: usize,
+    runtime_id: usize,
     native_wrapper: GlobalRef,
     plan: Arc<dyn ExecutionPlan>,
     batch_receiver: Receiver<Result<Option<RecordBatch>>>,
@@ -94,6 +111,7 @@ impl NativeExecutionRuntime {
             execution_plan.schema(),
             &ExecutionPlanMetricsSet::new(),
         );
+        exec_ctx.task_resources().set_stage_id(stage_id);
 
         let num_worker_threads = {
             let worker_threads_per_cpu = TOKIO_WORKER_THREADS_PER_CPU.value().unwrap_or(0);
@@ -105,6 +123,7 @@ impl NativeExecutionRuntime {
         // propagate classloader and task context to spawned children threads
         let spark_task_context = jni_call_static!(JniBridge.getTaskContext() -> JObject)?;
         let spark_task_context_global = jni_new_global_ref!(spark_task_context.as_obj())?;
+        let cancel_token = exec_ctx.cancel_token().clone();
         let mut tokio_runtime_builder = tokio::runtime::Builder::new_multi_thread();
         tokio_runtime_builder
             .thread_name(format!("blaze-native-stage-{stage_id}-part-{partition_id}"))
@@ -118,11 +137,13 @@ impl NativeExecutionRuntime {
                 );
                 THREAD_STAGE_ID.set(stage_id);
                 THREAD_PARTITION_ID.set(partition_id);
+                set_thread_cancel_token(cancel_token.clone());
             });
         if num_worker_threads > 0 {
             tokio_runtime_builder.worker_threads(num_worker_threads as usize);
         }
         let tokio_runtime = tokio_runtime_builder.build()?;
+        let runtime_id = register_runtime(tokio_runtime.handle().clone());
 
         // spawn batch producer
         let (batch_sender, batch_receiver) = std::sync::mpsc::sync_channel(1);
@@ -212,6 +233,8 @@ impl NativeExecutionRuntime {
 
         let native_execution_runtime = Self {
             exec_ctx: exec_ctx.clone(),
+            stage_id,
+            runtime_id,
             native_wrapper: native_wrapper.clone(),
             plan: execution_plan.clone(),
             tokio_runtime,
@@ -263,7 +286,12 @@ impl NativeExecutionRuntime {
 
         cancel_all_tasks(&self.exec_ctx.task_ctx()); // cancel all pending streams
         self.join_handle.abort();
+        deregister_runtime(self.runtime_id);
         self.tokio_runtime.shutdown_background();
+
+        // delete disk spill files, including those of cancelled operators
+        self.exec_ctx.task_resources().spill_scope().release_all();
+        write_trace(&self.exec_ctx, self.stage_id);
         log::info!("(partition={partition}) native execution finalized");
     }
 
@@ -276,6 +304,89 @@ impl NativeExecutionRuntime {
     }
 }
 
+/// worker statistics of all running native runtimes, busy duration also
+/// includes runtimes already finished
+pub struct TokioStats {
+    pub num_runtimes: usize,
+    pub num_workers: usize,
+    pub num_alive_tasks: usize,
+    pub global_queue_depth: usize,
+    pub worker_busy_duration: Duration,
+}
+
+pub fn tokio_stats() -> TokioStats {
+    let mut stats = TokioStats {
+        num_runtimes: 0,
+        num_workers: 0,
+        num_alive_tasks: 0,
+        global_queue_depth: 0,
+        worker_busy_duration: Duration::from_nanos(FINISHED_WORKER_BUSY_NANOS.load(Relaxed)),
+    };
+    for handle in running_runtimes().lock().unwrap().values() {
+        let metrics = handle.metrics();
+        stats.num_runtimes += 1;
+        stats.num_workers += metrics.num_workers();
+        stats.num_alive_tasks += metrics.num_alive_tasks();
+        stats.global_queue_depth += metrics.global_queue_depth();
+        stats.worker_busy_duration += worker_busy_duration(handle);
+    }
+    stats
+}
+
+static NEXT_RUNTIME_ID: AtomicUsize = AtomicUsize::new(0);
+static FINISHED_WORKER_BUSY_NANOS: AtomicU64 = AtomicU64::new(0);
+
+fn running_runtimes() -> &'static Mutex<HashMap<usize, Handle>> {
+    static RUNNING_RUNTIMES: OnceCell<Mutex<HashMap<usize, Handle>>> = OnceCell::new();
+    RUNNING_RUNTIMES.get_or_init(Mutex::default)
+}
+
+fn register_runtime(handle: Handle) -> usize {
+    let runtime_id = NEXT_RUNTIME_ID.fetch_add(1, Relaxed);
+    running_runtimes()
+        .lock()
+        .unwrap()
+        .insert(runtime_id, handle);
+    runtime_id
+}
+
+fn deregister_runtime(runtime_id: usize) {
+    if let Some(handle) = running_runtimes().lock().unwrap().remove(&runtime_id) {
+        let busy_nanos = worker_busy_duration(&handle).as_nanos() as u64;
+        FINISHED_WORKER_BUSY_NANOS.fetch_add(busy_nanos, Relaxed);
+    }
+}
+
+fn worker_busy_duration(handle: &Handle) -> Duration {
+    let metrics = handle.metrics();
+    (0..metrics.num_workers())
+        .map(|worker| metrics.worker_total_busy_duration(worker))
+        .sum()
+}
+
+// writes execution trace of the task as a chrome trace file if enabled
+fn write_trace(exec_ctx: &ExecutionContext, stage_id: usize) {
+    let Some(tracer) = exec_ctx.task_resources().tracer() else {
+        return;
+    };
+    let partition = exec_ctx.partition_id();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let trace_path = trace_dir().join(format!(
+        "blaze-trace-stage-{stage_id}-part-{partition}-{timestamp}.json"
+    ));
+    let process_name = format!("stage {stage_id} partition {partition}");
+    match tracer.write_to_file(&trace_path, &process_name) {
+        Ok(()) => log::info!(
+            "(partition={partition}) execution trace written to {}",
+            trace_path.display()
+        ),
+        Err(err) => log::warn!("(partition={partition}) error writing execution trace: {err}"),
+    }
+}
+
 fn set_error(native_wrapper: &GlobalRef, message: &str, cause: Option<JObject>) -> Result<()> {
     let message = jni_new_string!(message.to_owned())?;
     let e = jni_new_object!(JavaRuntimeException(
@@ -0,0 +1,95 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+};
+
+use datafusion::common::Result;
+
+use crate::df_execution_err;
+
+thread_local! {
+    static THREAD_CANCEL_TOKEN: RefCell<Option<Arc<CancellationToken>>> = RefCell::new(None);
+}
+
+/// cooperative cancellation token shared by all execution contexts of a task.
+/// hot loops poll the token periodically and stop with an error once the task
+/// is cancelled.
+#[derive(Debug, Default)]
+pub struct CancellationToken {
+    cancelled: AtomicBool,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Relaxed)
+    }
+
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return df_execution_err!("task cancelled");
+        }
+        Ok(())
+    }
+}
+
+/// binds the token to the current thread, called when starting the worker
+/// threads of a task's runtime
+pub fn set_thread_cancel_token(token: Arc<CancellationToken>) {
+    THREAD_CANCEL_TOKEN.with(|cell| *cell.borrow_mut() = Some(token));
+}
+
+/// checks the token bound to the current thread, used by code that has no
+/// access to the execution context (like scalar functions)
+pub fn check_thread_cancelled() -> Result<()> {
+    THREAD_CANCEL_TOKEN.with(|cell| match cell.borrow().as_ref() {
+        Some(token) => token.check_cancelled(),
+        None => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::cancellation::{check_thread_cancelled, set_thread_cancel_token, CancellationToken};
+
+    #[test]
+    fn test_thread_cancel_token() {
+        let token = Arc::new(CancellationToken::default());
+        let token_cloned = token.clone();
+        std::thread::spawn(move || {
+            assert!(check_thread_cancelled().is_ok());
+            set_thread_cancel_token(token_cloned.clone());
+            assert!(check_thread_cancelled().is_ok());
+            token_cloned.cancel();
+            assert!(check_thread_cancelled().is_err());
+        })
+        .join()
+        .unwrap();
+
+        // other threads are not affected
+        assert!(token.is_cancelled());
+        assert!(check_thread_cancelled().is_ok());
+    }
+}
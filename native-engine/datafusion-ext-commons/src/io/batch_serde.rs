@@ -28,16 +28,19 @@ use unchecked_index::unchecked_index;
 
 use crate::{
     df_unimplemented_err,
-    io::{read_bytes_slice, read_len, write_len},
+    io::{
+        column_encoding::{read_encoded_array, write_encoded_array},
+        read_bytes_slice, read_len, write_len,
+    },
 };
 
 pub fn write_batch(num_rows: usize, cols: &[ArrayRef], mut output: impl Write) -> Result<()> {
     // write number of columns and rows
     write_len(num_rows, &mut output)?;
 
-    // write columns
+    // write columns, each column is prefixed with its encoding
     for col in cols {
-        write_array(col, &mut output)?;
+        write_encoded_array(col, &mut output)?;
     }
     Ok(())
 }
@@ -50,7 +53,7 @@ pub fn read_batch(mut input: impl Read, schema: &SchemaRef) -> Result<(usize, Ve
     let cols = schema
         .fields()
         .into_iter()
-        .map(|field| read_array(&mut input, &field.data_type(), num_rows))
+        .map(|field| read_encoded_array(&mut input, &field.data_type(), num_rows))
         .collect::<Result<_>>()?;
     Ok((num_rows, cols))
 }
@@ -89,6 +92,7 @@ pub fn write_array<W: Write>(array: &dyn Array, output: &mut W) -> Result<()> {
         DataType::List(_field) => write_list_array(as_list_array(array), output)?,
         DataType::Map(..) => write_map_array(as_map_array(array), output)?,
         DataType::Struct(_) => write_struct_array(as_struct_array(array), output)?,
+        DataType::Dictionary(..) => write_dictionary_array(array.as_any_dictionary(), output)?,
         other => df_unimplemented_err!("unsupported data type: {other}")?,
     }
     Ok(())
@@ -135,11 +139,14 @@ pub fn read_array<R: Read>(
             read_map_array(num_rows, input, map_field, *is_sorted)?
         }
         DataType::Struct(fields) => read_struct_array(num_rows, input, fields)?,
+        DataType::Dictionary(key_type, value_type) => {
+            read_dictionary_array(num_rows, input, data_type, key_type, value_type)?
+        }
         other => df_unimplemented_err!("unsupported data type: {other}")?,
     })
 }
 
-fn write_bits_buffer<W: Write>(
+pub(super) fn write_bits_buffer<W: Write>(
     buffer: &Buffer,
     bits_offset: usize,
     bits_len: usize,
@@ -160,7 +167,7 @@ fn write_bits_buffer<W: Write>(
     Ok(())
 }
 
-fn read_bits_buffer<R: Read>(input: &mut R, bits_len: usize) -> Result<Buffer> {
+pub(super) fn read_bits_buffer<R: Read>(input: &mut R, bits_len: usize) -> Result<Buffer> {
     let buf = read_bytes_slice(input, (bits_len + 7) / 8)?;
     Ok(Buffer::from_vec(buf.into()))
 }
@@ -409,6 +416,33 @@ fn read_struct_array<R: Read>(num_rows: usize, input: &mut R, fields: &Fields) -
     Ok(make_array(array_data))
 }
 
+fn write_dictionary_array<W: Write>(array: &dyn AnyDictionaryArray, output: &mut W) -> Result<()> {
+    write_array(array.keys(), output)?;
+    write_len(array.values().len(), output)?;
+    write_array(array.values(), output)?;
+    Ok(())
+}
+
+fn read_dictionary_array<R: Read>(
+    num_rows: usize,
+    input: &mut R,
+    data_type: &DataType,
+    key_type: &DataType,
+    value_type: &DataType,
+) -> Result<ArrayRef> {
+    let keys = read_array(input, key_type, num_rows)?;
+    let values_len = read_len(input)?;
+    let values = read_array(input, value_type, values_len)?;
+
+    let array_data = keys
+        .into_data()
+        .into_builder()
+        .data_type(data_type.clone())
+        .child_data(vec![values.into_data()])
+        .build()?;
+    Ok(make_array(array_data))
+}
+
 fn write_boolean_array<W: Write>(array: &BooleanArray, output: &mut W) -> Result<()> {
     let array_data = array.to_data();
     if let Some(null_buffer) = array_data.nulls() {
@@ -521,7 +555,7 @@ fn read_bytes_array<R: Read>(
     Ok(make_array(array_data))
 }
 
-fn write_primitive_raw_array<T: Default + Copy + Sized, W: Write>(
+pub(super) fn write_primitive_raw_array<T: Default + Copy + Sized, W: Write>(
     array: &[T],
     output: &mut W,
 ) -> Result<()> {
@@ -544,7 +578,7 @@ fn write_primitive_raw_array<T: Default + Copy + Sized, W: Write>(
     Ok(())
 }
 
-fn read_primitive_raw_array<T: Default + Copy + Sized, R: Read>(
+pub(super) fn read_primitive_raw_array<T: Default + Copy + Sized, R: Read>(
     input: &mut R,
     num_items: usize,
 ) -> Result<Vec<T>> {
@@ -796,4 +830,34 @@ mod test {
             sliced
         );
     }
+
+    #[test]
+    fn test_write_and_read_batch_for_dictionary() {
+        let keys = Int32Array::from(vec![Some(0), Some(2), None, Some(1), Some(2)]);
+        let values: ArrayRef = Arc::new(StringArray::from(vec!["aa", "bb", "cc"]));
+        let dict_array: ArrayRef = Arc::new(DictionaryArray::new(keys, values));
+        let batch =
+            RecordBatch::try_from_iter_with_nullable(vec![("dict", dict_array, true)]).unwrap();
+
+        // test read after write
+        let mut buf = vec![];
+        write_batch(batch.num_rows(), batch.columns(), &mut buf).unwrap();
+        let mut cursor = Cursor::new(buf);
+        let (decoded_num_rows, decoded_cols) = read_batch(&mut cursor, &batch.schema()).unwrap();
+        assert_eq!(
+            recover_named_batch(decoded_num_rows, &decoded_cols, batch.schema()).unwrap(),
+            batch
+        );
+
+        // test read after write sliced
+        let sliced = batch.slice(1, 3);
+        let mut buf = vec![];
+        write_batch(sliced.num_rows(), sliced.columns(), &mut buf).unwrap();
+        let mut cursor = Cursor::new(buf);
+        let (decoded_num_rows, decoded_cols) = read_batch(&mut cursor, &batch.schema()).unwrap();
+        assert_eq!(
+            recover_named_batch(decoded_num_rows, &decoded_cols, sliced.schema()).unwrap(),
+            sliced
+        );
+    }
 }
@@ -0,0 +1,699 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// adaptive encodings of top-level columns in serialized batches. each column
+// is written with the encoding producing the smallest output, the encoding tag
+// is written before the encoded data of each column:
+//
+//  Plain:      see batch_serde::write_array
+//  Dictionary: nulls, num_distinct, distinct value lens, distinct value bytes,
+//              key bit width, bit-packed keys (for Utf8/Binary)
+//  RunLength:  nulls, num_runs, run values, run lens (for integers), or
+//              nulls, first value, num_runs, run lens (for booleans)
+//  BitPacked:  nulls, min value, bit width, bit-packed (value - min)
+//  Delta:      nulls, first value, bit width, bit-packed deltas (for sorted
+//              integers)
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    mem::size_of,
+};
+
+use arrow::{
+    array::*,
+    buffer::{Buffer, MutableBuffer},
+    datatypes::*,
+};
+use blaze_jni_bridge::{
+    conf::{BooleanConf, COLUMN_ENCODING_ENABLE},
+    is_jni_bridge_inited,
+};
+use datafusion::common::Result;
+use once_cell::sync::OnceCell;
+
+use crate::{
+    df_execution_err,
+    io::{
+        batch_serde::{
+            read_array, read_bits_buffer, read_primitive_raw_array, write_array, write_bits_buffer,
+            write_primitive_raw_array,
+        },
+        read_bytes_slice, read_len, read_raw_slice, read_u8, write_len, write_raw_slice, write_u8,
+    },
+};
+
+/// encodings are only tried on columns with at least this number of rows
+const MIN_NUM_ROWS_FOR_ENCODING: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnEncoding {
+    Plain,
+    Dictionary,
+    RunLength,
+    BitPacked,
+    Delta,
+}
+
+impl ColumnEncoding {
+    pub fn tag(&self) -> u8 {
+        match self {
+            ColumnEncoding::Plain => 0,
+            ColumnEncoding::Dictionary => 1,
+            ColumnEncoding::RunLength => 2,
+            ColumnEncoding::BitPacked => 3,
+            ColumnEncoding::Delta => 4,
+        }
+    }
+
+    pub fn try_from_tag(tag: u8) -> Result<Self> {
+        Ok(match tag {
+            0 => ColumnEncoding::Plain,
+            1 => ColumnEncoding::Dictionary,
+            2 => ColumnEncoding::RunLength,
+            3 => ColumnEncoding::BitPacked,
+            4 => ColumnEncoding::Delta,
+            other => df_execution_err!("unknown column encoding tag: {other}")?,
+        })
+    }
+}
+
+macro_rules! dispatch_int_type {
+    ($data_type:expr, $f:ident($($arg:expr),*)) => {{
+        match $data_type {
+            DataType::Int8 => Some($f::<Int8Type>($($arg),*)),
+            DataType::Int16 => Some($f::<Int16Type>($($arg),*)),
+            DataType::Int32 => Some($f::<Int32Type>($($arg),*)),
+            DataType::Int64 => Some($f::<Int64Type>($($arg),*)),
+            DataType::UInt8 => Some($f::<UInt8Type>($($arg),*)),
+            DataType::UInt16 => Some($f::<UInt16Type>($($arg),*)),
+            DataType::UInt32 => Some($f::<UInt32Type>($($arg),*)),
+            DataType::UInt64 => Some($f::<UInt64Type>($($arg),*)),
+            DataType::Date32 => Some($f::<Date32Type>($($arg),*)),
+            DataType::Date64 => Some($f::<Date64Type>($($arg),*)),
+            DataType::Timestamp(TimeUnit::Second, _) => Some($f::<TimestampSecondType>($($arg),*)),
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                Some($f::<TimestampMillisecondType>($($arg),*))
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                Some($f::<TimestampMicrosecondType>($($arg),*))
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                Some($f::<TimestampNanosecondType>($($arg),*))
+            }
+            _ => None,
+        }
+    }};
+}
+
+fn column_encoding_enabled() -> bool {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    *ENABLED
+        .get_or_try_init(|| {
+            if is_jni_bridge_inited() {
+                COLUMN_ENCODING_ENABLE.value()
+            } else {
+                Ok(true) // for testing
+            }
+        })
+        .expect("error reading spark.blaze.columnEncoding.enable")
+}
+
+/// writes encoding tag and array with the most suitable encoding, returns the
+/// encoding used
+pub fn write_encoded_array<W: Write>(array: &dyn Array, output: &mut W) -> Result<ColumnEncoding> {
+    if array.len() >= MIN_NUM_ROWS_FOR_ENCODING && column_encoding_enabled() {
+        let encoding = match array.data_type() {
+            DataType::Boolean => write_boolean_encoded(as_boolean_array(array), output)?,
+            DataType::Utf8 => write_bytes_encoded(as_string_array(array), output)?,
+            DataType::Binary => write_bytes_encoded(as_generic_binary_array::<i32>(array), output)?,
+            data_type => dispatch_int_type!(data_type, write_int_encoded(array, output))
+                .transpose()?
+                .flatten(),
+        };
+        if let Some(encoding) = encoding {
+            return Ok(encoding);
+        }
+    }
+    write_u8(ColumnEncoding::Plain.tag(), output)?;
+    write_array(array, output)?;
+    Ok(ColumnEncoding::Plain)
+}
+
+/// reads encoding tag and array written by write_encoded_array()
+pub fn read_encoded_array<R: Read>(
+    input: &mut R,
+    data_type: &DataType,
+    num_rows: usize,
+) -> Result<ArrayRef> {
+    let encoding = ColumnEncoding::try_from_tag(read_u8(input)?)?;
+    match (encoding, data_type) {
+        (ColumnEncoding::Plain, _) => read_array(input, data_type, num_rows),
+        (ColumnEncoding::RunLength, DataType::Boolean) => read_boolean_encoded(input, num_rows),
+        (ColumnEncoding::Dictionary, DataType::Utf8 | DataType::Binary) => {
+            read_bytes_encoded(input, data_type, num_rows)
+        }
+        (ColumnEncoding::RunLength | ColumnEncoding::BitPacked | ColumnEncoding::Delta, _) => {
+            dispatch_int_type!(
+                data_type,
+                read_int_encoded(input, data_type, num_rows, encoding)
+            )
+            .unwrap_or_else(|| {
+                df_execution_err!("unsupported encoding {encoding:?} for data type {data_type}")
+            })
+        }
+        _ => df_execution_err!("unsupported encoding {encoding:?} for data type {data_type}"),
+    }
+}
+
+trait EncodableInt: ArrowNativeType + Ord {
+    /// converts to 64-bit two's complement, differences of two values are
+    /// computed with wrapping arithmetic
+    fn to_bits64(self) -> u64;
+    fn from_bits64(bits: u64) -> Self;
+}
+
+macro_rules! impl_encodable_int {
+    ($($t:ty),*) => {
+        $(
+            impl EncodableInt for $t {
+                fn to_bits64(self) -> u64 {
+                    self as i64 as u64
+                }
+
+                fn from_bits64(bits: u64) -> Self {
+                    bits as $t
+                }
+            }
+        )*
+    };
+}
+impl_encodable_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+fn write_int_encoded<PT: ArrowPrimitiveType, W: Write>(
+    array: &dyn Array,
+    output: &mut W,
+) -> Result<Option<ColumnEncoding>>
+where
+    PT::Native: EncodableInt,
+{
+    let values: &[PT::Native] = as_primitive_array::<PT>(array).values();
+    let num_values = values.len();
+    let value_size = size_of::<PT::Native>();
+
+    let mut min = values[0];
+    let mut max = values[0];
+    let mut num_runs = 1;
+    let mut sorted = true;
+    let mut max_delta = 0;
+    for (&prev, &cur) in values.iter().zip(&values[1..]) {
+        min = min.min(cur);
+        max = max.max(cur);
+        if cur != prev {
+            num_runs += 1;
+        }
+        if cur < prev {
+            sorted = false;
+        } else {
+            max_delta = max_delta.max(cur.to_bits64().wrapping_sub(prev.to_bits64()));
+        }
+    }
+    let min_bits = min.to_bits64();
+    let packed_bit_width = bit_width(max.to_bits64().wrapping_sub(min_bits));
+    let delta_bit_width = bit_width(max_delta);
+
+    let plain_size = num_values * value_size;
+    let run_length_size = num_runs * (value_size + 2);
+    let bit_packed_size = value_size + bit_packed_len(num_values, packed_bit_width);
+    let delta_size = if sorted {
+        value_size + bit_packed_len(num_values - 1, delta_bit_width)
+    } else {
+        usize::MAX
+    };
+    let (encoding, encoded_size) = [
+        (ColumnEncoding::RunLength, run_length_size),
+        (ColumnEncoding::BitPacked, bit_packed_size),
+        (ColumnEncoding::Delta, delta_size),
+    ]
+    .into_iter()
+    .min_by_key(|&(_, size)| size)
+    .unwrap();
+
+    // encoded values are less compressible than byte-transposed plain values,
+    // so only use encodings with significant gain
+    if encoded_size > plain_size / 2 {
+        return Ok(None);
+    }
+
+    write_u8(encoding.tag(), output)?;
+    write_nulls(array, output)?;
+    match encoding {
+        ColumnEncoding::RunLength => {
+            let mut run_values: Vec<PT::Native> = vec![];
+            let mut run_lens: Vec<usize> = vec![];
+            for &value in values {
+                match run_values.last() {
+                    Some(&last) if last == value => *run_lens.last_mut().unwrap() += 1,
+                    _ => {
+                        run_values.push(value);
+                        run_lens.push(1);
+                    }
+                }
+            }
+            write_len(run_values.len(), output)?;
+            write_primitive_raw_array(&run_values, output)?;
+            for run_len in run_lens {
+                write_len(run_len, output)?;
+            }
+        }
+        ColumnEncoding::BitPacked => {
+            write_raw_slice(&[min], &mut *output)?;
+            write_u8(packed_bit_width, output)?;
+            let packed = values.iter().map(|v| v.to_bits64().wrapping_sub(min_bits));
+            write_bit_packed(packed, packed_bit_width, output)?;
+        }
+        ColumnEncoding::Delta => {
+            write_raw_slice(&values[..1], &mut *output)?;
+            write_u8(delta_bit_width, output)?;
+            let deltas = values
+                .iter()
+                .zip(&values[1..])
+                .map(|(prev, cur)| cur.to_bits64().wrapping_sub(prev.to_bits64()));
+            write_bit_packed(deltas, delta_bit_width, output)?;
+        }
+        _ => unreachable!(),
+    }
+    Ok(Some(encoding))
+}
+
+fn read_int_encoded<PT: ArrowPrimitiveType>(
+    input: &mut impl Read,
+    data_type: &DataType,
+    num_rows: usize,
+    encoding: ColumnEncoding,
+) -> Result<ArrayRef>
+where
+    PT::Native: EncodableInt,
+{
+    let null_buffer = read_nulls(input, num_rows)?;
+    let values: Vec<PT::Native> = match encoding {
+        ColumnEncoding::RunLength => {
+            let num_runs = read_len(input)?;
+            let run_values = read_primitive_raw_array::<PT::Native, _>(input, num_runs)?;
+            let mut values = Vec::with_capacity(num_rows);
+            for run_value in run_values {
+                let run_len = read_len(input)?;
+                values.extend(std::iter::repeat(run_value).take(run_len));
+            }
+            values
+        }
+        ColumnEncoding::BitPacked => {
+            let mut min = [PT::Native::default()];
+            read_raw_slice(&mut min, &mut *input)?;
+            let min_bits = min[0].to_bits64();
+            let bit_width = read_u8(input)?;
+            read_bit_packed(input, num_rows, bit_width)?
+                .into_iter()
+                .map(|packed| PT::Native::from_bits64(min_bits.wrapping_add(packed)))
+                .collect()
+        }
+        ColumnEncoding::Delta => {
+            let mut first = [PT::Native::default()];
+            read_raw_slice(&mut first, &mut *input)?;
+            let bit_width = read_u8(input)?;
+            let deltas = read_bit_packed(input, num_rows.saturating_sub(1), bit_width)?;
+            let mut values = Vec::with_capacity(num_rows);
+            let mut cur_bits = first[0].to_bits64();
+            values.push(first[0]);
+            for delta in deltas {
+                cur_bits = cur_bits.wrapping_add(delta);
+                values.push(PT::Native::from_bits64(cur_bits));
+            }
+            values
+        }
+        _ => unreachable!(),
+    };
+    if values.len() != num_rows {
+        return df_execution_err!(
+            "error decoding {encoding:?} column: expect {num_rows} rows, got {}",
+            values.len()
+        );
+    }
+
+    let array_data = ArrayData::try_new(
+        data_type.clone(),
+        num_rows,
+        null_buffer,
+        0,
+        vec![Buffer::from_vec(values)],
+        vec![],
+    )?;
+    Ok(make_array(array_data))
+}
+
+fn write_boolean_encoded<W: Write>(
+    array: &BooleanArray,
+    output: &mut W,
+) -> Result<Option<ColumnEncoding>> {
+    let values = array.values();
+    let max_num_runs = values.len() / 16; // each run takes about one byte
+    let first_value = values.value(0);
+
+    let mut run_lens = vec![];
+    let mut cur_value = first_value;
+    let mut cur_run_len = 0;
+    for value in values.iter() {
+        if value != cur_value {
+            run_lens.push(cur_run_len);
+            if run_lens.len() >= max_num_runs {
+                return Ok(None);
+            }
+            cur_value = value;
+            cur_run_len = 0;
+        }
+        cur_run_len += 1;
+    }
+    run_lens.push(cur_run_len);
+
+    write_u8(ColumnEncoding::RunLength.tag(), output)?;
+    write_nulls(array, output)?;
+    write_u8(first_value as u8, output)?;
+    write_len(run_lens.len(), output)?;
+    for run_len in run_lens {
+        write_len(run_len, output)?;
+    }
+    Ok(Some(ColumnEncoding::RunLength))
+}
+
+fn read_boolean_encoded(input: &mut impl Read, num_rows: usize) -> Result<ArrayRef> {
+    let null_buffer = read_nulls(input, num_rows)?;
+    let mut cur_value = read_u8(input)? != 0;
+    let num_runs = read_len(input)?;
+    let mut values = BooleanBufferBuilder::new(num_rows);
+    for _ in 0..num_runs {
+        values.append_n(read_len(input)?, cur_value);
+        cur_value = !cur_value;
+    }
+    if values.len() != num_rows {
+        return df_execution_err!(
+            "error decoding boolean column: expect {num_rows} rows, got {}",
+            values.len()
+        );
+    }
+
+    let array_data = ArrayData::try_new(
+        DataType::Boolean,
+        num_rows,
+        null_buffer,
+        0,
+        vec![values.finish().into_inner()],
+        vec![],
+    )?;
+    Ok(make_array(array_data))
+}
+
+fn write_bytes_encoded<T: ByteArrayType<Offset = i32>, W: Write>(
+    array: &GenericByteArray<T>,
+    output: &mut W,
+) -> Result<Option<ColumnEncoding>> {
+    let num_rows = array.len();
+    let max_num_distinct = num_rows / 4;
+    let offsets = array.value_offsets();
+    let data = array.value_data();
+
+    let mut dict: HashMap<&[u8], u32> = HashMap::new();
+    let mut distinct_values: Vec<&[u8]> = vec![];
+    let mut keys = Vec::with_capacity(num_rows);
+    for i in 0..num_rows {
+        let value = &data[offsets[i] as usize..offsets[i + 1] as usize];
+        let key = *dict.entry(value).or_insert_with(|| {
+            distinct_values.push(value);
+            distinct_values.len() as u32 - 1
+        });
+        if distinct_values.len() > max_num_distinct {
+            return Ok(None);
+        }
+        keys.push(key as u64);
+    }
+
+    let key_bit_width = bit_width(distinct_values.len() as u64 - 1);
+    let plain_size = (offsets[num_rows] - offsets[0]) as usize + num_rows * 4;
+    let dict_size = distinct_values.iter().map(|v| v.len() + 4).sum::<usize>()
+        + bit_packed_len(num_rows, key_bit_width);
+    if dict_size > plain_size / 2 {
+        return Ok(None);
+    }
+
+    write_u8(ColumnEncoding::Dictionary.tag(), output)?;
+    write_nulls(array, output)?;
+    write_len(distinct_values.len(), output)?;
+    let distinct_lens = distinct_values
+        .iter()
+        .map(|v| v.len() as i32)
+        .collect::<Vec<_>>();
+    write_primitive_raw_array(&distinct_lens, output)?;
+    for value in distinct_values {
+        output.write_all(value)?;
+    }
+    write_u8(key_bit_width, output)?;
+    write_bit_packed(keys.into_iter(), key_bit_width, output)?;
+    Ok(Some(ColumnEncoding::Dictionary))
+}
+
+fn read_bytes_encoded(
+    input: &mut impl Read,
+    data_type: &DataType,
+    num_rows: usize,
+) -> Result<ArrayRef> {
+    let null_buffer = read_nulls(input, num_rows)?;
+    let num_distinct = read_len(input)?;
+    let distinct_lens = read_primitive_raw_array::<i32, _>(input, num_distinct)?;
+    let mut distinct_offsets = Vec::with_capacity(num_distinct + 1);
+    let mut cur_offset = 0;
+    distinct_offsets.push(0);
+    for len in distinct_lens {
+        cur_offset += len as usize;
+        distinct_offsets.push(cur_offset);
+    }
+    let distinct_data = read_bytes_slice(input, cur_offset)?;
+
+    let key_bit_width = read_u8(input)?;
+    let keys = read_bit_packed(input, num_rows, key_bit_width)?;
+    let mut offsets_buffer = MutableBuffer::new((num_rows + 1) * 4);
+    let mut data = vec![];
+    offsets_buffer.push(0i32);
+    for key in keys {
+        let key = key as usize;
+        if key >= num_distinct {
+            return df_execution_err!("error decoding dictionary column: invalid key {key}");
+        }
+        data.extend_from_slice(&distinct_data[distinct_offsets[key]..distinct_offsets[key + 1]]);
+        offsets_buffer.push(data.len() as i32);
+    }
+
+    let array_data = ArrayData::try_new(
+        data_type.clone(),
+        num_rows,
+        null_buffer,
+        0,
+        vec![offsets_buffer.into(), Buffer::from_vec(data)],
+        vec![],
+    )?;
+    Ok(make_array(array_data))
+}
+
+fn write_nulls<W: Write>(array: &dyn Array, output: &mut W) -> Result<()> {
+    if let Some(null_buffer) = array.nulls() {
+        write_len(1, output)?;
+        write_bits_buffer(
+            null_buffer.buffer(),
+            null_buffer.offset(),
+            null_buffer.len(),
+            output,
+        )?;
+    } else {
+        write_len(0, output)?;
+    }
+    Ok(())
+}
+
+fn read_nulls(input: &mut impl Read, num_rows: usize) -> Result<Option<Buffer>> {
+    let has_null_buffer = read_len(input)? == 1;
+    Ok(if has_null_buffer {
+        Some(read_bits_buffer(input, num_rows)?)
+    } else {
+        None
+    })
+}
+
+fn bit_width(max_value: u64) -> u8 {
+    (u64::BITS - max_value.leading_zeros()) as u8
+}
+
+fn bit_packed_len(num_values: usize, bit_width: u8) -> usize {
+    (num_values * bit_width as usize + 7) / 8
+}
+
+fn write_bit_packed<W: Write>(
+    values: impl Iterator<Item = u64>,
+    bit_width: u8,
+    output: &mut W,
+) -> Result<()> {
+    let mut packed = Vec::with_capacity(8192);
+    let mut acc = 0u128;
+    let mut acc_bits = 0;
+    for value in values {
+        acc |= (value as u128) << acc_bits;
+        acc_bits += bit_width as u32;
+        while acc_bits >= 8 {
+            packed.push(acc as u8);
+            acc >>= 8;
+            acc_bits -= 8;
+        }
+        if packed.len() >= 8192 {
+            output.write_all(&packed)?;
+            packed.clear();
+        }
+    }
+    if acc_bits > 0 {
+        packed.push(acc as u8);
+    }
+    output.write_all(&packed)?;
+    Ok(())
+}
+
+fn read_bit_packed(input: &mut impl Read, num_values: usize, bit_width: u8) -> Result<Vec<u64>> {
+    if bit_width > 64 {
+        return df_execution_err!("error decoding bit-packed data: invalid bit width {bit_width}");
+    }
+    let packed = read_bytes_slice(input, bit_packed_len(num_values, bit_width))?;
+    let bit_width = bit_width as u32;
+    let mask = match bit_width {
+        64 => u64::MAX,
+        _ => (1u64 << bit_width) - 1,
+    };
+
+    let mut values = Vec::with_capacity(num_values);
+    let mut packed_iter = packed.iter();
+    let mut acc = 0u128;
+    let mut acc_bits = 0;
+    for _ in 0..num_values {
+        while acc_bits < bit_width {
+            let byte = match packed_iter.next() {
+                Some(&byte) => byte,
+                None => return df_execution_err!("error decoding bit-packed data: exhausted"),
+            };
+            acc |= (byte as u128) << acc_bits;
+            acc_bits += 8;
+        }
+        values.push(acc as u64 & mask);
+        acc >>= bit_width;
+        acc_bits -= bit_width;
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Cursor, sync::Arc};
+
+    use arrow::{array::*, datatypes::*};
+
+    use crate::io::column_encoding::{
+        read_bit_packed, read_encoded_array, write_encoded_array, ColumnEncoding,
+    };
+
+    fn assert_encoding(array: ArrayRef, expected_encoding: ColumnEncoding) {
+        for array in [array.clone(), array.slice(3, array.len() - 7)] {
+            let mut buf = vec![];
+            let encoding = write_encoded_array(&array, &mut buf).unwrap();
+            assert_eq!(encoding, expected_encoding);
+
+            let decoded =
+                read_encoded_array(&mut Cursor::new(&buf), array.data_type(), array.len()).unwrap();
+            assert_eq!(&decoded, &array);
+        }
+    }
+
+    #[test]
+    fn test_column_encodings() {
+        // repetitive strings
+        let strings: ArrayRef = Arc::new(StringArray::from_iter(
+            (0..1000).map(|i| (i % 10 != 0).then(|| format!("category-{}", i % 7))),
+        ));
+        assert_encoding(strings, ColumnEncoding::Dictionary);
+
+        // distinct strings
+        let strings: ArrayRef = Arc::new(StringArray::from_iter_values(
+            (0..1000).map(|i| format!("value-{i}")),
+        ));
+        assert_encoding(strings, ColumnEncoding::Plain);
+
+        // long runs of integers
+        let ints: ArrayRef = Arc::new(Int64Array::from_iter(
+            (0..1000).map(|i| (i % 100 != 0).then_some(i / 300 - 2)),
+        ));
+        assert_encoding(ints, ColumnEncoding::RunLength);
+
+        // small range of integers
+        let ints: ArrayRef = Arc::new(Int32Array::from_iter_values(
+            (0..1000).map(|i| -100000 + (i * 7919) % 13),
+        ));
+        assert_encoding(ints, ColumnEncoding::BitPacked);
+
+        // sorted integers
+        let timestamps: ArrayRef = Arc::new(
+            TimestampMillisecondArray::from_iter_values(
+                (0..1000).map(|i| 1700000000000 + i * 1000 + i % 3),
+            )
+            .with_timezone("UTC"),
+        );
+        assert_encoding(timestamps, ColumnEncoding::Delta);
+
+        // unsigned integers with values above i64::MAX
+        let uints: ArrayRef = Arc::new(UInt64Array::from_iter_values(
+            (0..1000).map(|i| u64::MAX - (i * 31) % 5),
+        ));
+        assert_encoding(uints, ColumnEncoding::BitPacked);
+
+        // random integers
+        let ints: ArrayRef = Arc::new(Int64Array::from_iter_values(
+            (0..1000i64).map(|i| i.wrapping_mul(0x9e3779b97f4a7c15u64 as i64)),
+        ));
+        assert_encoding(ints, ColumnEncoding::Plain);
+
+        // long runs of booleans
+        let bools: ArrayRef = Arc::new(BooleanArray::from_iter(
+            (0..1000).map(|i| (i % 99 != 0).then_some(i / 200 % 2 == 0)),
+        ));
+        assert_encoding(bools, ColumnEncoding::RunLength);
+
+        // alternating booleans
+        let bools: ArrayRef = Arc::new(BooleanArray::from_iter_values(
+            (0..1000).map(|i| i % 2 == 0),
+        ));
+        assert_encoding(bools, ColumnEncoding::Plain);
+    }
+
+    #[test]
+    fn test_corrupted_bit_packed() {
+        let packed = vec![0xffu8; 100];
+        assert!(read_bit_packed(&mut Cursor::new(&packed), 10, 65).is_err());
+        assert!(read_bit_packed(&mut Cursor::new(&packed[..3]), 10, 7).is_err());
+        assert_eq!(
+            read_bit_packed(&mut Cursor::new(&packed), 10, 7).unwrap(),
+            vec![0x7f; 10],
+        );
+    }
+}
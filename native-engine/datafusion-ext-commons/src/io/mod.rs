@@ -26,6 +26,7 @@ pub use scalar_serde::{read_scalar, write_scalar};
 use crate::arrow::cast::cast;
 
 mod batch_serde;
+mod column_encoding;
 mod scalar_serde;
 
 pub fn write_raw_slice<T: Sized + Copy>(
@@ -26,6 +26,7 @@ use unchecked_index::UncheckedIndex;
 
 pub mod algorithm;
 pub mod arrow;
+pub mod cancellation;
 pub mod hadoop_fs;
 pub mod hash;
 pub mod io;
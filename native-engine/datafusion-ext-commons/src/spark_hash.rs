@@ -39,8 +39,9 @@ pub fn create_xxhash64_hashes(len: usize, arrays: &[ArrayRef], seed: i64) -> Vec
 /// Creates hash values for every row, based on the values in the
 /// columns.
 ///
-/// The number of rows to hash is determined by `hashes_buffer.len()`.
-/// `hashes_buffer` should be pre-sized appropriately
+/// Rows start from `seed` and every column is folded into the running hash,
+/// so null values leave the hash unchanged, the same as Spark's
+/// `HashExpression`.
 #[inline]
 pub fn create_hashes<T: num::PrimInt>(
     len: usize,
@@ -48,52 +49,95 @@ pub fn create_hashes<T: num::PrimInt>(
     seed: T,
     h: impl Fn(&[u8], T) -> T + Copy,
 ) -> Vec<T> {
-    if arrays.is_empty() {
-        return vec![seed; len];
+    let mut hash_buffer = vec![seed; len];
+    for col in arrays {
+        hash_array(col, &mut hash_buffer, h);
     }
-    let mut hash_buffer = vec![T::zero(); len];
-
-    // hash first column
-    hash_array(&arrays[0], &mut hash_buffer, seed, true, h);
+    hash_buffer
+}
 
-    // hash rest columns
-    for col in arrays.iter().skip(1) {
-        hash_array(col, &mut hash_buffer, seed, false, h);
+/// Creates hive hash values for every row, compatible with Spark's
+/// `HiveHash` expression.
+pub fn create_hive_hashes(len: usize, arrays: &[ArrayRef]) -> Vec<i32> {
+    let mut hash_buffer = vec![0i32; len];
+    for col in arrays {
+        assert_eq!(col.len(), len);
+        for (idx, hash) in hash_buffer.iter_mut().enumerate() {
+            *hash = hash.wrapping_mul(31).wrapping_add(hive_hash_one(col, idx));
+        }
     }
     hash_buffer
 }
 
+#[inline]
+fn normalize_f32(v: f32) -> f32 {
+    // spark treats -0.0 and 0.0 as equal, and all NaNs are canonicalized
+    if v == 0.0 {
+        0.0
+    } else if v.is_nan() {
+        f32::NAN
+    } else {
+        v
+    }
+}
+
+#[inline]
+fn normalize_f64(v: f64) -> f64 {
+    if v == 0.0 {
+        0.0
+    } else if v.is_nan() {
+        f64::NAN
+    } else {
+        v
+    }
+}
+
+/// Hashes a decimal value the way spark does: compact decimals are hashed as
+/// the unscaled long value, others as the bytes of `BigInteger.toByteArray()`.
+#[inline]
+fn hash_decimal<T: num::PrimInt>(
+    value: i128,
+    precision: u8,
+    hash: T,
+    h: impl Fn(&[u8], T) -> T + Copy,
+) -> T {
+    if precision <= 18 {
+        return h((value as i64).to_le_bytes().as_ref(), hash);
+    }
+
+    // minimal big-endian two's-complement representation
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < bytes.len() - 1 {
+        let (b, next_sign) = (bytes[start], bytes[start + 1] & 0x80);
+        if (b == 0x00 && next_sign == 0) || (b == 0xff && next_sign != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    h(&bytes[start..], hash)
+}
+
 #[inline]
 fn hash_array<T: num::PrimInt>(
     array: &ArrayRef,
     hashes_buffer: &mut [T],
-    initial_seed: T,
-    is_initial: bool,
     h: impl Fn(&[u8], T) -> T + Copy,
 ) {
     assert_eq!(array.len(), hashes_buffer.len());
 
-    macro_rules! initial_seed_or {
-        ($h:expr) => {{
-            if is_initial {
-                initial_seed
-            } else {
-                $h
-            }
-        }};
-    }
-
     macro_rules! hash_array {
         ($array_type:ident, $column:ident, $hashes:ident, $h:expr) => {
             let array = $column.as_any().downcast_ref::<$array_type>().unwrap();
             if array.null_count() == 0 {
                 for (i, hash) in $hashes.iter_mut().enumerate() {
-                    *hash = $h(&array.value(i).as_ref(), initial_seed_or!(*hash));
+                    *hash = $h(&array.value(i).as_ref(), *hash);
                 }
             } else {
                 for (i, hash) in $hashes.iter_mut().enumerate() {
                     if !array.is_null(i) {
-                        *hash = $h(&array.value(i).as_ref(), initial_seed_or!(*hash));
+                        *hash = $h(&array.value(i).as_ref(), *hash);
                     }
                 }
             }
@@ -102,47 +146,27 @@ fn hash_array<T: num::PrimInt>(
 
     macro_rules! hash_array_primitive {
         ($array_type:ident, $column:ident, $ty:ident, $hashes:ident, $h:expr) => {
+            hash_array_primitive!(
+                $array_type,
+                $column,
+                $ty,
+                $hashes,
+                $h,
+                std::convert::identity
+            );
+        };
+        ($array_type:ident, $column:ident, $ty:ident, $hashes:ident, $h:expr, $normalize:expr) => {
             let array = $column.as_any().downcast_ref::<$array_type>().unwrap();
             let values = array.values();
 
             if array.null_count() == 0 {
                 for (hash, value) in $hashes.iter_mut().zip(values.iter()) {
-                    *hash = $h(
-                        (*value as $ty).to_le_bytes().as_ref(),
-                        initial_seed_or!(*hash),
-                    );
+                    *hash = $h(($normalize)(*value as $ty).to_le_bytes().as_ref(), *hash);
                 }
             } else {
                 for (i, (hash, value)) in $hashes.iter_mut().zip(values.iter()).enumerate() {
                     if !array.is_null(i) {
-                        *hash = $h(
-                            (*value as $ty).to_le_bytes().as_ref(),
-                            initial_seed_or!(*hash),
-                        );
-                    }
-                }
-            }
-        };
-    }
-
-    macro_rules! hash_array_decimal {
-        ($array_type:ident, $column:ident, $hashes:ident, $h:expr) => {
-            let array = $column.as_any().downcast_ref::<$array_type>().unwrap();
-
-            if array.null_count() == 0 {
-                for (i, hash) in $hashes.iter_mut().enumerate() {
-                    *hash = $h(
-                        array.value(i).to_le_bytes().as_ref(),
-                        initial_seed_or!(*hash),
-                    );
-                }
-            } else {
-                for (i, hash) in $hashes.iter_mut().enumerate() {
-                    if !array.is_null(i) {
-                        *hash = $h(
-                            array.value(i).to_le_bytes().as_ref(),
-                            initial_seed_or!(*hash),
-                        );
+                        *hash = $h(($normalize)(*value as $ty).to_le_bytes().as_ref(), *hash);
                     }
                 }
             }
@@ -159,7 +183,7 @@ fn hash_array<T: num::PrimInt>(
                         (if array.value(i) { 1u32 } else { 0u32 })
                             .to_le_bytes()
                             .as_ref(),
-                        initial_seed_or!(*hash),
+                        *hash,
                     );
                 }
             } else {
@@ -169,7 +193,7 @@ fn hash_array<T: num::PrimInt>(
                             (if array.value(i) { 1u32 } else { 0u32 })
                                 .to_le_bytes()
                                 .as_ref(),
-                            initial_seed_or!(*hash),
+                            *hash,
                         );
                     }
                 }
@@ -188,10 +212,10 @@ fn hash_array<T: num::PrimInt>(
             hash_array_primitive!(Int64Array, array, i64, hashes_buffer, h);
         }
         DataType::Float32 => {
-            hash_array_primitive!(Float32Array, array, f32, hashes_buffer, h);
+            hash_array_primitive!(Float32Array, array, f32, hashes_buffer, h, normalize_f32);
         }
         DataType::Float64 => {
-            hash_array_primitive!(Float64Array, array, f64, hashes_buffer, h);
+            hash_array_primitive!(Float64Array, array, f64, hashes_buffer, h, normalize_f64);
         }
         DataType::Timestamp(TimeUnit::Second, _) => {
             hash_array_primitive!(TimestampSecondArray, array, i64, hashes_buffer, h);
@@ -223,38 +247,19 @@ fn hash_array<T: num::PrimInt>(
         DataType::LargeUtf8 => {
             hash_array!(LargeStringArray, array, hashes_buffer, h);
         }
-        DataType::Decimal128(..) => {
-            hash_array_decimal!(Decimal128Array, array, hashes_buffer, h);
+        DataType::Decimal128(precision, _) => {
+            let array = array.as_any().downcast_ref::<Decimal128Array>().unwrap();
+            for (i, hash) in hashes_buffer.iter_mut().enumerate() {
+                if array.is_valid(i) {
+                    *hash = hash_decimal(array.value(i), *precision, *hash, h);
+                }
+            }
         }
         DataType::Dictionary(index_type, _) => match index_type.as_ref() {
-            DataType::Int8 => create_hashes_dictionary::<Int8Type, _>(
-                array,
-                hashes_buffer,
-                initial_seed,
-                is_initial,
-                h,
-            ),
-            DataType::Int16 => create_hashes_dictionary::<Int16Type, _>(
-                array,
-                hashes_buffer,
-                initial_seed,
-                is_initial,
-                h,
-            ),
-            DataType::Int32 => create_hashes_dictionary::<Int32Type, _>(
-                array,
-                hashes_buffer,
-                initial_seed,
-                is_initial,
-                h,
-            ),
-            DataType::Int64 => create_hashes_dictionary::<Int64Type, _>(
-                array,
-                hashes_buffer,
-                initial_seed,
-                is_initial,
-                h,
-            ),
+            DataType::Int8 => create_hashes_dictionary::<Int8Type, _>(array, hashes_buffer, h),
+            DataType::Int16 => create_hashes_dictionary::<Int16Type, _>(array, hashes_buffer, h),
+            DataType::Int32 => create_hashes_dictionary::<Int32Type, _>(array, hashes_buffer, h),
+            DataType::Int64 => create_hashes_dictionary::<Int64Type, _>(array, hashes_buffer, h),
             other => panic!("Unsupported dictionary type in hasher hashing: {other}"),
         },
         _ => {
@@ -270,16 +275,10 @@ fn hash_array<T: num::PrimInt>(
 fn create_hashes_dictionary<K: ArrowDictionaryKeyType, T: num::PrimInt>(
     array: &ArrayRef,
     hashes_buffer: &mut [T],
-    initial_seed: T,
-    is_initial: bool,
     h: impl Fn(&[u8], T) -> T + Copy,
 ) {
     let dict_array = array.as_any().downcast_ref::<DictionaryArray<K>>().unwrap();
 
-    if is_initial {
-        hashes_buffer.fill(initial_seed);
-    }
-
     // Hash each dictionary value once, and then use that computed
     // hash for each key value to avoid a potentially expensive
     // redundant hashing for large dictionary elements (e.g. strings)
@@ -299,9 +298,30 @@ fn hash_one<T: num::PrimInt>(
 ) {
     macro_rules! hash_one_primitive {
         ($array_type:ident, $column:ident, $ty:ident, $hash:ident, $idx:ident, $h:expr) => {
+            hash_one_primitive!(
+                $array_type,
+                $column,
+                $ty,
+                $hash,
+                $idx,
+                $h,
+                std::convert::identity
+            );
+        };
+        (
+            $array_type:ident,
+            $column:ident,
+            $ty:ident,
+            $hash:ident,
+            $idx:ident,
+            $h:expr,
+            $normalize:expr
+        ) => {
             let array = $column.as_any().downcast_ref::<$array_type>().unwrap();
             *$hash = $h(
-                (array.value($idx as usize) as $ty).to_le_bytes().as_ref(),
+                ($normalize)(array.value($idx as usize) as $ty)
+                    .to_le_bytes()
+                    .as_ref(),
                 *$hash,
             );
         };
@@ -314,10 +334,13 @@ fn hash_one<T: num::PrimInt>(
         };
     }
 
-    macro_rules! hash_one_decimal {
+    macro_rules! hash_one_list {
         ($array_type:ident, $column:ident, $hash:ident, $idx:ident, $h:expr) => {
-            let array = $column.as_any().downcast_ref::<$array_type>().unwrap();
-            *$hash = $h(array.value($idx as usize).to_le_bytes().as_ref(), *$hash);
+            let list_array = $column.as_any().downcast_ref::<$array_type>().unwrap();
+            let value_array = list_array.value($idx);
+            for i in 0..value_array.len() {
+                hash_one(&value_array, i, $hash, $h);
+            }
         };
     }
 
@@ -346,18 +369,18 @@ fn hash_one<T: num::PrimInt>(
                 hash_one_primitive!(Int64Array, col, i64, hash, idx, h);
             }
             DataType::Float32 => {
-                hash_one_primitive!(Float32Array, col, f32, hash, idx, h);
+                hash_one_primitive!(Float32Array, col, f32, hash, idx, h, normalize_f32);
             }
             DataType::Float64 => {
-                hash_one_primitive!(Float64Array, col, f64, hash, idx, h);
+                hash_one_primitive!(Float64Array, col, f64, hash, idx, h, normalize_f64);
             }
-            DataType::Timestamp(TimeUnit::Second, None) => {
+            DataType::Timestamp(TimeUnit::Second, _) => {
                 hash_one_primitive!(TimestampSecondArray, col, i64, hash, idx, h);
             }
-            DataType::Timestamp(TimeUnit::Millisecond, None) => {
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
                 hash_one_primitive!(TimestampMillisecondArray, col, i64, hash, idx, h);
             }
-            DataType::Timestamp(TimeUnit::Microsecond, None) => {
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
                 hash_one_primitive!(TimestampMicrosecondArray, col, i64, hash, idx, h);
             }
             DataType::Timestamp(TimeUnit::Nanosecond, _) => {
@@ -381,16 +404,25 @@ fn hash_one<T: num::PrimInt>(
             DataType::LargeUtf8 => {
                 hash_one_binary!(LargeStringArray, col, hash, idx, h);
             }
-            DataType::Decimal128(..) => {
-                hash_one_decimal!(Decimal128Array, col, hash, idx, h);
+            DataType::Decimal128(precision, _) => {
+                let array = col.as_any().downcast_ref::<Decimal128Array>().unwrap();
+                *hash = hash_decimal(array.value(idx), *precision, *hash, h);
             }
-            DataType::List(..) => {
-                let list_array = col.as_any().downcast_ref::<ListArray>().unwrap();
-                let value_array = list_array.value(idx);
-                for i in 0..value_array.len() {
-                    hash_one(&value_array, i, hash, h);
+            DataType::Dictionary(..) => {
+                downcast_dictionary_array! {
+                    col => {
+                        let key = col.keys().value(idx).as_usize();
+                        hash_one(col.values(), key, hash, h);
+                    }
+                    other => panic!("Unsupported dictionary type in hasher: {other}"),
                 }
             }
+            DataType::List(..) => {
+                hash_one_list!(ListArray, col, hash, idx, h);
+            }
+            DataType::LargeList(..) => {
+                hash_one_list!(LargeListArray, col, hash, idx, h);
+            }
             DataType::Map(..) => {
                 let map_array = col.as_any().downcast_ref::<MapArray>().unwrap();
                 let kv_array = map_array.value(idx);
@@ -412,6 +444,130 @@ fn hash_one<T: num::PrimInt>(
     }
 }
 
+fn hive_hash_one(col: &ArrayRef, idx: usize) -> i32 {
+    macro_rules! value_of {
+        ($array_type:ident) => {
+            col.as_any()
+                .downcast_ref::<$array_type>()
+                .unwrap()
+                .value(idx)
+        };
+    }
+
+    #[inline]
+    fn hive_hash_long(v: i64) -> i32 {
+        (v ^ ((v as u64) >> 32) as i64) as i32
+    }
+
+    #[inline]
+    fn hive_hash_bytes(bytes: &[u8]) -> i32 {
+        bytes.iter().fold(0i32, |hash, &b| {
+            hash.wrapping_mul(31).wrapping_add(b as i8 as i32)
+        })
+    }
+
+    #[inline]
+    fn hive_hash_timestamp(micros: i64) -> i32 {
+        let secs = micros / 1_000_000;
+        let nanos = (micros % 1_000_000) * 1000;
+        let result = (secs << 30) | nanos;
+        (((result as u64) >> 32) as i64 ^ result) as i32
+    }
+
+    #[inline]
+    fn hive_hash_decimal(unscaled: i128, scale: i8) -> i32 {
+        // hash code of the normalized java BigDecimal (trailing zeros stripped)
+        if unscaled == 0 {
+            return 0;
+        }
+        let (mut unscaled, mut scale) = (unscaled, scale as i32);
+        while scale > 0 && unscaled % 10 == 0 {
+            unscaled /= 10;
+            scale -= 1;
+        }
+        let magnitude = unscaled.unsigned_abs();
+        let mut int_hash = 0i32;
+        for shift in [96, 64, 32, 0] {
+            let word = (magnitude >> shift) as u32;
+            int_hash = int_hash.wrapping_mul(31).wrapping_add(word as i32);
+        }
+        int_hash = int_hash.wrapping_mul(unscaled.signum() as i32);
+        int_hash.wrapping_mul(31).wrapping_add(scale)
+    }
+
+    if col.is_null(idx) {
+        return 0;
+    }
+    match col.data_type() {
+        DataType::Null => 0,
+        DataType::Boolean => value_of!(BooleanArray) as i32,
+        DataType::Int8 => value_of!(Int8Array) as i32,
+        DataType::Int16 => value_of!(Int16Array) as i32,
+        DataType::Int32 => value_of!(Int32Array),
+        DataType::Int64 => hive_hash_long(value_of!(Int64Array)),
+        DataType::Float32 => normalize_f32(value_of!(Float32Array)).to_bits() as i32,
+        DataType::Float64 => {
+            hive_hash_long(normalize_f64(value_of!(Float64Array)).to_bits() as i64)
+        }
+        DataType::Date32 => value_of!(Date32Array),
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            hive_hash_timestamp(value_of!(TimestampSecondArray).wrapping_mul(1_000_000))
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            hive_hash_timestamp(value_of!(TimestampMillisecondArray).wrapping_mul(1000))
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            hive_hash_timestamp(value_of!(TimestampMicrosecondArray))
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            hive_hash_timestamp(value_of!(TimestampNanosecondArray) / 1000)
+        }
+        DataType::Binary => hive_hash_bytes(value_of!(BinaryArray)),
+        DataType::LargeBinary => hive_hash_bytes(value_of!(LargeBinaryArray)),
+        DataType::Utf8 => hive_hash_bytes(value_of!(StringArray).as_bytes()),
+        DataType::LargeUtf8 => hive_hash_bytes(value_of!(LargeStringArray).as_bytes()),
+        DataType::Decimal128(_, scale) => hive_hash_decimal(value_of!(Decimal128Array), *scale),
+        DataType::Dictionary(..) => {
+            downcast_dictionary_array! {
+                col => hive_hash_one(col.values(), col.keys().value(idx).as_usize()),
+                other => panic!("Unsupported dictionary type in hive hasher: {other}"),
+            }
+        }
+        DataType::List(..) => {
+            let value_array = value_of!(ListArray);
+            (0..value_array.len()).fold(0i32, |hash, i| {
+                hash.wrapping_mul(31)
+                    .wrapping_add(hive_hash_one(&value_array, i))
+            })
+        }
+        DataType::LargeList(..) => {
+            let value_array = value_of!(LargeListArray);
+            (0..value_array.len()).fold(0i32, |hash, i| {
+                hash.wrapping_mul(31)
+                    .wrapping_add(hive_hash_one(&value_array, i))
+            })
+        }
+        DataType::Map(..) => {
+            let kv_array = value_of!(MapArray);
+            let key_array = kv_array.column(0);
+            let value_array = kv_array.column(1);
+            (0..kv_array.len()).fold(0i32, |hash, i| {
+                hash.wrapping_add(hive_hash_one(key_array, i) ^ hive_hash_one(value_array, i))
+            })
+        }
+        DataType::Struct(_) => {
+            let struct_array = col.as_any().downcast_ref::<StructArray>().unwrap();
+            struct_array.columns().iter().fold(0i32, |hash, field| {
+                hash.wrapping_mul(31)
+                    .wrapping_add(hive_hash_one(field, idx))
+            })
+        }
+        other => panic!("Unsupported data type in hive hasher: {other}"),
+    }
+}
+
+// expected values of murmur3/xxhash64 tests are produced by spark with the
+// queries in dev/spark-hash-vectors.sql
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -421,8 +577,8 @@ mod tests {
             make_array, Array, ArrayData, ArrayRef, Int32Array, Int64Array, Int8Array, MapArray,
             StringArray, StructArray, UInt32Array,
         },
-        buffer::Buffer,
-        datatypes::{DataType, Field, ToByteSlice},
+        buffer::{Buffer, NullBuffer, OffsetBuffer},
+        datatypes::{DataType, Field, Fields, Int32Type, Int64Type, ToByteSlice},
     };
 
     use super::*;
@@ -542,6 +698,477 @@ mod tests {
         assert_eq!(hashes, expected);
     }
 
+    #[test]
+    fn test_null_keeps_seed() {
+        let i = Arc::new(Int32Array::from(vec![Some(1), None])) as ArrayRef;
+        let j = Arc::new(Int32Array::from(vec![None, None])) as ArrayRef;
+        let hashes = create_murmur3_hashes(2, &[i.clone()], 42);
+        assert_eq!(hashes, vec![-559580957, 42]);
+        let hashes = create_murmur3_hashes(2, &[j.clone(), i.clone()], 42);
+        assert_eq!(hashes, vec![-559580957, 42]);
+        let hashes = create_xxhash64_hashes(2, &[j, i], 42);
+        assert_eq!(hashes, vec![-6698625589789238999, 42]);
+    }
+
+    #[test]
+    fn test_float_normalization() {
+        let f = Arc::new(Float32Array::from(vec![
+            0.0,
+            -0.0,
+            f32::NAN,
+            -f32::NAN,
+            1.5,
+        ])) as ArrayRef;
+        let hashes = create_murmur3_hashes(5, &[f.clone()], 42);
+        assert_eq!(
+            hashes,
+            vec![933211791, 933211791, -349261430, -349261430, -221251528]
+        );
+        let hashes = create_xxhash64_hashes(5, &[f], 42);
+        assert_eq!(
+            hashes,
+            vec![
+                3614696996920510707,
+                3614696996920510707,
+                2692338816207849720,
+                2692338816207849720,
+                6163473420726370430,
+            ]
+        );
+
+        let d = Arc::new(Float64Array::from(vec![0.0, -0.0, f64::NAN])) as ArrayRef;
+        let hashes = create_murmur3_hashes(3, &[d.clone()], 42);
+        assert_eq!(hashes, vec![-1670924195, -1670924195, -1281358385]);
+        let hashes = create_xxhash64_hashes(3, &[d], 42);
+        assert_eq!(
+            hashes,
+            vec![
+                -5252525462095825812,
+                -5252525462095825812,
+                -3127944061524951246,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decimal() {
+        // compact decimals are hashed as long values
+        let d = Arc::new(
+            Decimal128Array::from(vec![Some(12345), Some(-100), None])
+                .with_precision_and_scale(10, 2)
+                .unwrap(),
+        ) as ArrayRef;
+        let hashes = create_murmur3_hashes(3, &[d.clone()], 42);
+        assert_eq!(hashes, vec![1416086240, 904948192, 42]);
+        let hashes = create_xxhash64_hashes(3, &[d], 42);
+        assert_eq!(hashes, vec![8791244235932249694, 5675770457807661948, 42]);
+
+        // others are hashed as the bytes of java BigInteger
+        let d = Arc::new(
+            Decimal128Array::from(vec![12345, -10_000_000_000_000_000_000, 0])
+                .with_precision_and_scale(20, 2)
+                .unwrap(),
+        ) as ArrayRef;
+        let hashes = create_murmur3_hashes(3, &[d.clone()], 42);
+        assert_eq!(hashes, vec![589679666, -651938147, -783713497]);
+        let hashes = create_xxhash64_hashes(3, &[d], 42);
+        assert_eq!(
+            hashes,
+            vec![
+                -3765588051240043440,
+                8362328957718276525,
+                -8959994473701255385,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decimal_precisions() {
+        // (precision, scale, unscaled values, murmur3 hashes, xxhash64 hashes)
+        let cases: Vec<(u8, i8, Vec<i128>, Vec<i32>, Vec<i64>)> = vec![
+            (
+                1,
+                0,
+                vec![1, -9, 0],
+                vec![-1712319331, 1838415974, -1670924195],
+                vec![
+                    -7001672635703045582,
+                    6280094535572400640,
+                    -5252525462095825812,
+                ],
+            ),
+            (
+                18,
+                0,
+                vec![999_999_999_999_999_999, -999_999_999_999_999_999, 1],
+                vec![-1795328666, 1962370902, -1712319331],
+                vec![
+                    2162198894918931945,
+                    4265531446127695490,
+                    -7001672635703045582,
+                ],
+            ),
+            (
+                19,
+                0,
+                vec![
+                    1,
+                    -1,
+                    9_999_999_999_999_999_999,
+                    -9_999_999_999_999_999_999,
+                    0,
+                ],
+                vec![-386724586, 1398487324, 584036537, -66784248, -783713497],
+                vec![
+                    6668291691252061002,
+                    -4006032525457443936,
+                    -768917144950141986,
+                    8615971145055448513,
+                    -8959994473701255385,
+                ],
+            ),
+            (
+                28,
+                10,
+                vec![1_270_000_000_000, -1_280_000_000_000, 2_550_000_000_000],
+                vec![514718374, -1427974491, -1216112937],
+                vec![
+                    -6803195062585532585,
+                    -9172950850964570473,
+                    3608620471447835063,
+                ],
+            ),
+            (
+                38,
+                0,
+                vec![10i128.pow(38) - 1, -(10i128.pow(38) - 1), -1],
+                vec![-817514053, 1400911110, 1398487324],
+                vec![
+                    -47190729175993179,
+                    -2254039905620870768,
+                    -4006032525457443936,
+                ],
+            ),
+        ];
+
+        for (precision, scale, values, expected_murmur3, expected_xxhash64) in cases {
+            let num_rows = values.len();
+            let d = Arc::new(
+                Decimal128Array::from(values)
+                    .with_precision_and_scale(precision, scale)
+                    .unwrap(),
+            ) as ArrayRef;
+            let hashes = create_murmur3_hashes(num_rows, &[d.clone()], 42);
+            assert_eq!(hashes, expected_murmur3, "precision={precision}");
+            let hashes = create_xxhash64_hashes(num_rows, &[d], 42);
+            assert_eq!(hashes, expected_xxhash64, "precision={precision}");
+        }
+    }
+
+    #[test]
+    fn test_nested_list() {
+        let l = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1), Some(2), Some(3)]),
+            Some(vec![]),
+            None,
+            Some(vec![Some(4), None]),
+        ])) as ArrayRef;
+
+        // generated with Murmur3Hash(Seq(Literal(Array(1, 2, 3))), 42).eval()
+        let hashes = create_murmur3_hashes(4, &[l.clone()], 42);
+        assert_eq!(hashes, vec![-912918097, 42, 42, -397064898]);
+
+        let hashes = create_xxhash64_hashes(4, &[l], 42);
+        assert_eq!(
+            hashes,
+            vec![8592097078962733837, 42, 42, -8344648708406692296]
+        );
+    }
+
+    #[test]
+    fn test_nested_struct() {
+        let s = Arc::new(StructArray::new(
+            Fields::from(vec![
+                Field::new("a", DataType::Int32, true),
+                Field::new("b", DataType::Utf8, true),
+            ]),
+            vec![
+                Arc::new(Int32Array::from(vec![Some(1), None, Some(7)])) as ArrayRef,
+                Arc::new(StringArray::from(vec!["a", "b", "z"])) as ArrayRef,
+            ],
+            Some(NullBuffer::from(vec![true, true, false])),
+        )) as ArrayRef;
+
+        let hashes = create_murmur3_hashes(3, &[s.clone()], 42);
+        assert_eq!(hashes, vec![-936062819, 1905031361, 42]);
+
+        let hashes = create_xxhash64_hashes(3, &[s], 42);
+        assert_eq!(hashes, vec![8205864924878002737, -6391946315847899181, 42]);
+    }
+
+    #[test]
+    fn test_nested_map() {
+        let mut builder = MapBuilder::new(None, StringBuilder::new(), Int32Builder::new());
+        builder.keys().append_value("k");
+        builder.values().append_value(1);
+        builder.append(true).unwrap();
+        builder.keys().append_value("x");
+        builder.values().append_value(1);
+        builder.keys().append_value("y");
+        builder.values().append_value(2);
+        builder.append(true).unwrap();
+        let m = Arc::new(builder.finish()) as ArrayRef;
+
+        let hashes = create_murmur3_hashes(2, &[m.clone()], 42);
+        assert_eq!(hashes, vec![-723207348, -1915144056]);
+
+        let hashes = create_xxhash64_hashes(2, &[m], 42);
+        assert_eq!(hashes, vec![-3868663538592345351, 6701831459966452470]);
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        // array<struct<a: int, b: array<bigint>>>
+        let b = ListArray::from_iter_primitive::<Int64Type, _, _>(vec![
+            Some(vec![Some(2), Some(3)]),
+            None,
+        ]);
+        let s = StructArray::new(
+            Fields::from(vec![
+                Field::new("a", DataType::Int32, true),
+                Field::new("b", b.data_type().clone(), true),
+            ]),
+            vec![
+                Arc::new(Int32Array::from(vec![Some(1), None])) as ArrayRef,
+                Arc::new(b) as ArrayRef,
+            ],
+            None,
+        );
+        let l = Arc::new(ListArray::new(
+            Arc::new(Field::new("item", s.data_type().clone(), true)),
+            OffsetBuffer::new(vec![0, 2, 2].into()),
+            Arc::new(s),
+            None,
+        )) as ArrayRef;
+
+        let hashes = create_murmur3_hashes(2, &[l.clone()], 42);
+        assert_eq!(hashes, vec![1177396896, 42]);
+
+        let hashes = create_xxhash64_hashes(2, &[l], 42);
+        assert_eq!(hashes, vec![-5700046881192428777, 42]);
+    }
+
+    #[test]
+    fn test_nested_nulls() {
+        let assert_hashes = |array: ArrayRef, murmur3: Vec<i32>, xxhash64: Vec<i64>| {
+            let num_rows = array.len();
+            assert_eq!(
+                create_murmur3_hashes(num_rows, &[array.clone()], 42),
+                murmur3
+            );
+            assert_eq!(create_xxhash64_hashes(num_rows, &[array], 42), xxhash64);
+        };
+
+        // null elements leave the hash unchanged
+        let l = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![None]),
+            Some(vec![None, None]),
+            None,
+        ]);
+        assert_hashes(Arc::new(l), vec![42, 42, 42], vec![42, 42, 42]);
+
+        // array<array<int>>
+        let mut builder = ListBuilder::new(ListBuilder::new(Int32Builder::new()));
+        builder.values().values().append_value(1);
+        builder.values().values().append_null();
+        builder.values().append(true);
+        builder.values().append(false);
+        builder.values().append(true);
+        builder.append(true);
+        builder.values().values().append_null();
+        builder.values().append(true);
+        builder.append(true);
+        builder.values().append(false);
+        builder.append(true);
+        assert_hashes(
+            Arc::new(builder.finish()),
+            vec![-559580957, 42, 42],
+            vec![-6698625589789238999, 42, 42],
+        );
+
+        // struct<a: int, b: string>
+        let s = StructArray::new(
+            Fields::from(vec![
+                Field::new("a", DataType::Int32, true),
+                Field::new("b", DataType::Utf8, true),
+            ]),
+            vec![
+                Arc::new(Int32Array::from(vec![None, Some(1)])) as ArrayRef,
+                Arc::new(StringArray::from(vec![None, Some("a")])) as ArrayRef,
+            ],
+            Some(NullBuffer::from(vec![true, false])),
+        );
+        assert_hashes(Arc::new(s), vec![42, 42], vec![42, 42]);
+
+        // struct<a: struct<p: int, q: int>, b: int>
+        let inner = StructArray::new(
+            Fields::from(vec![
+                Field::new("p", DataType::Int32, true),
+                Field::new("q", DataType::Int32, true),
+            ]),
+            vec![
+                Arc::new(Int32Array::from(vec![None, Some(5), None])) as ArrayRef,
+                Arc::new(Int32Array::from(vec![Some(1), Some(6), None])) as ArrayRef,
+            ],
+            Some(NullBuffer::from(vec![true, false, true])),
+        );
+        let s = StructArray::new(
+            Fields::from(vec![
+                Field::new("a", inner.data_type().clone(), true),
+                Field::new("b", DataType::Int32, true),
+            ]),
+            vec![
+                Arc::new(inner) as ArrayRef,
+                Arc::new(Int32Array::from(vec![None, Some(2), None])) as ArrayRef,
+            ],
+            None,
+        );
+        assert_hashes(
+            Arc::new(s),
+            vec![-559580957, 1765031574, 42],
+            vec![-6698625589789238999, 8420071140774656230, 42],
+        );
+
+        // struct<a: array<int>, b: string>
+        let a = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![None, Some(2)]),
+            None,
+        ]);
+        let s = StructArray::new(
+            Fields::from(vec![
+                Field::new("a", a.data_type().clone(), true),
+                Field::new("b", DataType::Utf8, true),
+            ]),
+            vec![
+                Arc::new(a) as ArrayRef,
+                Arc::new(StringArray::from(vec![Some("x"), None])) as ArrayRef,
+            ],
+            None,
+        );
+        assert_hashes(
+            Arc::new(s),
+            vec![-1858824732, 42],
+            vec![7320293303008432836, 42],
+        );
+
+        // map<int, int>
+        let mut builder = MapBuilder::new(None, Int32Builder::new(), Int32Builder::new());
+        builder.keys().append_value(1);
+        builder.values().append_null();
+        builder.append(true).unwrap();
+        builder.keys().append_value(1);
+        builder.values().append_null();
+        builder.keys().append_value(2);
+        builder.values().append_value(3);
+        builder.append(true).unwrap();
+        assert_hashes(
+            Arc::new(builder.finish()),
+            vec![-559580957, -912918097],
+            vec![-6698625589789238999, 8592097078962733837],
+        );
+
+        // map<int, array<int>>
+        let mut builder = MapBuilder::new(
+            None,
+            Int32Builder::new(),
+            ListBuilder::new(Int32Builder::new()),
+        );
+        builder.keys().append_value(1);
+        builder.values().append(false);
+        builder.keys().append_value(2);
+        builder.values().values().append_null();
+        builder.values().values().append_value(3);
+        builder.values().append(true);
+        builder.append(true).unwrap();
+        builder.keys().append_value(1);
+        builder.values().append(true);
+        builder.append(true).unwrap();
+        assert_hashes(
+            Arc::new(builder.finish()),
+            vec![-912918097, -559580957],
+            vec![8592097078962733837, -6698625589789238999],
+        );
+    }
+
+    #[test]
+    fn test_hive_hash() {
+        // generated with HiveHash(Seq(...)).eval()
+        let i = Arc::new(Int32Array::from(vec![Some(1), None, Some(-1)])) as ArrayRef;
+        assert_eq!(create_hive_hashes(3, &[i]), vec![1, 0, -1]);
+
+        let l = Arc::new(Int64Array::from(vec![1, -1, 1 << 40])) as ArrayRef;
+        assert_eq!(create_hive_hashes(3, &[l]), vec![1, 0, 256]);
+
+        let s = Arc::new(StringArray::from(vec!["Spark", "", "天地"])) as ArrayRef;
+        assert_eq!(create_hive_hashes(3, &[s]), vec![80085693, 0, -860571953]);
+
+        let i = Arc::new(Int32Array::from(vec![1])) as ArrayRef;
+        let s = Arc::new(StringArray::from(vec!["a"])) as ArrayRef;
+        assert_eq!(create_hive_hashes(1, &[i, s]), vec![128]);
+
+        let d = Arc::new(
+            Decimal128Array::from(vec![12345, 100, -150, 0])
+                .with_precision_and_scale(38, 2)
+                .unwrap(),
+        ) as ArrayRef;
+        assert_eq!(create_hive_hashes(4, &[d]), vec![382697, 31, -464, 0]);
+
+        let d = Arc::new(
+            Decimal128Array::from(vec![-1_000_000_000_000_000_000_000_000_000_007])
+                .with_precision_and_scale(38, 5)
+                .unwrap(),
+        ) as ArrayRef;
+        assert_eq!(create_hive_hashes(1, &[d]), vec![2043238534]);
+
+        let t = Arc::new(TimestampMicrosecondArray::from(vec![
+            1_000_001,
+            -1,
+            1_700_000_000_123_456,
+        ])) as ArrayRef;
+        assert_eq!(
+            create_hive_hashes(3, &[t]),
+            vec![1073742824, 999, 504313408]
+        );
+    }
+
+    #[test]
+    fn test_hive_hash_nested() {
+        let l = Arc::new(ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1), Some(2)]),
+            None,
+        ])) as ArrayRef;
+        assert_eq!(create_hive_hashes(2, &[l]), vec![33, 0]);
+
+        let mut builder = MapBuilder::new(None, Int32Builder::new(), Int32Builder::new());
+        builder.keys().append_value(1);
+        builder.values().append_value(2);
+        builder.append(true).unwrap();
+        let m = Arc::new(builder.finish()) as ArrayRef;
+        assert_eq!(create_hive_hashes(1, &[m]), vec![3]);
+
+        let s = Arc::new(StructArray::from(vec![
+            (
+                Arc::new(Field::new("a", DataType::Int32, true)),
+                Arc::new(Int32Array::from(vec![1])) as ArrayRef,
+            ),
+            (
+                Arc::new(Field::new("b", DataType::Utf8, true)),
+                Arc::new(StringArray::from(vec!["a"])) as ArrayRef,
+            ),
+        ])) as ArrayRef;
+        assert_eq!(create_hive_hashes(1, &[s]), vec![128]);
+    }
+
     #[test]
     fn test_map_array() {
         // Construct key and values
@@ -17,6 +17,7 @@ use std::{
     fmt::{Debug, Display, Formatter},
     hash::Hasher,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use arrow::{
@@ -29,10 +30,17 @@ use blaze_jni_bridge::{
     is_task_running, jni_call, jni_new_direct_byte_buffer, jni_new_global_ref, jni_new_object,
 };
 use datafusion::{
-    error::Result, logical_expr::ColumnarValue, physical_expr::physical_exprs_bag_equal,
-    physical_plan::PhysicalExpr,
+    error::Result,
+    logical_expr::ColumnarValue,
+    physical_expr::{physical_exprs_bag_equal, PhysicalExprRef},
+    physical_plan::{
+        metrics::{Count, Time},
+        PhysicalExpr,
+    },
+};
+use datafusion_ext_commons::{
+    arrow::cast::cast, df_execution_err, downcast_any, hash::mur::spark_compatible_murmur3_hash,
 };
-use datafusion_ext_commons::{arrow::cast::cast, df_execution_err};
 use jni::objects::GlobalRef;
 use once_cell::sync::OnceCell;
 
@@ -45,9 +53,58 @@ pub struct SparkUDFWrapperExpr {
     pub params: Vec<Arc<dyn PhysicalExpr>>,
     pub import_schema: SchemaRef,
     pub params_schema: OnceCell<SchemaRef>,
+    pub metrics: SparkUDFWrapperMetrics,
     jcontext: OnceCell<GlobalRef>,
 }
 
+/// Metrics of a single UDF, shared by all copies of the expr created by
+/// `with_new_children()`.
+#[derive(Debug, Clone)]
+pub struct SparkUDFWrapperMetrics {
+    /// number of evaluated batches
+    pub num_calls: Count,
+    /// number of evaluated rows
+    pub num_rows: Count,
+    /// time spent in JVM evaluation, including exporting/importing via FFI.
+    /// time of a fused round trip is evenly split by all UDFs of the group
+    pub elapsed_compute: Time,
+    /// number of JNI round trips and total time of them, shared by all UDFs
+    /// of the plan. bound by the plan when registering metrics
+    jni_metrics: Arc<OnceCell<(Count, Time)>>,
+}
+
+impl Default for SparkUDFWrapperMetrics {
+    fn default() -> Self {
+        Self {
+            num_calls: Count::new(),
+            num_rows: Count::new(),
+            elapsed_compute: Time::new(),
+            jni_metrics: Arc::default(),
+        }
+    }
+}
+
+impl SparkUDFWrapperMetrics {
+    /// Binds the JNI metrics shared by all UDFs of the plan, only the first
+    /// binding takes effect.
+    pub fn bind_jni_metrics(&self, num_jni_calls: Count, jni_time: Time) {
+        let _ = self.jni_metrics.set((num_jni_calls, jni_time));
+    }
+
+    fn record_eval(&self, num_rows: usize, elapsed: Duration) {
+        self.num_calls.add(1);
+        self.num_rows.add(num_rows);
+        self.elapsed_compute.add_duration(elapsed);
+    }
+
+    fn record_jni_call(&self, elapsed: Duration) {
+        if let Some((num_jni_calls, jni_time)) = self.jni_metrics.get() {
+            num_jni_calls.add(1);
+            jni_time.add_duration(elapsed);
+        }
+    }
+}
+
 impl PartialEq<dyn Any> for SparkUDFWrapperExpr {
     fn eq(&self, other: &dyn Any) -> bool {
         down_cast_any_ref(other)
@@ -76,10 +133,54 @@ impl SparkUDFWrapperExpr {
             params,
             import_schema: Arc::new(Schema::new(vec![Field::new("", return_type, true)])),
             params_schema: OnceCell::new(),
+            metrics: SparkUDFWrapperMetrics::default(),
             jcontext: OnceCell::new(),
         })
     }
 
+    /// Stable id of the UDF used in metric names, computed from the serialized
+    /// bytes so that the JVM side (`NativeHelper.getSparkUdfMetrics`) gets the
+    /// same id without relying on the order of UDFs.
+    pub fn udf_id(&self) -> String {
+        format!(
+            "{:08x}",
+            spark_compatible_murmur3_hash(&self.serialized, 42) as u32
+        )
+    }
+
+    pub fn params_schema(&self, input_schema: &Schema) -> Result<SchemaRef> {
+        self.params_schema
+            .get_or_try_init(|| -> Result<SchemaRef> {
+                let mut param_fields = Vec::with_capacity(self.params.len());
+                for param in &self.params {
+                    param_fields.push(Field::new(
+                        "",
+                        param.data_type(input_schema)?,
+                        param.nullable(input_schema)?,
+                    ));
+                }
+                Ok(Arc::new(Schema::new(param_fields)))
+            })
+            .cloned()
+    }
+
+    fn evaluate_params(&self, batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+        let params_schema = self.params_schema(&batch.schema())?;
+        self.params
+            .iter()
+            .zip(params_schema.fields())
+            .map(|(param, field)| {
+                let param_array = param
+                    .evaluate(batch)
+                    .and_then(|r| r.into_array(batch.num_rows()))?;
+                if param_array.data_type() == field.data_type() {
+                    return Ok(param_array);
+                }
+                cast(&param_array, field.data_type())
+            })
+            .collect()
+    }
+
     fn jcontext(&self) -> Result<GlobalRef> {
         self.jcontext
             .get_or_try_init(|| {
@@ -128,43 +229,24 @@ impl PhysicalExpr for SparkUDFWrapperExpr {
             return Ok(ColumnarValue::Array(new_empty_array(&self.return_type)));
         }
 
-        // init params schema
-        let params_schema = self
-            .params_schema
-            .get_or_try_init(|| -> Result<SchemaRef> {
-                let mut param_fields = Vec::with_capacity(self.params.len());
-                for param in &self.params {
-                    param_fields.push(Field::new(
-                        "",
-                        param.data_type(batch_schema.as_ref())?,
-                        param.nullable(batch_schema.as_ref())?,
-                    ));
-                }
-                Ok(Arc::new(Schema::new(param_fields)))
-            })?;
-
         // evaluate params
-        let params: Vec<ArrayRef> = self
-            .params
-            .iter()
-            .zip(params_schema.fields())
-            .map(|(param, field)| {
-                let param_array = param.evaluate(batch).and_then(|r| r.into_array(num_rows))?;
-                cast(&param_array, field.data_type())
-            })
-            .collect::<Result<_>>()?;
+        let params_schema = self.params_schema(&batch_schema)?;
+        let params = self.evaluate_params(batch)?;
         let params_batch = RecordBatch::try_new_with_options(
-            params_schema.clone(),
+            params_schema,
             params,
             &RecordBatchOptions::new().with_row_count(Some(num_rows)),
         )?;
 
         // invoke UDF through JNI
-        Ok(ColumnarValue::Array(invoke_udf(
-            self.jcontext()?,
-            params_batch,
-            self.import_schema.clone(),
-        )?))
+        let start_time = Instant::now();
+        let jcontext = self.jcontext()?;
+        let eval = |export_ptr: i64, import_ptr: i64| jni_call!(SparkUDFWrapperContext(jcontext.as_obj()).eval(export_ptr, import_ptr) -> ());
+        let result = invoke_udf(params_batch, &self.import_schema, eval)?;
+        let elapsed = start_time.elapsed();
+        self.metrics.record_eval(num_rows, elapsed);
+        self.metrics.record_jni_call(elapsed);
+        Ok(ColumnarValue::Array(result.column(0).clone()))
     }
 
     fn children(&self) -> Vec<&Arc<dyn PhysicalExpr>> {
@@ -175,12 +257,15 @@ impl PhysicalExpr for SparkUDFWrapperExpr {
         self: Arc<Self>,
         children: Vec<Arc<dyn PhysicalExpr>>,
     ) -> Result<Arc<dyn PhysicalExpr>> {
-        Ok(Arc::new(Self::try_new(
-            self.serialized.clone(),
-            self.return_type.clone(),
-            self.return_nullable.clone(),
-            children,
-        )?))
+        Ok(Arc::new(Self {
+            metrics: self.metrics.clone(),
+            ..Self::try_new(
+                self.serialized.clone(),
+                self.return_type.clone(),
+                self.return_nullable.clone(),
+                children,
+            )?
+        }))
     }
 
     fn dyn_hash(&self, state: &mut dyn Hasher) {
@@ -188,24 +273,148 @@ impl PhysicalExpr for SparkUDFWrapperExpr {
     }
 }
 
+/// A group of UDFs evaluated on the same batches, whose params are exported
+/// and results are imported in one JNI round trip.
+pub struct SparkFusedUDFWrapper {
+    udf_exprs: Vec<PhysicalExprRef>,
+    import_schema: SchemaRef,
+    jcontext: OnceCell<GlobalRef>,
+}
+
+impl SparkFusedUDFWrapper {
+    pub fn try_new(udf_exprs: Vec<PhysicalExprRef>) -> Result<Self> {
+        let mut import_fields = Vec::with_capacity(udf_exprs.len());
+        for udf_expr in &udf_exprs {
+            let udf = downcast_any!(udf_expr, SparkUDFWrapperExpr)?;
+            import_fields.push(Field::new("", udf.return_type.clone(), true));
+        }
+        Ok(Self {
+            udf_exprs,
+            import_schema: Arc::new(Schema::new(import_fields)),
+            jcontext: OnceCell::new(),
+        })
+    }
+
+    pub fn udf_exprs(&self) -> &[PhysicalExprRef] {
+        &self.udf_exprs
+    }
+
+    fn udfs(&self) -> impl Iterator<Item = &SparkUDFWrapperExpr> {
+        self.udf_exprs.iter().map(|udf_expr| {
+            // checked in try_new()
+            udf_expr
+                .as_any()
+                .downcast_ref::<SparkUDFWrapperExpr>()
+                .unwrap()
+        })
+    }
+
+    fn jcontext(&self) -> Result<GlobalRef> {
+        self.jcontext
+            .get_or_try_init(|| {
+                // serialized as big-endian: num_udfs, (len, bytes) * num_udfs
+                let mut serialized = vec![];
+                serialized.extend_from_slice(&(self.udf_exprs.len() as i32).to_be_bytes());
+                for udf in self.udfs() {
+                    serialized.extend_from_slice(&(udf.serialized.len() as i32).to_be_bytes());
+                    serialized.extend_from_slice(&udf.serialized);
+                }
+                let serialized_buf = jni_new_direct_byte_buffer!(&serialized)?;
+                let jcontext_local =
+                    jni_new_object!(SparkFusedUDFWrapperContext(serialized_buf.as_obj()))?;
+                jni_new_global_ref!(jcontext_local.as_obj())
+            })
+            .cloned()
+    }
+
+    /// Evaluates all UDFs in the group, returns results in the order of UDFs.
+    pub fn evaluate_all(&self, batch: &RecordBatch) -> Result<Vec<ArrayRef>> {
+        if !is_task_running() {
+            df_execution_err!("SparkFusedUDFWrapper: is_task_running=false")?;
+        }
+
+        let num_rows = batch.num_rows();
+        if num_rows == 0 {
+            return Ok(self
+                .udfs()
+                .map(|udf| new_empty_array(&udf.return_type))
+                .collect());
+        }
+
+        // evaluate params of all UDFs, concatenated in the order of UDFs
+        let mut param_fields = vec![];
+        let mut params = vec![];
+        for udf in self.udfs() {
+            param_fields.extend(udf.params_schema(&batch.schema())?.fields().iter().cloned());
+            params.extend(udf.evaluate_params(batch)?);
+        }
+        let params_batch = RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(param_fields)),
+            params,
+            &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+        )?;
+
+        // invoke all UDFs through one JNI call
+        let start_time = Instant::now();
+        let jcontext = self.jcontext()?;
+        let eval = |export_ptr: i64, import_ptr: i64| {
+            jni_call!(
+                SparkFusedUDFWrapperContext(jcontext.as_obj()).eval(export_ptr, import_ptr) -> ()
+            )
+        };
+        let result = invoke_udf(params_batch, &self.import_schema, eval)?;
+        let elapsed = start_time.elapsed();
+        let elapsed_per_udf = elapsed / self.udf_exprs.len() as u32;
+        for (i, udf) in self.udfs().enumerate() {
+            udf.metrics.record_eval(num_rows, elapsed_per_udf);
+            if i == 0 {
+                // all UDFs of the group belong to the same plan and share the
+                // jni metrics
+                udf.metrics.record_jni_call(elapsed);
+            }
+        }
+        Ok(result.columns().to_vec())
+    }
+}
+
 fn invoke_udf(
-    jcontext: GlobalRef,
     params_batch: RecordBatch,
-    result_schema: SchemaRef,
-) -> Result<ArrayRef> {
+    result_schema: &Schema,
+    eval: impl FnOnce(i64, i64) -> Result<()>,
+) -> Result<StructArray> {
     // evalute via context
     let struct_array = StructArray::from(params_batch);
     let mut export_ffi_array = FFI_ArrowArray::new(&struct_array.to_data());
     let mut import_ffi_array = FFI_ArrowArray::empty();
-    jni_call!(SparkUDFWrapperContext(jcontext.as_obj()).eval(
+    eval(
         &mut export_ffi_array as *mut FFI_ArrowArray as i64,
         &mut import_ffi_array as *mut FFI_ArrowArray as i64,
-    ) -> ())?;
+    )?;
 
-    // import output from context
-    let import_ffi_schema = FFI_ArrowSchema::try_from(result_schema.as_ref())?;
+    // import output from context, the imported buffers are owned by the JVM
+    // side and released by the FFI struct, so no copying is needed
+    let import_ffi_schema = FFI_ArrowSchema::try_from(result_schema)?;
     let import_struct_array =
         make_array(unsafe { from_ffi(import_ffi_array, &import_ffi_schema)? });
-    let import_array = as_struct_array(&import_struct_array).column(0).clone();
-    Ok(import_array)
+    Ok(as_struct_array(&import_struct_array).clone())
+}
+
+#[cfg(test)]
+mod test {
+    use arrow::datatypes::DataType;
+
+    use crate::spark_udf_wrapper::SparkUDFWrapperExpr;
+
+    #[test]
+    fn test_udf_id() {
+        // same as Murmur3_x86_32.hashUnsafeBytes(serialized, 42) in JVM side
+        let udf =
+            SparkUDFWrapperExpr::try_new(b"hello".to_vec(), DataType::Int32, true, vec![]).unwrap();
+        assert_eq!(udf.udf_id(), "c3e28528");
+
+        // metric names do not depend on params or position of the UDF
+        let udf2 = SparkUDFWrapperExpr::try_new(b"hello".to_vec(), DataType::Int64, false, vec![])
+            .unwrap();
+        assert_eq!(udf.udf_id(), udf2.udf_id());
+    }
 }
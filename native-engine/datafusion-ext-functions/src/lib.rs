@@ -16,13 +16,17 @@
 
 use std::sync::Arc;
 
+use arrow::datatypes::DataType;
 use datafusion::{common::Result, logical_expr::ScalarFunctionImplementation};
 use datafusion_ext_commons::df_unimplemented_err;
 
 mod brickhouse;
 mod spark_check_overflow;
+mod spark_crypto;
 mod spark_dates;
 pub mod spark_get_json_object;
+mod spark_hive_hash;
+mod spark_json;
 mod spark_make_array;
 mod spark_make_decimal;
 mod spark_murmur3_hash;
@@ -30,8 +34,22 @@ mod spark_null_if;
 mod spark_strings;
 mod spark_unscaled_value;
 mod spark_xxhash64;
+#[cfg(feature = "wasm-udf")]
+pub mod wasm_udf;
 
-pub fn create_spark_ext_function(name: &str) -> Result<ScalarFunctionImplementation> {
+pub fn create_spark_ext_function(
+    name: &str,
+    return_type: &DataType,
+) -> Result<ScalarFunctionImplementation> {
+    // experimental native-only wasm UDFs, not emitted by the JVM side
+    if let Some(wasm_udf_name) = name.strip_prefix("Wasm:") {
+        #[cfg(feature = "wasm-udf")]
+        return wasm_udf::create_wasm_udf(wasm_udf_name, return_type);
+        #[cfg(not(feature = "wasm-udf"))]
+        return df_unimplemented_err!(
+            "wasm udf {wasm_udf_name} requires blaze built with wasm-udf feature"
+        );
+    }
     Ok(match name {
         "Placeholder" => Arc::new(|_| panic!("placeholder() should never be called")),
         "NullIf" => Arc::new(spark_null_if::spark_null_if),
@@ -41,9 +59,21 @@ pub fn create_spark_ext_function(name: &str) -> Result<ScalarFunctionImplementat
         "CheckOverflow" => Arc::new(spark_check_overflow::spark_check_overflow),
         "Murmur3Hash" => Arc::new(spark_murmur3_hash::spark_murmur3_hash),
         "XxHash64" => Arc::new(spark_xxhash64::spark_xxhash64),
+        "HiveHash" => Arc::new(spark_hive_hash::spark_hive_hash),
+        "Md5" => Arc::new(spark_crypto::spark_md5),
+        "Sha1" => Arc::new(spark_crypto::spark_sha1),
+        "Sha2" => Arc::new(spark_crypto::spark_sha2),
+        "Crc32" => Arc::new(spark_crypto::spark_crc32),
         "GetJsonObject" => Arc::new(spark_get_json_object::spark_get_json_object),
         "GetParsedJsonObject" => Arc::new(spark_get_json_object::spark_get_parsed_json_object),
         "ParseJson" => Arc::new(spark_get_json_object::spark_parse_json),
+        "FromJson" => {
+            let return_type = return_type.clone();
+            Arc::new(move |args| spark_json::spark_from_json(args, &return_type))
+        }
+        "ToJson" => Arc::new(spark_json::spark_to_json),
+        "JsonArrayLength" => Arc::new(spark_json::spark_json_array_length),
+        "SchemaOfJson" => Arc::new(spark_json::spark_schema_of_json),
         "MakeArray" => Arc::new(spark_make_array::array),
         "StringSpace" => Arc::new(spark_strings::string_space),
         "StringRepeat" => Arc::new(spark_strings::string_repeat),
@@ -0,0 +1,168 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, AsArray, Int64Array, StringArray},
+    datatypes::DataType,
+};
+use datafusion::{
+    common::{Result, ScalarValue},
+    physical_plan::ColumnarValue,
+};
+use datafusion_ext_commons::df_execution_err;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+
+/// implements org.apache.spark.sql.catalyst.expressions.Md5
+pub fn spark_md5(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let array = args[0].clone().into_array(1)?;
+    let digests = map_bytes(&array, hex_digest::<Md5>)?;
+    Ok(ColumnarValue::Array(Arc::new(StringArray::from(digests))))
+}
+
+/// implements org.apache.spark.sql.catalyst.expressions.Sha1
+pub fn spark_sha1(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let array = args[0].clone().into_array(1)?;
+    let digests = map_bytes(&array, hex_digest::<Sha1>)?;
+    Ok(ColumnarValue::Array(Arc::new(StringArray::from(digests))))
+}
+
+/// implements org.apache.spark.sql.catalyst.expressions.Sha2
+///
+/// bit length 0 is treated as 256, unsupported bit lengths produce nulls.
+pub fn spark_sha2(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let array = args[0].clone().into_array(1)?;
+    let bit_length = match &args[1] {
+        ColumnarValue::Scalar(ScalarValue::Int32(Some(n))) => *n,
+        ColumnarValue::Scalar(scalar) if scalar.is_null() => {
+            return Ok(ColumnarValue::Scalar(ScalarValue::Utf8(None)));
+        }
+        _ => df_execution_err!("sha2 bit length only supports literal int32")?,
+    };
+
+    let digests = match bit_length {
+        224 => map_bytes(&array, hex_digest::<Sha224>)?,
+        0 | 256 => map_bytes(&array, hex_digest::<Sha256>)?,
+        384 => map_bytes(&array, hex_digest::<Sha384>)?,
+        512 => map_bytes(&array, hex_digest::<Sha512>)?,
+        _ => vec![None; array.len()],
+    };
+    Ok(ColumnarValue::Array(Arc::new(StringArray::from(digests))))
+}
+
+/// implements org.apache.spark.sql.catalyst.expressions.Crc32
+pub fn spark_crc32(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let array = args[0].clone().into_array(1)?;
+    let checksums = map_bytes(&array, |bytes| crc32fast::hash(bytes) as i64)?;
+    Ok(ColumnarValue::Array(Arc::new(Int64Array::from(checksums))))
+}
+
+fn hex_digest<D: Digest>(bytes: &[u8]) -> String {
+    hex::encode(D::digest(bytes))
+}
+
+fn map_bytes<T>(array: &ArrayRef, f: impl Fn(&[u8]) -> T) -> Result<Vec<Option<T>>> {
+    Ok(match array.data_type() {
+        DataType::Binary => array.as_binary::<i32>().iter().map(|v| v.map(&f)).collect(),
+        DataType::LargeBinary => array.as_binary::<i64>().iter().map(|v| v.map(&f)).collect(),
+        DataType::Utf8 => array
+            .as_string::<i32>()
+            .iter()
+            .map(|v| v.map(|s| f(s.as_bytes())))
+            .collect(),
+        DataType::LargeUtf8 => array
+            .as_string::<i64>()
+            .iter()
+            .map(|v| v.map(|s| f(s.as_bytes())))
+            .collect(),
+        DataType::Null => (0..array.len()).map(|_| None).collect(),
+        other => df_execution_err!("digest functions do not support data type: {other}")?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error, sync::Arc};
+
+    use arrow::array::{ArrayRef, BinaryArray, Int64Array, StringArray};
+    use datafusion::{common::ScalarValue, logical_expr::ColumnarValue};
+
+    use crate::spark_crypto::{spark_crc32, spark_md5, spark_sha1, spark_sha2};
+
+    fn input() -> ColumnarValue {
+        ColumnarValue::Array(Arc::new(StringArray::from(vec![Some("Spark"), None])))
+    }
+
+    #[test]
+    fn test_md5_sha1() -> Result<(), Box<dyn Error>> {
+        let result = spark_md5(&[input()])?.into_array(2)?;
+        let expected: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("8cde774d6f7333752ed72cacddb05126"),
+            None,
+        ]));
+        assert_eq!(&result, &expected);
+
+        let result = spark_sha1(&[input()])?.into_array(2)?;
+        let expected: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("85f5955f4b27a9a4c2aab6ffe5d7189fc298b92c"),
+            None,
+        ]));
+        assert_eq!(&result, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sha2() -> Result<(), Box<dyn Error>> {
+        let sha2 = |bits: i32| -> Result<ArrayRef, Box<dyn Error>> {
+            let bits = ColumnarValue::Scalar(ScalarValue::Int32(Some(bits)));
+            Ok(spark_sha2(&[input(), bits])?.into_array(2)?)
+        };
+        let expected =
+            |digest: Option<&str>| -> ArrayRef { Arc::new(StringArray::from(vec![digest, None])) };
+
+        let sha224 = "dbeab94971678d36af2195851c0f7485775a2a7c60073d62fc04549c";
+        let sha256 = "529bc3b07127ecb7e53a4dcf1991d9152c24537d919178022b2c42657f79a26b";
+        let sha384 = "1e40b8d06c248a1cc32428c22582b6219d072283078fa140d9ad297ecadf2cab\
+                      efc341b857ad36226aa8d6d79f2ab67d";
+        let sha512 = "44844a586c54c9a212da1dbfe05c5f1705de1af5fda1f0d36297623249b279fd\
+                      8f0ccec03f888f4fb13bf7cd83fdad58591c797f81121a23cfdd5e0897795238";
+        assert_eq!(&sha2(224)?, &expected(Some(sha224)));
+        assert_eq!(&sha2(256)?, &expected(Some(sha256)));
+        assert_eq!(&sha2(0)?, &expected(Some(sha256)));
+        assert_eq!(&sha2(384)?, &expected(Some(sha384)));
+        assert_eq!(&sha2(512)?, &expected(Some(sha512)));
+        assert_eq!(&sha2(100)?, &expected(None));
+        Ok(())
+    }
+
+    #[test]
+    fn test_crc32() -> Result<(), Box<dyn Error>> {
+        let input = ColumnarValue::Array(Arc::new(BinaryArray::from(vec![
+            Some(b"Spark".as_ref()),
+            Some(b"ABC".as_ref()),
+            None,
+        ])));
+        let result = spark_crc32(&[input])?.into_array(3)?;
+        let expected: ArrayRef = Arc::new(Int64Array::from(vec![
+            Some(1557323817),
+            Some(2743272264),
+            None,
+        ]));
+        assert_eq!(&result, &expected);
+        Ok(())
+    }
+}
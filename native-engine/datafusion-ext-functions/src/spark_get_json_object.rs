@@ -186,6 +186,17 @@ pub fn spark_get_parsed_json_simple_field(
     Ok(Arc::new(output))
 }
 
+/// parses json string into sonic-rs value, fail-backing to serde-json for
+/// inputs which are not accepted by sonic-rs (like unescaped control
+/// characters)
+pub(crate) fn parse_sonic_value(s: &str) -> Option<sonic_rs::Value> {
+    if let Ok(v) = sonic_rs::from_str::<sonic_rs::Value>(s) {
+        return Some(v);
+    }
+    let v = serde_json::from_str::<serde_json::Value>(s).ok()?;
+    sonic_rs::from_str::<sonic_rs::Value>(&serde_json::to_string(&v).ok()?).ok()
+}
+
 #[derive(Debug)]
 enum ParsedJsonValue {
     SerdeJson(serde_json::Value),
@@ -0,0 +1,74 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::array::*;
+use datafusion::{common::Result, physical_plan::ColumnarValue};
+use datafusion_ext_commons::spark_hash::create_hive_hashes;
+
+/// implements org.apache.spark.sql.catalyst.expressions.HiveHash
+pub fn spark_hive_hash(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let len = args
+        .iter()
+        .map(|arg| match arg {
+            ColumnarValue::Array(array) => array.len(),
+            ColumnarValue::Scalar(_) => 1,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let arrays = args
+        .iter()
+        .map(|arg| {
+            Ok(match arg {
+                ColumnarValue::Array(array) => array.clone(),
+                ColumnarValue::Scalar(scalar) => scalar.to_array_of_size(len)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let hashes = create_hive_hashes(len, &arrays);
+
+    Ok(ColumnarValue::Array(Arc::new(Int32Array::from(hashes))))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error, sync::Arc};
+
+    use arrow::array::{ArrayRef, Int32Array, Int64Array, StringArray};
+    use datafusion::logical_expr::ColumnarValue;
+
+    use crate::spark_hive_hash::spark_hive_hash;
+
+    #[test]
+    fn test_hive_hash() -> Result<(), Box<dyn Error>> {
+        let result = spark_hive_hash(&vec![
+            ColumnarValue::Array(Arc::new(Int64Array::from(vec![Some(1), Some(-1), None]))),
+            ColumnarValue::Array(Arc::new(StringArray::from(vec![
+                Some("Spark"),
+                None,
+                Some("a"),
+            ]))),
+        ])?
+        .into_array(3)?;
+
+        let expected = Int32Array::from(vec![Some(80085724), Some(0), Some(97)]);
+        let expected: ArrayRef = Arc::new(expected);
+
+        assert_eq!(&result, &expected);
+        Ok(())
+    }
+}
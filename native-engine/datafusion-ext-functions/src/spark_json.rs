@@ -0,0 +1,1228 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    str::FromStr,
+    sync::Arc,
+};
+
+use arrow::{
+    array::{timezone::Tz, *},
+    buffer::{NullBuffer, OffsetBuffer, ScalarBuffer},
+    compute::kernels::nullif::nullif,
+    datatypes::*,
+};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Offset, TimeZone, Timelike, Utc};
+use datafusion::{
+    common::{Result, ScalarValue},
+    physical_plan::ColumnarValue,
+};
+use datafusion_ext_commons::{
+    arrow::cast::cast, cancellation::check_thread_cancelled, df_execution_err, df_unimplemented_err,
+};
+use sonic_rs::{JsonContainerTrait, JsonValueTrait};
+
+use crate::spark_get_json_object::parse_sonic_value;
+
+// number of rows parsed between cancellation checks
+const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+/// implements org.apache.spark.sql.catalyst.expressions.JsonToStructs
+/// from_json(str, [option_key, option_value]*), the target schema is taken from
+/// the return type of the function
+pub fn spark_from_json(args: &[ColumnarValue], return_type: &DataType) -> Result<ColumnarValue> {
+    let json_strings = args[0].clone().into_array(1)?;
+    let json_strings = json_strings.as_string::<i32>();
+    let options = JsonOptions::try_new(&args[1..])?;
+    let num_rows = json_strings.len();
+
+    // the corrupt record column is filled with the raw input instead of being
+    // parsed
+    let corrupt_field_idx = match return_type {
+        DataType::Struct(fields) => fields.iter().position(|field| {
+            field.name() == &options.corrupt_record_column && field.data_type() == &DataType::Utf8
+        }),
+        _ => None,
+    };
+    let parse_type = match (return_type, corrupt_field_idx) {
+        (DataType::Struct(fields), Some(idx)) => DataType::Struct(
+            fields
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != idx)
+                .map(|(_, field)| field.clone())
+                .collect(),
+        ),
+        _ => return_type.clone(),
+    };
+
+    let mut corrupted = vec![false; num_rows];
+    let mut parsed = Vec::with_capacity(num_rows);
+    for (row_idx, s) in json_strings.iter().enumerate() {
+        if row_idx % CANCEL_CHECK_INTERVAL == 0 {
+            check_thread_cancelled()?;
+        }
+        // empty input is treated as null, like spark does
+        let s = s.filter(|s| !s.trim().is_empty());
+        let value = s.and_then(parse_sonic_value);
+        corrupted[row_idx] = s.is_some() && value.is_none();
+        parsed.push(value);
+    }
+    let values = parsed.iter().map(|v| v.as_ref()).collect::<Vec<_>>();
+    let row_ids = (0..num_rows).collect::<Vec<_>>();
+
+    let mut converter = JsonConverter {
+        options: &options,
+        corrupted: &mut corrupted,
+    };
+    let converted = converter.convert(&values, &row_ids, &parse_type)?;
+
+    if !corrupted.contains(&true) && corrupt_field_idx.is_none() {
+        return Ok(ColumnarValue::Array(converted));
+    }
+    if options.mode == ParseMode::FailFast && corrupted.contains(&true) {
+        let first_bad = corrupted.iter().position(|&c| c).unwrap();
+        return df_execution_err!(
+            "Malformed records are detected in record parsing. Parse Mode: FAILFAST. \
+             To process malformed records as null result, try setting the option 'mode' \
+             as 'PERMISSIVE'. Malformed record: {}",
+            json_strings.value(first_bad),
+        );
+    }
+
+    // PERMISSIVE mode: malformed rows produce a non-null struct with all fields
+    // set to null (except the corrupt record column), or null for non-struct
+    // schemas
+    let corrupted_mask = BooleanArray::from(corrupted.clone());
+    let output: ArrayRef = match &parse_type {
+        DataType::Struct(_) => {
+            let converted = converted.as_struct();
+            let mut columns = converted
+                .columns()
+                .iter()
+                .map(|column| Ok(nullif(column, &corrupted_mask)?))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(idx) = corrupt_field_idx {
+                let corrupt_records: ArrayRef = Arc::new(
+                    json_strings
+                        .iter()
+                        .zip(&corrupted)
+                        .map(|(s, &corrupted)| s.filter(|_| corrupted))
+                        .collect::<StringArray>(),
+                );
+                columns.insert(idx, corrupt_records);
+            }
+            let fields = match return_type {
+                DataType::Struct(fields) => fields.clone(),
+                _ => unreachable!(),
+            };
+            let nulls = converted.nulls().map(|nulls| {
+                NullBuffer::from(
+                    nulls
+                        .iter()
+                        .zip(&corrupted)
+                        .map(|(valid, &corrupted)| valid || corrupted)
+                        .collect::<Vec<_>>(),
+                )
+            });
+            Arc::new(StructArray::try_new(fields, columns, nulls)?)
+        }
+        _ => nullif(&converted, &corrupted_mask)?,
+    };
+    Ok(ColumnarValue::Array(output))
+}
+
+/// implements org.apache.spark.sql.catalyst.expressions.StructsToJson
+/// to_json(struct/map/array, [option_key, option_value]*)
+pub fn spark_to_json(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let input = args[0].clone().into_array(1)?;
+    let options = JsonOptions::try_new(&args[1..])?;
+    let timestamp_pattern = match &options.timestamp_format {
+        Some(pattern) => pattern.clone(),
+        None => parse_java_pattern(DEFAULT_TIMESTAMP_FORMAT)?,
+    };
+    let writer = JsonWriter {
+        options: &options,
+        timestamp_pattern,
+    };
+
+    let mut builder = StringBuilder::with_capacity(input.len(), 0);
+    let mut buf = String::new();
+    for row_idx in 0..input.len() {
+        if row_idx % CANCEL_CHECK_INTERVAL == 0 {
+            check_thread_cancelled()?;
+        }
+        if input.is_null(row_idx) {
+            builder.append_null();
+            continue;
+        }
+        buf.clear();
+        writer.write_value(&mut buf, &input, row_idx)?;
+        builder.append_value(&buf);
+    }
+    Ok(ColumnarValue::Array(Arc::new(builder.finish())))
+}
+
+/// implements org.apache.spark.sql.catalyst.expressions.LengthOfJsonArray
+pub fn spark_json_array_length(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let json_strings = args[0].clone().into_array(1)?;
+    let mut output = Int32Builder::with_capacity(json_strings.len());
+    for (row_idx, s) in json_strings.as_string::<i32>().iter().enumerate() {
+        if row_idx % CANCEL_CHECK_INTERVAL == 0 {
+            check_thread_cancelled()?;
+        }
+        output.append_option(
+            s.and_then(parse_sonic_value)
+                .and_then(|value| value.as_array().map(|array| array.len() as i32)),
+        );
+    }
+    let output = output.finish();
+    Ok(ColumnarValue::Array(Arc::new(output)))
+}
+
+/// implements org.apache.spark.sql.catalyst.expressions.SchemaOfJson
+/// returns the inferred schema in spark's DDL format, like `ARRAY<STRUCT<a:
+/// BIGINT>>`
+pub fn spark_schema_of_json(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let json_strings = args[0].clone().into_array(1)?;
+    let mut output = StringBuilder::new();
+    for (row_idx, s) in json_strings.as_string::<i32>().iter().enumerate() {
+        if row_idx % CANCEL_CHECK_INTERVAL == 0 {
+            check_thread_cancelled()?;
+        }
+        match s {
+            Some(s) => match parse_sonic_value(s) {
+                Some(value) => output.append_value(InferredType::infer(&value).to_ddl()),
+                None => df_execution_err!("schema_of_json: cannot infer schema from: {s}")?,
+            },
+            None => output.append_null(),
+        }
+    }
+    Ok(ColumnarValue::Array(Arc::new(output.finish())))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParseMode {
+    Permissive,
+    FailFast,
+}
+
+struct JsonOptions {
+    mode: ParseMode,
+    corrupt_record_column: String,
+    timestamp_format: Option<Vec<PatternItem>>,
+    date_format: Option<Vec<PatternItem>>,
+    time_zone: Tz,
+    time_zone_name: String,
+    ignore_null_fields: bool,
+}
+
+impl JsonOptions {
+    fn try_new(option_args: &[ColumnarValue]) -> Result<Self> {
+        let mut options = HashMap::new();
+        for kv in option_args.chunks(2) {
+            match kv {
+                [ColumnarValue::Scalar(ScalarValue::Utf8(Some(k))), ColumnarValue::Scalar(ScalarValue::Utf8(Some(v)))] =>
+                {
+                    // spark json options are case-insensitive
+                    options.insert(k.to_lowercase(), v.clone());
+                }
+                _ => df_execution_err!("json options must be pairs of utf8 literals")?,
+            }
+        }
+
+        let mode = match options.get("mode").map(|s| s.to_uppercase()).as_deref() {
+            None | Some("PERMISSIVE") => ParseMode::Permissive,
+            Some("FAILFAST") => ParseMode::FailFast,
+            Some(other) => df_unimplemented_err!("json parse mode not supported: {other}")?,
+        };
+        let time_zone_name = options
+            .get("timezone")
+            .cloned()
+            .unwrap_or_else(|| "UTC".to_string());
+        let time_zone = Tz::from_str(&time_zone_name)?;
+
+        Ok(Self {
+            mode,
+            corrupt_record_column: options
+                .get("columnnameofcorruptrecord")
+                .cloned()
+                .unwrap_or_else(|| "_corrupt_record".to_string()),
+            timestamp_format: options
+                .get("timestampformat")
+                .map(|pattern| parse_java_pattern(pattern))
+                .transpose()?,
+            date_format: options
+                .get("dateformat")
+                .map(|pattern| parse_java_pattern(pattern))
+                .transpose()?,
+            time_zone,
+            time_zone_name,
+            ignore_null_fields: options
+                .get("ignorenullfields")
+                .map(|v| v.eq_ignore_ascii_case("true"))
+                .unwrap_or(true),
+        })
+    }
+}
+
+/// converts parsed json values into arrow arrays column by column. values which
+/// cannot be converted to the target type mark their top-level rows as
+/// corrupted.
+struct JsonConverter<'a> {
+    options: &'a JsonOptions,
+    corrupted: &'a mut [bool],
+}
+
+impl JsonConverter<'_> {
+    fn convert(
+        &mut self,
+        values: &[Option<&sonic_rs::Value>],
+        row_ids: &[usize],
+        data_type: &DataType,
+    ) -> Result<ArrayRef> {
+        macro_rules! convert_primitive {
+            ($arrowty:ty, $convert:expr) => {{
+                let convert = $convert;
+                let array: PrimitiveArray<$arrowty> = values
+                    .iter()
+                    .zip(row_ids)
+                    .map(|(value, &row_id)| {
+                        let value = value.filter(|v| !v.is_null())?;
+                        let converted = convert(value);
+                        if converted.is_none() {
+                            self.corrupted[row_id] = true;
+                        }
+                        converted
+                    })
+                    .collect();
+                Arc::new(array.with_data_type(data_type.clone())) as ArrayRef
+            }};
+        }
+
+        Ok(match data_type {
+            DataType::Null => new_null_array(data_type, values.len()),
+            DataType::Boolean => {
+                let array: BooleanArray = values
+                    .iter()
+                    .zip(row_ids)
+                    .map(|(value, &row_id)| {
+                        let value = value.filter(|v| !v.is_null())?;
+                        let converted = value.as_bool();
+                        if converted.is_none() {
+                            self.corrupted[row_id] = true;
+                        }
+                        converted
+                    })
+                    .collect();
+                Arc::new(array)
+            }
+            DataType::Int8 => convert_primitive!(Int8Type, |v: &sonic_rs::Value| v
+                .as_i64()
+                .and_then(|v| i8::try_from(v).ok())),
+            DataType::Int16 => convert_primitive!(Int16Type, |v: &sonic_rs::Value| v
+                .as_i64()
+                .and_then(|v| i16::try_from(v).ok())),
+            DataType::Int32 => convert_primitive!(Int32Type, |v: &sonic_rs::Value| v
+                .as_i64()
+                .and_then(|v| i32::try_from(v).ok())),
+            DataType::Int64 => convert_primitive!(Int64Type, |v: &sonic_rs::Value| v.as_i64()),
+            DataType::Float32 => {
+                convert_primitive!(Float32Type, |v: &sonic_rs::Value| json_to_f64(v)
+                    .map(|v| v as f32))
+            }
+            DataType::Float64 => convert_primitive!(Float64Type, json_to_f64),
+            DataType::Utf8 => {
+                let array: StringArray = values
+                    .iter()
+                    .map(|value| {
+                        let value = value.filter(|v| !v.is_null())?;
+                        match value.as_str() {
+                            Some(s) => Some(s.to_string()),
+                            None => sonic_rs::to_string(value).ok(),
+                        }
+                    })
+                    .collect();
+                Arc::new(array)
+            }
+            DataType::Decimal128(..) => {
+                let texts = values
+                    .iter()
+                    .map(|value| {
+                        let value = value.filter(|v| !v.is_null())?;
+                        match (value.as_str(), value.as_number()) {
+                            (Some(s), _) => Some(s.to_string()),
+                            (None, Some(n)) => Some(n.to_string()),
+                            _ => Some(String::new()), // invalid, marked as corrupted after casting
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                self.cast_texts(texts, row_ids, data_type)?
+            }
+            DataType::Date32 => match &self.options.date_format {
+                Some(pattern) => {
+                    let format = to_chrono_format(pattern)?;
+                    convert_primitive!(Date32Type, |v: &sonic_rs::Value| {
+                        let date = NaiveDate::parse_from_str(v.as_str()?, &format).ok()?;
+                        Some(date.num_days_from_ce() - EPOCH_DAYS_FROM_CE)
+                    })
+                }
+                None => {
+                    let texts = values
+                        .iter()
+                        .map(|value| {
+                            let value = value.filter(|v| !v.is_null())?;
+                            Some(value.as_str().unwrap_or_default().to_string())
+                        })
+                        .collect::<Vec<_>>();
+                    self.cast_texts(texts, row_ids, data_type)?
+                }
+            },
+            DataType::Timestamp(TimeUnit::Microsecond, _) => match &self.options.timestamp_format {
+                Some(pattern) => {
+                    let tz = &self.options.time_zone;
+                    convert_primitive!(TimestampMicrosecondType, |v: &sonic_rs::Value| {
+                        match v.as_i64() {
+                            Some(secs) => secs.checked_mul(1000000),
+                            None => parse_timestamp_with_pattern(v.as_str()?, pattern, tz),
+                        }
+                    })
+                }
+                None => {
+                    // integral numbers are treated as seconds since epoch
+                    let texts = values
+                        .iter()
+                        .map(|value| {
+                            let value = value.filter(|v| !v.is_null())?;
+                            match (value.as_str(), value.as_i64()) {
+                                (Some(s), _) => Some(s.to_string()),
+                                (None, Some(secs)) => Some(
+                                    Utc.timestamp_opt(secs, 0)
+                                        .single()
+                                        .map(|dt| dt.to_rfc3339())
+                                        .unwrap_or_default(),
+                                ),
+                                _ => Some(String::new()),
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    let with_tz = DataType::Timestamp(
+                        TimeUnit::Microsecond,
+                        Some(self.options.time_zone_name.as_str().into()),
+                    );
+                    let casted = self.cast_texts(texts, row_ids, &with_tz)?;
+                    Arc::new(
+                        casted
+                            .as_primitive::<TimestampMicrosecondType>()
+                            .clone()
+                            .with_data_type(data_type.clone()),
+                    )
+                }
+            },
+            DataType::List(field) => {
+                let mut offsets = vec![0i32];
+                let mut child_values = vec![];
+                let mut child_row_ids = vec![];
+                let mut valids = vec![];
+                for (value, &row_id) in values.iter().zip(row_ids) {
+                    match value.filter(|v| !v.is_null()) {
+                        Some(value) => match value.as_array() {
+                            Some(array) => {
+                                child_values.extend(array.iter().map(Some));
+                                child_row_ids.extend(std::iter::repeat(row_id).take(array.len()));
+                                valids.push(true);
+                            }
+                            None => {
+                                self.corrupted[row_id] = true;
+                                valids.push(false);
+                            }
+                        },
+                        None => valids.push(false),
+                    }
+                    offsets.push(child_values.len() as i32);
+                }
+                let child = self.convert(&child_values, &child_row_ids, field.data_type())?;
+                Arc::new(ListArray::try_new(
+                    field.clone(),
+                    OffsetBuffer::new(ScalarBuffer::from(offsets)),
+                    child,
+                    Some(NullBuffer::from(valids)),
+                )?)
+            }
+            DataType::Map(entries_field, sorted) => {
+                let DataType::Struct(entry_fields) = entries_field.data_type() else {
+                    return df_execution_err!("invalid map entries type: {entries_field:?}");
+                };
+                if entry_fields[0].data_type() != &DataType::Utf8 {
+                    return df_unimplemented_err!(
+                        "from_json: map key type must be string, got {}",
+                        entry_fields[0].data_type(),
+                    );
+                }
+                let mut offsets = vec![0i32];
+                let mut keys = vec![];
+                let mut child_values = vec![];
+                let mut child_row_ids = vec![];
+                let mut valids = vec![];
+                for (value, &row_id) in values.iter().zip(row_ids) {
+                    match value.filter(|v| !v.is_null()) {
+                        Some(value) => match value.as_object() {
+                            Some(object) => {
+                                for (k, v) in object.iter() {
+                                    keys.push(k.to_string());
+                                    child_values.push(Some(v));
+                                    child_row_ids.push(row_id);
+                                }
+                                valids.push(true);
+                            }
+                            None => {
+                                self.corrupted[row_id] = true;
+                                valids.push(false);
+                            }
+                        },
+                        None => valids.push(false),
+                    }
+                    offsets.push(child_values.len() as i32);
+                }
+                let key_array: ArrayRef = Arc::new(StringArray::from(keys));
+                let value_array =
+                    self.convert(&child_values, &child_row_ids, entry_fields[1].data_type())?;
+                let entries =
+                    StructArray::try_new(entry_fields.clone(), vec![key_array, value_array], None)?;
+                Arc::new(MapArray::try_new(
+                    entries_field.clone(),
+                    OffsetBuffer::new(ScalarBuffer::from(offsets)),
+                    entries,
+                    Some(NullBuffer::from(valids)),
+                    *sorted,
+                )?)
+            }
+            DataType::Struct(fields) => {
+                let mut valids = Vec::with_capacity(values.len());
+                let objects = values
+                    .iter()
+                    .zip(row_ids)
+                    .map(|(value, &row_id)| {
+                        let value = value.filter(|v| !v.is_null());
+                        let object = value.and_then(|v| v.as_object());
+                        if value.is_some() && object.is_none() {
+                            self.corrupted[row_id] = true;
+                        }
+                        valids.push(object.is_some());
+                        object
+                    })
+                    .collect::<Vec<_>>();
+                let columns = fields
+                    .iter()
+                    .map(|field| {
+                        let field_values = objects
+                            .iter()
+                            .map(|object| object.and_then(|object| object.get(field.name())))
+                            .collect::<Vec<_>>();
+                        self.convert(&field_values, row_ids, field.data_type())
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(StructArray::try_new(
+                    fields.clone(),
+                    columns,
+                    Some(NullBuffer::from(valids)),
+                )?)
+            }
+            other => df_unimplemented_err!("from_json: unsupported data type: {other}")?,
+        })
+    }
+
+    /// casts texts with spark compatible casting, non-null texts which cannot
+    /// be casted mark their rows as corrupted
+    fn cast_texts(
+        &mut self,
+        texts: Vec<Option<String>>,
+        row_ids: &[usize],
+        data_type: &DataType,
+    ) -> Result<ArrayRef> {
+        let texts = StringArray::from(texts);
+        let casted = cast(&texts, data_type)?;
+        for (i, &row_id) in row_ids.iter().enumerate() {
+            if texts.is_valid(i) && casted.is_null(i) {
+                self.corrupted[row_id] = true;
+            }
+        }
+        Ok(casted)
+    }
+}
+
+fn json_to_f64(value: &sonic_rs::Value) -> Option<f64> {
+    if let Some(s) = value.as_str() {
+        // spark accepts non-numeric numbers as strings
+        return match s {
+            "NaN" => Some(f64::NAN),
+            "Infinity" | "+Infinity" | "INF" | "+INF" => Some(f64::INFINITY),
+            "-Infinity" | "-INF" => Some(f64::NEG_INFINITY),
+            _ => None,
+        };
+    }
+    value.as_f64()
+}
+
+struct JsonWriter<'a> {
+    options: &'a JsonOptions,
+    timestamp_pattern: Vec<PatternItem>,
+}
+
+impl JsonWriter<'_> {
+    fn write_value(&self, out: &mut String, array: &ArrayRef, idx: usize) -> Result<()> {
+        if array.is_null(idx) {
+            out.push_str("null");
+            return Ok(());
+        }
+        match array.data_type() {
+            DataType::Null => out.push_str("null"),
+            DataType::Boolean => {
+                let _ = write!(out, "{}", array.as_boolean().value(idx));
+            }
+            DataType::Int8 => {
+                let _ = write!(out, "{}", array.as_primitive::<Int8Type>().value(idx));
+            }
+            DataType::Int16 => {
+                let _ = write!(out, "{}", array.as_primitive::<Int16Type>().value(idx));
+            }
+            DataType::Int32 => {
+                let _ = write!(out, "{}", array.as_primitive::<Int32Type>().value(idx));
+            }
+            DataType::Int64 => {
+                let _ = write!(out, "{}", array.as_primitive::<Int64Type>().value(idx));
+            }
+            DataType::Float32 => {
+                let v = array.as_primitive::<Float32Type>().value(idx);
+                write_java_float(out, v as f64, v.is_finite().then(|| v.to_string()));
+            }
+            DataType::Float64 => {
+                let v = array.as_primitive::<Float64Type>().value(idx);
+                write_java_float(out, v, v.is_finite().then(|| v.to_string()));
+            }
+            DataType::Decimal128(..) => {
+                out.push_str(&array.as_primitive::<Decimal128Type>().value_as_string(idx));
+            }
+            DataType::Utf8 => write_json_string(out, array.as_string::<i32>().value(idx)),
+            DataType::Date32 => {
+                let days = array.as_primitive::<Date32Type>().value(idx);
+                let date = NaiveDate::from_num_days_from_ce_opt(days + EPOCH_DAYS_FROM_CE)
+                    .unwrap_or_default();
+                let formatted = match &self.options.date_format {
+                    Some(pattern) => format_with_pattern(pattern, &date.into(), 0),
+                    None => date.format("%Y-%m-%d").to_string(),
+                };
+                write_json_string(out, &formatted);
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                let micros = array.as_primitive::<TimestampMicrosecondType>().value(idx);
+                let utc = DateTime::from_timestamp_micros(micros).unwrap_or_default();
+                let local = self.options.time_zone.from_utc_datetime(&utc.naive_utc());
+                let offset_secs = local.offset().fix().local_minus_utc();
+                let formatted =
+                    format_with_pattern(&self.timestamp_pattern, &local.naive_local(), offset_secs);
+                write_json_string(out, &formatted);
+            }
+            DataType::List(_) => {
+                let list = array.as_list::<i32>();
+                let values = list.value(idx);
+                out.push('[');
+                for i in 0..values.len() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    self.write_value(out, &values, i)?;
+                }
+                out.push(']');
+            }
+            DataType::Map(..) => {
+                let map = array.as_map();
+                let entries = map.value(idx);
+                let keys = entries.column(0);
+                let values = entries.column(1);
+                out.push('{');
+                let mut key_buf = String::new();
+                for i in 0..entries.len() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    match keys.data_type() {
+                        DataType::Utf8 => write_json_string(out, keys.as_string::<i32>().value(i)),
+                        _ => {
+                            // non-string keys are written as their string
+                            // representation
+                            key_buf.clear();
+                            self.write_value(&mut key_buf, keys, i)?;
+                            write_json_string(out, key_buf.trim_matches('"'));
+                        }
+                    }
+                    out.push(':');
+                    self.write_value(out, values, i)?;
+                }
+                out.push('}');
+            }
+            DataType::Struct(fields) => {
+                let struct_array = array.as_struct();
+                out.push('{');
+                let mut first = true;
+                for (field, column) in fields.iter().zip(struct_array.columns()) {
+                    if column.is_null(idx) && self.options.ignore_null_fields {
+                        continue;
+                    }
+                    if !first {
+                        out.push(',');
+                    }
+                    first = false;
+                    write_json_string(out, field.name());
+                    out.push(':');
+                    self.write_value(out, column, idx)?;
+                }
+                out.push('}');
+            }
+            other => df_unimplemented_err!("to_json: unsupported data type: {other}")?,
+        }
+        Ok(())
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push_str(&serde_json::to_string(s).unwrap_or_default());
+}
+
+/// writes a floating point number in the same format as java's
+/// Double.toString()
+fn write_java_float(out: &mut String, v: f64, shortest_repr: Option<String>) {
+    let Some(repr) = shortest_repr else {
+        // jackson quotes non-numeric numbers by default
+        out.push_str(match v {
+            v if v.is_nan() => "\"NaN\"",
+            v if v > 0.0 => "\"Infinity\"",
+            _ => "\"-Infinity\"",
+        });
+        return;
+    };
+    let abs = v.abs();
+    if abs == 0.0 || (1e-3..1e7).contains(&abs) {
+        out.push_str(&repr);
+        if !repr.contains('.') {
+            out.push_str(".0");
+        }
+        return;
+    }
+
+    // scientific notation, like 1.0E10 or 1.5E-5
+    let digits = repr.trim_start_matches('-').replace('.', "");
+    let int_len = repr
+        .trim_start_matches('-')
+        .split('.')
+        .next()
+        .unwrap_or("")
+        .len() as i32;
+    let leading_zeros = digits.len() - digits.trim_start_matches('0').len();
+    let digits = digits.trim_matches('0');
+    let digits = if digits.is_empty() { "0" } else { digits };
+    let exponent = int_len - 1 - leading_zeros as i32;
+    if v < 0.0 {
+        out.push('-');
+    }
+    out.push_str(&digits[..1]);
+    out.push('.');
+    out.push_str(if digits.len() > 1 { &digits[1..] } else { "0" });
+    let _ = write!(out, "E{exponent}");
+}
+
+const EPOCH_DAYS_FROM_CE: i32 = 719163;
+
+const DEFAULT_TIMESTAMP_FORMAT: &str = "yyyy-MM-dd'T'HH:mm:ss.SSSXXX";
+
+/// items of a java DateTimeFormatter pattern, only the commonly used subset
+/// is supported
+#[derive(Debug, Clone, PartialEq)]
+enum PatternItem {
+    Year(usize),
+    Month(usize),
+    Day(usize),
+    Hour(usize),
+    Minute(usize),
+    Second(usize),
+    Fraction(usize),
+    Offset(usize),
+    Literal(String),
+}
+
+fn parse_java_pattern(pattern: &str) -> Result<Vec<PatternItem>> {
+    let chars = pattern.chars().collect::<Vec<_>>();
+    let mut items = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\'' {
+            // quoted literal, '' is an escaped quote
+            let mut literal = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                        literal.push('\'');
+                        i += 2;
+                    }
+                    Some('\'') => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => {
+                        literal.push(c);
+                        i += 1;
+                    }
+                    None => df_execution_err!("unterminated literal in pattern: {pattern}")?,
+                }
+            }
+            if literal.is_empty() {
+                literal.push('\'');
+            }
+            items.push(PatternItem::Literal(literal));
+            continue;
+        }
+        if !c.is_ascii_alphabetic() {
+            if c == '[' || c == ']' {
+                df_unimplemented_err!("optional sections are not supported in pattern: {pattern}")?;
+            }
+            items.push(PatternItem::Literal(c.to_string()));
+            i += 1;
+            continue;
+        }
+        let count = chars[i..].iter().take_while(|&&ch| ch == c).count();
+        items.push(match c {
+            'y' | 'u' => PatternItem::Year(count),
+            'M' | 'L' if count <= 2 => PatternItem::Month(count),
+            'd' => PatternItem::Day(count),
+            'H' => PatternItem::Hour(count),
+            'm' => PatternItem::Minute(count),
+            's' => PatternItem::Second(count),
+            'S' => PatternItem::Fraction(count),
+            'X' | 'x' | 'Z' => PatternItem::Offset(count),
+            _ => df_unimplemented_err!("unsupported pattern letter '{c}' in pattern: {pattern}")?,
+        });
+        i += count;
+    }
+    Ok(items)
+}
+
+fn to_chrono_format(items: &[PatternItem]) -> Result<String> {
+    let mut format = String::new();
+    for item in items {
+        match item {
+            PatternItem::Year(2) => format.push_str("%y"),
+            PatternItem::Year(_) => format.push_str("%Y"),
+            PatternItem::Month(_) => format.push_str("%m"),
+            PatternItem::Day(_) => format.push_str("%d"),
+            PatternItem::Hour(_) => format.push_str("%H"),
+            PatternItem::Minute(_) => format.push_str("%M"),
+            PatternItem::Second(_) => format.push_str("%S"),
+            PatternItem::Fraction(n @ (3 | 6 | 9)) => {
+                let _ = write!(format, "%{n}f");
+            }
+            PatternItem::Fraction(n) => {
+                df_unimplemented_err!("unsupported fraction length in pattern: {n}")?
+            }
+            PatternItem::Offset(n) if *n >= 3 => format.push_str("%:z"),
+            PatternItem::Offset(_) => format.push_str("%z"),
+            PatternItem::Literal(literal) => format.push_str(&literal.replace('%', "%%")),
+        }
+    }
+    Ok(format)
+}
+
+fn parse_timestamp_with_pattern(s: &str, items: &[PatternItem], tz: &Tz) -> Option<i64> {
+    let format = to_chrono_format(items).ok()?;
+    let has_offset = items
+        .iter()
+        .any(|item| matches!(item, PatternItem::Offset(_)));
+    let has_time = items
+        .iter()
+        .any(|item| matches!(item, PatternItem::Hour(_)));
+
+    if has_offset {
+        // chrono does not accept 'Z' as zero offset
+        let s = match s.strip_suffix('Z') {
+            Some(prefix) if format.ends_with("%:z") => format!("{prefix}+00:00"),
+            Some(prefix) => format!("{prefix}+0000"),
+            None => s.to_string(),
+        };
+        return DateTime::parse_from_str(&s, &format)
+            .ok()
+            .map(|dt| dt.timestamp_micros());
+    }
+    let naive = if has_time {
+        NaiveDateTime::parse_from_str(s, &format).ok()?
+    } else {
+        NaiveDate::parse_from_str(s, &format)
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+    };
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.timestamp_micros())
+}
+
+fn format_with_pattern(items: &[PatternItem], local: &NaiveDateTime, offset_secs: i32) -> String {
+    let mut out = String::new();
+    for item in items {
+        let _ = match item {
+            PatternItem::Year(2) => write!(out, "{:02}", local.year() % 100),
+            PatternItem::Year(n) => write!(out, "{:0width$}", local.year(), width = *n),
+            PatternItem::Month(n) => write!(out, "{:0width$}", local.month(), width = *n),
+            PatternItem::Day(n) => write!(out, "{:0width$}", local.day(), width = *n),
+            PatternItem::Hour(n) => write!(out, "{:0width$}", local.hour(), width = *n),
+            PatternItem::Minute(n) => write!(out, "{:0width$}", local.minute(), width = *n),
+            PatternItem::Second(n) => write!(out, "{:0width$}", local.second(), width = *n),
+            PatternItem::Fraction(n) => {
+                let nanos = format!("{:09}", local.nanosecond() % 1_000_000_000);
+                write!(out, "{:0<width$.width$}", nanos, width = *n)
+            }
+            PatternItem::Offset(_) if offset_secs == 0 => write!(out, "Z"),
+            PatternItem::Offset(n) => {
+                let sign = if offset_secs < 0 { '-' } else { '+' };
+                let hours = offset_secs.abs() / 3600;
+                let minutes = offset_secs.abs() % 3600 / 60;
+                match n {
+                    1 if minutes == 0 => write!(out, "{sign}{hours:02}"),
+                    1 | 2 => write!(out, "{sign}{hours:02}{minutes:02}"),
+                    _ => write!(out, "{sign}{hours:02}:{minutes:02}"),
+                }
+            }
+            PatternItem::Literal(literal) => write!(out, "{literal}"),
+        };
+    }
+    out
+}
+
+/// json schema inferring, compatible with spark's JsonInferSchema
+#[derive(Debug, Clone, PartialEq)]
+enum InferredType {
+    Null,
+    Boolean,
+    Long,
+    Decimal(u8),
+    Double,
+    String,
+    Array(Box<InferredType>),
+    Struct(BTreeMap<String, InferredType>),
+}
+
+impl InferredType {
+    fn infer(value: &sonic_rs::Value) -> Self {
+        match value.get_type() {
+            sonic_rs::JsonType::Null => Self::Null,
+            sonic_rs::JsonType::Boolean => Self::Boolean,
+            sonic_rs::JsonType::String => Self::String,
+            sonic_rs::JsonType::Number => {
+                if value.as_i64().is_some() {
+                    Self::Long
+                } else if value.as_u64().is_some() {
+                    Self::Decimal(20)
+                } else {
+                    Self::Double
+                }
+            }
+            sonic_rs::JsonType::Array => Self::Array(Box::new(
+                value
+                    .as_array()
+                    .into_iter()
+                    .flat_map(|array| array.iter())
+                    .map(Self::infer)
+                    .fold(Self::Null, Self::merge),
+            )),
+            sonic_rs::JsonType::Object => Self::Struct(
+                value
+                    .as_object()
+                    .into_iter()
+                    .flat_map(|object| object.iter())
+                    .fold(BTreeMap::new(), |mut fields, (k, v)| {
+                        let inferred = Self::infer(v);
+                        let merged = match fields.remove(k) {
+                            Some(existed) => Self::merge(existed, inferred),
+                            None => inferred,
+                        };
+                        fields.insert(k.to_string(), merged);
+                        fields
+                    }),
+            ),
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (t1, t2) if t1 == t2 => t1,
+            (Self::Null, t) | (t, Self::Null) => t,
+            (Self::Long, Self::Decimal(p)) | (Self::Decimal(p), Self::Long) => Self::Decimal(p),
+            (Self::Decimal(p1), Self::Decimal(p2)) => Self::Decimal(p1.max(p2)),
+            (Self::Long | Self::Decimal(_), Self::Double)
+            | (Self::Double, Self::Long | Self::Decimal(_)) => Self::Double,
+            (Self::Array(t1), Self::Array(t2)) => Self::Array(Box::new(t1.merge(*t2))),
+            (Self::Struct(mut fields1), Self::Struct(fields2)) => {
+                for (k, t2) in fields2 {
+                    let merged = match fields1.remove(&k) {
+                        Some(t1) => t1.merge(t2),
+                        None => t2,
+                    };
+                    fields1.insert(k, merged);
+                }
+                Self::Struct(fields1)
+            }
+            _ => Self::String,
+        }
+    }
+
+    fn to_ddl(&self) -> String {
+        match self {
+            Self::Null | Self::String => "STRING".to_string(),
+            Self::Boolean => "BOOLEAN".to_string(),
+            Self::Long => "BIGINT".to_string(),
+            Self::Decimal(p) => format!("DECIMAL({p},0)"),
+            Self::Double => "DOUBLE".to_string(),
+            Self::Array(t) => format!("ARRAY<{}>", t.to_ddl()),
+            Self::Struct(fields) => format!(
+                "STRUCT<{}>",
+                fields
+                    .iter()
+                    .map(|(name, t)| format!("{}: {}", quote_if_needed(name), t.to_ddl()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+fn quote_if_needed(name: &str) -> String {
+    let is_plain = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.chars().all(|c| c.is_ascii_digit());
+    if is_plain {
+        name.to_string()
+    } else {
+        format!("`{}`", name.replace('`', "``"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error, sync::Arc};
+
+    use arrow::{array::*, datatypes::*};
+    use datafusion::{common::ScalarValue, logical_expr::ColumnarValue};
+    use datafusion_ext_commons::cancellation::{set_thread_cancel_token, CancellationToken};
+
+    use crate::spark_json::{
+        spark_from_json, spark_json_array_length, spark_schema_of_json, spark_to_json,
+    };
+
+    fn options(kvs: &[(&str, &str)]) -> Vec<ColumnarValue> {
+        kvs.iter()
+            .flat_map(|(k, v)| {
+                [
+                    ColumnarValue::Scalar(ScalarValue::from(*k)),
+                    ColumnarValue::Scalar(ScalarValue::from(*v)),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_from_json_struct() -> Result<(), Box<dyn Error>> {
+        let input: ArrayRef = Arc::new(StringArray::from(vec![
+            Some(r#"{"a": 1, "b": "x", "c": [1.5, 2], "d": {"k1": true, "k2": null}}"#),
+            Some(r#"{"a": 2, "c": null}"#),
+            Some(r#"{"a": "bad"}"#),
+            Some(r#"{"a": 3"#),
+            None,
+        ]));
+        let map_type = DataType::Map(
+            Arc::new(Field::new(
+                "entries",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("key", DataType::Utf8, false),
+                    Field::new("value", DataType::Boolean, true),
+                ])),
+                false,
+            )),
+            false,
+        );
+        let return_type = DataType::Struct(Fields::from(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Utf8, true),
+            Field::new_list("c", Field::new("item", DataType::Float64, true), true),
+            Field::new("d", map_type, true),
+            Field::new("_corrupt_record", DataType::Utf8, true),
+        ]));
+
+        let mut args = vec![ColumnarValue::Array(input)];
+        args.extend(options(&[("mode", "PERMISSIVE")]));
+        let output = spark_from_json(&args, &return_type)?.into_array(1)?;
+        let output = output.as_struct();
+
+        assert_eq!(output.len(), 5);
+        assert!(output.is_null(4));
+
+        // malformed rows are non-null structs with null fields
+        for row_idx in [2, 3] {
+            assert!(output.is_valid(row_idx));
+            for col_idx in 0..4 {
+                assert!(output.column(col_idx).is_null(row_idx));
+            }
+        }
+        assert_eq!(
+            output.column(0).as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![Some(1), Some(2), None, None, None]),
+        );
+        assert_eq!(
+            output.column(1).as_string::<i32>(),
+            &StringArray::from(vec![Some("x"), None, None, None, None]),
+        );
+        let c = output.column(2).as_list::<i32>();
+        assert_eq!(
+            c.value(0).as_primitive::<Float64Type>(),
+            &Float64Array::from(vec![1.5, 2.0]),
+        );
+        assert!(c.is_null(1));
+        let d = output.column(3).as_map();
+        assert_eq!(d.value(0).len(), 2);
+        assert_eq!(
+            output.column(4).as_string::<i32>(),
+            &StringArray::from(vec![
+                None,
+                None,
+                Some(r#"{"a": "bad"}"#),
+                Some(r#"{"a": 3"#),
+                None,
+            ]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_json_failfast() {
+        let input: ArrayRef = Arc::new(StringArray::from(vec![r#"{"a": 1}"#, r#"{"a": "#]));
+        let return_type =
+            DataType::Struct(Fields::from(vec![Field::new("a", DataType::Int64, true)]));
+        let mut args = vec![ColumnarValue::Array(input)];
+        args.extend(options(&[("mode", "FAILFAST")]));
+        assert!(spark_from_json(&args, &return_type).is_err());
+    }
+
+    #[test]
+    fn test_from_json_cancelled() {
+        let input: ArrayRef = Arc::new(StringArray::from(vec![r#"{"a": 1}"#]));
+        let return_type =
+            DataType::Struct(Fields::from(vec![Field::new("a", DataType::Int64, true)]));
+        let args = vec![ColumnarValue::Array(input)];
+        std::thread::spawn(move || {
+            let cancel_token = Arc::new(CancellationToken::default());
+            set_thread_cancel_token(cancel_token.clone());
+            assert!(spark_from_json(&args, &return_type).is_ok());
+            cancel_token.cancel();
+            let err = spark_from_json(&args, &return_type).unwrap_err();
+            assert!(err.to_string().contains("task cancelled"), "{err}");
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_from_json_timestamp_format() -> Result<(), Box<dyn Error>> {
+        let input: ArrayRef = Arc::new(StringArray::from(vec![
+            r#"{"t": "2024/01/02 03:04:05", "d": "02.01.2024"}"#,
+            r#"{"t": "2024-01-02T03:04:05"}"#,
+        ]));
+        let return_type = DataType::Struct(Fields::from(vec![
+            Field::new("t", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+            Field::new("d", DataType::Date32, true),
+        ]));
+        let mut args = vec![ColumnarValue::Array(input)];
+        args.extend(options(&[
+            ("timestampFormat", "yyyy/MM/dd HH:mm:ss"),
+            ("dateFormat", "dd.MM.yyyy"),
+            ("timeZone", "+08:00"),
+        ]));
+        let output = spark_from_json(&args, &return_type)?.into_array(1)?;
+        let output = output.as_struct();
+        assert_eq!(
+            output.column(0).as_primitive::<TimestampMicrosecondType>(),
+            &TimestampMicrosecondArray::from(vec![Some(1704135845000000), None]),
+        );
+        assert_eq!(
+            output.column(1).as_primitive::<Date32Type>(),
+            &Date32Array::from(vec![Some(19724), None]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_json() -> Result<(), Box<dyn Error>> {
+        let a: ArrayRef = Arc::new(Int32Array::from(vec![Some(1), None]));
+        let b: ArrayRef = Arc::new(Float64Array::from(vec![Some(1.0), Some(1.5e10)]));
+        let c: ArrayRef = Arc::new(StringArray::from(vec![Some("x\"y"), None]));
+        let t: ArrayRef = Arc::new(TimestampMicrosecondArray::from(vec![
+            Some(1704135845123000),
+            None,
+        ]));
+        let input: ArrayRef = Arc::new(StructArray::try_from(vec![
+            ("a", a),
+            ("b", b),
+            ("c", c),
+            ("t", t),
+        ])?);
+        let output = spark_to_json(&[ColumnarValue::Array(input)])?.into_array(1)?;
+        assert_eq!(
+            output.as_string::<i32>(),
+            &StringArray::from(vec![
+                r#"{"a":1,"b":1.0,"c":"x\"y","t":"2024-01-01T19:04:05.123Z"}"#,
+                r#"{"b":1.5E10}"#,
+            ]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_array_length() -> Result<(), Box<dyn Error>> {
+        let input: ArrayRef = Arc::new(StringArray::from(vec![
+            Some("[1, 2, [3, 4]]"),
+            Some("[]"),
+            Some(r#"{"a": 1}"#),
+            Some("[1, 2"),
+            None,
+        ]));
+        let output = spark_json_array_length(&[ColumnarValue::Array(input)])?.into_array(1)?;
+        assert_eq!(
+            output.as_primitive::<Int32Type>(),
+            &Int32Array::from(vec![Some(3), Some(0), None, None, None]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_of_json() -> Result<(), Box<dyn Error>> {
+        let input = ColumnarValue::Scalar(ScalarValue::from(
+            r#"[{"col": 0, "b": [1, 2.5], "a b": null}, {"col": "x", "c": {"d": true}}]"#,
+        ));
+        let output = spark_schema_of_json(&[input])?.into_array(1)?;
+        assert_eq!(
+            output.as_string::<i32>().value(0),
+            "ARRAY<STRUCT<`a b`: STRING, b: ARRAY<DOUBLE>, c: STRUCT<d: BOOLEAN>, col: STRING>>",
+        );
+        Ok(())
+    }
+}
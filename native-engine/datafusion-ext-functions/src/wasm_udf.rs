@@ -0,0 +1,482 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// scalar UDFs compiled to webassembly, referenced by ext function name
+// "Wasm:<udf_name>". modules are registered with register_wasm_udf() or
+// loaded from <spark.blaze.udf.wasm.dir>/<udf_name>.wasm.
+//
+// EXPERIMENTAL and native-only: this module is built only with the
+// non-default wasm-udf feature, and no spark expression is converted to a
+// "Wasm:" function by the JVM side yet. it can only be used by plans built
+// directly with the native API (like blaze-serde protobuf plans).
+//
+// a module has no imports and exports:
+//   memory: the linear memory
+//   alloc(size: i32) -> i32: allocates guest memory, 0 is never returned
+//   eval(args_ptr: i32, num_args: i32, num_rows: i32) -> i32
+//
+// each argument is described by 5 i32 values at args_ptr:
+//   type_tag, validity_ptr, values_ptr, values_len, offsets_ptr
+// and the returned pointer points to 4 i32 values describing the result:
+//   validity_ptr, values_ptr, values_len, offsets_ptr
+//
+// buffers are in arrow layout with zero offset: validity is a bitmap (0 for
+// no nulls), values of primitive types are little-endian, strings/binaries
+// have (num_rows + 1) i32 offsets starting from 0.
+//
+// every evaluation runs in a fresh instance whose memory size is limited.
+// execution is metered with fuel proportional to the number of rows, so a
+// guest looping forever traps instead of hanging the task.
+
+use std::{collections::HashMap, fmt::Display, path::Path, sync::Arc};
+
+use arrow::{
+    array::{make_array, Array, ArrayData, ArrayRef, AsArray},
+    buffer::{BooleanBuffer, Buffer, MutableBuffer, NullBuffer},
+    datatypes::DataType,
+};
+use blaze_jni_bridge::{
+    conf,
+    conf::{IntConf, StringConf},
+};
+use datafusion::{
+    common::{DataFusionError, Result},
+    logical_expr::{ColumnarValue, ScalarFunctionImplementation},
+};
+use datafusion_ext_commons::{df_execution_err, df_unimplemented_err};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use wasmtime::{
+    Config, Engine, Instance, InstancePre, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+static WASM_UDFS: Lazy<Mutex<HashMap<String, Arc<WasmUDF>>>> = Lazy::new(Default::default);
+
+/// registers a wasm module (binary or text format) as scalar UDF
+pub fn register_wasm_udf(
+    name: &str,
+    wasm: &[u8],
+    memory_limit: usize,
+    fuel_per_row: u64,
+) -> Result<()> {
+    let udf = Arc::new(WasmUDF::try_new(wasm, memory_limit, fuel_per_row)?);
+    WASM_UDFS.lock().insert(name.to_string(), udf);
+    Ok(())
+}
+
+pub fn create_wasm_udf(name: &str, return_type: &DataType) -> Result<ScalarFunctionImplementation> {
+    type_tag(return_type)?;
+    let udf = get_wasm_udf(name)?;
+    let return_type = return_type.clone();
+    Ok(Arc::new(move |args| udf.eval(args, &return_type)))
+}
+
+fn get_wasm_udf(name: &str) -> Result<Arc<WasmUDF>> {
+    if let Some(udf) = WASM_UDFS.lock().get(name) {
+        return Ok(udf.clone());
+    }
+
+    let dir = conf::WASM_UDF_DIR.value()?;
+    let memory_limit = conf::WASM_UDF_MEMORY_LIMIT.value()? as usize;
+    let fuel_per_row = conf::WASM_UDF_FUEL_PER_ROW.value()? as u64;
+    let path = Path::new(&dir).join(format!("{name}.wasm"));
+    let wasm = std::fs::read(&path)
+        .or_else(|err| df_execution_err!("error reading wasm udf from {path:?}: {err}"))?;
+    let udf = Arc::new(WasmUDF::try_new(&wasm, memory_limit, fuel_per_row)?);
+    log::info!("loaded wasm udf {name} from {path:?}");
+    Ok(WASM_UDFS
+        .lock()
+        .entry(name.to_string())
+        .or_insert(udf)
+        .clone())
+}
+
+struct WasmUDF {
+    engine: Engine,
+    instance_pre: InstancePre<StoreLimits>,
+    memory_limit: usize,
+    fuel_per_row: u64,
+}
+
+// fuel for writing arguments and reading results, which is not related to
+// number of rows
+const BASE_FUEL: u64 = 1000000;
+
+impl WasmUDF {
+    fn try_new(wasm: &[u8], memory_limit: usize, fuel_per_row: u64) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(wasm_err)?;
+        let module = Module::new(&engine, wasm).map_err(wasm_err)?;
+
+        // no host functions are linked, so the module is fully sandboxed
+        let linker = Linker::new(&engine);
+        let instance_pre = linker.instantiate_pre(&module).map_err(wasm_err)?;
+        Ok(Self {
+            engine,
+            instance_pre,
+            memory_limit,
+            fuel_per_row,
+        })
+    }
+
+    fn eval(&self, args: &[ColumnarValue], return_type: &DataType) -> Result<ColumnarValue> {
+        let num_rows = args
+            .iter()
+            .find_map(|arg| match arg {
+                ColumnarValue::Array(array) => Some(array.len()),
+                ColumnarValue::Scalar(_) => None,
+            })
+            .unwrap_or(1);
+        let arrays = args
+            .iter()
+            .map(|arg| arg.clone().into_array(num_rows))
+            .collect::<Result<Vec<_>>>()?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.memory_limit)
+            .trap_on_grow_failure(true)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store
+            .set_fuel(BASE_FUEL.saturating_add(self.fuel_per_row.saturating_mul(num_rows as u64)))
+            .map_err(wasm_err)?;
+        let instance = self
+            .instance_pre
+            .instantiate(&mut store)
+            .map_err(wasm_err)?;
+        let mut guest = Guest::try_new(&mut store, &instance)?;
+
+        let mut arg_descs = vec![];
+        for array in &arrays {
+            arg_descs.extend(guest.write_array(array)?);
+        }
+        let args_ptr = guest.write_i32s(&arg_descs)?;
+        let result_ptr = guest
+            .eval
+            .call(
+                &mut *guest.store,
+                (args_ptr, arrays.len() as i32, num_rows as i32),
+            )
+            .map_err(wasm_err)?;
+        let result = guest.read_array(result_ptr, return_type, num_rows)?;
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+struct Guest<'a> {
+    store: &'a mut Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    eval: TypedFunc<(i32, i32, i32), i32>,
+}
+
+impl<'a> Guest<'a> {
+    fn try_new(store: &'a mut Store<StoreLimits>, instance: &Instance) -> Result<Self> {
+        let Some(memory) = instance.get_memory(&mut *store, "memory") else {
+            return df_execution_err!("wasm udf does not export memory");
+        };
+        let alloc = instance
+            .get_typed_func(&mut *store, "alloc")
+            .map_err(wasm_err)?;
+        let eval = instance
+            .get_typed_func(&mut *store, "eval")
+            .map_err(wasm_err)?;
+        Ok(Self {
+            store,
+            memory,
+            alloc,
+            eval,
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<i32> {
+        let ptr = self
+            .alloc
+            .call(&mut *self.store, bytes.len() as i32)
+            .map_err(wasm_err)?;
+        self.memory
+            .write(&mut *self.store, ptr as usize, bytes)
+            .map_err(wasm_err)?;
+        Ok(ptr)
+    }
+
+    fn write_i32s(&mut self, values: &[i32]) -> Result<i32> {
+        let bytes = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.write(&bytes)
+    }
+
+    fn read(&self, ptr: i32, len: usize) -> Result<Buffer> {
+        // use arrow's aligned buffer instead of Vec<u8>
+        let mut buf = MutableBuffer::from_len_zeroed(len);
+        self.memory
+            .read(&*self.store, ptr as usize, buf.as_slice_mut())
+            .map_err(wasm_err)?;
+        Ok(buf.into())
+    }
+
+    fn read_i32s<const N: usize>(&self, ptr: i32) -> Result<[i32; N]> {
+        let buf = self.read(ptr, N * 4)?;
+        Ok(std::array::from_fn(|i| {
+            i32::from_le_bytes(buf[i * 4..][..4].try_into().unwrap())
+        }))
+    }
+
+    fn write_array(&mut self, array: &ArrayRef) -> Result<[i32; 5]> {
+        let (type_tag, width) = type_tag(array.data_type())?;
+        let validity_ptr = match array.nulls() {
+            Some(nulls) if nulls.null_count() > 0 => {
+                self.write(nulls.inner().sliced().as_slice())?
+            }
+            _ => 0,
+        };
+
+        let (values_ptr, values_len, offsets_ptr) = match array.data_type() {
+            DataType::Utf8 => {
+                let array = array.as_string::<i32>();
+                self.write_offsets_and_values(array.value_offsets(), array.values())?
+            }
+            DataType::Binary => {
+                let array = array.as_binary::<i32>();
+                self.write_offsets_and_values(array.value_offsets(), array.values())?
+            }
+            _ => {
+                let width = width.expect("primitive type");
+                let data = array.to_data();
+                let values =
+                    &data.buffers()[0].as_slice()[data.offset() * width..][..data.len() * width];
+                (self.write(values)?, values.len() as i32, 0)
+            }
+        };
+        Ok([type_tag, validity_ptr, values_ptr, values_len, offsets_ptr])
+    }
+
+    fn write_offsets_and_values(
+        &mut self,
+        offsets: &[i32],
+        values: &[u8],
+    ) -> Result<(i32, i32, i32)> {
+        let first = offsets[0];
+        let last = offsets[offsets.len() - 1];
+        let offsets = offsets
+            .iter()
+            .map(|offset| offset - first)
+            .collect::<Vec<_>>();
+        let values = &values[first as usize..last as usize];
+        let offsets_ptr = self.write_i32s(&offsets)?;
+        let values_ptr = self.write(values)?;
+        Ok((values_ptr, values.len() as i32, offsets_ptr))
+    }
+
+    fn read_array(&self, ptr: i32, data_type: &DataType, num_rows: usize) -> Result<ArrayRef> {
+        let [validity_ptr, values_ptr, values_len, offsets_ptr] = self.read_i32s::<4>(ptr)?;
+        let nulls = if validity_ptr != 0 {
+            let validity = self.read(validity_ptr, num_rows.div_ceil(8))?;
+            Some(NullBuffer::new(BooleanBuffer::new(validity, 0, num_rows)))
+        } else {
+            None
+        };
+
+        let mut builder = ArrayData::builder(data_type.clone())
+            .len(num_rows)
+            .nulls(nulls);
+        match type_tag(data_type)? {
+            (_, Some(width)) => {
+                if values_len as usize != num_rows * width {
+                    return df_execution_err!(
+                        "wasm udf returned {values_len} bytes, expect {}",
+                        num_rows * width,
+                    );
+                }
+                builder = builder.add_buffer(self.read(values_ptr, values_len as usize)?);
+            }
+            (_, None) => {
+                builder = builder
+                    .add_buffer(self.read(offsets_ptr, (num_rows + 1) * 4)?)
+                    .add_buffer(self.read(values_ptr, values_len as usize)?);
+            }
+        }
+
+        // full validation, since the output of wasm is not trusted
+        Ok(make_array(builder.build()?))
+    }
+}
+
+/// returns type tag and width of primitive types
+fn type_tag(data_type: &DataType) -> Result<(i32, Option<usize>)> {
+    Ok(match data_type {
+        DataType::Int8 => (0, Some(1)),
+        DataType::Int16 => (1, Some(2)),
+        DataType::Int32 => (2, Some(4)),
+        DataType::Int64 => (3, Some(8)),
+        DataType::Float32 => (4, Some(4)),
+        DataType::Float64 => (5, Some(8)),
+        DataType::Date32 => (6, Some(4)),
+        DataType::Utf8 => (7, None),
+        DataType::Binary => (8, None),
+        other => df_unimplemented_err!("wasm udf does not support data type: {other}")?,
+    })
+}
+
+fn wasm_err(err: impl Display) -> DataFusionError {
+    DataFusionError::Execution(format!("wasm udf error: {err}"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error, sync::Arc};
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use datafusion::logical_expr::ColumnarValue;
+
+    use crate::wasm_udf::{create_wasm_udf, register_wasm_udf};
+
+    // bump allocator growing memory on demand
+    const ALLOC: &str = r#"
+        (memory (export "memory") 1)
+        (global $heap (mut i32) (i32.const 1024))
+        (func $alloc (export "alloc") (param $size i32) (result i32)
+            (local $ptr i32) (local $end i32)
+            (local.set $ptr (global.get $heap))
+            (local.set $end (i32.and
+                (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+                (i32.const -8)))
+            (if (i32.gt_u (local.get $end) (i32.mul (memory.size) (i32.const 65536)))
+                (then (drop (memory.grow (i32.div_u
+                    (i32.sub
+                        (i32.add (local.get $end) (i32.const 65535))
+                        (i32.mul (memory.size) (i32.const 65536)))
+                    (i32.const 65536))))))
+            (global.set $heap (local.get $end))
+            (local.get $ptr))
+    "#;
+
+    // writes result descriptor
+    const RESULT: &str = r#"
+        (func $result (param $validity i32) (param $values i32) (param $values_len i32)
+                      (param $offsets i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (call $alloc (i32.const 16)))
+            (i32.store offset=0 (local.get $ptr) (local.get $validity))
+            (i32.store offset=4 (local.get $ptr) (local.get $values))
+            (i32.store offset=8 (local.get $ptr) (local.get $values_len))
+            (i32.store offset=12 (local.get $ptr) (local.get $offsets))
+            (local.get $ptr))
+    "#;
+
+    // plus_one(int64)
+    const PLUS_ONE: &str = r#"
+        (func (export "eval") (param $args i32) (param $num_args i32) (param $num_rows i32)
+                              (result i32)
+            (local $in i32) (local $out i32) (local $i i32)
+            (local.set $in (i32.load offset=8 (local.get $args)))
+            (local.set $out (call $alloc (i32.mul (local.get $num_rows) (i32.const 8))))
+            (block $done
+                (loop $loop
+                    (br_if $done (i32.ge_u (local.get $i) (local.get $num_rows)))
+                    (i64.store
+                        (i32.add (local.get $out) (i32.mul (local.get $i) (i32.const 8)))
+                        (i64.add
+                            (i64.load
+                                (i32.add (local.get $in) (i32.mul (local.get $i) (i32.const 8))))
+                            (i64.const 1)))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $loop)))
+            (call $result
+                (i32.load offset=4 (local.get $args))
+                (local.get $out)
+                (i32.mul (local.get $num_rows) (i32.const 8))
+                (i32.const 0)))
+    "#;
+
+    // identity(string)
+    const IDENTITY: &str = r#"
+        (func (export "eval") (param $args i32) (param $num_args i32) (param $num_rows i32)
+                              (result i32)
+            (call $result
+                (i32.load offset=4 (local.get $args))
+                (i32.load offset=8 (local.get $args))
+                (i32.load offset=12 (local.get $args))
+                (i32.load offset=16 (local.get $args))))
+    "#;
+
+    // grows memory beyond limit
+    const GREEDY: &str = r#"
+        (func (export "eval") (param $args i32) (param $num_args i32) (param $num_rows i32)
+                              (result i32)
+            (call $alloc (i32.const 1000000000)))
+    "#;
+
+    // never returns
+    const ENDLESS: &str = r#"
+        (func (export "eval") (param $args i32) (param $num_args i32) (param $num_rows i32)
+                              (result i32)
+            (loop $loop (br $loop))
+            (i32.const 0))
+    "#;
+
+    fn module(eval: &str) -> String {
+        format!("(module {ALLOC} {RESULT} {eval})")
+    }
+
+    #[test]
+    fn test_wasm_udf_primitive() -> Result<(), Box<dyn Error>> {
+        register_wasm_udf("plus_one", module(PLUS_ONE).as_bytes(), 1 << 20, 1000)?;
+        let udf = create_wasm_udf("plus_one", &arrow::datatypes::DataType::Int64)?;
+
+        let input = Int64Array::from(vec![Some(1), None, Some(3), Some(-5)]);
+        let sliced: ArrayRef = Arc::new(input.slice(1, 3));
+        let result = udf(&[ColumnarValue::Array(sliced)])?.into_array(3)?;
+        let expected: ArrayRef = Arc::new(Int64Array::from(vec![None, Some(4), Some(-4)]));
+        assert_eq!(&result, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wasm_udf_string() -> Result<(), Box<dyn Error>> {
+        register_wasm_udf("identity", module(IDENTITY).as_bytes(), 1 << 20, 1000)?;
+        let udf = create_wasm_udf("identity", &arrow::datatypes::DataType::Utf8)?;
+
+        let input = StringArray::from(vec![Some("a"), Some("Spark"), None, Some(""), Some("wasm")]);
+        let sliced: ArrayRef = Arc::new(input.slice(1, 4));
+        let result = udf(&[ColumnarValue::Array(sliced.clone())])?.into_array(4)?;
+        assert_eq!(&result, &sliced);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wasm_udf_memory_limit() -> Result<(), Box<dyn Error>> {
+        register_wasm_udf("greedy", module(GREEDY).as_bytes(), 1 << 20, 1000)?;
+        let udf = create_wasm_udf("greedy", &arrow::datatypes::DataType::Int64)?;
+
+        let input: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 3]));
+        assert!(udf(&[ColumnarValue::Array(input)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wasm_udf_fuel_limit() -> Result<(), Box<dyn Error>> {
+        register_wasm_udf("endless", module(ENDLESS).as_bytes(), 1 << 20, 1000)?;
+        let udf = create_wasm_udf("endless", &arrow::datatypes::DataType::Int64)?;
+
+        let input: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 3]));
+        assert!(udf(&[ColumnarValue::Array(input)]).is_err());
+        Ok(())
+    }
+}
@@ -41,10 +41,15 @@ use crate::{
     common::{
         execution_context::{ExecutionContext, WrappedRecordBatchSender},
         timer_helper::TimerHelper,
+        trace::TraceLane,
         SliceAsRawBytes,
     },
     memmgr::{
-        spill::{try_new_spill, Spill, SpillCompressedReader, SpillCompressedWriter},
+        async_spill::AsyncSpillWriter,
+        spill::{
+            spill_merge_max_fan_in, try_new_spill, Spill, SpillCompressedReader,
+            SpillCompressedWriter,
+        },
         MemConsumer, MemConsumerInfo, MemManager,
     },
 };
@@ -63,6 +68,7 @@ pub struct AggTable {
     mem_consumer_info: Option<Weak<MemConsumerInfo>>,
     in_mem: Mutex<InMemTable>,
     spills: Mutex<Vec<Box<dyn Spill>>>,
+    spill_writer: Mutex<AsyncSpillWriter<Box<dyn Spill>>>,
     agg_ctx: Arc<AggContext>,
     exec_ctx: Arc<ExecutionContext>,
     output_time: Time,
@@ -84,6 +90,10 @@ impl AggTable {
                 merging_time.clone(),
             )),
             spills: Mutex::default(),
+            spill_writer: Mutex::new(AsyncSpillWriter::new(
+                "AggTable",
+                exec_ctx.spill_metrics().clone(),
+            )),
             agg_ctx,
             exec_ctx,
             output_time,
@@ -125,7 +135,7 @@ impl AggTable {
     }
 
     pub async fn has_spill(&self) -> bool {
-        !self.spills.lock().await.is_empty()
+        !self.spills.lock().await.is_empty() || self.spill_writer.lock().await.num_pending() > 0
     }
 
     pub async fn mode(&self) -> InMemMode {
@@ -141,7 +151,8 @@ impl AggTable {
         self.set_spillable(false);
 
         let in_mem = self.renew_in_mem_table(InMemMode::Hashing).await;
-        let spills = std::mem::take(&mut *self.spills.lock().await);
+        let mut spills = std::mem::take(&mut *self.spills.lock().await);
+        spills.extend(self.spill_writer.lock().await.wait_all().await?);
         let batch_size = batch_size();
 
         if in_mem.num_records() == 0 && spills.is_empty() {
@@ -167,6 +178,7 @@ impl AggTable {
             // output in reversed order, so we can truncate records and free
             // memory as soon as possible
             for begin in (0..num_records).step_by(output_batch_size).rev() {
+                self.exec_ctx.check_cancelled()?;
                 let end = std::cmp::min(begin + output_batch_size, num_records);
                 let batch = self.agg_ctx.convert_records_to_batch(
                     &keys[begin..end],
@@ -199,11 +211,10 @@ impl AggTable {
 
         // write rest data into an in-memory buffer if in-mem data is small
         // otherwise write into spill
-        let mut spills = spills;
         if in_mem.num_records() > 0 {
-            let spill_metrics = self.exec_ctx.spill_metrics().clone();
+            let exec_ctx = self.exec_ctx.clone();
             let spill = tokio::task::spawn_blocking(move || {
-                let mut spill: Box<dyn Spill> = try_new_spill(&spill_metrics)?;
+                let mut spill: Box<dyn Spill> = try_new_spill(&exec_ctx, "Agg")?;
                 in_mem.try_into_spill(&mut spill)?; // spill staging records
                 Ok::<_, DataFusionError>(spill)
             })
@@ -214,6 +225,19 @@ impl AggTable {
             spills.push(spill);
         }
 
+        // too many spills -- merge into larger spills in multiple levels, so
+        // the number of cursors opened at once is bounded
+        let max_fan_in = spill_merge_max_fan_in();
+        while spills.len() > max_fan_in {
+            let mut remaining = std::mem::take(&mut spills);
+            while !remaining.is_empty() {
+                let merging = remaining
+                    .drain(..remaining.len().min(max_fan_in))
+                    .collect::<Vec<_>>();
+                spills.push(merge_spills(merging, &self.agg_ctx, &self.exec_ctx)?);
+            }
+        }
+
         let mut cursors = vec![];
         for spill in &mut spills {
             cursors.push(RecordsSpillCursor::try_from_spill(spill, &self.agg_ctx)?);
@@ -231,6 +255,8 @@ impl AggTable {
         while let cur_bucket_idx = cursors.peek().cur_bucket_idx
             && cur_bucket_idx < NUM_SPILL_BUCKETS
         {
+            self.exec_ctx.check_cancelled()?;
+
             // process current bucket
             while let mut min_cursor = cursors.peek_mut()
                 && min_cursor.cur_bucket_idx == cur_bucket_idx
@@ -288,6 +314,18 @@ impl MemConsumer for AggTable {
             .expect("consumer info not set")
     }
 
+    fn mem_stage_id(&self) -> Option<usize> {
+        self.exec_ctx.stage_id()
+    }
+
+    fn mem_partition_id(&self) -> Option<usize> {
+        Some(self.exec_ctx.partition_id())
+    }
+
+    fn mem_trace_lane(&self) -> Option<TraceLane> {
+        self.exec_ctx.trace_lane(self.name()).cloned()
+    }
+
     async fn spill(&self) -> Result<()> {
         if self.agg_ctx.supports_partial_skipping && self.agg_ctx.partial_skipping_skip_spill {
             return df_execution_err!("AGG_SPILL_PARTIAL_SKIPPING");
@@ -304,15 +342,23 @@ impl MemConsumer for AggTable {
         }
         let cur_in_mem = in_mem.renew(next_in_mem_mode);
 
-        let spill_metrics = self.exec_ctx.spill_metrics().clone();
-        let cur_spill = tokio::task::spawn_blocking(move || {
-            let mut spill = try_new_spill(&spill_metrics)?;
-            cur_in_mem.try_into_spill(&mut spill)?;
-            Ok::<_, DataFusionError>(spill)
-        })
-        .await
-        .expect("tokio error")?;
-        spills.push(cur_spill);
+        // frozen in-mem table is written in background and accounted by the
+        // spill writer, new input can be accepted
+        let exec_ctx = self.exec_ctx.clone();
+        let consumer_info = self.get_consumer_info().clone();
+        let mut spill_writer = self.spill_writer.lock().await;
+        spill_writer
+            .submit(cur_in_mem.mem_used(), self.mem_trace_lane(), move || {
+                let mut spill = try_new_spill(&exec_ctx, "Agg")?;
+                cur_in_mem.try_into_spill(&mut spill)?;
+                if let Some(consumer_info) = consumer_info.upgrade() {
+                    consumer_info.record_spill(spill.spill_len());
+                }
+                Ok(spill)
+            })
+            .await;
+        spills.extend(spill_writer.take_finished().await?);
+        drop(spill_writer);
         drop(spills);
         drop(in_mem);
         self.update_mem_used(0).await?;
@@ -652,6 +698,65 @@ fn read_spill_bucket(
     Ok(())
 }
 
+/// merges records of spills bucket by bucket into one spill
+fn merge_spills(
+    mut spills: Vec<Box<dyn Spill>>,
+    agg_ctx: &Arc<AggContext>,
+    exec_ctx: &Arc<ExecutionContext>,
+) -> Result<Box<dyn Spill>> {
+    assert!(spills.len() >= 1);
+    if spills.len() == 1 {
+        return Ok(spills.into_iter().next().unwrap());
+    }
+
+    let mut cursors = vec![];
+    for spill in &mut spills {
+        cursors.push(RecordsSpillCursor::try_from_spill(spill, agg_ctx)?);
+    }
+    let mut cursors: RadixQueue<RecordsSpillCursor> = RadixQueue::new(cursors, NUM_SPILL_BUCKETS);
+
+    let mut output_spill = try_new_spill(exec_ctx, "Agg")?;
+    let mut output_writer = output_spill.get_compressed_writer();
+    let mut map = AggHashMap::default();
+    let mut acc_table = agg_ctx.create_acc_table(0);
+
+    while let cur_bucket_idx = cursors.peek().cur_bucket_idx
+        && cur_bucket_idx < NUM_SPILL_BUCKETS
+    {
+        exec_ctx.check_cancelled()?;
+
+        // merge records of current bucket
+        while let mut min_cursor = cursors.peek_mut()
+            && min_cursor.cur_bucket_idx == cur_bucket_idx
+        {
+            let (mut bucket_acc_table, bucket_key_rows) = min_cursor.read_bucket()?;
+            let map_indices = map.upsert_records(bucket_key_rows);
+            acc_table.resize(map.len());
+            for (agg_idx, agg) in agg_ctx.aggs.iter().enumerate() {
+                agg.agg.partial_merge(
+                    &mut acc_table.cols_mut()[agg_idx],
+                    IdxSelection::IndicesU32(&map_indices),
+                    &mut bucket_acc_table.cols_mut()[agg_idx],
+                    IdxSelection::Range(0, map_indices.len()),
+                )?;
+            }
+        }
+
+        // write merged records of current bucket
+        let keys = map.take_keys();
+        write_len(cur_bucket_idx, &mut output_writer)?;
+        write_len(keys.len(), &mut output_writer)?;
+        write_spill_bucket(&mut output_writer, &acc_table, keys.iter(), 0..keys.len())?;
+        acc_table.resize(0);
+    }
+
+    // EOF
+    write_len(NUM_SPILL_BUCKETS, &mut output_writer)?;
+    write_len(0, &mut output_writer)?;
+    output_writer.finish()?;
+    Ok(output_spill)
+}
+
 pub struct RecordsSpillCursor<'a> {
     input: SpillCompressedReader<'a>,
     agg_ctx: Arc<AggContext>,
@@ -184,7 +184,11 @@ pub fn execute_build_hash_map(
             if !fallback_to_sorted {
                 let data_batch =
                     coalesce_batches_unchecked(data_schema, &std::mem::take(&mut staging_batches));
-                let hash_map = JoinHashMap::create_from_data_batch(data_batch, &keys)?;
+                let hash_map = JoinHashMap::create_from_data_batch(
+                    data_batch,
+                    &keys,
+                    exec_ctx.cancel_token(),
+                )?;
                 sender.send(hash_map.into_hash_map_batch()?).await;
                 exec_ctx
                     .baseline_metrics()
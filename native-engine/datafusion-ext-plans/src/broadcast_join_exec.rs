@@ -72,6 +72,7 @@ use crate::{
         join_utils::{JoinType, JoinType::*},
         JoinParams, JoinProjection,
     },
+    memmgr::{policy::MEM_PRIORITY_HIGH, reservation::MemReservation},
     sort_exec::create_default_ascending_sort_exec,
     sort_merge_join_exec::SortMergeJoinExec,
 };
@@ -205,6 +206,7 @@ impl BroadcastJoinExec {
             },
             move |sender| {
                 sender.exclude_time(exec_ctx_cloned.baseline_metrics().elapsed_compute());
+                sender.limit_output_rows();
                 execute_join(
                     left,
                     right,
@@ -588,7 +590,7 @@ async fn collect_join_hash_map_without_caching(
 
     let hash_map_batches: Vec<RecordBatch> = input.try_collect().await?;
     build_time.with_timer(|| {
-        let join_hash_map = match hash_map_batches.len() {
+        let mut join_hash_map = match hash_map_batches.len() {
             0 => JoinHashMap::create_empty(hash_map_schema, key_exprs)?,
             1 => {
                 if hash_map_batches[0].num_rows() == 0 {
@@ -599,6 +601,15 @@ async fn collect_join_hash_map_without_caching(
             }
             n => return df_execution_err!("expect zero or one hash map batch, got {n}"),
         };
+
+        // broadcast hash map may be cached and shared by tasks, account its
+        // memory in mem manager until all tasks release it
+        let mem_reservation = MemReservation::new_with_priority(
+            "BroadcastJoinHashMap",
+            join_hash_map.mem_size(),
+            MEM_PRIORITY_HIGH,
+        );
+        join_hash_map.set_mem_reservation(mem_reservation);
         Ok(CollectJoinHashMapResult::Map(Arc::new(join_hash_map)))
     })
 }
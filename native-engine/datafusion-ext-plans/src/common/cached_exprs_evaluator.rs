@@ -28,6 +28,7 @@ use arrow::{
     datatypes::{DataType, Schema, SchemaRef},
     record_batch::{RecordBatch, RecordBatchOptions},
 };
+use blaze_jni_bridge::{conf, conf::BooleanConf};
 use datafusion::{
     common::{
         cast::as_boolean_array,
@@ -41,10 +42,16 @@ use datafusion::{
     physical_expr_common::utils::scatter,
     physical_plan::ColumnarValue,
 };
-use datafusion_ext_commons::{arrow::cast::cast, uda::UserDefinedArray};
+use datafusion_ext_commons::{
+    arrow::{array_size::ArraySize, cast::cast},
+    uda::UserDefinedArray,
+};
+use datafusion_ext_exprs::spark_udf_wrapper::{SparkFusedUDFWrapper, SparkUDFWrapperExpr};
 use itertools::Itertools;
 use parking_lot::Mutex;
 
+use crate::memmgr::reservation::MemReservation;
+
 pub struct CachedExprsEvaluator {
     transformed_projection_exprs: Vec<PhysicalExprRef>,
     transformed_pruned_filter_exprs: Vec<(PhysicalExprRef, Vec<usize>)>,
@@ -58,8 +65,17 @@ impl CachedExprsEvaluator {
         projection_exprs: Vec<PhysicalExprRef>,
         output_schema: SchemaRef,
     ) -> Result<Self> {
-        let (transformed_exprs, cache) =
-            transform_to_cached_exprs(&[filter_exprs.clone(), projection_exprs.clone()].concat())?;
+        let cache = Cache::new(0);
+
+        // spark UDFs in projection exprs can be fused into one JNI round trip
+        let projection_exprs = if conf::UDF_FUSION_ENABLE.value().unwrap_or(false) {
+            fuse_spark_udfs(&projection_exprs, &cache)?
+        } else {
+            projection_exprs
+        };
+
+        let transformed_exprs =
+            transform_to_cached_exprs(&[filter_exprs.clone(), projection_exprs].concat(), &cache)?;
         let (transformed_filter_exprs, transformed_projection_exprs) =
             transformed_exprs.split_at(filter_exprs.len());
 
@@ -163,7 +179,10 @@ impl CachedExprsEvaluator {
     }
 }
 
-fn transform_to_cached_exprs(exprs: &[PhysicalExprRef]) -> Result<(Vec<PhysicalExprRef>, Cache)> {
+fn transform_to_cached_exprs(
+    exprs: &[PhysicalExprRef],
+    cache: &Cache,
+) -> Result<Vec<PhysicalExprRef>> {
     // count all children exprs
     fn count(expr: &PhysicalExprRef, expr_counts: &mut HashMap<ExprKey, usize>) {
         expr_counts
@@ -230,10 +249,11 @@ fn transform_to_cached_exprs(exprs: &[PhysicalExprRef]) -> Result<(Vec<PhysicalE
     }
 
     // generate cached expr ids
+    let first_cache_id = cache.alloc(dups.len());
     let cached_expr_ids: HashMap<ExprKey, usize> = dups
         .into_iter()
         .enumerate()
-        .map(|(id, expr)| (expr, id))
+        .map(|(id, expr)| (expr, first_cache_id + id))
         .collect();
 
     // transform all exprs with CachedExpr using dup_exprs
@@ -295,12 +315,109 @@ fn transform_to_cached_exprs(exprs: &[PhysicalExprRef]) -> Result<(Vec<PhysicalE
         })
     }
 
-    let cache = Cache::new(cached_expr_ids.len());
-    let transformed_exprs = exprs
+    exprs
+        .iter()
+        .map(|expr| Ok(transform(expr.clone(), &cached_expr_ids, cache)?))
+        .collect()
+}
+
+/// Returns indices of children which are always evaluated with the same batch
+/// as their parent (excluding branches of short circuiting exprs).
+fn eager_children_indices(expr: &PhysicalExprRef) -> Vec<usize> {
+    let num_children = expr.children().len();
+    if num_children > 0
+        && (expr.as_any().downcast_ref::<CaseExpr>().is_some()
+            || expr.as_any().downcast_ref::<SCAndExpr>().is_some()
+            || expr.as_any().downcast_ref::<SCOrExpr>().is_some())
+    {
+        if let Some(case_expr) = expr.as_any().downcast_ref::<CaseExpr>() {
+            if case_expr.expr().is_some() && num_children >= 2 {
+                return vec![0, 1];
+            }
+        }
+        return vec![0];
+    }
+    (0..num_children).collect()
+}
+
+/// Replaces eagerly evaluated spark UDFs with fused ones, so all of them are
+/// evaluated in one JNI round trip when the first one is evaluated.
+fn fuse_spark_udfs(exprs: &[PhysicalExprRef], cache: &Cache) -> Result<Vec<PhysicalExprRef>> {
+    fn is_spark_udf(expr: &PhysicalExprRef) -> bool {
+        expr.as_any()
+            .downcast_ref::<SparkUDFWrapperExpr>()
+            .is_some()
+    }
+
+    fn contains_spark_udf(expr: &PhysicalExprRef) -> bool {
+        is_spark_udf(expr) || expr.children().into_iter().any(contains_spark_udf)
+    }
+
+    // UDFs depending on other UDFs are not fusable
+    fn is_fusable(expr: &PhysicalExprRef) -> bool {
+        is_spark_udf(expr) && !expr.children().into_iter().any(contains_spark_udf)
+    }
+
+    fn collect(expr: &PhysicalExprRef, fusable_udfs: &mut Vec<ExprKey>) {
+        if is_fusable(expr) {
+            let expr_key = ExprKey(expr.clone());
+            if !fusable_udfs.contains(&expr_key) {
+                fusable_udfs.push(expr_key);
+            }
+            return;
+        }
+        let children = expr.children();
+        for i in eager_children_indices(expr) {
+            collect(children[i], fusable_udfs);
+        }
+    }
+    let mut fusable_udfs = vec![];
+    for expr in exprs {
+        collect(expr, &mut fusable_udfs);
+    }
+    if fusable_udfs.len() < 2 {
+        return Ok(exprs.to_vec());
+    }
+
+    let first_cache_id = cache.alloc(fusable_udfs.len());
+    let fused = Arc::new(SparkFusedUDFWrapper::try_new(
+        fusable_udfs.iter().map(|key| key.0.clone()).collect(),
+    )?);
+
+    fn transform(
+        expr: &PhysicalExprRef,
+        fusable_udfs: &[ExprKey],
+        fused: &Arc<SparkFusedUDFWrapper>,
+        first_cache_id: usize,
+        cache: &Cache,
+    ) -> Result<PhysicalExprRef> {
+        if is_fusable(expr) {
+            let expr_key = ExprKey(expr.clone());
+            let idx = fusable_udfs
+                .iter()
+                .position(|key| key == &expr_key)
+                .unwrap();
+            return Ok(Arc::new(FusedSparkUDFExpr {
+                cache: cache.clone(),
+                first_cache_id,
+                idx,
+                fused: fused.clone(),
+            }));
+        }
+
+        let mut children = expr.children().into_iter().cloned().collect::<Vec<_>>();
+        if children.is_empty() {
+            return Ok(expr.clone());
+        }
+        for i in eager_children_indices(expr) {
+            children[i] = transform(&children[i], fusable_udfs, fused, first_cache_id, cache)?;
+        }
+        expr.clone().with_new_children(children)
+    }
+    exprs
         .iter()
-        .map(|expr| Ok(transform(expr.clone(), &cached_expr_ids, &cache)?))
-        .collect::<Result<_>>()?;
-    Ok((transformed_exprs, cache))
+        .map(|expr| transform(expr, &fusable_udfs, &fused, first_cache_id, cache))
+        .collect()
 }
 
 /// A physical expr wrapper to use in HashSet/HashMap
@@ -384,19 +501,108 @@ impl PhysicalExpr for CachedExpr {
     }
 }
 
+/// A physical expr evaluating one UDF of a fused group, results of the other
+/// UDFs are put into cache and taken by their own exprs
+#[derive(Clone)]
+struct FusedSparkUDFExpr {
+    cache: Cache,
+    first_cache_id: usize,
+    idx: usize,
+    fused: Arc<SparkFusedUDFWrapper>,
+}
+
+impl Display for FusedSparkUDFExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Debug for FusedSparkUDFExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Fused(")?;
+        std::fmt::Debug::fmt(&self.fused.udf_exprs()[self.idx], f)?;
+        write!(f, ")")?;
+        Ok(())
+    }
+}
+
+impl PartialEq<dyn Any> for FusedSparkUDFExpr {
+    fn eq(&self, other: &dyn Any) -> bool {
+        other
+            .downcast_ref::<Self>()
+            .map(|other| Arc::ptr_eq(&other.fused, &self.fused) && other.idx == self.idx)
+            .unwrap_or(false)
+    }
+}
+
+impl PhysicalExpr for FusedSparkUDFExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        self.fused.udf_exprs()[self.idx].data_type(input_schema)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        self.fused.udf_exprs()[self.idx].nullable(input_schema)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        self.cache.get(self.first_cache_id + self.idx, || {
+            let mut evaluated = None;
+            for (idx, result) in self.fused.evaluate_all(batch)?.into_iter().enumerate() {
+                let value = ColumnarValue::Array(result);
+                if idx == self.idx {
+                    evaluated = Some(value);
+                } else {
+                    self.cache.set(self.first_cache_id + idx, value);
+                }
+            }
+            Ok(evaluated.expect("missing fused UDF result"))
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn PhysicalExpr>> {
+        // params are evaluated inside the fused group
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn PhysicalExpr>>,
+    ) -> Result<Arc<dyn PhysicalExpr>> {
+        Ok(self)
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        self.fused.udf_exprs()[self.idx].dyn_hash(state);
+    }
+}
+
 /// A struct holding all evaluated values of cachable expressions
 #[derive(Clone)]
 struct Cache {
     values: Arc<Mutex<Vec<Option<ColumnarValue>>>>,
+    mem_reservation: Arc<MemReservation>,
 }
 
 impl Cache {
     fn new(len: usize) -> Self {
         Self {
             values: Arc::new(Mutex::new(vec![None; len])),
+            mem_reservation: Arc::new(MemReservation::new("CachedExprsEvaluator", 0)),
         }
     }
 
+    /// Allocates `num` more values, returns the id of the first one
+    fn alloc(&self, num: usize) -> usize {
+        let mut values = self.values.lock();
+        let first_id = values.len();
+        values.resize(first_id + num, None);
+        first_id
+    }
+
     fn with<T>(&self, func: impl Fn(&Self) -> Result<T>) -> Result<T> {
         self.reset(); // reset before using cache
         let result = func(&self);
@@ -413,10 +619,18 @@ impl Cache {
             return Ok(cached.clone());
         }
         let cached = evaluate_on_vacant()?;
-        self.values.lock()[id] = Some(cached.clone());
+        let mut values = self.values.lock();
+        values[id] = Some(cached.clone());
+        self.update_mem_used(&values);
         Ok(cached)
     }
 
+    fn set(&self, id: usize, value: ColumnarValue) {
+        let mut values = self.values.lock();
+        values[id] = Some(value);
+        self.update_mem_used(&values);
+    }
+
     fn update_all(
         &self,
         on_update: impl Fn(Option<ColumnarValue>) -> Result<Option<ColumnarValue>>,
@@ -425,13 +639,28 @@ impl Cache {
         let updated_values = current_values
             .into_iter()
             .map(|value| on_update(value))
-            .collect::<Result<_>>()?;
+            .collect::<Result<Vec<_>>>()?;
+        self.update_mem_used(&updated_values);
         *self.values.lock() = updated_values;
         Ok(())
     }
 
     fn reset(&self) {
         self.values.lock().fill(None);
+        self.mem_reservation.resize(0);
+    }
+
+    // cached arrays are held until the cache is reset, so they are accounted
+    // in the mem manager
+    fn update_mem_used(&self, values: &[Option<ColumnarValue>]) {
+        let mem_used = values
+            .iter()
+            .map(|value| match value {
+                Some(ColumnarValue::Array(array)) => array.get_array_mem_size(),
+                _ => 0,
+            })
+            .sum();
+        self.mem_reservation.resize(mem_used);
     }
 }
 
@@ -504,3 +733,91 @@ fn filter_one_pred(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::datatypes::DataType;
+    use datafusion::{
+        common::{Result, ScalarValue},
+        logical_expr::Operator,
+        physical_expr::{
+            expressions::{BinaryExpr, CaseExpr, Column, Literal},
+            PhysicalExprRef,
+        },
+    };
+    use datafusion_ext_exprs::spark_udf_wrapper::SparkUDFWrapperExpr;
+
+    use crate::common::cached_exprs_evaluator::{fuse_spark_udfs, Cache, FusedSparkUDFExpr};
+
+    fn udf(id: u8, param: PhysicalExprRef) -> Result<PhysicalExprRef> {
+        Ok(Arc::new(SparkUDFWrapperExpr::try_new(
+            vec![id],
+            DataType::Int32,
+            true,
+            vec![param],
+        )?))
+    }
+
+    fn collect_fused_idx(expr: &PhysicalExprRef, fused_idx: &mut Vec<usize>) {
+        if let Some(fused) = expr.as_any().downcast_ref::<FusedSparkUDFExpr>() {
+            fused_idx.push(fused.idx);
+        }
+        for child in expr.children() {
+            collect_fused_idx(child, fused_idx);
+        }
+    }
+
+    #[test]
+    fn test_fuse_spark_udfs() -> Result<()> {
+        let col: PhysicalExprRef = Arc::new(Column::new("a", 0));
+        let zero: PhysicalExprRef = Arc::new(Literal::new(ScalarValue::Int32(Some(0))));
+        let udf1 = udf(1, col.clone())?;
+        let udf2 = udf(2, col.clone())?;
+        let udf3 = udf(3, col.clone())?;
+        let udf5 = udf(5, col.clone())?;
+        let udf4 = udf(4, udf5.clone())?;
+
+        let exprs: Vec<PhysicalExprRef> = vec![
+            // udf1(a)
+            udf1.clone(),
+            // case when udf2(a) > 0 then udf3(a) end
+            Arc::new(CaseExpr::try_new(
+                None,
+                vec![(
+                    Arc::new(BinaryExpr::new(udf2, Operator::Gt, zero)),
+                    udf3.clone(),
+                )],
+                None,
+            )?),
+            // udf1(a) + udf4(udf5(a))
+            Arc::new(BinaryExpr::new(udf1, Operator::Plus, udf4)),
+        ];
+
+        // udf3 is conditionally evaluated and udf4 depends on udf5, so only
+        // udf1, udf2 and udf5 are fused
+        let cache = Cache::new(0);
+        let fused_exprs = fuse_spark_udfs(&exprs, &cache)?;
+        let fused_idx = fused_exprs
+            .iter()
+            .map(|expr| {
+                let mut fused_idx = vec![];
+                collect_fused_idx(expr, &mut fused_idx);
+                fused_idx
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(fused_idx, vec![vec![0], vec![1], vec![0, 2]]);
+        assert_eq!(cache.values.lock().len(), 3);
+
+        // nothing to fuse with a single UDF
+        let cache = Cache::new(0);
+        let fused_exprs = fuse_spark_udfs(&[udf3], &cache)?;
+        assert!(fused_exprs[0]
+            .as_any()
+            .downcast_ref::<SparkUDFWrapperExpr>()
+            .is_some());
+        assert_eq!(cache.values.lock().len(), 0);
+        Ok(())
+    }
+}
@@ -13,38 +13,61 @@
 // limitations under the License.
 
 use std::{
+    collections::HashMap,
     future::Future,
     panic::AssertUnwindSafe,
     pin::Pin,
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+        Arc, Weak,
+    },
     task::{ready, Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use arrow::{array::RecordBatch, datatypes::SchemaRef};
-use blaze_jni_bridge::{conf, conf::BooleanConf, is_task_running};
+use blaze_jni_bridge::{
+    conf,
+    conf::{BooleanConf, IntConf},
+    is_jni_bridge_inited, is_task_running,
+};
+use bytesize::ByteSize;
 use datafusion::{
     common::Result,
     execution::{RecordBatchStream, SendableRecordBatchStream, TaskContext},
+    physical_expr::PhysicalExprRef,
     physical_plan::{
-        metrics::{BaselineMetrics, Count, ExecutionPlanMetricsSet, MetricBuilder, Time},
+        metrics::{
+            BaselineMetrics, Count, ExecutionPlanMetricsSet, MetricBuilder, MetricValue, Time,
+        },
         stream::{RecordBatchReceiverStream, RecordBatchStreamAdapter},
         ExecutionPlan,
     },
 };
 use datafusion_ext_commons::{
     arrow::{array_size::ArraySize, coalesce::coalesce_batches_unchecked},
-    batch_size, df_execution_err, suggested_batch_mem_size,
+    batch_size,
+    cancellation::CancellationToken,
+    df_execution_err, suggested_batch_mem_size,
 };
+use datafusion_ext_exprs::spark_udf_wrapper::SparkUDFWrapperExpr;
 use futures::{Stream, StreamExt};
 use futures_util::FutureExt;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use serde_json::{json, Value};
 use tokio::sync::mpsc::Sender;
 
 use crate::{
-    common::{column_pruning::ExecuteWithColumnPruning, timer_helper::TimerHelper},
-    memmgr::metrics::SpillMetrics,
+    common::{
+        column_pruning::ExecuteWithColumnPruning,
+        timer_helper::TimerHelper,
+        trace::{trace_enabled, TaskTracer, TraceLane},
+    },
+    memmgr::{
+        disk_spill::{DiskSpillManager, TaskSpillScope},
+        metrics::SpillMetrics,
+    },
 };
 
 pub struct ExecutionContext {
@@ -55,6 +78,8 @@ pub struct ExecutionContext {
     baseline_metrics: BaselineMetrics,
     spill_metrics: Arc<OnceCell<SpillMetrics>>,
     input_stat_metrics: Arc<OnceCell<Option<InputBatchStatistics>>>,
+    task_resources: Arc<TaskResources>,
+    trace_lane: Arc<OnceCell<Option<TraceLane>>>,
 }
 
 impl ExecutionContext {
@@ -65,6 +90,7 @@ impl ExecutionContext {
         metrics: &ExecutionPlanMetricsSet,
     ) -> Arc<Self> {
         Arc::new(Self {
+            task_resources: TaskResources::get_or_create(&task_ctx),
             task_ctx,
             partition_id,
             output_schema,
@@ -72,6 +98,7 @@ impl ExecutionContext {
             metrics: metrics.clone(),
             spill_metrics: Arc::default(),
             input_stat_metrics: Arc::default(),
+            trace_lane: Arc::default(),
         })
     }
 
@@ -84,6 +111,8 @@ impl ExecutionContext {
             baseline_metrics: self.baseline_metrics.clone(),
             spill_metrics: self.spill_metrics.clone(),
             input_stat_metrics: self.input_stat_metrics.clone(),
+            task_resources: self.task_resources.clone(),
+            trace_lane: self.trace_lane.clone(),
         })
     }
 
@@ -95,6 +124,36 @@ impl ExecutionContext {
         self.partition_id
     }
 
+    pub fn stage_id(&self) -> Option<usize> {
+        self.task_resources.stage_id()
+    }
+
+    pub fn cancel_token(&self) -> &Arc<CancellationToken> {
+        &self.task_resources.cancel_token
+    }
+
+    pub fn task_resources(&self) -> &Arc<TaskResources> {
+        &self.task_resources
+    }
+
+    /// gets the trace lane of this operator if tracing is enabled, name is
+    /// only used by the first call which creates the lane
+    pub fn trace_lane(&self, name: &str) -> Option<&TraceLane> {
+        self.trace_lane
+            .get_or_init(|| {
+                let tracer = self.task_resources.tracer.as_ref()?;
+                Some(tracer.new_lane(name))
+            })
+            .as_ref()
+    }
+
+    /// returns an error if the task has been cancelled or has exceeded its
+    /// deadline, long-running loops should call this periodically
+    pub fn check_cancelled(&self) -> Result<()> {
+        self.task_resources.cancel_token.check_cancelled()?;
+        self.task_resources.check_deadline()
+    }
+
     pub fn output_schema(&self) -> SchemaRef {
         self.output_schema.clone()
     }
@@ -122,6 +181,53 @@ impl ExecutionContext {
             .counter(name.to_owned(), self.partition_id)
     }
 
+    /// Registers metrics of all spark UDFs used in the exprs. Each UDF has its
+    /// own metrics named by its stable id (`udf_<id>_*`, identical UDFs are
+    /// summed), and jni calls/time of all UDFs are counted once as
+    /// `udf_jni_calls` and `udf_time`.
+    pub fn register_spark_udf_metrics(&self, exprs: &[PhysicalExprRef]) {
+        fn collect<'a>(expr: &'a PhysicalExprRef, udfs: &mut Vec<&'a SparkUDFWrapperExpr>) {
+            if let Some(udf) = expr.as_any().downcast_ref::<SparkUDFWrapperExpr>() {
+                udfs.push(udf);
+            }
+            for child in expr.children() {
+                collect(child, udfs);
+            }
+        }
+        let mut udfs = vec![];
+        for expr in exprs {
+            collect(expr, &mut udfs);
+        }
+        if udfs.is_empty() {
+            return;
+        }
+
+        let register = |value: MetricValue| {
+            MetricBuilder::new(self.execution_plan_metrics())
+                .with_partition(self.partition_id)
+                .build(value)
+        };
+        let num_jni_calls = self.register_counter_metric("udf_jni_calls");
+        let jni_time = self.register_timer_metric("udf_time");
+        for udf in udfs {
+            let id = udf.udf_id();
+            let metrics = &udf.metrics;
+            register(MetricValue::Count {
+                name: format!("udf_{id}_calls").into(),
+                count: metrics.num_calls.clone(),
+            });
+            register(MetricValue::Count {
+                name: format!("udf_{id}_rows").into(),
+                count: metrics.num_rows.clone(),
+            });
+            register(MetricValue::Time {
+                name: format!("udf_{id}_time").into(),
+                time: metrics.elapsed_compute.clone(),
+            });
+            metrics.bind_jni_metrics(num_jni_calls.clone(), jni_time.clone());
+        }
+    }
+
     pub fn coalesce_with_default_batch_size(
         self: &Arc<Self>,
         input: SendableRecordBatchStream,
@@ -244,7 +350,8 @@ impl ExecutionContext {
         self: &Arc<Self>,
         input: &Arc<dyn ExecutionPlan>,
     ) -> Result<SendableRecordBatchStream> {
-        input.execute(self.partition_id, self.task_ctx.clone())
+        let executed = input.execute(self.partition_id, self.task_ctx.clone())?;
+        Ok(self.trace_input(input, executed))
     }
 
     pub fn execute_projected(
@@ -252,7 +359,63 @@ impl ExecutionContext {
         input: &Arc<dyn ExecutionPlan>,
         projection: &[usize],
     ) -> Result<SendableRecordBatchStream> {
-        input.execute_projected(self.partition_id, self.task_ctx.clone(), projection)
+        let executed =
+            input.execute_projected(self.partition_id, self.task_ctx.clone(), projection)?;
+        Ok(self.trace_input(input, executed))
+    }
+
+    // records batches polled from an executed plan node in the node's own trace
+    // lane, so that every node is displayed in the trace even if it does not
+    // record spans itself
+    fn trace_input(
+        &self,
+        input: &Arc<dyn ExecutionPlan>,
+        executed: SendableRecordBatchStream,
+    ) -> SendableRecordBatchStream {
+        struct TracedStream {
+            input: SendableRecordBatchStream,
+            trace_lane: TraceLane,
+            poll_start_time: Option<Instant>,
+        }
+
+        impl RecordBatchStream for TracedStream {
+            fn schema(&self) -> SchemaRef {
+                self.input.schema()
+            }
+        }
+
+        impl Stream for TracedStream {
+            type Item = Result<RecordBatch>;
+
+            fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+                let poll_start_time = *self.poll_start_time.get_or_insert_with(Instant::now);
+                let polled = ready!(self.input.poll_next_unpin(cx));
+                self.poll_start_time = None;
+                match &polled {
+                    Some(Ok(batch)) => {
+                        let args = json!({ "num_rows": batch.num_rows() });
+                        self.trace_lane
+                            .complete("poll batch", poll_start_time, args);
+                    }
+                    Some(Err(err)) => {
+                        let args = json!({ "error": err.to_string() });
+                        self.trace_lane.instant("error", args);
+                    }
+                    None => self.trace_lane.instant("end of stream", Value::Null),
+                }
+                Poll::Ready(polled)
+            }
+        }
+
+        let Some(tracer) = self.task_resources.tracer() else {
+            return executed;
+        };
+        let trace_lane = tracer.new_lane(input.name());
+        Box::pin(TracedStream {
+            input: executed,
+            trace_lane,
+            poll_start_time: None,
+        })
     }
 
     pub fn stat_input(
@@ -286,10 +449,11 @@ impl ExecutionContext {
         desc: &'static str,
         output: impl FnOnce(Arc<WrappedRecordBatchSender>) -> Fut + Send + 'static,
     ) -> SendableRecordBatchStream {
+        let _ = self.trace_lane(desc); // create lane with operator name
         let mut stream_builder = RecordBatchReceiverStream::builder(self.output_schema(), 1);
         let err_sender = stream_builder.tx().clone();
         let wrapped_sender =
-            WrappedRecordBatchSender::new(self.clone(), stream_builder.tx().clone());
+            WrappedRecordBatchSender::new(self.clone(), desc, stream_builder.tx().clone());
 
         stream_builder.spawn(async move {
             let result = AssertUnwindSafe(async move {
@@ -368,16 +532,28 @@ fn working_senders() -> &'static Mutex<Vec<Weak<WrappedRecordBatchSender>>> {
 
 pub struct WrappedRecordBatchSender {
     exec_ctx: Arc<ExecutionContext>,
+    desc: &'static str,
     sender: Sender<Result<RecordBatch>>,
     exclude_time: OnceCell<Time>,
+    limit_output_rows: AtomicBool,
+    num_output_rows: AtomicUsize,
+    start_time: Instant,
 }
 
 impl WrappedRecordBatchSender {
-    pub fn new(exec_ctx: Arc<ExecutionContext>, sender: Sender<Result<RecordBatch>>) -> Arc<Self> {
+    pub fn new(
+        exec_ctx: Arc<ExecutionContext>,
+        desc: &'static str,
+        sender: Sender<Result<RecordBatch>>,
+    ) -> Arc<Self> {
         let wrapped = Arc::new(Self {
             exec_ctx,
+            desc,
             sender,
             exclude_time: OnceCell::new(),
+            limit_output_rows: AtomicBool::new(false),
+            num_output_rows: AtomicUsize::new(0),
+            start_time: Instant::now(),
         });
         let mut working_senders = working_senders().lock();
         working_senders.push(Arc::downgrade(&wrapped));
@@ -392,27 +568,76 @@ impl WrappedRecordBatchSender {
         self.exclude_time.get_or_init(|| exclude_time.clone());
     }
 
+    /// applies spark.blaze.limit.maxOperatorOutputRows to this sender, used by
+    /// operators which may produce much more rows than their inputs, like
+    /// generate and joins
+    pub fn limit_output_rows(&self) {
+        self.limit_output_rows.store(true, Relaxed);
+    }
+
+    /// checks limits of output rows and deadline before sending a batch
+    pub fn check_limits(&self, batch: &RecordBatch) -> Result<()> {
+        if self.limit_output_rows.load(Relaxed) {
+            let desc = self.desc;
+            let max_output_rows = self
+                .exec_ctx
+                .task_resources
+                .limits()
+                .max_operator_output_rows;
+            let num_output_rows =
+                self.num_output_rows.fetch_add(batch.num_rows(), Relaxed) + batch.num_rows();
+            if max_output_rows > 0 && num_output_rows > max_output_rows {
+                return df_execution_err!(
+                    "{desc} exceeded max output rows: {num_output_rows} > {max_output_rows} \
+                     (spark.blaze.limit.maxOperatorOutputRows)"
+                );
+            }
+        }
+        self.exec_ctx.task_resources.check_deadline()
+    }
+
     pub async fn send(&self, batch: RecordBatch) {
         if batch.num_rows() == 0 {
             return;
         }
+        let num_rows = batch.num_rows();
+        self.check_limits(&batch)
+            .unwrap_or_else(|err| panic!("output_with_sender[{}]: {err}", self.desc));
+
+        let trace_lane = self.exec_ctx.trace_lane(self.desc);
+        if let Some(trace_lane) = trace_lane {
+            if self.num_output_rows.load(Relaxed) == num_rows {
+                trace_lane.complete("first batch", self.start_time, Value::Null);
+            }
+        }
+
         let exclude_time = self.exclude_time.get().cloned();
-        let send_time = exclude_time.as_ref().map(|_| Instant::now());
+        let send_time = Instant::now();
         self.sender
             .send(Ok(batch))
             .await
             .unwrap_or_else(|err| panic!("output_with_sender: send error: {err}"));
 
-        send_time.inspect(|send_time| {
-            exclude_time
-                .as_ref()
-                .unwrap()
-                .sub_duration(send_time.elapsed());
-        });
+        if let Some(exclude_time) = exclude_time {
+            exclude_time.sub_duration(send_time.elapsed());
+        }
+        if let Some(trace_lane) = trace_lane {
+            trace_lane.complete("output batch", send_time, json!({ "num_rows": num_rows }));
+        }
     }
 }
 
 pub fn cancel_all_tasks(task_ctx: &Arc<TaskContext>) {
+    // interrupt running loops of the task, the entry is kept so that contexts
+    // created later also see the cancelled token
+    let task_resources = all_task_resources()
+        .lock()
+        .get(&(Arc::as_ptr(task_ctx) as usize))
+        .and_then(|resources| resources.upgrade());
+    if let Some(task_resources) = task_resources {
+        task_resources.cancel_token.cancel();
+    }
+
     let mut working_senders = working_senders().lock();
     *working_senders = std::mem::take(&mut *working_senders)
         .into_iter()
@@ -429,3 +654,227 @@ pub fn cancel_all_tasks(task_ctx: &Arc<TaskContext>) {
         })
         .collect();
 }
+
+/// resource limits of a task, zero means unlimited
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TaskLimits {
+    pub max_operator_output_rows: usize,
+    pub max_spill_bytes: usize,
+    pub deadline: Duration,
+}
+
+impl TaskLimits {
+    fn from_conf() -> Self {
+        static LIMITS: OnceCell<TaskLimits> = OnceCell::new();
+        *LIMITS.get_or_init(|| {
+            if !is_jni_bridge_inited() {
+                return TaskLimits::default(); // for testing
+            }
+            let read = |value: Result<i32>| value.unwrap_or(0).max(0) as usize;
+            let deadline_secs = read(conf::LIMIT_TASK_DEADLINE_SECS.value());
+            TaskLimits {
+                max_operator_output_rows: read(conf::LIMIT_MAX_OPERATOR_OUTPUT_ROWS.value()),
+                max_spill_bytes: read(conf::LIMIT_MAX_TASK_SPILL_MB.value()) << 20,
+                deadline: Duration::from_secs(deadline_secs as u64),
+            }
+        })
+    }
+}
+
+/// states shared by all execution contexts of a task, including the
+/// cancellation token and usages of limited resources
+pub struct TaskResources {
+    cancel_token: Arc<CancellationToken>,
+    tracer: Option<Arc<TaskTracer>>,
+    limits: OnceCell<TaskLimits>,
+    start_time: Instant,
+    spilled_bytes: AtomicUsize,
+    spill_scope: OnceCell<Arc<TaskSpillScope>>,
+    stage_id: OnceCell<usize>,
+}
+
+impl TaskResources {
+    // gets resources of a task, tasks are identified by their task contexts,
+    // which are held by execution contexts until the task is finished
+    fn get_or_create(task_ctx: &Arc<TaskContext>) -> Arc<Self> {
+        let mut all_task_resources = all_task_resources().lock();
+        all_task_resources.retain(|_, resources| resources.strong_count() > 0);
+
+        let key = Arc::as_ptr(task_ctx) as usize;
+        if let Some(resources) = all_task_resources
+            .get(&key)
+            .and_then(|resources| resources.upgrade())
+        {
+            return resources;
+        }
+        let resources = Arc::new(Self {
+            cancel_token: Arc::default(),
+            tracer: trace_enabled().then(TaskTracer::new),
+            limits: OnceCell::new(),
+            start_time: Instant::now(),
+            spilled_bytes: AtomicUsize::new(0),
+            spill_scope: OnceCell::new(),
+            stage_id: OnceCell::new(),
+        });
+        all_task_resources.insert(key, Arc::downgrade(&resources));
+        resources
+    }
+
+    pub fn tracer(&self) -> Option<&Arc<TaskTracer>> {
+        self.tracer.as_ref()
+    }
+
+    /// stage of the task, set by the native runtime and used in status
+    /// reports
+    pub fn stage_id(&self) -> Option<usize> {
+        self.stage_id.get().copied()
+    }
+
+    pub fn set_stage_id(&self, stage_id: usize) {
+        let _ = self.stage_id.set(stage_id);
+    }
+
+    /// disk spill files and usages of the task, spill files are deleted when
+    /// the scope is released or dropped with the task
+    pub fn spill_scope(&self) -> &Arc<TaskSpillScope> {
+        self.spill_scope
+            .get_or_init(|| DiskSpillManager::get().new_task_scope())
+    }
+
+    pub fn limits(&self) -> &TaskLimits {
+        self.limits.get_or_init(TaskLimits::from_conf)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_limits(&self, limits: TaskLimits) {
+        self.limits
+            .set(limits)
+            .expect("task limits already initialized");
+    }
+
+    pub fn spilled_bytes(&self) -> usize {
+        self.spilled_bytes.load(Relaxed)
+    }
+
+    /// accounts bytes written to spills by an operator, returns an error if
+    /// the task has spilled more than its limit
+    pub fn add_spilled_bytes(&self, desc: &str, num_bytes: usize) -> std::io::Result<()> {
+        let max_spill_bytes = self.limits().max_spill_bytes;
+        let spilled_bytes = self.spilled_bytes.fetch_add(num_bytes, Relaxed) + num_bytes;
+        if max_spill_bytes > 0 && spilled_bytes > max_spill_bytes {
+            return Err(std::io::Error::other(format!(
+                "{desc} exceeded max spill size of task: {} > {} (spark.blaze.limit.maxTaskSpillMB)",
+                ByteSize(spilled_bytes as u64),
+                ByteSize(max_spill_bytes as u64),
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn check_deadline(&self) -> Result<()> {
+        let deadline = self.limits().deadline;
+        if !deadline.is_zero() && self.start_time.elapsed() > deadline {
+            return df_execution_err!(
+                "task exceeded deadline: {:?} > {deadline:?} \
+                 (spark.blaze.limit.taskDeadlineSeconds)",
+                self.start_time.elapsed(),
+            );
+        }
+        Ok(())
+    }
+}
+
+fn all_task_resources() -> &'static Mutex<HashMap<usize, Weak<TaskResources>>> {
+    static ALL_TASK_RESOURCES: OnceCell<Mutex<HashMap<usize, Weak<TaskResources>>>> =
+        OnceCell::new();
+    ALL_TASK_RESOURCES.get_or_init(Mutex::default)
+}
+
+#[cfg(test)]
+mod test {
+    use arrow::{
+        array::Int32Array,
+        datatypes::{DataType, Field, Schema},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_cancel_token() -> Result<()> {
+        let task_ctx1 = Arc::new(TaskContext::default());
+        let task_ctx2 = Arc::new(TaskContext::default());
+        let schema = Arc::new(Schema::empty());
+        let metrics = ExecutionPlanMetricsSet::new();
+        let exec_ctx1 = ExecutionContext::new(task_ctx1.clone(), 0, schema.clone(), &metrics);
+        let exec_ctx2 = ExecutionContext::new(task_ctx1.clone(), 0, schema.clone(), &metrics);
+        let exec_ctx3 = ExecutionContext::new(task_ctx2.clone(), 0, schema.clone(), &metrics);
+
+        // contexts of the same task share one token
+        assert!(Arc::ptr_eq(
+            exec_ctx1.cancel_token(),
+            exec_ctx2.cancel_token()
+        ));
+        assert!(!Arc::ptr_eq(
+            exec_ctx1.cancel_token(),
+            exec_ctx3.cancel_token()
+        ));
+        exec_ctx1.check_cancelled()?;
+
+        // only the cancelled task is interrupted
+        cancel_all_tasks(&task_ctx1);
+        assert!(exec_ctx1.check_cancelled().is_err());
+        assert!(exec_ctx2
+            .with_new_output_schema(schema.clone())
+            .check_cancelled()
+            .is_err());
+        exec_ctx3.check_cancelled()?;
+
+        // contexts created after cancelling are also cancelled
+        let exec_ctx4 = ExecutionContext::new(task_ctx1, 0, schema, &metrics);
+        assert!(exec_ctx4.check_cancelled().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_limits() -> Result<()> {
+        let task_ctx = Arc::new(TaskContext::default());
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let metrics = ExecutionPlanMetricsSet::new();
+        let exec_ctx = ExecutionContext::new(task_ctx, 0, schema.clone(), &metrics);
+        exec_ctx.task_resources().set_limits(TaskLimits {
+            max_operator_output_rows: 10,
+            deadline: Duration::from_millis(100),
+            ..TaskLimits::default()
+        });
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from_iter_values(0..4))])?;
+
+        // output rows are limited per operator, only for limited senders
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let sender1 = WrappedRecordBatchSender::new(exec_ctx.clone(), "Test1", tx.clone());
+        let sender2 = WrappedRecordBatchSender::new(exec_ctx.clone(), "Test2", tx.clone());
+        let sender3 = WrappedRecordBatchSender::new(exec_ctx.clone(), "Test3", tx);
+        sender1.limit_output_rows();
+        sender2.limit_output_rows();
+        sender1.check_limits(&batch)?;
+        sender1.check_limits(&batch)?;
+        sender2.check_limits(&batch)?;
+        let err = sender1.check_limits(&batch).unwrap_err();
+        assert!(
+            err.to_string().contains("Test1 exceeded max output rows"),
+            "{err}"
+        );
+        for _ in 0..3 {
+            sender3.check_limits(&batch)?;
+        }
+
+        // deadline is checked by both senders and running loops
+        exec_ctx.check_cancelled()?;
+        std::thread::sleep(Duration::from_millis(200));
+        let err = exec_ctx.check_cancelled().unwrap_err();
+        assert!(err.to_string().contains("task exceeded deadline"), "{err}");
+        assert!(sender2.check_limits(&batch).is_err());
+        assert!(sender3.check_limits(&batch).is_err());
+        Ok(())
+    }
+}
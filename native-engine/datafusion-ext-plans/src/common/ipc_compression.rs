@@ -15,10 +15,26 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::io::{BufReader, Read, Take, Write};
+use std::{
+    collections::VecDeque,
+    io::{BufReader, Cursor, Read, Write},
+    sync::Arc,
+};
 
-use arrow::{array::ArrayRef, datatypes::SchemaRef};
-use blaze_jni_bridge::{conf, conf::StringConf, is_jni_bridge_inited};
+use arrow::{
+    array::{ArrayRef, RecordBatch, RecordBatchOptions},
+    datatypes::{Field, Schema, SchemaRef},
+    ipc::{
+        reader::StreamReader,
+        writer::{IpcWriteOptions, StreamWriter},
+        CompressionType,
+    },
+};
+use blaze_jni_bridge::{
+    conf,
+    conf::{BooleanConf, IntConf, StringConf},
+    is_jni_bridge_inited,
+};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use datafusion::common::Result;
 use datafusion_ext_commons::{
@@ -28,28 +44,118 @@ use datafusion_ext_commons::{
 use once_cell::sync::OnceCell;
 
 pub const DEFAULT_SHUFFLE_COMPRESSION_TARGET_BUF_SIZE: usize = 4194304;
-const ZSTD_LEVEL: i32 = 1;
+const DEFAULT_ZSTD_LEVEL: i32 = 1;
+const LZ4_BLOCK_SIZE: usize = 65536;
+
+// every block starts with a u32 block length, a u32 crc32c checksum and a block
+// id. block length and checksum cover the block id and payload. the block id is
+// the codec id for blocks with compressed data, or one of the following ids
+pub const BLOCK_HEADER_LEN: usize = 9;
+const BLOCK_ID_OFFSET: usize = 8;
+const BLOCK_ID_ZSTD_WITH_DICT: u8 = 0x10;
+const BLOCK_ID_ZSTD_DICT: u8 = 0x11;
+const BLOCK_ID_ARROW_IPC: u8 = 0x12;
+
+// zstd dictionary is trained from the first batches of a stream, only if all
+// of them are small enough
+const ZSTD_DICT_NUM_SAMPLES: usize = 32;
+const ZSTD_DICT_MAX_SAMPLE_SIZE: usize = 65536;
+const ZSTD_DICT_MAX_SIZE: usize = 16384;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoCompressionCodec {
+    None,
+    Lz4,
+    Lz4Block,
+    Zstd,
+    Snappy,
+}
+
+impl IoCompressionCodec {
+    pub fn try_from_name(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "none" | "uncompressed" => Ok(Self::None),
+            "lz4" => Ok(Self::Lz4),
+            "lz4_block" => Ok(Self::Lz4Block),
+            "zstd" => Ok(Self::Zstd),
+            "snappy" => Ok(Self::Snappy),
+            _ => df_execution_err!("unsupported codec: {}", name),
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Lz4 => 1,
+            Self::Lz4Block => 2,
+            Self::Zstd => 3,
+            Self::Snappy => 4,
+        }
+    }
+
+    pub fn try_from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+            2 => Ok(Self::Lz4Block),
+            3 => Ok(Self::Zstd),
+            4 => Ok(Self::Snappy),
+            _ => df_execution_err!("unsupported codec id: {}", id),
+        }
+    }
+}
 
 pub struct IpcCompressionWriter<W: Write> {
     output: W,
+    codec: IoCompressionCodec,
     shared_buf: VecBuffer,
     block_writer: IoCompressionWriter<VecBufferWrite>,
     block_empty: bool,
+    dict_trainer: Option<ZstdDictTrainer>,
+    arrow_ipc_writer: Option<ArrowIpcStreamWriter>,
 }
 unsafe impl<W: Write> Send for IpcCompressionWriter<W> {}
 
 impl<W: Write> IpcCompressionWriter<W> {
     pub fn new(output: W) -> Self {
-        let mut shared_buf = VecBuffer::default();
-        shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
+        Self::new_with_codec(output, io_compression_codec())
+    }
 
-        let block_writer = IoCompressionWriter::new_with_configured_codec(shared_buf.writer());
+    pub fn new_with_codec(output: W, codec: IoCompressionCodec) -> Self {
+        let mut shared_buf = VecBuffer::default();
+        let block_writer =
+            start_block(&mut shared_buf, codec, None).expect("error creating compression encoder");
         Self {
             output,
+            codec,
             shared_buf,
             block_writer,
             block_empty: true,
+            dict_trainer: None,
+            arrow_ipc_writer: None,
+        }
+    }
+
+    /// enables zstd dictionary training for streams of small blocks. the
+    /// trained dictionary is written into the stream before its first use, so
+    /// the output must be read as a whole stream instead of separated segments
+    pub fn with_dictionary_training(mut self, enabled: bool) -> Self {
+        if enabled && self.codec == IoCompressionCodec::Zstd {
+            self.dict_trainer = Some(ZstdDictTrainer::default());
         }
+        self
+    }
+
+    /// writes standard arrow ipc streams instead of blaze batches. every
+    /// finished buffer is a block containing a complete arrow ipc stream with
+    /// ipc body compression, prefixed by the block header. the output files
+    /// are a sequence of such blocks and cannot be opened by standard arrow
+    /// tools directly, use the ipc_dump utility to inspect them
+    pub fn try_with_arrow_ipc_format(mut self, enabled: bool) -> Result<Self> {
+        if enabled {
+            self.arrow_ipc_writer = Some(ArrowIpcStreamWriter::try_new(self.codec)?);
+        }
+        Ok(self)
     }
 
     pub fn set_output(&mut self, output: W) {
@@ -58,13 +164,35 @@ impl<W: Write> IpcCompressionWriter<W> {
             "IpcCompressionWriter must be empty while changing output"
         );
         self.output = output;
+
+        // dictionary must be written again into the new output
+        if let Some(dict_trainer) = &mut self.dict_trainer {
+            dict_trainer.dictionary_written = false;
+        }
     }
 
     pub fn write_batch(&mut self, num_rows: usize, cols: &[ArrayRef]) -> Result<()> {
         if num_rows == 0 {
             return Ok(());
         }
-        write_one_batch(num_rows, cols, &mut self.block_writer)?;
+        if let Some(arrow_ipc_writer) = &mut self.arrow_ipc_writer {
+            arrow_ipc_writer.write_batch(num_rows, cols)?;
+            if arrow_ipc_writer.buffered_len() as f64
+                >= DEFAULT_SHUFFLE_COMPRESSION_TARGET_BUF_SIZE as f64 * 0.9
+            {
+                self.finish_current_buf()?;
+            }
+            return Ok(());
+        }
+        match &mut self.dict_trainer {
+            Some(dict_trainer) if dict_trainer.is_collecting() => {
+                let mut sample = vec![];
+                write_one_batch(num_rows, cols, &mut sample)?;
+                self.block_writer.write_all(&sample)?;
+                dict_trainer.add_sample(sample);
+            }
+            _ => write_one_batch(num_rows, cols, &mut self.block_writer)?,
+        }
         self.block_empty = false;
 
         let buf_len = self.shared_buf.inner().len();
@@ -75,27 +203,55 @@ impl<W: Write> IpcCompressionWriter<W> {
     }
 
     pub fn finish_current_buf(&mut self) -> Result<()> {
+        if let Some(arrow_ipc_writer) = &mut self.arrow_ipc_writer {
+            if let Some(stream) = arrow_ipc_writer.finish()? {
+                write_block(&mut self.output, BLOCK_ID_ARROW_IPC, &stream)?;
+            }
+            return Ok(());
+        }
         if !self.block_empty {
             // finish current buf
             self.block_writer.finish_internal()?;
 
+            // write dictionary before the first block using it
+            if let Some(dict_trainer) = &mut self.dict_trainer {
+                if self.shared_buf.inner()[BLOCK_ID_OFFSET] == BLOCK_ID_ZSTD_WITH_DICT {
+                    dict_trainer.write_dictionary_if_needed(&mut self.output)?;
+                }
+            }
+
             // write
-            let block_len = self.shared_buf.inner().len() - 4;
-            self.shared_buf.inner_mut()[0..4]
+            let buf = self.shared_buf.inner_mut();
+            let block_len = buf.len() - BLOCK_ID_OFFSET;
+            let checksum = crc32c::crc32c(&buf[BLOCK_ID_OFFSET..]);
+            buf[0..4]
                 .as_mut()
                 .write_u32::<LittleEndian>(block_len as u32)?;
+            buf[4..8].as_mut().write_u32::<LittleEndian>(checksum)?;
             self.output.write_all(self.shared_buf.inner())?;
 
-            // open next buf
-            self.shared_buf.inner_mut().clear();
-            self.shared_buf.inner_mut().extend_from_slice(&[0u8; 4]);
-            self.block_writer =
-                IoCompressionWriter::new_with_configured_codec(self.shared_buf.writer());
+            // open next buf, using the dictionary if trained
+            let dictionary = match &mut self.dict_trainer {
+                Some(dict_trainer) => {
+                    dict_trainer.train_if_ready();
+                    dict_trainer.dictionary.as_deref()
+                }
+                None => None,
+            };
+            self.block_writer = start_block(&mut self.shared_buf, self.codec, dictionary)?;
             self.block_empty = true;
         }
         Ok(())
     }
 
+    /// size of compressed data buffered in the current block
+    pub fn buffered_len(&self) -> usize {
+        if let Some(arrow_ipc_writer) = &self.arrow_ipc_writer {
+            return arrow_ipc_writer.buffered_len();
+        }
+        self.shared_buf.inner().len()
+    }
+
     pub fn inner(&self) -> &W {
         &self.output
     }
@@ -105,84 +261,357 @@ impl<W: Write> IpcCompressionWriter<W> {
     }
 }
 
-pub struct IpcCompressionReader<R: Read + 'static> {
+fn start_block(
+    shared_buf: &mut VecBuffer,
+    codec: IoCompressionCodec,
+    zstd_dictionary: Option<&[u8]>,
+) -> Result<IoCompressionWriter<VecBufferWrite>> {
+    let block_id = match zstd_dictionary {
+        Some(_) => BLOCK_ID_ZSTD_WITH_DICT,
+        None => codec.id(),
+    };
+    shared_buf.inner_mut().clear();
+    shared_buf
+        .inner_mut()
+        .extend_from_slice(&[0u8; BLOCK_HEADER_LEN]);
+    shared_buf.inner_mut()[BLOCK_ID_OFFSET] = block_id;
+
+    match zstd_dictionary {
+        Some(dictionary) => Ok(IoCompressionWriter::ZSTD(zstd::Encoder::with_dictionary(
+            shared_buf.writer(),
+            zstd_level(),
+            dictionary,
+        )?)),
+        None => IoCompressionWriter::try_new_with_codec(codec, shared_buf.writer()),
+    }
+}
+
+// writes a whole block with header, used for blocks which are not compressed
+// through the shared buffer
+fn write_block(output: &mut impl Write, block_id: u8, payload: &[u8]) -> Result<()> {
+    let checksum = crc32c::crc32c_append(crc32c::crc32c(&[block_id]), payload);
+    output.write_u32::<LittleEndian>(payload.len() as u32 + 1)?;
+    output.write_u32::<LittleEndian>(checksum)?;
+    output.write_all(&[block_id])?;
+    output.write_all(payload)?;
+    Ok(())
+}
+
+/// returns true if the block header is of a block written in arrow ipc format
+pub fn is_arrow_ipc_block_header(header: &[u8; BLOCK_HEADER_LEN]) -> bool {
+    header[BLOCK_ID_OFFSET] == BLOCK_ID_ARROW_IPC
+}
+
+struct ArrowIpcStreamWriter {
+    options: IpcWriteOptions,
+    writer: Option<StreamWriter<Vec<u8>>>,
+}
+
+impl ArrowIpcStreamWriter {
+    fn try_new(codec: IoCompressionCodec) -> Result<Self> {
+        // arrow ipc body compression supports only lz4 frame and zstd
+        let compression = match codec {
+            IoCompressionCodec::None => None,
+            IoCompressionCodec::Zstd => Some(CompressionType::ZSTD),
+            _ => Some(CompressionType::LZ4_FRAME),
+        };
+        Ok(Self {
+            options: IpcWriteOptions::default().try_with_compression(compression)?,
+            writer: None,
+        })
+    }
+
+    fn write_batch(&mut self, num_rows: usize, cols: &[ArrayRef]) -> Result<()> {
+        let schema = Arc::new(Schema::new(
+            cols.iter()
+                .enumerate()
+                .map(|(i, col)| Field::new(format!("c{i}"), col.data_type().clone(), true))
+                .collect::<Vec<_>>(),
+        ));
+        let batch = RecordBatch::try_new_with_options(
+            schema.clone(),
+            cols.to_vec(),
+            &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+        )?;
+
+        if self.writer.is_none() {
+            self.writer = Some(StreamWriter::try_new_with_options(
+                vec![],
+                &schema,
+                self.options.clone(),
+            )?);
+        }
+        self.writer.as_mut().unwrap().write(&batch)?;
+        Ok(())
+    }
+
+    fn buffered_len(&self) -> usize {
+        self.writer
+            .as_ref()
+            .map(|writer| writer.get_ref().len())
+            .unwrap_or(0)
+    }
+
+    // finishes the current stream, returns none if no batches are written
+    fn finish(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(None);
+        };
+        writer.finish()?;
+        Ok(Some(writer.into_inner()?))
+    }
+}
+
+#[derive(Default)]
+struct ZstdDictTrainer {
+    samples: Vec<Vec<u8>>,
+    trained: bool,
+    dictionary: Option<Vec<u8>>,
+    dictionary_written: bool,
+}
+
+impl ZstdDictTrainer {
+    fn is_collecting(&self) -> bool {
+        !self.trained && self.samples.len() < ZSTD_DICT_NUM_SAMPLES
+    }
+
+    fn add_sample(&mut self, sample: Vec<u8>) {
+        // large batches produce large blocks, which do not benefit from a
+        // dictionary
+        if sample.len() > ZSTD_DICT_MAX_SAMPLE_SIZE {
+            self.samples.clear();
+            self.trained = true;
+            return;
+        }
+        self.samples.push(sample);
+    }
+
+    fn train_if_ready(&mut self) {
+        if self.trained || self.samples.len() < ZSTD_DICT_NUM_SAMPLES {
+            return;
+        }
+        let samples = std::mem::take(&mut self.samples);
+        self.trained = true;
+        match zstd::dict::from_samples(&samples, ZSTD_DICT_MAX_SIZE) {
+            Ok(dictionary) => {
+                log::info!("trained zstd dictionary, size={}", dictionary.len());
+                self.dictionary = Some(dictionary);
+            }
+            Err(err) => {
+                log::warn!("error training zstd dictionary, fallback to plain zstd: {err}");
+            }
+        }
+    }
+
+    fn write_dictionary_if_needed(&mut self, output: &mut impl Write) -> Result<()> {
+        if !self.dictionary_written {
+            let dictionary = self.dictionary.as_ref().expect("missing zstd dictionary");
+            write_block(output, BLOCK_ID_ZSTD_DICT, dictionary)?;
+            self.dictionary_written = true;
+        }
+        Ok(())
+    }
+}
+
+pub struct IpcCompressionReader<R: Read> {
     input: InputState<R>,
+    zstd_dictionary: Option<Vec<u8>>,
+    arrow_ipc_batches: VecDeque<(usize, Vec<ArrayRef>)>,
 }
 unsafe impl<R: Read> Send for IpcCompressionReader<R> {}
 
 #[derive(Default)]
-enum InputState<R: Read + 'static> {
+enum InputState<R: Read> {
     #[default]
     Unreachable,
     BlockStart(R),
-    BlockContent(IoCompressionReader<Take<R>>),
+    BlockLoaded(Vec<u8>, R),
+    BlockContent(IoCompressionReader<Cursor<Vec<u8>>>, R),
 }
 
 impl<R: Read> IpcCompressionReader<R> {
     pub fn new(input: R) -> Self {
         Self {
             input: InputState::BlockStart(input),
+            zstd_dictionary: None,
+            arrow_ipc_batches: VecDeque::new(),
         }
     }
 
     pub fn read_batch(&mut self, schema: &SchemaRef) -> Result<Option<(usize, Vec<ArrayRef>)>> {
-        struct Reader<'a, R: Read + 'static>(&'a mut IpcCompressionReader<R>);
+        struct Reader<'a, R: Read>(&'a mut IpcCompressionReader<R>);
         impl<'a, R: Read> Read for Reader<'a, R> {
             fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
                 match std::mem::take(&mut self.0.input) {
-                    InputState::BlockStart(mut input) => {
-                        let block_len = match input.read_u32::<LittleEndian>() {
-                            Ok(block_len) => block_len,
-                            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    InputState::BlockStart(mut input) => match read_block(&mut input)? {
+                        Some(block) => {
+                            self.0.input = InputState::BlockLoaded(block, input);
+                            self.read(buf)
+                        }
+                        None => {
+                            self.0.input = InputState::BlockStart(input);
+                            Ok(0)
+                        }
+                    },
+                    InputState::BlockLoaded(block, input) => {
+                        let block_id = block[0];
+                        let mut block = Cursor::new(block);
+                        block.set_position(1);
+
+                        let block_reader = match block_id {
+                            BLOCK_ID_ARROW_IPC => {
+                                // arrow ipc blocks contain whole batches and
+                                // are
+                                // read by read_batch(), stop at the block
+                                self.0.input = InputState::BlockLoaded(block.into_inner(), input);
                                 return Ok(0);
                             }
-                            Err(err) => {
-                                return Err(err);
+                            BLOCK_ID_ZSTD_DICT => {
+                                let dictionary = block.into_inner().split_off(1);
+                                self.0.zstd_dictionary = Some(dictionary);
+                                self.0.input = InputState::BlockStart(input);
+                                return self.read(buf);
+                            }
+                            BLOCK_ID_ZSTD_WITH_DICT => {
+                                let dictionary =
+                                    self.0.zstd_dictionary.as_deref().ok_or_else(|| {
+                                        std::io::Error::new(
+                                            std::io::ErrorKind::InvalidData,
+                                            "missing zstd dictionary",
+                                        )
+                                    })?;
+                                IoCompressionReader::ZSTD(zstd::Decoder::with_dictionary(
+                                    BufReader::new(block),
+                                    dictionary,
+                                )?)
                             }
+                            codec_id => IoCompressionReader::try_new_with_codec(
+                                IoCompressionCodec::try_from_id(codec_id)?,
+                                block,
+                            )?,
                         };
-                        let taken = input.take(block_len as u64);
-
-                        self.0.input = InputState::BlockContent(IoCompressionReader::try_new(
-                            io_compression_codec(),
-                            taken,
-                        )?);
+                        self.0.input = InputState::BlockContent(block_reader, input);
                         self.read(buf)
                     }
-                    InputState::BlockContent(mut block_reader) => match block_reader.read(buf) {
-                        Ok(len) if len > 0 => {
-                            self.0.input = InputState::BlockContent(block_reader);
-                            Ok(len)
-                        }
-                        Ok(_zero) => {
-                            let input = block_reader.finish_into_inner()?;
-                            self.0.input = InputState::BlockStart(input.into_inner());
-                            self.read(buf)
+                    InputState::BlockContent(mut block_reader, input) => {
+                        match block_reader.read(buf) {
+                            Ok(len) if len > 0 => {
+                                self.0.input = InputState::BlockContent(block_reader, input);
+                                Ok(len)
+                            }
+                            Ok(_zero) => {
+                                block_reader.finish_into_inner()?;
+                                self.0.input = InputState::BlockStart(input);
+                                self.read(buf)
+                            }
+                            Err(err) => Err(err),
                         }
-                        Err(err) => Err(err),
-                    },
+                    }
                     _ => unreachable!(),
                 }
             }
         }
-        read_one_batch(&mut Reader(self), schema)
+
+        loop {
+            if let Some(batch) = self.arrow_ipc_batches.pop_front() {
+                return Ok(Some(batch));
+            }
+            if let InputState::BlockLoaded(block, _) = &self.input {
+                if block[0] == BLOCK_ID_ARROW_IPC {
+                    let InputState::BlockLoaded(block, input) = std::mem::take(&mut self.input)
+                    else {
+                        unreachable!()
+                    };
+                    self.input = InputState::BlockStart(input);
+                    let stream = Cursor::new(&block[1..]);
+                    for batch in StreamReader::try_new_unbuffered(stream, None)? {
+                        let batch = batch?;
+                        self.arrow_ipc_batches
+                            .push_back((batch.num_rows(), batch.columns().to_vec()));
+                    }
+                    continue;
+                }
+            }
+
+            // blaze batches are read through blocks, stops at the end of input
+            // or before an arrow ipc block
+            match read_one_batch(&mut Reader(self), schema)? {
+                Some(batch) => return Ok(Some(batch)),
+                None if matches!(&self.input, InputState::BlockLoaded(..)) => continue,
+                None => return Ok(None),
+            }
+        }
     }
 }
 
+// reads a whole block and verifies its checksum before decoding, so corrupted
+// data is not passed to decoders. returns none at the end of input
+fn read_block<R: Read>(input: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+    let block_len = match input.read_u32::<LittleEndian>() {
+        Ok(block_len) => block_len,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if block_len == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "ipc compression block missing block id",
+        ));
+    }
+    let expected_checksum = input.read_u32::<LittleEndian>()?;
+
+    let mut block = vec![];
+    input.take(block_len as u64).read_to_end(&mut block)?;
+    if block.len() < block_len as usize {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!(
+                "ipc compression block truncated: expected {} bytes, got {}, \
+                 data may be corrupted",
+                block_len,
+                block.len(),
+            ),
+        ));
+    }
+    let checksum = crc32c::crc32c(&block);
+    if checksum != expected_checksum {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "ipc compression block checksum mismatch: expected \
+                 {expected_checksum:#010x}, got {checksum:#010x}, \
+                 data may be corrupted",
+            ),
+        ));
+    }
+    Ok(Some(block))
+}
+
 pub enum IoCompressionWriter<W: Write> {
+    None(W),
     LZ4(lz4_flex::frame::FrameEncoder<W>),
+    LZ4Block(Lz4BlockEncoder<W>),
     ZSTD(zstd::Encoder<'static, W>),
+    Snappy(snap::write::FrameEncoder<W>),
 }
 
 impl<W: Write> IoCompressionWriter<W> {
     pub fn new_with_configured_codec(inner: W) -> Self {
-        Self::try_new(io_compression_codec(), inner).expect("error creating compression encoder")
+        Self::try_new_with_codec(io_compression_codec(), inner)
+            .expect("error creating compression encoder")
     }
 
     pub fn try_new(codec: &str, inner: W) -> Result<Self> {
+        Self::try_new_with_codec(IoCompressionCodec::try_from_name(codec)?, inner)
+    }
+
+    pub fn try_new_with_codec(codec: IoCompressionCodec, inner: W) -> Result<Self> {
         match codec {
-            "lz4" => Ok(Self::LZ4(lz4_flex::frame::FrameEncoder::new(inner))),
-            "zstd" => Ok(Self::ZSTD(zstd::Encoder::new(inner, ZSTD_LEVEL)?)),
-            _ => df_execution_err!("unsupported codec: {}", codec),
+            IoCompressionCodec::None => Ok(Self::None(inner)),
+            IoCompressionCodec::Lz4 => Ok(Self::LZ4(lz4_flex::frame::FrameEncoder::new(inner))),
+            IoCompressionCodec::Lz4Block => Ok(Self::LZ4Block(Lz4BlockEncoder::new(inner))),
+            IoCompressionCodec::Zstd => Ok(Self::ZSTD(zstd::Encoder::new(inner, zstd_level())?)),
+            IoCompressionCodec::Snappy => Ok(Self::Snappy(snap::write::FrameEncoder::new(inner))),
         }
     }
 
@@ -192,12 +621,27 @@ impl<W: Write> IoCompressionWriter<W> {
 
     fn finish_internal(&mut self) -> Result<()> {
         match self {
+            IoCompressionWriter::None(w) => {
+                w.flush()?;
+            }
             IoCompressionWriter::LZ4(w) => {
                 w.try_finish()
                     .or_else(|_| df_execution_err!("ipc compresion error"))?;
+                // encoder does not flush inner writer when finished, inner
+                // writer may have buffered data (like the last
+                // spill checksum frame)
+                w.get_mut().flush()?;
+            }
+            IoCompressionWriter::LZ4Block(w) => {
+                w.finish()?;
             }
             IoCompressionWriter::ZSTD(w) => {
                 w.do_finish()?;
+                w.get_mut().flush()?;
+            }
+            IoCompressionWriter::Snappy(w) => {
+                // flushing writes all pending data as a complete snappy frame
+                w.flush()?;
             }
         }
         Ok(())
@@ -207,41 +651,60 @@ impl<W: Write> IoCompressionWriter<W> {
 impl<W: Write> Write for IoCompressionWriter<W> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
+            IoCompressionWriter::None(w) => w.write(buf),
             IoCompressionWriter::LZ4(w) => w.write(buf),
+            IoCompressionWriter::LZ4Block(w) => w.write(buf),
             IoCompressionWriter::ZSTD(w) => w.write(buf),
+            IoCompressionWriter::Snappy(w) => w.write(buf),
         }
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
+            IoCompressionWriter::None(w) => w.flush(),
             IoCompressionWriter::LZ4(w) => w.flush(),
+            IoCompressionWriter::LZ4Block(w) => w.flush(),
             IoCompressionWriter::ZSTD(w) => w.flush(),
+            IoCompressionWriter::Snappy(w) => w.flush(),
         }
     }
 }
 
 pub enum IoCompressionReader<R: Read> {
+    None(R),
     LZ4(lz4_flex::frame::FrameDecoder<R>),
+    LZ4Block(Lz4BlockDecoder<R>),
     ZSTD(zstd::Decoder<'static, BufReader<R>>),
+    Snappy(snap::read::FrameDecoder<R>),
 }
 
 impl<R: Read> IoCompressionReader<R> {
     pub fn new_with_configured_codec(inner: R) -> Self {
-        Self::try_new(io_compression_codec(), inner).expect("error creating compression encoder")
+        Self::try_new_with_codec(io_compression_codec(), inner)
+            .expect("error creating compression encoder")
     }
 
     pub fn try_new(codec: &str, inner: R) -> Result<Self> {
+        Self::try_new_with_codec(IoCompressionCodec::try_from_name(codec)?, inner)
+    }
+
+    pub fn try_new_with_codec(codec: IoCompressionCodec, inner: R) -> Result<Self> {
         match codec {
-            "lz4" => Ok(Self::LZ4(lz4_flex::frame::FrameDecoder::new(inner))),
-            "zstd" => Ok(Self::ZSTD(zstd::Decoder::new(inner)?)),
-            _ => df_execution_err!("unsupported codec: {}", codec),
+            IoCompressionCodec::None => Ok(Self::None(inner)),
+            IoCompressionCodec::Lz4 => Ok(Self::LZ4(lz4_flex::frame::FrameDecoder::new(inner))),
+            IoCompressionCodec::Lz4Block => Ok(Self::LZ4Block(Lz4BlockDecoder::new(inner))),
+            IoCompressionCodec::Zstd => Ok(Self::ZSTD(zstd::Decoder::new(inner)?)),
+            IoCompressionCodec::Snappy => Ok(Self::Snappy(snap::read::FrameDecoder::new(inner))),
         }
     }
 
     pub fn finish_into_inner(self) -> Result<R> {
         match self {
+            Self::None(r) => Ok(r),
             Self::LZ4(r) => Ok(r.into_inner()),
+            Self::LZ4Block(r) => Ok(r.inner),
             Self::ZSTD(r) => Ok(r.finish().into_inner()),
+            Self::Snappy(r) => Ok(r.into_inner()),
         }
     }
 }
@@ -249,24 +712,157 @@ impl<R: Read> IoCompressionReader<R> {
 impl<R: Read> Read for IoCompressionReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
+            Self::None(r) => r.read(buf),
             Self::LZ4(r) => r.read(buf),
+            Self::LZ4Block(r) => r.read(buf),
             Self::ZSTD(r) => r.read(buf),
+            Self::Snappy(r) => r.read(buf),
+        }
+    }
+}
+
+/// lz4 block mode encoder, data is split into chunks of LZ4_BLOCK_SIZE and
+/// each chunk is written as a u32 compressed length followed by a lz4 block
+/// with prepended uncompressed size. cheaper than frame mode for it has no
+/// frame headers and checksums
+pub struct Lz4BlockEncoder<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Lz4BlockEncoder<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(LZ4_BLOCK_SIZE),
         }
     }
+
+    fn write_chunk(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            let compressed = lz4_flex::block::compress_prepend_size(&self.buf);
+            self.inner
+                .write_u32::<LittleEndian>(compressed.len() as u32)?;
+            self.inner.write_all(&compressed)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.write_chunk()?;
+        self.inner.flush()
+    }
 }
 
-fn io_compression_codec() -> &'static str {
-    static CODEC: OnceCell<String> = OnceCell::new();
-    CODEC
+impl<W: Write> Write for Lz4BlockEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(LZ4_BLOCK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() >= LZ4_BLOCK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct Lz4BlockDecoder<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Lz4BlockDecoder<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Lz4BlockDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.buf.len() {
+            let compressed_len = match self.inner.read_u32::<LittleEndian>() {
+                Ok(len) => len as usize,
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(0),
+                Err(err) => return Err(err),
+            };
+            let mut compressed = vec![0u8; compressed_len];
+            self.inner.read_exact(&mut compressed)?;
+            self.buf = lz4_flex::block::decompress_size_prepended(&compressed)
+                .map_err(std::io::Error::other)?;
+            self.pos = 0;
+        }
+        let len = buf.len().min(self.buf.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buf[self.pos..][..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+fn io_compression_codec() -> IoCompressionCodec {
+    static CODEC: OnceCell<IoCompressionCodec> = OnceCell::new();
+    *CODEC
+        .get_or_try_init(|| {
+            if is_jni_bridge_inited() {
+                // shuffle codec falls back to spark.io.compression.codec if not
+                // specified
+                let mut codec = conf::SHUFFLE_COMPRESSION_CODEC.value()?;
+                if codec.is_empty() {
+                    codec = conf::SPARK_IO_COMPRESSION_CODEC.value()?;
+                }
+                IoCompressionCodec::try_from_name(&codec)
+            } else {
+                Ok(IoCompressionCodec::Lz4) // for testing
+            }
+        })
+        .expect("error reading spark.blaze.shuffle.compression.codec")
+}
+
+fn zstd_level() -> i32 {
+    static LEVEL: OnceCell<i32> = OnceCell::new();
+    *LEVEL
+        .get_or_try_init(|| {
+            if is_jni_bridge_inited() {
+                conf::SPARK_IO_COMPRESSION_ZSTD_LEVEL.value()
+            } else {
+                Ok(DEFAULT_ZSTD_LEVEL) // for testing
+            }
+        })
+        .expect("error reading spark.io.compression.zstd.level")
+}
+
+pub fn arrow_ipc_format_enabled() -> bool {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    *ENABLED
         .get_or_try_init(|| {
             if is_jni_bridge_inited() {
-                conf::SPARK_IO_COMPRESSION_CODEC.value()
+                conf::SHUFFLE_ARROW_IPC_FORMAT_ENABLE.value()
             } else {
-                Ok(format!("lz4")) // for testing
+                Ok(false) // for testing
             }
         })
-        .expect("error reading spark.io.compression.codec")
-        .as_str()
+        .expect("error reading spark.blaze.shuffle.arrowIpcFormat.enable")
+}
+
+pub fn zstd_dict_training_enabled() -> bool {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    *ENABLED
+        .get_or_try_init(|| {
+            if is_jni_bridge_inited() {
+                conf::ZSTD_DICT_TRAINING_ENABLE.value()
+            } else {
+                Ok(false) // for testing
+            }
+        })
+        .expect("error reading spark.blaze.io.compression.zstd.dictTraining.enable")
 }
 
 #[derive(Default)]
@@ -308,7 +904,7 @@ impl VecBuffer {
 
 #[cfg(test)]
 mod tests {
-    use std::{error::Error, io::Cursor, sync::Arc};
+    use std::{error::Error, sync::Arc};
 
     use arrow::{
         array::StringArray,
@@ -340,4 +936,190 @@ mod tests {
         assert!(reader.read_batch(&schema)?.is_none());
         Ok(())
     }
+
+    #[test]
+    fn test_ipc_compression_codecs() -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![Field::new("", DataType::Utf8, false)]));
+        let test_arrays: Vec<ArrayRef> = (0..10)
+            .map(|i| -> ArrayRef {
+                Arc::new(StringArray::from_iter_values(
+                    (0..100000).map(|j| format!("value-{i}-{}", j % 1000)),
+                ))
+            })
+            .collect();
+
+        // blocks of different codecs are self-described and can be concatenated
+        let mut buf = vec![];
+        for codec in ["lz4", "lz4_block", "zstd", "snappy", "none"] {
+            let codec = IoCompressionCodec::try_from_name(codec)?;
+            let mut writer = IpcCompressionWriter::new_with_codec(&mut buf, codec);
+            for test_array in &test_arrays {
+                writer.write_batch(test_array.len(), &[test_array.clone()])?;
+            }
+            writer.finish_current_buf()?;
+        }
+
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        for _ in 0..5 {
+            for test_array in &test_arrays {
+                let (num_rows, arrays) = reader.read_batch(&schema)?.unwrap();
+                assert_eq!(num_rows, test_array.len());
+                assert_eq!(&arrays, &[test_array.clone()]);
+            }
+        }
+        assert!(reader.read_batch(&schema)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_compression_codecs() -> Result<(), Box<dyn Error>> {
+        let data = (0..200000)
+            .flat_map(|i| format!("{}", i % 3000).into_bytes())
+            .collect::<Vec<_>>();
+
+        for codec in ["lz4", "lz4_block", "zstd", "snappy", "none"] {
+            let mut buf = vec![];
+            let mut writer = IoCompressionWriter::try_new(codec, &mut buf)?;
+            writer.write_all(&data)?;
+            writer.finish()?;
+
+            let mut reader = IoCompressionReader::try_new(codec, Cursor::new(buf))?;
+            let mut decompressed = vec![];
+            reader.read_to_end(&mut decompressed)?;
+            assert_eq!(decompressed, data, "codec: {codec}");
+        }
+        assert!(IoCompressionCodec::try_from_name("lzf").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_zstd_dictionary() -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![Field::new("", DataType::Utf8, false)]));
+        let test_arrays: Vec<ArrayRef> = (0..300)
+            .map(|i| -> ArrayRef {
+                Arc::new(StringArray::from_iter_values((0..50).map(|j| {
+                    let city = ["beijing", "shanghai", "hangzhou", "shenzhen"][(i + j) % 4];
+                    let category = ["food", "cloth", "book"][(i * j) % 3];
+                    format!("city-{city}-category-{category}-{}", j % 7)
+                })))
+            })
+            .collect();
+
+        // write small blocks, each block contains only one batch
+        let write_blocks = |dict_training: bool| -> Result<Vec<u8>> {
+            let mut buf = vec![];
+            let mut writer =
+                IpcCompressionWriter::new_with_codec(&mut buf, IoCompressionCodec::Zstd)
+                    .with_dictionary_training(dict_training);
+            for test_array in &test_arrays {
+                writer.write_batch(test_array.len(), &[test_array.clone()])?;
+                writer.finish_current_buf()?;
+            }
+            Ok(buf)
+        };
+        let buf = write_blocks(true)?;
+        assert!(buf.len() < write_blocks(false)?.len());
+
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        for test_array in &test_arrays {
+            let (num_rows, arrays) = reader.read_batch(&schema)?.unwrap();
+            assert_eq!(num_rows, test_array.len());
+            assert_eq!(&arrays, &[test_array.clone()]);
+        }
+        assert!(reader.read_batch(&schema)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_arrow_ipc_format() -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![Field::new("", DataType::Utf8, false)]));
+        let test_arrays: Vec<ArrayRef> = (0..10)
+            .map(|i| -> ArrayRef {
+                Arc::new(StringArray::from_iter_values(
+                    (0..1000).map(|j| format!("value-{i}-{j}")),
+                ))
+            })
+            .collect();
+
+        // arrow ipc streams of different codecs, followed by blaze blocks
+        let mut buf = vec![];
+        let mut stream_lens = vec![];
+        for codec in ["lz4", "zstd", "none"] {
+            let codec = IoCompressionCodec::try_from_name(codec)?;
+            let mut writer = IpcCompressionWriter::new_with_codec(&mut buf, codec)
+                .try_with_arrow_ipc_format(true)?;
+            for test_array in &test_arrays {
+                writer.write_batch(test_array.len(), &[test_array.clone()])?;
+            }
+            writer.finish_current_buf()?;
+            stream_lens.push(buf.len() - stream_lens.iter().sum::<usize>());
+        }
+        let mut writer = IpcCompressionWriter::new(&mut buf);
+        for test_array in &test_arrays {
+            writer.write_batch(test_array.len(), &[test_array.clone()])?;
+        }
+        writer.finish_current_buf()?;
+
+        // each stream can be read by standard arrow ipc reader after the block
+        // header
+        let mut offset = 0;
+        for stream_len in stream_lens {
+            let header: &[u8; BLOCK_HEADER_LEN] = buf[offset..][..BLOCK_HEADER_LEN].try_into()?;
+            assert!(is_arrow_ipc_block_header(header));
+            let stream = Cursor::new(&buf[offset..][BLOCK_HEADER_LEN..stream_len]);
+            let batches = StreamReader::try_new(stream, None)?.collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(batches.len(), test_arrays.len());
+            assert_eq!(batches[0].column(0), &test_arrays[0]);
+            offset += stream_len;
+        }
+
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        for _ in 0..4 {
+            for test_array in &test_arrays {
+                let (num_rows, arrays) = reader.read_batch(&schema)?.unwrap();
+                assert_eq!(num_rows, test_array.len());
+                assert_eq!(&arrays, &[test_array.clone()]);
+            }
+        }
+        assert!(reader.read_batch(&schema)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipc_compression_checksum() -> Result<(), Box<dyn Error>> {
+        let schema = Arc::new(Schema::new(vec![Field::new("", DataType::Utf8, false)]));
+        let test_array: ArrayRef = Arc::new(StringArray::from_iter_values(
+            (0..1000).map(|i| format!("value-{i}")),
+        ));
+        let mut buf = vec![];
+        let mut writer = IpcCompressionWriter::new(&mut buf);
+        writer.write_batch(test_array.len(), &[test_array.clone()])?;
+        writer.finish_current_buf()?;
+
+        // flipped bit in payload
+        let mut corrupted = buf.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0x01;
+        let mut reader = IpcCompressionReader::new(Cursor::new(corrupted));
+        let err = reader.read_batch(&schema).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+
+        // truncated block
+        let truncated = buf[..buf.len() - 10].to_vec();
+        let mut reader = IpcCompressionReader::new(Cursor::new(truncated));
+        let err = reader.read_batch(&schema).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{err}");
+
+        // flipped bit in arrow ipc block
+        let mut buf = vec![];
+        let mut writer = IpcCompressionWriter::new(&mut buf).try_with_arrow_ipc_format(true)?;
+        writer.write_batch(test_array.len(), &[test_array.clone()])?;
+        writer.finish_current_buf()?;
+        let last = buf.len() - 1;
+        buf[last] ^= 0x01;
+        let mut reader = IpcCompressionReader::new(Cursor::new(buf));
+        let err = reader.read_batch(&schema).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+        Ok(())
+    }
 }
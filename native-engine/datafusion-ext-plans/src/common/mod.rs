@@ -19,6 +19,7 @@ pub mod ipc_compression;
 pub mod offsetted;
 pub mod stream_exec;
 pub mod timer_helper;
+pub mod trace;
 
 pub trait SliceAsRawBytes {
     fn as_raw_bytes<'a>(&self) -> &'a [u8];
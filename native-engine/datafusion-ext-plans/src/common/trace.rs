@@ -0,0 +1,235 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc,
+    },
+    time::Instant,
+};
+
+use blaze_jni_bridge::{
+    conf,
+    conf::{BooleanConf, StringConf},
+    is_jni_bridge_inited,
+};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// max number of events recorded in a task, later events are dropped
+const MAX_TRACE_EVENTS: usize = 1000000;
+
+/// returns true if tasks should record execution traces
+pub fn trace_enabled() -> bool {
+    static ENABLED: OnceCell<bool> = OnceCell::new();
+    *ENABLED.get_or_init(|| is_jni_bridge_inited() && conf::TRACE_ENABLE.value().unwrap_or(false))
+}
+
+/// directory of trace files, system temp dir is used if not configured
+pub fn trace_dir() -> PathBuf {
+    if is_jni_bridge_inited() {
+        if let Ok(dir) = conf::TRACE_DIR.value() {
+            if !dir.trim().is_empty() {
+                return PathBuf::from(dir.trim());
+            }
+        }
+    }
+    std::env::temp_dir()
+}
+
+/// records spans of all operators in a task and exports them in chrome trace
+/// event format, which can be viewed in chrome://tracing or perfetto.
+/// each operator is displayed as a thread in the timeline.
+pub struct TaskTracer {
+    start_time: Instant,
+    lanes: Mutex<Vec<String>>,
+    events: Mutex<Vec<TraceEvent>>,
+    num_dropped_events: AtomicUsize,
+}
+
+#[derive(Serialize)]
+struct TraceEvent {
+    name: Cow<'static, str>,
+    ph: &'static str,
+    ts: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s: Option<&'static str>,
+    pid: usize,
+    tid: usize,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    args: Value,
+}
+
+impl TaskTracer {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            start_time: Instant::now(),
+            lanes: Mutex::default(),
+            events: Mutex::default(),
+            num_dropped_events: AtomicUsize::new(0),
+        })
+    }
+
+    /// creates a new lane for an operator, events of different lanes are
+    /// displayed in different rows
+    pub fn new_lane(self: &Arc<Self>, name: impl Into<String>) -> TraceLane {
+        let mut lanes = self.lanes.lock();
+        lanes.push(name.into());
+        TraceLane {
+            tracer: self.clone(),
+            tid: lanes.len(),
+        }
+    }
+
+    pub fn num_events(&self) -> usize {
+        self.events.lock().len()
+    }
+
+    fn record(&self, event: TraceEvent) {
+        let mut events = self.events.lock();
+        if events.len() >= MAX_TRACE_EVENTS {
+            self.num_dropped_events.fetch_add(1, Relaxed);
+            return;
+        }
+        events.push(event);
+    }
+
+    fn micros_since_start(&self, time: Instant) -> u64 {
+        time.saturating_duration_since(self.start_time).as_micros() as u64
+    }
+
+    /// writes all recorded events as a chrome trace json file
+    pub fn write_to_file(&self, path: &Path, process_name: &str) -> std::io::Result<()> {
+        let mut metadata = vec![TraceEvent {
+            name: "process_name".into(),
+            ph: "M",
+            ts: 0,
+            dur: None,
+            s: None,
+            pid: 0,
+            tid: 0,
+            args: json!({ "name": process_name }),
+        }];
+        for (i, lane_name) in self.lanes.lock().iter().enumerate() {
+            metadata.push(TraceEvent {
+                name: "thread_name".into(),
+                ph: "M",
+                ts: 0,
+                dur: None,
+                s: None,
+                pid: 0,
+                tid: i + 1,
+                args: json!({ "name": lane_name }),
+            });
+        }
+
+        let events = self.events.lock();
+        let trace = json!({
+            "traceEvents": metadata.iter().chain(events.iter()).collect::<Vec<_>>(),
+            "displayTimeUnit": "ms",
+            "otherData": { "droppedEvents": self.num_dropped_events.load(Relaxed) },
+        });
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, &trace)?;
+        writer.flush()
+    }
+}
+
+/// events recorder of a single operator
+#[derive(Clone)]
+pub struct TraceLane {
+    tracer: Arc<TaskTracer>,
+    tid: usize,
+}
+
+impl TraceLane {
+    /// records a span starting from start_time and ending now
+    pub fn complete(&self, name: impl Into<Cow<'static, str>>, start_time: Instant, args: Value) {
+        let ts = self.tracer.micros_since_start(start_time);
+        let end = self.tracer.micros_since_start(Instant::now());
+        self.tracer.record(TraceEvent {
+            name: name.into(),
+            ph: "X",
+            ts,
+            dur: Some(end.saturating_sub(ts)),
+            s: None,
+            pid: 0,
+            tid: self.tid,
+            args,
+        });
+    }
+
+    /// records a point-in-time event
+    pub fn instant(&self, name: impl Into<Cow<'static, str>>, args: Value) {
+        self.tracer.record(TraceEvent {
+            name: name.into(),
+            ph: "i",
+            ts: self.tracer.micros_since_start(Instant::now()),
+            dur: None,
+            s: Some("t"),
+            pid: 0,
+            tid: self.tid,
+            args,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn test_task_tracer() -> std::io::Result<()> {
+        let tracer = TaskTracer::new();
+        let sort_lane = tracer.new_lane("Sort");
+        let agg_lane = tracer.new_lane("Agg");
+
+        let start_time = Instant::now();
+        sort_lane.complete("spill", start_time, json!({ "mem_used": 1024 }));
+        agg_lane.instant("first batch", Value::Null);
+        assert_eq!(tracer.num_events(), 2);
+
+        let path = tempfile::NamedTempFile::new()?.into_temp_path();
+        tracer.write_to_file(&path, "stage 1 partition 2")?;
+        let mut content = String::new();
+        File::open(&path)?.read_to_string(&mut content)?;
+        let trace: Value = serde_json::from_str(&content)?;
+
+        // metadata of process and lanes are followed by recorded events
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0]["args"]["name"], "stage 1 partition 2");
+        assert_eq!(events[1]["args"]["name"], "Sort");
+        assert_eq!(events[2]["args"]["name"], "Agg");
+        assert_eq!(events[3]["name"], "spill");
+        assert_eq!(events[3]["ph"], "X");
+        assert_eq!(events[3]["tid"], 1);
+        assert_eq!(events[3]["args"]["mem_used"], 1024);
+        assert_eq!(events[4]["name"], "first batch");
+        assert_eq!(events[4]["tid"], 2);
+        assert!(events[4].get("args").is_none());
+        Ok(())
+    }
+}
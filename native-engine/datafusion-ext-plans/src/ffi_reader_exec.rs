@@ -39,7 +39,7 @@ use datafusion_ext_commons::arrow::array_size::ArraySize;
 use jni::objects::GlobalRef;
 use once_cell::sync::OnceCell;
 
-use crate::common::execution_context::ExecutionContext;
+use crate::{common::execution_context::ExecutionContext, memmgr::reservation::MemReservation};
 
 pub struct FFIReaderExec {
     num_partitions: usize,
@@ -156,6 +156,10 @@ fn read_ffi(
             }
             let exporter = AutoCloseableExporter(exporter);
 
+            // imported batches are allocated by the exporter outside the mem
+            // manager, account the batch being output
+            let mem_reservation = MemReservation::new("FFIReader", 0);
+
             loop {
                 let batch = {
                     // load batch from ffi
@@ -184,6 +188,7 @@ fn read_ffi(
                         &RecordBatchOptions::new().with_row_count(Some(struct_array.len())),
                     )?;
                     size_counter.add(batch.get_array_mem_size());
+                    mem_reservation.resize(batch.get_array_mem_size());
                     exec_ctx_cloned
                         .baseline_metrics()
                         .record_output(batch.num_rows());
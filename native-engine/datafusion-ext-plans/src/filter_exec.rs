@@ -174,6 +174,7 @@ fn execute_filter(
     exec_ctx: Arc<ExecutionContext>,
 ) -> Result<SendableRecordBatchStream> {
     let input_schema = input.schema();
+    exec_ctx.register_spark_udf_metrics(&predicates);
     let cached_exprs_evaluator =
         CachedExprsEvaluator::try_new(predicates, vec![], input_schema.clone())?;
 
@@ -192,6 +192,7 @@ fn execute_generate(
         .clone()
         .output_with_sender("Generate", move |sender| async move {
             sender.exclude_time(exec_ctx.baseline_metrics().elapsed_compute());
+            sender.limit_output_rows();
             let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
             let last_child_outputs: Arc<Mutex<Option<Vec<ArrayRef>>>> = Arc::default();
             while let Some(batch) = exec_ctx
@@ -218,6 +219,7 @@ fn execute_generate(
                 // split batch into smaller slice to avoid too much memory usage
                 let slice_step = batch_size.sqrt().max(1);
                 for slice_start in (0..batch.num_rows()).step_by(slice_step) {
+                    exec_ctx.check_cancelled()?;
                     let slice_len = slice_step.min(batch.num_rows().saturating_sub(slice_start));
                     let slice = batch.slice(slice_start, slice_len);
 
@@ -36,7 +36,7 @@ use datafusion::{
     common::DataFusionError,
     error::Result,
     execution::context::TaskContext,
-    physical_expr::EquivalenceProperties,
+    physical_expr::{EquivalenceProperties, PhysicalSortExpr},
     physical_plan::{
         metrics::{ExecutionPlanMetricsSet, MetricsSet},
         DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan,
@@ -52,13 +52,20 @@ use jni::objects::{GlobalRef, JObject};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 
-use crate::common::{execution_context::ExecutionContext, ipc_compression::IpcCompressionReader};
+use crate::{
+    common::{execution_context::ExecutionContext, ipc_compression::IpcCompressionReader},
+    shuffle::{
+        local_reader::open_local_shuffle_segment,
+        sorted_merge::{ipc_sorted_run, ShuffleSortKeys, SortedRunsCollector},
+    },
+};
 
 #[derive(Debug, Clone)]
 pub struct IpcReaderExec {
     pub num_partitions: usize,
     pub ipc_provider_resource_id: String,
     pub schema: SchemaRef,
+    pub sort_exprs: Vec<PhysicalSortExpr>,
     pub metrics: ExecutionPlanMetricsSet,
     props: OnceCell<PlanProperties>,
 }
@@ -67,11 +74,13 @@ impl IpcReaderExec {
         num_partitions: usize,
         ipc_provider_resource_id: String,
         schema: SchemaRef,
+        sort_exprs: Vec<PhysicalSortExpr>,
     ) -> IpcReaderExec {
         IpcReaderExec {
             num_partitions,
             ipc_provider_resource_id,
             schema,
+            sort_exprs,
             metrics: ExecutionPlanMetricsSet::new(),
             props: OnceCell::new(),
         }
@@ -80,7 +89,11 @@ impl IpcReaderExec {
 
 impl DisplayAs for IpcReaderExec {
     fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
-        write!(f, "IpcReader: [{:?}]", &self.schema)
+        write!(f, "IpcReader: [{:?}]", &self.schema)?;
+        if !self.sort_exprs.is_empty() {
+            write!(f, ", sort_exprs={:?}", self.sort_exprs)?;
+        }
+        Ok(())
     }
 }
 
@@ -120,6 +133,7 @@ impl ExecutionPlan for IpcReaderExec {
             self.num_partitions,
             self.ipc_provider_resource_id.clone(),
             self.schema.clone(),
+            self.sort_exprs.clone(),
         )))
     }
 
@@ -143,6 +157,15 @@ impl ExecutionPlan for IpcReaderExec {
         assert!(!blocks_local.as_obj().is_null());
 
         let blocks = jni_new_global_ref!(blocks_local.as_obj())?;
+
+        // key-sorted shuffle: each block is a sorted map output
+        if !self.sort_exprs.is_empty() {
+            let sort_keys = Arc::new(ShuffleSortKeys::try_new(
+                self.sort_exprs.clone(),
+                &self.schema,
+            )?);
+            return read_ipc_sorted(blocks, exec_ctx.clone(), sort_keys);
+        }
         read_ipc(blocks, exec_ctx.clone())
     }
 
@@ -186,19 +209,16 @@ fn read_ipc(
                 .expect("tokio spawn_blocking error")?
             } {
                 // get ipc reader
-                let mut reader = tokio::task::spawn_blocking(move || {
-                    if jni_call!(BlazeBlockObject(block.as_obj()).hasFileSegment() -> bool)? {
-                        return get_file_reader(block.as_obj());
-                    }
-                    if jni_call!(BlazeBlockObject(block.as_obj()).hasByteBuffer() -> bool)? {
-                        return get_byte_buffer_reader(block.as_obj());
-                    }
-                    get_channel_reader(block.as_obj())
-                })
-                .await
-                .expect("tokio spawn_blocking error")?;
-
-                while let Some((num_rows, cols)) = reader.read_batch(&exec_ctx.output_schema())? {
+                let block_cloned = block.clone();
+                let mut reader =
+                    tokio::task::spawn_blocking(move || get_block_reader(block_cloned.as_obj()))
+                        .await
+                        .expect("tokio spawn_blocking error")?;
+
+                while let Some((num_rows, cols)) = reader
+                    .read_batch(&exec_ctx.output_schema())
+                    .map_err(|err| fetch_failed_if_corrupted(&block, err))?
+                {
                     let (cur_staging_num_rows, cur_staging_mem_size) = {
                         let staging_cols_cloned = staging_cols.clone();
                         let mut staging_cols = staging_cols_cloned.lock();
@@ -254,6 +274,98 @@ fn read_ipc(
         }))
 }
 
+// reads all blocks as sorted runs and k-way merges them by sort keys, so that
+// the output partition is sorted without re-sorting in downstream operators
+fn read_ipc_sorted(
+    blocks: GlobalRef,
+    exec_ctx: Arc<ExecutionContext>,
+    sort_keys: Arc<ShuffleSortKeys>,
+) -> Result<SendableRecordBatchStream> {
+    let size_counter = exec_ctx.register_counter_metric("size");
+
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("IpcReader", move |sender| async move {
+            sender.exclude_time(exec_ctx.baseline_metrics().elapsed_compute());
+            log::info!("start sorted ipc reading");
+
+            let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+            let schema = exec_ctx.output_schema();
+
+            // blocks exceeding the max merging fan-in are merged into spills
+            // before the final merging
+            let collector_exec_ctx = exec_ctx.clone();
+            let mut merger = tokio::task::spawn_blocking(move || {
+                let mut collector =
+                    SortedRunsCollector::new(collector_exec_ctx, sort_keys, schema.clone());
+                let mut num_blocks = 0;
+                while jni_call!(ScalaIterator(blocks.as_obj()).hasNext() -> bool)? {
+                    let block = jni_call!(ScalaIterator(blocks.as_obj()).next() -> JObject)?;
+                    let block = jni_new_global_ref!(block.as_obj())?;
+                    let reader = get_block_reader(block.as_obj())?;
+                    let run = ipc_sorted_run(reader, schema.clone()).map(move |batch| {
+                        batch.map_err(|err| fetch_failed_if_corrupted(&block, err))
+                    });
+                    collector.push(Box::new(run))?;
+                    num_blocks += 1;
+                }
+                log::info!(
+                    "merging {num_blocks} sorted blocks in {} runs",
+                    collector.num_runs()
+                );
+                collector.finish()
+            })
+            .await
+            .expect("tokio spawn_blocking error")?;
+
+            loop {
+                let (returned_merger, batch) = tokio::task::spawn_blocking(move || {
+                    let batch = merger.next_batch();
+                    (merger, batch)
+                })
+                .await
+                .expect("tokio spawn_blocking error");
+                merger = returned_merger;
+
+                let Some(batch) = batch? else {
+                    break;
+                };
+                size_counter.add(batch.get_array_mem_size());
+                exec_ctx.baseline_metrics().record_output(batch.num_rows());
+                sender.send(batch).await;
+            }
+            Ok(())
+        }))
+}
+
+// corrupted blocks are reported as fetch failures, so that spark regenerates
+// the map outputs instead of failing the task
+fn fetch_failed_if_corrupted(block: &GlobalRef, err: DataFusionError) -> DataFusionError {
+    if !err.to_string().contains("block checksum mismatch") {
+        return err;
+    }
+    let thrown = jni_new_string!(err.to_string()).and_then(|message| {
+        jni_call!(BlazeBlockObject(block.as_obj()).throwFetchFailed(message.as_obj()) -> ())
+    });
+    match thrown {
+        Err(fetch_failed) => fetch_failed,
+        Ok(()) => err,
+    }
+}
+
+fn get_block_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
+    if jni_call!(BlazeBlockObject(block).hasShuffleFiles() -> bool)? {
+        return get_local_shuffle_reader(block);
+    }
+    if jni_call!(BlazeBlockObject(block).hasFileSegment() -> bool)? {
+        return get_file_reader(block);
+    }
+    if jni_call!(BlazeBlockObject(block).hasByteBuffer() -> bool)? {
+        return get_byte_buffer_reader(block);
+    }
+    get_channel_reader(block)
+}
+
 fn get_channel_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
     let channel_reader = ReadableByteChannelReader::try_new(block)?;
     Ok(IpcCompressionReader::new(Box::new(
@@ -274,6 +386,25 @@ fn get_file_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read +
     )))
 }
 
+// local shuffle blocks are read directly from shuffle data files, without
+// opening input streams in jvm
+fn get_local_shuffle_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
+    let data_file_path = jni_call!(BlazeBlockObject(block).getDataFilePath() -> JObject)?;
+    let data_file_path = jni_get_string!(data_file_path.as_obj().into())?;
+    let index_file_path = jni_call!(BlazeBlockObject(block).getIndexFilePath() -> JObject)?;
+    let index_file_path = jni_get_string!(index_file_path.as_obj().into())?;
+    let start_partition = jni_call!(BlazeBlockObject(block).getStartPartition() -> i32)?;
+    let end_partition = jni_call!(BlazeBlockObject(block).getEndPartition() -> i32)?;
+
+    let reader = open_local_shuffle_segment(
+        &data_file_path,
+        &index_file_path,
+        start_partition as usize,
+        end_partition as usize,
+    )?;
+    Ok(IpcCompressionReader::new(reader))
+}
+
 fn get_byte_buffer_reader(block: JObject) -> Result<IpcCompressionReader<Box<dyn Read + Send>>> {
     let byte_buffer = jni_call!(BlazeBlockObject(block).getByteBuffer() -> JObject)?;
     if jni_call!(JavaBuffer(byte_buffer.as_obj()).isDirect() -> bool)? {
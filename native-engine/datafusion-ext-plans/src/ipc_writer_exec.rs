@@ -35,7 +35,8 @@ use jni::objects::{GlobalRef, JObject};
 use once_cell::sync::OnceCell;
 
 use crate::common::{
-    execution_context::ExecutionContext, ipc_compression::IpcCompressionWriter,
+    execution_context::ExecutionContext,
+    ipc_compression::{zstd_dict_training_enabled, IpcCompressionWriter},
     timer_helper::TimerHelper,
 };
 
@@ -156,7 +157,8 @@ pub async fn write_ipc(
                 }
             }
 
-            let mut writer = IpcCompressionWriter::new(IpcConsumerWrite(ipc_consumer));
+            let mut writer = IpcCompressionWriter::new(IpcConsumerWrite(ipc_consumer))
+                .with_dictionary_training(zstd_dict_training_enabled());
             while let Some(batch) = exec_ctx
                 .baseline_metrics()
                 .elapsed_compute()
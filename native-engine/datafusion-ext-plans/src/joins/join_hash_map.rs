@@ -27,6 +27,8 @@ use arrow::{
 };
 use datafusion::{common::Result, physical_expr::PhysicalExprRef};
 use datafusion_ext_commons::{
+    arrow::array_size::ArraySize,
+    cancellation::CancellationToken,
     io::{read_len, read_raw_slice, write_len, write_raw_slice},
     prefetch_read_data,
     spark_hash::create_hashes,
@@ -36,6 +38,11 @@ use itertools::Itertools;
 use once_cell::sync::OnceCell;
 use unchecked_index::UncheckedIndex;
 
+use crate::memmgr::reservation::MemReservation;
+
+// number of items processed between cancellation checks when building tables
+const CANCEL_CHECK_INTERVAL: usize = 65536;
+
 // empty:  lead=0, value=0
 // range:  lead=0, value=start, mapped_indices[start-1]=len
 // single: lead=1, value=idx
@@ -95,24 +102,35 @@ struct Table {
 }
 
 impl Table {
-    fn create_from_key_columns(num_rows: usize, key_columns: &[ArrayRef]) -> Result<Self> {
+    fn mem_size(&self) -> usize {
+        self.map.capacity() * size_of::<MapValueGroup>()
+            + self.mapped_indices.capacity() * size_of::<u32>()
+    }
+
+    fn create_from_key_columns(
+        num_rows: usize,
+        key_columns: &[ArrayRef],
+        cancel_token: &CancellationToken,
+    ) -> Result<Self> {
         assert!(
             num_rows < 1073741824,
             "join hash table: number of rows exceeded 2^30: {num_rows}"
         );
         let hashes = join_create_hashes(num_rows, key_columns);
-        Self::craete_from_key_columns_and_hashes(num_rows, key_columns, hashes)
+        Self::craete_from_key_columns_and_hashes(num_rows, key_columns, hashes, cancel_token)
     }
 
     fn craete_from_key_columns_and_hashes(
         num_rows: usize,
         key_columns: &[ArrayRef],
         hashes: Vec<u32>,
+        cancel_token: &CancellationToken,
     ) -> Result<Self> {
         assert!(
             num_rows < 1073741824,
             "join hash table: number of rows exceeded 2^30: {num_rows}"
         );
+        cancel_token.check_cancelled()?;
 
         let key_is_valid = |row_idx| key_columns.iter().all(|col| col.is_valid(row_idx));
         let mut mapped_indices = unchecked!(vec![]);
@@ -132,6 +150,9 @@ impl Table {
             .chunk_by(|(_, hash)| *hash)
             .into_iter()
         {
+            if map_items.len() % CANCEL_CHECK_INTERVAL == 0 {
+                cancel_token.check_cancelled()?;
+            }
             let pos = mapped_indices.len() as u32;
             mapped_indices.push(0);
             mapped_indices.extend(chunk.map(|(idx, _hash)| idx));
@@ -166,6 +187,9 @@ impl Table {
 
         const PREFETCH_AHEAD: usize = 4;
         for i in 0..map_items.len() {
+            if i % CANCEL_CHECK_INTERVAL == 0 {
+                cancel_token.check_cancelled()?;
+            }
             if i + PREFETCH_AHEAD < map_items.len() {
                 prefetch_read_data!(&map[entries![i + PREFETCH_AHEAD] as usize]);
             }
@@ -284,6 +308,7 @@ pub struct JoinHashMap {
     data_batch: RecordBatch,
     key_columns: Vec<ArrayRef>,
     table: Table,
+    mem_reservation: Option<MemReservation>,
 }
 
 // safety: JoinHashMap is Send + Sync
@@ -300,6 +325,7 @@ impl JoinHashMap {
     pub fn create_from_data_batch(
         data_batch: RecordBatch,
         key_exprs: &[PhysicalExprRef],
+        cancel_token: &CancellationToken,
     ) -> Result<Self> {
         let key_columns: Vec<ArrayRef> = key_exprs
             .iter()
@@ -310,12 +336,14 @@ impl JoinHashMap {
             })
             .collect::<Result<_>>()?;
 
-        let table = Table::create_from_key_columns(data_batch.num_rows(), &key_columns)?;
+        let table =
+            Table::create_from_key_columns(data_batch.num_rows(), &key_columns, cancel_token)?;
 
         Ok(Self {
             data_batch,
             key_columns,
             table,
+            mem_reservation: None,
         })
     }
 
@@ -323,19 +351,25 @@ impl JoinHashMap {
         data_batch: RecordBatch,
         key_columns: Vec<ArrayRef>,
         hashes: Vec<u32>,
+        cancel_token: &CancellationToken,
     ) -> Result<Self> {
-        let table =
-            Table::craete_from_key_columns_and_hashes(data_batch.num_rows(), &key_columns, hashes)?;
+        let table = Table::craete_from_key_columns_and_hashes(
+            data_batch.num_rows(),
+            &key_columns,
+            hashes,
+            cancel_token,
+        )?;
 
         Ok(Self {
             data_batch,
             key_columns,
             table,
+            mem_reservation: None,
         })
     }
     pub fn create_empty(hash_map_schema: SchemaRef, key_exprs: &[PhysicalExprRef]) -> Result<Self> {
         let data_batch = RecordBatch::new_empty(hash_map_schema);
-        Self::create_from_data_batch(data_batch, key_exprs)
+        Self::create_from_data_batch(data_batch, key_exprs, &CancellationToken::default())
     }
 
     pub fn record_batch_contains_hash_map(batch: &RecordBatch) -> bool {
@@ -365,6 +399,7 @@ impl JoinHashMap {
             data_batch,
             key_columns,
             table,
+            mem_reservation: None,
         })
     }
 
@@ -390,6 +425,17 @@ impl JoinHashMap {
         )?)
     }
 
+    pub fn mem_size(&self) -> usize {
+        // key columns are mostly evaluated from data batch and share its
+        // buffers
+        self.data_batch.get_array_mem_size() + self.table.mem_size()
+    }
+
+    /// accounts memory of this hash map in the mem manager until dropped
+    pub fn set_mem_reservation(&mut self, mem_reservation: MemReservation) {
+        self.mem_reservation = Some(mem_reservation);
+    }
+
     pub fn data_schema(&self) -> SchemaRef {
         self.data_batch().schema()
     }
@@ -469,3 +515,32 @@ pub fn join_table_field() -> FieldRef {
         .get_or_init(|| Arc::new(Field::new("~TABLE", DataType::Binary, true)))
         .clone()
 }
+
+#[cfg(test)]
+mod test {
+    use arrow::array::Int32Array;
+    use datafusion::physical_expr::expressions::Column;
+
+    use super::*;
+
+    #[test]
+    fn test_create_with_cancellation() -> Result<()> {
+        let data_batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)])),
+            vec![Arc::new(Int32Array::from_iter_values(0..200000))],
+        )?;
+        let key_exprs: Vec<PhysicalExprRef> = vec![Arc::new(Column::new("a", 0))];
+
+        let cancel_token = CancellationToken::default();
+        let hash_map =
+            JoinHashMap::create_from_data_batch(data_batch.clone(), &key_exprs, &cancel_token)?;
+        assert!(!hash_map.is_empty());
+
+        // building is interrupted once cancelled
+        cancel_token.cancel();
+        let err =
+            JoinHashMap::create_from_data_batch(data_batch, &key_exprs, &cancel_token).unwrap_err();
+        assert!(err.to_string().contains("task cancelled"), "{err}");
+        Ok(())
+    }
+}
@@ -38,6 +38,9 @@ pub mod orc_exec;
 pub mod parquet_exec;
 pub mod parquet_sink_exec;
 pub mod project_exec;
+pub mod python_eval_exec;
+pub mod range_bounds_exec;
+pub mod range_sample_exec;
 pub mod rename_columns_exec;
 pub mod rss_shuffle_writer_exec;
 pub mod shuffle_writer_exec;
@@ -0,0 +1,196 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{sync::Arc, time::Instant};
+
+use blaze_jni_bridge::{conf, conf::IntConf, is_jni_bridge_inited};
+use datafusion::common::Result;
+use once_cell::sync::OnceCell;
+use serde_json::json;
+use tokio::{sync::Semaphore, task::JoinHandle};
+
+use crate::{
+    common::{timer_helper::TimerHelper, trace::TraceLane},
+    memmgr::{metrics::SpillMetrics, reservation::MemReservation},
+};
+
+// default max number of spills being written in background
+const DEFAULT_MAX_INFLIGHT_SPILLS: usize = 2;
+
+/// writes spills with background io tasks, so serializing, compressing and
+/// writing of frozen in-memory buffers overlap with input processing of the
+/// consumer.
+///
+/// number of in-flight spills is bounded globally to limit memory held by
+/// frozen buffers, submitting waits when the queue is full and the waiting
+/// time is recorded in spill metrics. frozen buffers are accounted in mem
+/// manager until they are written.
+pub struct AsyncSpillWriter<T> {
+    spill_metrics: SpillMetrics,
+    pending: Vec<JoinHandle<Result<T>>>,
+    pending_mem: Arc<MemReservation>,
+}
+
+impl<T: Send + 'static> AsyncSpillWriter<T> {
+    pub fn new(name: &str, spill_metrics: SpillMetrics) -> Self {
+        Self {
+            spill_metrics,
+            pending: vec![],
+            pending_mem: Arc::new(MemReservation::new(format!("{name}.PendingSpills"), 0)),
+        }
+    }
+
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// submits a spill writing task running in background. mem_size is the
+    /// memory of the frozen buffer, which is released after written. the
+    /// writing is recorded as a spill span if trace lane is given
+    pub async fn submit(
+        &mut self,
+        mem_size: usize,
+        trace_lane: Option<TraceLane>,
+        write_spill: impl FnOnce() -> Result<T> + Send + 'static,
+    ) {
+        self.pending_mem.grow(mem_size);
+        let pending_mem = self.pending_mem.clone();
+
+        let permit = self
+            .spill_metrics
+            .spill_queue_wait_time
+            .with_timer_async(async {
+                spill_permits()
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("spill permits closed")
+            })
+            .await;
+
+        self.pending.push(tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let spill_start_time = Instant::now();
+            let written = write_spill();
+            pending_mem.shrink(mem_size);
+            if let Some(trace_lane) = trace_lane {
+                trace_lane.complete("spill", spill_start_time, json!({ "mem_used": mem_size }));
+            }
+            written
+        }));
+    }
+
+    /// takes spills which have been written, in submission order. stops at the
+    /// first spill still being written, so later spills are never taken before
+    /// earlier ones
+    pub async fn take_finished(&mut self) -> Result<Vec<T>> {
+        let num_finished = self
+            .pending
+            .iter()
+            .take_while(|handle| handle.is_finished())
+            .count();
+        let mut finished = vec![];
+        for handle in self.pending.drain(..num_finished).collect::<Vec<_>>() {
+            finished.push(handle.await.expect("tokio spawn_blocking error")?);
+        }
+        Ok(finished)
+    }
+
+    /// waits all pending spills to be written, in submission order
+    pub async fn wait_all(&mut self) -> Result<Vec<T>> {
+        let mut finished = vec![];
+        for handle in std::mem::take(&mut self.pending) {
+            finished.push(handle.await.expect("tokio spawn_blocking error")?);
+        }
+        Ok(finished)
+    }
+}
+
+fn spill_permits() -> &'static Arc<Semaphore> {
+    static SPILL_PERMITS: OnceCell<Arc<Semaphore>> = OnceCell::new();
+    SPILL_PERMITS.get_or_init(|| {
+        let max_inflight = if is_jni_bridge_inited() {
+            conf::SPILL_ASYNC_MAX_INFLIGHT
+                .value()
+                .map(|v| v.max(1) as usize)
+                .unwrap_or(DEFAULT_MAX_INFLIGHT_SPILLS)
+        } else {
+            DEFAULT_MAX_INFLIGHT_SPILLS
+        };
+        Arc::new(Semaphore::new(max_inflight))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use datafusion::physical_plan::metrics::ExecutionPlanMetricsSet;
+    use datafusion_ext_commons::df_execution_err;
+
+    use super::*;
+    use crate::common::trace::TaskTracer;
+
+    #[tokio::test]
+    async fn test_async_spill_writer() -> Result<()> {
+        let metrics = ExecutionPlanMetricsSet::new();
+        let mut writer = AsyncSpillWriter::new("Test", SpillMetrics::new(&metrics, 0));
+
+        for i in 0..10 {
+            writer
+                .submit(0, None, move || {
+                    std::thread::sleep(Duration::from_millis(10 - i));
+                    Ok(i)
+                })
+                .await;
+        }
+        assert!(writer.num_pending() > 0);
+
+        // spills are taken in submission order even if later ones finish first
+        let mut spills = writer.take_finished().await?;
+        spills.extend(writer.wait_all().await?);
+        assert_eq!(writer.num_pending(), 0);
+        assert_eq!(spills, (0..10).collect::<Vec<_>>());
+
+        // finished spills after an unfinished one are kept pending
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        writer
+            .submit(0, None, move || {
+                rx.recv().expect("recv error");
+                Ok(0)
+            })
+            .await;
+        writer.submit(0, None, || Ok(1)).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(writer.take_finished().await?.is_empty());
+        assert_eq!(writer.num_pending(), 2);
+        tx.send(()).expect("send error");
+        assert_eq!(writer.wait_all().await?, vec![0, 1]);
+
+        // background writing is recorded in the trace lane
+        let tracer = TaskTracer::new();
+        writer
+            .submit(0, Some(tracer.new_lane("Test")), || Ok(0))
+            .await;
+        writer.wait_all().await?;
+        assert_eq!(tracer.num_events(), 1);
+
+        // errors are returned when waiting
+        writer
+            .submit(0, None, || df_execution_err!("spill error"))
+            .await;
+        assert!(writer.wait_all().await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,432 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashSet,
+    ffi::CString,
+    fs,
+    fs::{File, OpenOptions},
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+};
+
+use blaze_jni_bridge::{
+    conf,
+    conf::{IntConf, StringConf},
+    is_jni_bridge_inited,
+};
+use bytesize::ByteSize;
+use datafusion::common::Result;
+use datafusion_ext_commons::df_execution_err;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+
+const SPILL_DIR_PREFIX: &str = "blaze-spill-";
+const SPILL_DIR_LOCK_SUFFIX: &str = ".lock";
+
+// local dirs with less free space are skipped when creating spill files
+const MIN_FREE_SPACE: u64 = 256 << 20; // 256MB
+
+/// manages disk spill files of all tasks in the executor.
+///
+/// spill files are created in a per-executor subdirectory of each configured
+/// local dir in round-robin order, disk usage is limited by per-task and
+/// per-executor quotas, and files belonging to a task are deleted when the
+/// task is completed or cancelled.
+///
+/// each subdirectory is guarded by a lock file which is locked by the executor
+/// during its lifetime, so subdirectories left by crashed executors can be
+/// safely detected and deleted.
+pub struct DiskSpillManager {
+    dirs: Vec<PathBuf>,
+    next_dir_idx: AtomicUsize,
+    task_quota: usize,
+    executor_quota: usize,
+    executor_used: Arc<AtomicUsize>,
+    _dir_locks: Vec<File>,
+}
+
+impl DiskSpillManager {
+    pub fn get() -> &'static Self {
+        static DISK_SPILL_MANAGER: OnceCell<DiskSpillManager> = OnceCell::new();
+        DISK_SPILL_MANAGER.get_or_init(|| {
+            Self::try_new_from_conf().unwrap_or_else(|err| {
+                log::warn!("error creating disk spill manager, using default settings: {err}");
+                Self::new(vec![], 0, 0)
+            })
+        })
+    }
+
+    fn try_new_from_conf() -> Result<Self> {
+        if !is_jni_bridge_inited() {
+            return Ok(Self::new(vec![], 0, 0));
+        }
+        let dirs = conf::SPILL_LOCAL_DIRS
+            .value()?
+            .split(',')
+            .map(str::trim)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        let task_quota_mb = conf::SPILL_DISK_QUOTA_PER_TASK_MB.value()?.max(0) as usize;
+        let executor_quota_mb = conf::SPILL_DISK_QUOTA_PER_EXECUTOR_MB.value()?.max(0) as usize;
+        Ok(Self::new(
+            dirs,
+            task_quota_mb << 20,
+            executor_quota_mb << 20,
+        ))
+    }
+
+    /// creates a disk spill manager, quotas of 0 means unlimited
+    pub fn new(dirs: Vec<PathBuf>, task_quota: usize, executor_quota: usize) -> Self {
+        let executor_dir_name = format!("{SPILL_DIR_PREFIX}{}", uuid::Uuid::new_v4());
+        let mut executor_dirs = vec![];
+        let mut dir_locks = vec![];
+        for dir in dirs {
+            // spill dirs left by crashed executors are never deleted by
+            // themselves
+            remove_stale_spill_dirs(&dir);
+            match create_locked_dir(&dir, &executor_dir_name) {
+                Ok((executor_dir, dir_lock)) => {
+                    executor_dirs.push(executor_dir);
+                    dir_locks.push(dir_lock);
+                }
+                Err(err) => log::warn!("skipped spill dir {}: {err}", dir.display()),
+            }
+        }
+        let dirs = executor_dirs;
+
+        log::info!(
+            "disk spill manager initialized with dirs: {:?}, task quota: {}, executor quota: {}",
+            dirs,
+            display_quota(task_quota),
+            display_quota(executor_quota),
+        );
+
+        Self {
+            dirs,
+            next_dir_idx: AtomicUsize::new(0),
+            task_quota: unlimited_if_zero(task_quota),
+            executor_quota: unlimited_if_zero(executor_quota),
+            executor_used: Arc::default(),
+            _dir_locks: dir_locks,
+        }
+    }
+
+    pub fn executor_used(&self) -> usize {
+        self.executor_used.load(SeqCst)
+    }
+
+    /// creates a spill scope for a task, spill files and disk usage are
+    /// tracked in the scope. the scope is held in task resources and released
+    /// when the task is finished.
+    pub fn new_task_scope(&self) -> Arc<TaskSpillScope> {
+        Arc::new(TaskSpillScope {
+            task_quota: self.task_quota,
+            executor_quota: self.executor_quota,
+            task_used: AtomicUsize::new(0),
+            executor_used: self.executor_used.clone(),
+            files: Mutex::default(),
+            released: AtomicBool::new(false),
+        })
+    }
+
+    /// creates a spill file in configured local dirs, returns None if no dir
+    /// is configured
+    pub fn create_spill_file(&self) -> Result<Option<(File, String)>> {
+        if self.dirs.is_empty() {
+            return Ok(None);
+        }
+        let start_idx = self.next_dir_idx.fetch_add(1, SeqCst);
+        for i in 0..self.dirs.len() {
+            let dir = &self.dirs[(start_idx + i) % self.dirs.len()];
+            if available_space(dir).is_some_and(|space| space < MIN_FREE_SPACE) {
+                continue;
+            }
+            let file_name = format!("spill-{}", uuid::Uuid::new_v4());
+            let file_path = dir.join(file_name).to_string_lossy().to_string();
+            let file = OpenOptions::new() // create file and open under rw mode
+                .create_new(true)
+                .write(true)
+                .read(true)
+                .open(&file_path)?;
+            return Ok(Some((file, file_path)));
+        }
+        df_execution_err!(
+            "no spill dir has enough free space (at least {}): {:?}",
+            ByteSize(MIN_FREE_SPACE),
+            self.dirs,
+        )
+    }
+}
+
+/// spill files and disk usage of a task
+pub struct TaskSpillScope {
+    task_quota: usize,
+    executor_quota: usize,
+    task_used: AtomicUsize,
+    executor_used: Arc<AtomicUsize>,
+    files: Mutex<HashSet<String>>,
+    released: AtomicBool,
+}
+
+impl Drop for TaskSpillScope {
+    fn drop(&mut self) {
+        self.release_all();
+    }
+}
+
+impl TaskSpillScope {
+    pub fn task_used(&self) -> usize {
+        self.task_used.load(SeqCst)
+    }
+
+    /// reserves disk usage before writing spill data, returns error if task or
+    /// executor quota is exceeded
+    pub fn try_reserve(&self, size: usize) -> std::io::Result<()> {
+        let task_used = self.task_used.fetch_add(size, SeqCst) + size;
+        let executor_used = self.executor_used.fetch_add(size, SeqCst) + size;
+        if task_used > self.task_quota || executor_used > self.executor_quota {
+            self.release(size);
+            return Err(std::io::Error::other(format!(
+                "disk spill quota exceeded: task used {}/{}, executor used {}/{}",
+                ByteSize(task_used as u64),
+                display_quota(self.task_quota),
+                ByteSize(executor_used as u64),
+                display_quota(self.executor_quota),
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn release(&self, size: usize) {
+        if self.released.load(SeqCst) {
+            return; // all disk usage has been released with the task
+        }
+        self.task_used.fetch_sub(size, SeqCst);
+        self.executor_used.fetch_sub(size, SeqCst);
+    }
+
+    pub fn register_file(&self, file_path: &str) {
+        self.files.lock().insert(file_path.to_owned());
+    }
+
+    /// deletes a spill file, the file may have already been deleted if the
+    /// task is released
+    pub fn remove_file(&self, file_path: &str) {
+        if self.files.lock().remove(file_path) {
+            if let Err(err) = fs::remove_file(file_path) {
+                log::warn!("Was unable to delete spill file: {file_path}. error: {err}");
+            }
+        }
+    }
+
+    /// deletes all spill files of a completed or cancelled task
+    pub fn release_all(&self) {
+        if self.released.swap(true, SeqCst) {
+            return;
+        }
+        let task_used = self.task_used.swap(0, SeqCst);
+        self.executor_used.fetch_sub(task_used, SeqCst);
+
+        let files = std::mem::take(&mut *self.files.lock());
+        if !files.is_empty() {
+            log::info!("deleting {} spill files of released task", files.len());
+        }
+        for file_path in files {
+            if let Err(err) = fs::remove_file(&file_path) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Was unable to delete spill file: {file_path}. error: {err}");
+                }
+            }
+        }
+    }
+}
+
+fn unlimited_if_zero(quota: usize) -> usize {
+    if quota == 0 {
+        usize::MAX
+    } else {
+        quota
+    }
+}
+
+fn display_quota(quota: usize) -> String {
+    if quota == 0 || quota == usize::MAX {
+        return "unlimited".to_owned();
+    }
+    ByteSize(quota as u64).to_string()
+}
+
+fn available_space(dir: &Path) -> Option<u64> {
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    // safety: path is a valid c string and stat is a valid output buffer
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+// creates an executor spill dir under the local dir, the returned lock file is
+// locked until it is dropped
+fn create_locked_dir(dir: &Path, executor_dir_name: &str) -> std::io::Result<(PathBuf, File)> {
+    fs::create_dir_all(dir)?;
+
+    // the lock file is locked before it is renamed to the visible name, so
+    // other executors never see an unlocked lock file of a live executor
+    let lock_path = dir.join(format!("{executor_dir_name}{SPILL_DIR_LOCK_SUFFIX}"));
+    let tmp_lock_path = dir.join(format!(".{executor_dir_name}{SPILL_DIR_LOCK_SUFFIX}.tmp"));
+    let dir_lock = File::create(&tmp_lock_path)?;
+    if !try_lock_file(&dir_lock)? {
+        return Err(std::io::Error::other("cannot lock newly created lock file"));
+    }
+    fs::rename(&tmp_lock_path, &lock_path)?;
+
+    let executor_dir = dir.join(executor_dir_name);
+    fs::create_dir(&executor_dir)?;
+    Ok((executor_dir, dir_lock))
+}
+
+// tries to lock a file exclusively without blocking, the lock is released when
+// the file is closed, including when the process exits
+fn try_lock_file(file: &File) -> std::io::Result<bool> {
+    // safety: fd is valid during the lifetime of file
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        return Ok(false);
+    }
+    Err(err)
+}
+
+// removes spill dirs created by executors which are no longer alive, that is,
+// whose lock files are not locked
+fn remove_stale_spill_dirs(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(executor_dir_name) = file_name
+            .strip_prefix(SPILL_DIR_PREFIX)
+            .and_then(|suffix| suffix.strip_suffix(SPILL_DIR_LOCK_SUFFIX))
+            .map(|uuid| format!("{SPILL_DIR_PREFIX}{uuid}"))
+        else {
+            continue;
+        };
+        let lock_path = entry.path();
+        let stale = match File::open(&lock_path).and_then(|lock| try_lock_file(&lock)) {
+            Ok(stale) => stale,
+            Err(err) => {
+                log::warn!(
+                    "error locking spill dir lock {}: {err}",
+                    lock_path.display()
+                );
+                false
+            }
+        };
+        if stale {
+            let executor_dir = dir.join(executor_dir_name);
+            let result = match fs::remove_dir_all(&executor_dir) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+                _ => fs::remove_file(&lock_path),
+            };
+            match result {
+                Ok(()) => log::info!("removed stale spill dir: {}", executor_dir.display()),
+                Err(err) => log::warn!(
+                    "Was unable to delete stale spill dir: {}. error: {err}",
+                    executor_dir.display()
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_disk_spill_manager() -> Result<()> {
+        let dir1 = tempfile::tempdir()?;
+        let dir2 = tempfile::tempdir()?;
+
+        // stale spill dir of a dead executor, whose lock file is not locked
+        let stale_dir = dir1.path().join(format!("{SPILL_DIR_PREFIX}stale"));
+        fs::create_dir(&stale_dir)?;
+        File::create(stale_dir.join("spill-0"))?;
+        File::create(
+            dir1.path()
+                .join(format!("{SPILL_DIR_PREFIX}stale{SPILL_DIR_LOCK_SUFFIX}")),
+        )?;
+
+        // spill dir of a live executor is kept
+        let live_manager = DiskSpillManager::new(vec![dir1.path().to_path_buf()], 0, 0);
+        let (_file, live_file_path) = live_manager.create_spill_file()?.expect("dirs configured");
+
+        let manager = DiskSpillManager::new(
+            vec![dir1.path().to_path_buf(), dir2.path().to_path_buf()],
+            1000,
+            1500,
+        );
+        assert!(!stale_dir.exists());
+        assert!(Path::new(&live_file_path).exists());
+
+        // spill files are created in dirs in round-robin order
+        let scope1 = manager.new_task_scope();
+        let scope2 = manager.new_task_scope();
+        let mut file_paths = vec![];
+        for scope in [&scope1, &scope1, &scope2] {
+            let (_file, file_path) = manager.create_spill_file()?.expect("dirs configured");
+            scope.register_file(&file_path);
+            file_paths.push(file_path);
+        }
+        assert!(file_paths[0].starts_with(manager.dirs[0].to_str().unwrap()));
+        assert!(file_paths[1].starts_with(manager.dirs[1].to_str().unwrap()));
+        assert!(file_paths[2].starts_with(manager.dirs[0].to_str().unwrap()));
+        assert!(!file_paths[0].starts_with(live_manager.dirs[0].to_str().unwrap()));
+
+        // task and executor quotas are enforced
+        scope1.try_reserve(800)?;
+        assert!(scope1.try_reserve(300).is_err());
+        scope2.try_reserve(600)?;
+        assert!(scope2.try_reserve(200).is_err());
+        assert_eq!(manager.executor_used(), 1400);
+        scope1.release(800);
+        scope2.try_reserve(200)?;
+        assert_eq!(scope1.task_used(), 0);
+        assert_eq!(scope2.task_used(), 800);
+
+        // removed file is not deleted again
+        scope1.remove_file(&file_paths[0]);
+        assert!(!Path::new(&file_paths[0]).exists());
+
+        // all files are deleted when task is released or its scope is dropped
+        scope1.release_all();
+        assert!(!Path::new(&file_paths[1]).exists());
+        assert!(Path::new(&file_paths[2]).exists());
+        drop(scope2);
+        assert!(!Path::new(&file_paths[2]).exists());
+        assert_eq!(manager.executor_used(), 0);
+        Ok(())
+    }
+}
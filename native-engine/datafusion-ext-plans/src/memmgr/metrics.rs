@@ -23,6 +23,7 @@ pub struct SpillMetrics {
     pub mem_spill_iotime: Time,
     pub disk_spill_size: Gauge,
     pub disk_spill_iotime: Time,
+    pub spill_queue_wait_time: Time,
 }
 
 impl SpillMetrics {
@@ -35,6 +36,8 @@ impl SpillMetrics {
             disk_spill_size: MetricBuilder::new(metrics).gauge("disk_spill_size", partition),
             disk_spill_iotime: MetricBuilder::new(metrics)
                 .subset_time("disk_spill_iotime", partition),
+            spill_queue_wait_time: MetricBuilder::new(metrics)
+                .subset_time("spill_queue_wait_time", partition),
         }
     }
 }
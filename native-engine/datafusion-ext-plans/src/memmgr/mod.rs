@@ -12,12 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod async_spill;
+pub mod disk_spill;
 pub mod metrics;
+pub mod policy;
+pub mod reservation;
 pub mod spill;
 
 use std::{
+    collections::VecDeque,
     sync::{Arc, Weak},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
@@ -26,15 +31,30 @@ use bytesize::ByteSize;
 use datafusion::common::Result;
 use once_cell::sync::OnceCell;
 use parking_lot::{Condvar, Mutex};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    common::trace::TraceLane,
+    memmgr::policy::{
+        create_mem_policy, FairSharePolicy, MemConsumerStat, MemOperation, MemPolicy,
+        MemPolicyContext, MEM_PRIORITY_NORMAL,
+    },
+};
 
 static MEM_MANAGER: OnceCell<Arc<MemManager>> = OnceCell::new();
 
 // never triggers waiting/spilling for consumers which use very little memory
 const MIN_TRIGGER_SIZE: usize = 1 << 24; // 16MB
 
+// max number of deregistered consumers kept for status reports
+const MAX_FINISHED_CONSUMERS: usize = 100;
+
 pub struct MemManager {
     total: usize,
+    policy: Box<dyn MemPolicy>,
     consumers: Mutex<Vec<Arc<MemConsumerInfo>>>,
+    finished_consumers: Mutex<VecDeque<MemConsumerSnapshot>>,
     status: Mutex<MemManagerStatus>,
     cv: Condvar,
 }
@@ -42,14 +62,21 @@ pub struct MemManager {
 impl MemManager {
     pub fn init(total: usize) {
         MEM_MANAGER.get_or_init(|| {
+            let policy = create_mem_policy().unwrap_or_else(|err| {
+                log::warn!("mem manager: error creating policy, using fair-share: {err}");
+                Box::new(FairSharePolicy)
+            });
             log::info!(
-                "mem manager initialized with total memory: {}",
+                "mem manager initialized with total memory: {}, policy: {}",
                 ByteSize(total as u64),
+                policy.name(),
             );
 
             Arc::new(MemManager {
                 total,
+                policy,
                 consumers: Mutex::default(),
+                finished_consumers: Mutex::default(),
                 status: Mutex::default(),
                 cv: Condvar::default(),
             })
@@ -77,13 +104,13 @@ impl MemManager {
     }
 
     pub fn register_consumer(mut consumer: Arc<dyn MemConsumer>, spillable: bool) {
-        let consumer_info = Arc::new(MemConsumerInfo {
-            name: consumer.name().to_owned(),
-            status: Mutex::new(MemConsumerStatus {
-                mem_used: 0,
-                spillable,
-            }),
-        });
+        let consumer_info = Arc::new(MemConsumerInfo::new(
+            consumer.name().to_owned(),
+            consumer.mem_stage_id(),
+            consumer.mem_partition_id(),
+            consumer.mem_priority(),
+            spillable,
+        ));
         log::info!("mem manager registering consumer: {}", consumer.name());
 
         // safety:
@@ -92,23 +119,26 @@ impl MemManager {
             let consumer_mut = Arc::get_mut_unchecked(&mut consumer);
             consumer_mut.set_consumer_info(Arc::downgrade(&consumer_info));
         }
+        Self::get().add_consumer_info(consumer_info);
+    }
 
-        let mm = Self::get();
-        let mut mm_consumers = mm.consumers.lock();
-        let mut mm_status = mm.status.lock();
-        mm_consumers.push(consumer_info);
-        mm_status.num_consumers += 1;
-        if spillable {
+    pub fn deregister_consumer(consumer: &dyn MemConsumer) {
+        Self::get().remove_consumer_info(&consumer.consumer_info());
+    }
+
+    fn add_consumer_info(&self, consumer_info: Arc<MemConsumerInfo>) {
+        let mut mm_consumers = self.consumers.lock();
+        let mut mm_status = self.status.lock();
+        if consumer_info.status.lock().spillable {
             mm_status.num_spillables += 1;
         }
+        mm_consumers.push(consumer_info);
+        mm_status.num_consumers += 1;
     }
 
-    pub fn deregister_consumer(consumer: &dyn MemConsumer) {
-        let mm = Self::get();
-        let mut mm_consumers = mm.consumers.lock();
-        let mut mm_status = mm.status.lock();
-
-        let consumer_info = consumer.consumer_info();
+    fn remove_consumer_info(&self, consumer_info: &Arc<MemConsumerInfo>) {
+        let mut mm_consumers = self.consumers.lock();
+        let mut mm_status = self.status.lock();
         let consumer_status = consumer_info.status.lock();
 
         // update mm status
@@ -125,12 +155,21 @@ impl MemManager {
 
         // remove consumer info
         for i in 0..mm_consumers.len() {
-            if Arc::ptr_eq(&mm_consumers[i], &consumer_info) {
-                log::info!("mem manager deregistered consumer: {}", consumer.name());
+            if Arc::ptr_eq(&mm_consumers[i], consumer_info) {
+                log::info!("mem manager deregistered consumer: {}", consumer_info.name);
                 mm_consumers.swap_remove(i);
 
+                let finished = consumer_info.snapshot(&consumer_status);
+                drop(consumer_status);
                 drop(mm_status);
                 drop(mm_consumers);
+
+                // keep status of recently finished consumers for reports
+                let mut finished_consumers = self.finished_consumers.lock();
+                if finished_consumers.len() >= MAX_FINISHED_CONSUMERS {
+                    finished_consumers.pop_front();
+                }
+                finished_consumers.push_back(finished);
                 return;
             }
         }
@@ -150,21 +189,110 @@ impl MemManager {
         for consumer in &*self.consumers.lock() {
             let consumer_status = consumer.status.lock();
             log::info!(
-                "* consumer: {}, spillable: {}, mem_used: {}",
+                "* consumer: {}, spillable: {}, priority: {}, mem_used: {}",
                 consumer.name,
                 consumer_status.spillable,
+                consumer.priority,
                 ByteSize(consumer_status.mem_used as u64),
             );
         }
     }
+
+    // requests the largest spillable consumer to spill on its next memory
+    // update, used when memory overflows due to unspillable reservations
+    fn request_spill_largest(&self) {
+        let mm_consumers = self.consumers.lock();
+        let largest = mm_consumers
+            .iter()
+            .filter_map(|consumer| {
+                let consumer_status = consumer.status.lock();
+                let mem_used = consumer_status.mem_used;
+                (consumer_status.spillable && mem_used > 0).then_some((consumer, mem_used))
+            })
+            .max_by_key(|&(_, mem_used)| mem_used);
+
+        if let Some((consumer, mem_used)) = largest {
+            log::info!(
+                "mem manager requesting {} to spill (mem_used: {})",
+                consumer.name,
+                ByteSize(mem_used as u64),
+            );
+            consumer.request_spill();
+        }
+    }
+
+    /// collects memory status of all registered consumers and recently
+    /// finished consumers, used by status reports
+    pub fn snapshot(&self) -> MemManagerSnapshot {
+        let mm_status = *self.status.lock();
+        let consumers = self
+            .consumers
+            .lock()
+            .iter()
+            .map(|consumer| consumer.snapshot(&consumer.status.lock()))
+            .collect();
+        let finished_consumers = self.finished_consumers.lock().iter().cloned().collect();
+
+        MemManagerSnapshot {
+            total: self.total,
+            total_used: mm_status.total_used,
+            peak_total_used: mm_status.peak_total_used,
+            mem_spillables: mm_status.mem_spillables,
+            jvm_direct_used: get_mem_jvm_direct_used(),
+            num_spills: mm_status.num_spills,
+            spilled_mem: mm_status.spilled_mem,
+            policy: self.policy.name().to_owned(),
+            consumers,
+            finished_consumers,
+        }
+    }
+
+    // collects memory status of the growing consumer and all other spillable
+    // consumers, which is used by the policy to make decisions. infos of the
+    // spillable consumers are returned in the same order
+    fn policy_context(
+        &self,
+        total_used: usize,
+        total_managed: usize,
+        consumer_info: &Arc<MemConsumerInfo>,
+    ) -> (MemPolicyContext, MemConsumerStat, Vec<Arc<MemConsumerInfo>>) {
+        let consumer_stat = MemConsumerStat {
+            mem_used: consumer_info.status.lock().mem_used,
+            priority: consumer_info.priority,
+        };
+        let mut spillables = vec![consumer_stat];
+        let mut spillable_infos = vec![consumer_info.clone()];
+        for consumer in &*self.consumers.lock() {
+            if Arc::ptr_eq(consumer, consumer_info) {
+                continue;
+            }
+            let consumer_status = *consumer.status.lock();
+            if consumer_status.spillable {
+                spillables.push(MemConsumerStat {
+                    mem_used: consumer_status.mem_used,
+                    priority: consumer.priority,
+                });
+                spillable_infos.push(consumer.clone());
+            }
+        }
+        let ctx = MemPolicyContext {
+            total_used,
+            total_managed,
+            spillables,
+        };
+        (ctx, consumer_stat, spillable_infos)
+    }
 }
 
 #[derive(Default, Clone, Copy)]
 struct MemManagerStatus {
     num_consumers: usize,
     total_used: usize,
+    peak_total_used: usize,
     num_spillables: usize,
     mem_spillables: usize,
+    num_spills: usize,
+    spilled_mem: usize,
 }
 
 impl MemManagerStatus {
@@ -173,6 +301,7 @@ impl MemManagerStatus {
 
         let new_used = (self.total_used as isize + diff_used) as usize;
         let old_used = std::mem::replace(&mut self.total_used, new_used);
+        self.peak_total_used = self.peak_total_used.max(new_used);
 
         // freeing some memory, notifies all waiting growers
         if new_used < old_used {
@@ -185,13 +314,115 @@ impl MemManagerStatus {
 #[derive(Debug)]
 pub struct MemConsumerInfo {
     name: String,
+    stage_id: Option<usize>,
+    partition_id: Option<usize>,
+    priority: u32,
     status: Mutex<MemConsumerStatus>,
 }
 
+impl MemConsumerInfo {
+    fn new(
+        name: String,
+        stage_id: Option<usize>,
+        partition_id: Option<usize>,
+        priority: u32,
+        spillable: bool,
+    ) -> Self {
+        Self {
+            name,
+            stage_id,
+            partition_id,
+            priority,
+            status: Mutex::new(MemConsumerStatus {
+                mem_used: 0,
+                spillable,
+                peak_mem_used: 0,
+                num_spills: 0,
+                spilled_mem: 0,
+                force_spill: false,
+            }),
+        }
+    }
+
+    fn snapshot(&self, status: &MemConsumerStatus) -> MemConsumerSnapshot {
+        MemConsumerSnapshot {
+            name: self.name.clone(),
+            stage_id: self.stage_id,
+            partition_id: self.partition_id,
+            priority: self.priority,
+            spillable: status.spillable,
+            mem_used: status.mem_used,
+            peak_mem_used: status.peak_mem_used,
+            num_spills: status.num_spills,
+            spilled_mem: status.spilled_mem,
+        }
+    }
+
+    // requests the consumer to spill on its next memory update. returns false
+    // if the consumer is no longer spillable or has nothing to spill
+    fn request_spill(&self) -> bool {
+        let mut status = self.status.lock();
+        if status.spillable && status.mem_used > 0 {
+            status.force_spill = true;
+            return true;
+        }
+        false
+    }
+
+    /// records a spill written by the consumer for status reports. consumers
+    /// call this in their spill paths with the length of written spill data,
+    /// so spills triggered by both the mem manager and consumers themselves
+    /// are counted
+    pub fn record_spill(&self, spill_len: usize) {
+        {
+            let mut mm_status = MemManager::get().status.lock();
+            mm_status.num_spills += 1;
+            mm_status.spilled_mem += spill_len;
+        }
+        let mut consumer_status = self.status.lock();
+        consumer_status.num_spills += 1;
+        consumer_status.spilled_mem += spill_len;
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 struct MemConsumerStatus {
     mem_used: usize,
     spillable: bool,
+    peak_mem_used: usize,
+    num_spills: usize,
+    spilled_mem: usize,
+    force_spill: bool,
+}
+
+/// memory status of the mem manager and its consumers
+#[derive(Clone, Debug, Serialize)]
+pub struct MemManagerSnapshot {
+    pub total: usize,
+    pub total_used: usize,
+    pub peak_total_used: usize,
+    pub mem_spillables: usize,
+    pub jvm_direct_used: usize,
+    pub num_spills: usize,
+    pub spilled_mem: usize,
+    pub policy: String,
+    pub consumers: Vec<MemConsumerSnapshot>,
+    pub finished_consumers: Vec<MemConsumerSnapshot>,
+}
+
+/// memory status of a consumer, spilled_mem is the total length of spill data
+/// written by the consumer
+#[derive(Clone, Debug, Serialize)]
+pub struct MemConsumerSnapshot {
+    pub name: String,
+    pub stage_id: Option<usize>,
+    pub partition_id: Option<usize>,
+    pub priority: u32,
+    pub spillable: bool,
+    pub mem_used: usize,
+    pub peak_mem_used: usize,
+    pub num_spills: usize,
+    pub spilled_mem: usize,
 }
 
 #[async_trait]
@@ -200,6 +431,28 @@ pub trait MemConsumer: Send + Sync {
     fn set_consumer_info(&mut self, consumer_info: Weak<MemConsumerInfo>);
     fn get_consumer_info(&self) -> &Weak<MemConsumerInfo>;
 
+    /// priority of this consumer, consumers with higher priority keep more
+    /// memory under the priority policy
+    fn mem_priority(&self) -> u32 {
+        MEM_PRIORITY_NORMAL
+    }
+
+    /// stage of the task running this consumer, used in status reports
+    fn mem_stage_id(&self) -> Option<usize> {
+        None
+    }
+
+    /// partition of the task running this consumer, used in status reports
+    fn mem_partition_id(&self) -> Option<usize> {
+        None
+    }
+
+    /// trace lane of the operator running this consumer, spills and waits
+    /// are recorded in the lane if tracing is enabled
+    fn mem_trace_lane(&self) -> Option<TraceLane> {
+        None
+    }
+
     fn consumer_info(&self) -> Arc<MemConsumerInfo> {
         self.get_consumer_info()
             .upgrade()
@@ -215,9 +468,10 @@ pub trait MemConsumer: Send + Sync {
         let total_managed = total
             .saturating_sub(get_mem_jvm_direct_used())
             .saturating_sub(mem_unspillable);
-        let mem_used = self.consumer_info().status.lock().mem_used;
-        let consumer_mem_max = total_managed / mm_status.num_spillables.max(1);
-        mem_used as f64 / consumer_mem_max as f64
+        let (ctx, consumer_stat, _) =
+            mm.policy_context(mm_status.total_used, total_managed, &self.consumer_info());
+        let consumer_mem_max = mm.policy.consumer_mem_max(&ctx, &consumer_stat);
+        consumer_stat.mem_used as f64 / consumer_mem_max.max(1) as f64
     }
 
     fn set_spillable(&self, spillable: bool) {
@@ -281,21 +535,17 @@ async fn update_consumer_mem_used_with_custom_updater(
     let consumer_info = consumer.consumer_info();
     let total = mm.total;
 
-    #[derive(Clone, Copy, PartialEq)]
-    enum Operation {
-        Spill,   // spill this consumer
-        Wait,    // wait other consumers to spill
-        Nothing, // do nothing
-    }
-
     let (mem_unspillable, mem_jvm_direct_used);
-    let (mem_used, total_used, operation) = {
+    let (mem_used, total_used, operation, spillable_infos) = {
         let mut mm_status = mm.status.lock();
         let mut consumer_status = consumer_info.status.lock();
 
         // update consumer info
         let (old_used, new_used) = updater(&mut consumer_status);
+        consumer_status.peak_mem_used = consumer_status.peak_mem_used.max(new_used);
         let spillable = consumer_status.spillable;
+        let force_spill_requested = std::mem::take(&mut consumer_status.force_spill);
+        let force_spill = force_spill_requested && spillable && new_used > 0;
         let diff_used = new_used as isize - old_used as isize;
 
         // update mm status
@@ -307,13 +557,27 @@ async fn update_consumer_mem_used_with_custom_updater(
             mm_status.mem_spillables = (mm_status.mem_spillables as isize + diff_used) as usize;
         }
 
-        // consumer is unspillable/shrinking, no need to wait or spill
-        if old_used == 0 || !spillable || new_used < old_used {
+        // consumer is unspillable/not growing/too small, no need to wait or
+        // spill unless requested by reservations
+        if !spillable
+            || (!force_spill
+                && (old_used == 0 || new_used <= old_used || new_used <= MIN_TRIGGER_SIZE))
+        {
+            drop(consumer_status);
+            drop(mm_status);
+
+            // the requested consumer cannot spill now, pass the request to
+            // another consumer if memory still overflows
+            if force_spill_requested
+                && !force_spill
+                && total_used + get_mem_jvm_direct_used() > mm.total
+            {
+                mm.request_spill_largest();
+            }
             return Ok(());
         }
 
         // unlock
-        let num_spillables = mm_status.num_spillables;
         let mem_spillables = mm_status.mem_spillables;
         drop(consumer_status);
         drop(mm_status);
@@ -327,47 +591,62 @@ async fn update_consumer_mem_used_with_custom_updater(
         let total_managed = total
             .saturating_sub(mem_jvm_direct_used) // jvm direct memory
             .saturating_sub(mem_unspillable); // unspillable memory
-        let consumer_mem_max = total_managed / num_spillables;
-        let consumer_mem_min = consumer_mem_max / 8;
-
-        let total_overflowed = total_used > total_managed;
-        let consumer_overflowed = new_used > consumer_mem_max;
-        let operation = if (total_overflowed || consumer_overflowed)
-            && new_used > MIN_TRIGGER_SIZE
-            && new_used > old_used
-        {
-            if spillable && new_used > consumer_mem_min {
-                Operation::Spill
-            } else {
-                Operation::Wait
-            }
+        let (ctx, consumer_stat, spillable_infos) =
+            mm.policy_context(total_used, total_managed, &consumer_info);
+        let operation = if force_spill {
+            MemOperation::Spill // requested by reservations or other consumers
         } else {
-            Operation::Nothing
+            mm.policy.decide(&ctx, &consumer_stat)
         };
-        (new_used, total_used, operation)
+        (new_used, total_used, operation, spillable_infos)
     };
     let mut operation = operation;
 
+    // request another consumer to spill on its next update, which is done by
+    // its owner with its own up-to-date status. waits for the freed memory, or
+    // spills itself if the other consumer stays idle until timeout
+    if let MemOperation::SpillOther(idx) = operation {
+        let other_info = &spillable_infos[idx];
+        if other_info.request_spill() {
+            log::info!(
+                "mem manager requesting {} to spill for {consumer_name} (policy: {}), total: {}/{}",
+                other_info.name,
+                mm.policy.name(),
+                ByteSize(total_used as u64),
+                ByteSize(mm.total as u64),
+            );
+        }
+        operation = MemOperation::Wait;
+    }
+
     // trigger waiting for resources
-    if operation == Operation::Wait {
+    if operation == MemOperation::Wait {
         const WAIT_TIME: Duration = Duration::from_millis(10000);
 
+        let trace_lane = consumer.mem_trace_lane();
+        let wait_start_time = Instant::now();
         let mut mm_status = mm.status.lock();
         let wait = mm
             .cv
             .wait_while_for(&mut mm_status, |s| total < s.total_used, WAIT_TIME);
+        drop(mm_status);
 
+        if let Some(trace_lane) = trace_lane {
+            let args = json!({ "mem_used": mem_used, "timed_out": wait.timed_out() });
+            trace_lane.complete("mem wait", wait_start_time, args);
+        }
         if wait.timed_out() {
             log::warn!("mem manager: consumer {consumer_name} timeout waiting for resources");
-            operation = Operation::Spill;
+            operation = MemOperation::Spill;
         }
     }
 
     // trigger spilling
-    if operation == Operation::Spill {
+    if operation == MemOperation::Spill {
         log::info!(
-            "mem manager spilling {consumer_name} (mem_used: {}), total: {}/{}, unspillable: {}, jvm_direct: {}",
+            "mem manager spilling {consumer_name} (mem_used: {}, policy: {}), total: {}/{}, unspillable: {}, jvm_direct: {}",
             ByteSize(mem_used as u64),
+            mm.policy.name(),
             ByteSize(total_used as u64),
             ByteSize(mm.total as u64),
             ByteSize(mem_unspillable as u64),
@@ -0,0 +1,323 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Reverse;
+
+use blaze_jni_bridge::{conf, conf::StringConf, is_jni_bridge_inited};
+use datafusion::common::Result;
+use datafusion_ext_commons::df_execution_err;
+
+use crate::memmgr::MIN_TRIGGER_SIZE;
+
+// priorities of memory consumers, used as weights in the priority policy.
+// consumers with higher priority keep more memory while others are spilling.
+// shuffle buffers are written out anyway, spilling them is relatively cheap,
+// so shuffle repartitioners use the low priority.
+pub const MEM_PRIORITY_LOW: u32 = 1;
+pub const MEM_PRIORITY_NORMAL: u32 = 2;
+pub const MEM_PRIORITY_HIGH: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemOperation {
+    Spill,             // spill this consumer
+    SpillOther(usize), // ask another consumer to spill, indexed in MemPolicyContext::spillables
+    Wait,              // wait other consumers to spill
+    Nothing,           // do nothing
+}
+
+/// memory status of a spillable consumer
+#[derive(Clone, Copy, Debug)]
+pub struct MemConsumerStat {
+    pub mem_used: usize,
+    pub priority: u32,
+}
+
+/// memory status of the mem manager seen by policies
+#[derive(Clone, Debug)]
+pub struct MemPolicyContext {
+    /// memory used by all consumers
+    pub total_used: usize,
+
+    /// memory available for spillable consumers, excluding jvm direct memory
+    /// and memory used by unspillable consumers
+    pub total_managed: usize,
+
+    /// all spillable consumers, including the one being decided
+    pub spillables: Vec<MemConsumerStat>,
+}
+
+impl MemPolicyContext {
+    pub fn total_overflowed(&self) -> bool {
+        self.total_used > self.total_managed
+    }
+}
+
+/// decides how spillable consumers share managed memory
+pub trait MemPolicy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// max memory the consumer can hold before being spilled
+    fn consumer_mem_max(&self, ctx: &MemPolicyContext, consumer: &MemConsumerStat) -> usize;
+
+    /// decides the operation of a growing consumer, only called when the
+    /// consumer uses more than MIN_TRIGGER_SIZE memory
+    fn decide(&self, ctx: &MemPolicyContext, consumer: &MemConsumerStat) -> MemOperation {
+        let consumer_mem_max = self.consumer_mem_max(ctx, consumer);
+        let consumer_mem_min = consumer_mem_max / 8;
+        let consumer_overflowed = consumer.mem_used > consumer_mem_max;
+
+        if ctx.total_overflowed() || consumer_overflowed {
+            if consumer.mem_used > consumer_mem_min {
+                return MemOperation::Spill;
+            }
+            return MemOperation::Wait;
+        }
+        MemOperation::Nothing
+    }
+}
+
+pub fn create_mem_policy() -> Result<Box<dyn MemPolicy>> {
+    if !is_jni_bridge_inited() {
+        return Ok(Box::new(FairSharePolicy));
+    }
+    create_mem_policy_by_name(&conf::MEMMGR_POLICY.value()?)
+}
+
+pub fn create_mem_policy_by_name(name: &str) -> Result<Box<dyn MemPolicy>> {
+    match name.to_lowercase().as_str() {
+        "fair" | "fair-share" => Ok(Box::new(FairSharePolicy)),
+        "priority" => Ok(Box::new(PriorityPolicy)),
+        "largest-first" => Ok(Box::new(LargestFirstPolicy)),
+        _ => df_execution_err!("unsupported mem manager policy: {name}"),
+    }
+}
+
+/// all spillable consumers share managed memory equally
+pub struct FairSharePolicy;
+
+impl MemPolicy for FairSharePolicy {
+    fn name(&self) -> &'static str {
+        "fair-share"
+    }
+
+    fn consumer_mem_max(&self, ctx: &MemPolicyContext, _consumer: &MemConsumerStat) -> usize {
+        ctx.total_managed / ctx.spillables.len().max(1)
+    }
+}
+
+/// spillable consumers share managed memory weighted by their priorities.
+/// when total memory overflows, consumers with lower priorities are spilled
+/// first, even if they are not growing.
+pub struct PriorityPolicy;
+
+impl MemPolicy for PriorityPolicy {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn consumer_mem_max(&self, ctx: &MemPolicyContext, consumer: &MemConsumerStat) -> usize {
+        let total_priorities = ctx
+            .spillables
+            .iter()
+            .map(|c| c.priority as usize)
+            .sum::<usize>()
+            .max(consumer.priority as usize)
+            .max(1);
+        (ctx.total_managed as u128 * consumer.priority as u128 / total_priorities as u128) as usize
+    }
+
+    fn decide(&self, ctx: &MemPolicyContext, consumer: &MemConsumerStat) -> MemOperation {
+        if consumer.mem_used > self.consumer_mem_max(ctx, consumer) {
+            return MemOperation::Spill;
+        }
+        if ctx.total_overflowed() {
+            let lowest_priority_holder = ctx
+                .spillables
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.priority < consumer.priority && c.mem_used > MIN_TRIGGER_SIZE)
+                .min_by_key(|(_, c)| (c.priority, Reverse(c.mem_used)));
+            if let Some((idx, _)) = lowest_priority_holder {
+                return MemOperation::SpillOther(idx);
+            }
+            return MemOperation::Spill;
+        }
+        MemOperation::Nothing
+    }
+}
+
+/// a consumer can use all managed memory, when total memory overflows, only
+/// the largest consumer is spilled, growing consumers trigger its spilling.
+pub struct LargestFirstPolicy;
+
+impl MemPolicy for LargestFirstPolicy {
+    fn name(&self) -> &'static str {
+        "largest-first"
+    }
+
+    fn consumer_mem_max(&self, ctx: &MemPolicyContext, _consumer: &MemConsumerStat) -> usize {
+        ctx.total_managed
+    }
+
+    fn decide(&self, ctx: &MemPolicyContext, consumer: &MemConsumerStat) -> MemOperation {
+        if !ctx.total_overflowed() {
+            return MemOperation::Nothing;
+        }
+        let largest = ctx
+            .spillables
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, c)| c.mem_used)
+            .filter(|(_, c)| c.mem_used > consumer.mem_used);
+        match largest {
+            Some((idx, _)) => MemOperation::SpillOther(idx),
+            None => MemOperation::Spill,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MB: usize = 1 << 20;
+
+    #[derive(Default, Debug)]
+    struct SimConsumer {
+        mem_used: usize,
+        num_spills: usize,
+        max_mem_used: usize,
+    }
+
+    // simulates consumers growing by fixed steps in rounds, returns status of
+    // each consumer. waiting consumers do not grow in the current round.
+    // spilling is done immediately, even for consumers not being decided.
+    fn simulate(
+        policy: &dyn MemPolicy,
+        total_managed: usize,
+        workloads: &[(u32, usize)], // (priority, step)
+        num_rounds: usize,
+    ) -> Vec<SimConsumer> {
+        let mut consumers = workloads
+            .iter()
+            .map(|_| SimConsumer::default())
+            .collect::<Vec<_>>();
+
+        for _ in 0..num_rounds {
+            for (i, &(_, step)) in workloads.iter().enumerate() {
+                consumers[i].mem_used += step;
+                if consumers[i].mem_used <= MIN_TRIGGER_SIZE {
+                    continue;
+                }
+                let ctx = MemPolicyContext {
+                    total_used: consumers.iter().map(|c| c.mem_used).sum(),
+                    total_managed,
+                    spillables: consumers
+                        .iter()
+                        .zip(workloads)
+                        .map(|(c, &(priority, _))| MemConsumerStat {
+                            mem_used: c.mem_used,
+                            priority,
+                        })
+                        .collect(),
+                };
+                let stat = ctx.spillables[i];
+                match policy.decide(&ctx, &stat) {
+                    MemOperation::Spill => {
+                        // the largest consumer is always spilled in
+                        // largest-first policy
+                        if policy.name() == "largest-first" {
+                            assert!(ctx.spillables.iter().all(|c| c.mem_used <= stat.mem_used));
+                        }
+                        consumers[i].mem_used = 0;
+                        consumers[i].num_spills += 1;
+                    }
+                    MemOperation::SpillOther(idx) => {
+                        if policy.name() == "largest-first" {
+                            assert!(ctx
+                                .spillables
+                                .iter()
+                                .all(|c| c.mem_used <= ctx.spillables[idx].mem_used));
+                        }
+                        consumers[idx].mem_used = 0;
+                        consumers[idx].num_spills += 1;
+                    }
+                    MemOperation::Wait => {
+                        consumers[i].mem_used -= step;
+                    }
+                    MemOperation::Nothing => {}
+                }
+                consumers[i].max_mem_used = consumers[i].max_mem_used.max(consumers[i].mem_used);
+            }
+        }
+        consumers
+    }
+
+    #[test]
+    fn test_create_mem_policy() -> Result<()> {
+        assert_eq!(create_mem_policy()?.name(), "fair-share");
+        assert_eq!(create_mem_policy_by_name("fair")?.name(), "fair-share");
+        assert_eq!(create_mem_policy_by_name("Priority")?.name(), "priority");
+        assert_eq!(
+            create_mem_policy_by_name("largest-first")?.name(),
+            "largest-first"
+        );
+        assert!(create_mem_policy_by_name("unknown").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fair_share_policy() {
+        let workloads = [(MEM_PRIORITY_HIGH, 10 * MB); 4];
+        let consumers = simulate(&FairSharePolicy, 400 * MB, &workloads, 100);
+
+        // consumers are spilled evenly and never exceed their shares
+        for c in &consumers {
+            assert!(c.num_spills > 0);
+            assert_eq!(c.num_spills, consumers[0].num_spills);
+            assert!(c.max_mem_used <= 100 * MB);
+        }
+    }
+
+    #[test]
+    fn test_priority_policy() {
+        let workloads = [(MEM_PRIORITY_HIGH, 10 * MB), (MEM_PRIORITY_LOW, 10 * MB)];
+
+        // in fair-share policy, priorities are ignored
+        let consumers = simulate(&FairSharePolicy, 300 * MB, &workloads, 100);
+        assert_eq!(consumers[0].num_spills, consumers[1].num_spills);
+
+        // high-priority consumer keeps more memory and spills less
+        let consumers = simulate(&PriorityPolicy, 300 * MB, &workloads, 100);
+        assert!(consumers[0].num_spills > 0);
+        assert!(consumers[0].num_spills * 2 < consumers[1].num_spills);
+        assert!(consumers[0].max_mem_used > 200 * MB);
+        assert!(consumers[1].max_mem_used <= 60 * MB);
+    }
+
+    #[test]
+    fn test_largest_first_policy() {
+        let workloads = [
+            (MEM_PRIORITY_NORMAL, 20 * MB),
+            (MEM_PRIORITY_NORMAL, 5 * MB),
+        ];
+        let consumers = simulate(&LargestFirstPolicy, 400 * MB, &workloads, 30);
+
+        // only the fast-growing consumer is spilled, the small one triggers
+        // its spilling
+        assert!(consumers[0].num_spills > 0);
+        assert!(consumers[0].max_mem_used > 200 * MB);
+        assert_eq!(consumers[1].num_spills, 0);
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytesize::ByteSize;
+
+use crate::memmgr::{
+    get_mem_jvm_direct_used, policy::MEM_PRIORITY_NORMAL, MemConsumerInfo, MemManager,
+};
+
+// growing reservations query jvm direct memory only if total used memory
+// exceeds this ratio of the total memory
+const RESERVATION_JVM_DIRECT_CHECK_RATIO: f64 = 0.8;
+
+/// tracks memory of allocations which are not managed by memory consumers
+/// (like cached broadcast hash maps), the memory is accounted as unspillable
+/// in the mem manager until the reservation is dropped.
+///
+/// growing a reservation never blocks, but if total memory overflows, the
+/// largest spillable consumer is requested to spill on its next update.
+/// reservations are no-op if the mem manager is not initialized.
+pub struct MemReservation {
+    consumer_info: Option<Arc<MemConsumerInfo>>,
+}
+
+impl MemReservation {
+    pub fn new(name: impl Into<String>, size: usize) -> Self {
+        Self::new_with_priority(name, size, MEM_PRIORITY_NORMAL)
+    }
+
+    /// creates a reservation with the priority of its owner, which is shown
+    /// in status reports
+    pub fn new_with_priority(name: impl Into<String>, size: usize, priority: u32) -> Self {
+        if !MemManager::initialized() {
+            return Self {
+                consumer_info: None,
+            };
+        }
+        let consumer_info = Arc::new(MemConsumerInfo::new(
+            name.into(),
+            None,
+            None,
+            priority,
+            false,
+        ));
+        MemManager::get().add_consumer_info(consumer_info.clone());
+
+        let reservation = Self {
+            consumer_info: Some(consumer_info),
+        };
+        reservation.resize(size);
+        reservation
+    }
+
+    pub fn size(&self) -> usize {
+        self.consumer_info
+            .as_ref()
+            .map(|consumer_info| consumer_info.status.lock().mem_used)
+            .unwrap_or(0)
+    }
+
+    pub fn grow(&self, size: usize) {
+        self.update(|old_size| old_size + size);
+    }
+
+    pub fn shrink(&self, size: usize) {
+        self.update(|old_size| old_size.saturating_sub(size));
+    }
+
+    pub fn resize(&self, new_size: usize) {
+        self.update(|_| new_size);
+    }
+
+    // updates size under the lock, so that a reservation can be grown and
+    // shrunk concurrently
+    fn update(&self, updater: impl FnOnce(usize) -> usize) {
+        let Some(consumer_info) = &self.consumer_info else {
+            return;
+        };
+        let mm = MemManager::get();
+        let (old_size, new_size, total_used) = {
+            let mut mm_status = mm.status.lock();
+            let mut consumer_status = consumer_info.status.lock();
+            let new_size = updater(consumer_status.mem_used);
+            let old_size = std::mem::replace(&mut consumer_status.mem_used, new_size);
+            consumer_status.peak_mem_used = consumer_status.peak_mem_used.max(new_size);
+            let diff_size = new_size as isize - old_size as isize;
+            (
+                old_size,
+                new_size,
+                mm_status.update_total_used_with_diff(diff_size),
+            )
+        };
+        if new_size <= old_size {
+            return;
+        }
+
+        // total memory overflows, let spillable consumers free some memory.
+        // jvm direct memory is only queried (through jni) when total memory is
+        // close to overflow, smaller overflows caused by jvm direct memory are
+        // still found by consumers on their own updates
+        if total_used < (mm.total as f64 * RESERVATION_JVM_DIRECT_CHECK_RATIO) as usize {
+            return;
+        }
+        let mem_jvm_direct_used = if total_used > mm.total {
+            0 // already overflows, no need to query jvm
+        } else {
+            get_mem_jvm_direct_used()
+        };
+        if total_used + mem_jvm_direct_used > mm.total {
+            log::info!(
+                "mem manager: reservation {} grows to {}, total: {}/{}, jvm_direct: {}",
+                consumer_info.name,
+                ByteSize(new_size as u64),
+                ByteSize(total_used as u64),
+                ByteSize(mm.total as u64),
+                ByteSize(mem_jvm_direct_used as u64),
+            );
+            mm.request_spill_largest();
+        }
+    }
+}
+
+impl Drop for MemReservation {
+    fn drop(&mut self) {
+        if let Some(consumer_info) = &self.consumer_info {
+            MemManager::get().remove_consumer_info(consumer_info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mem_reservation() {
+        MemManager::init(10000);
+        let mm = MemManager::get();
+
+        let reservation = MemReservation::new("TestReservation", 1000);
+        assert_eq!(reservation.size(), 1000);
+
+        reservation.grow(500);
+        reservation.shrink(1000);
+        assert_eq!(reservation.size(), 500);
+
+        let snapshot = mm.snapshot();
+        let reserved = snapshot
+            .consumers
+            .iter()
+            .find(|c| c.name == "TestReservation")
+            .expect("reservation registered");
+        assert!(!reserved.spillable);
+        assert_eq!(reserved.mem_used, 500);
+        assert_eq!(reserved.peak_mem_used, 1500);
+
+        // reservation is deregistered after dropping
+        drop(reservation);
+        assert!(mm
+            .snapshot()
+            .consumers
+            .iter()
+            .all(|c| c.name != "TestReservation"));
+    }
+}
@@ -14,44 +14,71 @@
 
 use std::{
     any::Any,
-    fs,
     fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Cursor, Read, Seek, Write},
-    sync::Arc,
+    io::{BufRead, BufReader, BufWriter, Cursor, Read, Seek, Write},
+    os::fd::AsRawFd,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Arc,
+    },
     time::Duration,
 };
 
 use blaze_jni_bridge::{
-    conf, conf::StringConf, is_jni_bridge_inited, jni_bridge::LocalRef, jni_call, jni_call_static,
-    jni_get_string, jni_new_direct_byte_buffer, jni_new_global_ref,
+    conf,
+    conf::{IntConf, StringConf},
+    is_jni_bridge_inited,
+    jni_bridge::LocalRef,
+    jni_call, jni_call_static, jni_get_string, jni_new_direct_byte_buffer, jni_new_global_ref,
 };
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use datafusion::{common::Result, parquet::file::reader::Length, physical_plan::metrics::Time};
 use jni::{objects::GlobalRef, sys::jlong};
 use log::warn;
+use memmap2::{Advice, Mmap, MmapOptions};
 use once_cell::sync::OnceCell;
 
 use crate::{
-    common::ipc_compression::{IoCompressionReader, IoCompressionWriter},
-    memmgr::metrics::SpillMetrics,
+    common::{
+        execution_context::{ExecutionContext, TaskResources},
+        ipc_compression::{IoCompressionCodec, IoCompressionReader, IoCompressionWriter},
+    },
+    memmgr::{
+        disk_spill::{DiskSpillManager, TaskSpillScope},
+        metrics::SpillMetrics,
+    },
 };
 
-pub type SpillCompressedReader<'a> = IoCompressionReader<BufReader<Box<dyn Read + Send + 'a>>>;
-pub type SpillCompressedWriter<'a> = IoCompressionWriter<BufWriter<Box<dyn Write + Send + 'a>>>;
+pub type SpillCompressedReader<'a> =
+    IoCompressionReader<SpillChecksumReader<Box<dyn BufRead + Send + 'a>>>;
+pub type SpillCompressedWriter<'a> =
+    IoCompressionWriter<SpillChecksumWriter<BufWriter<Box<dyn Write + Send + 'a>>>>;
+
+const SPILL_CHECKSUM_FRAME_SIZE: usize = 65536;
 
 pub trait Spill: Send + Sync {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
-    fn get_buf_reader<'a>(&'a self) -> BufReader<Box<dyn Read + Send + 'a>>;
+    fn get_buf_reader<'a>(&'a self) -> Box<dyn BufRead + Send + 'a>;
     fn get_buf_writer<'a>(&'a mut self) -> BufWriter<Box<dyn Write + Send + 'a>>;
 
+    /// length of data written to this spill
+    fn spill_len(&self) -> usize;
+
     fn get_compressed_reader(&self) -> SpillCompressedReader<'_> {
-        IoCompressionReader::try_new(spill_compression_codec(), self.get_buf_reader())
-            .expect("error creating compression reader")
+        IoCompressionReader::try_new_with_codec(
+            spill_compression_codec(),
+            SpillChecksumReader::new(self.get_buf_reader()),
+        )
+        .expect("error creating compression reader")
     }
 
     fn get_compressed_writer(&mut self) -> SpillCompressedWriter<'_> {
-        IoCompressionWriter::try_new(spill_compression_codec(), self.get_buf_writer())
-            .expect("error creating compression writer")
+        IoCompressionWriter::try_new_with_codec(
+            spill_compression_codec(),
+            SpillChecksumWriter::new(self.get_buf_writer()),
+        )
+        .expect("error creating compression writer")
     }
 }
 
@@ -64,8 +91,12 @@ impl Spill for Vec<u8> {
         self
     }
 
-    fn get_buf_reader<'a>(&'a self) -> BufReader<Box<dyn Read + Send + 'a>> {
-        BufReader::new(Box::new(Cursor::new(self)))
+    fn get_buf_reader<'a>(&'a self) -> Box<dyn BufRead + Send + 'a> {
+        Box::new(Cursor::new(self))
+    }
+
+    fn spill_len(&self) -> usize {
+        self.len()
     }
 
     fn get_buf_writer<'a>(&'a mut self) -> BufWriter<Box<dyn Write + Send + 'a>> {
@@ -73,39 +104,98 @@ impl Spill for Vec<u8> {
     }
 }
 
-fn spill_compression_codec() -> &'static str {
-    static CODEC: OnceCell<String> = OnceCell::new();
-    CODEC
+fn spill_compression_codec() -> IoCompressionCodec {
+    static CODEC: OnceCell<IoCompressionCodec> = OnceCell::new();
+    *CODEC
         .get_or_try_init(|| {
             if is_jni_bridge_inited() {
-                conf::SPILL_COMPRESSION_CODEC.value()
+                IoCompressionCodec::try_from_name(&conf::SPILL_COMPRESSION_CODEC.value()?)
             } else {
-                Ok(format!("lz4")) // for testing
+                Ok(IoCompressionCodec::Lz4) // for testing
             }
         })
         .expect("error reading spark.blaze.spill.compression.codec")
-        .as_str()
 }
 
-pub fn try_new_spill(spill_metrics: &SpillMetrics) -> Result<Box<dyn Spill>> {
-    if !is_jni_bridge_inited() || jni_call_static!(JniBridge.isDriverSide() -> bool)? {
-        Ok(Box::new(FileSpill::try_new(spill_metrics)?))
-    } else {
-        // use on heap spill if on-heap memory is available, otherwise use file spill
-        let hsm = jni_call_static!(JniBridge.getTaskOnHeapSpillManager() -> JObject)?;
-        if jni_call!(BlazeOnHeapSpillManager(hsm.as_obj()).isOnHeapAvailable() -> bool)? {
-            Ok(Box::new(OnHeapSpill::try_new(hsm, spill_metrics)?))
+/// creates a spill for the operator described by `desc`, which is used in
+/// errors of exceeding the max spill size of the task
+pub fn try_new_spill(exec_ctx: &ExecutionContext, desc: &'static str) -> Result<Box<dyn Spill>> {
+    let spill_metrics = exec_ctx.spill_metrics();
+    let scope = exec_ctx.task_resources().spill_scope().clone();
+    let spill: Box<dyn Spill> =
+        if !is_jni_bridge_inited() || jni_call_static!(JniBridge.isDriverSide() -> bool)? {
+            Box::new(FileSpill::try_new(spill_metrics, scope)?)
         } else {
-            Ok(Box::new(FileSpill::try_new(spill_metrics)?))
-        }
+            // use on heap spill if on-heap memory is available, otherwise use
+            // file spill
+            let hsm = jni_call_static!(JniBridge.getTaskOnHeapSpillManager() -> JObject)?;
+            if jni_call!(BlazeOnHeapSpillManager(hsm.as_obj()).isOnHeapAvailable() -> bool)? {
+                Box::new(OnHeapSpill::try_new(hsm, spill_metrics)?)
+            } else {
+                Box::new(FileSpill::try_new(spill_metrics, scope)?)
+            }
+        };
+
+    // count spilled bytes of the task only if spill size is limited, both
+    // on-heap and file spills are counted
+    let task_resources = exec_ctx.task_resources();
+    if task_resources.limits().max_spill_bytes > 0 {
+        return Ok(Box::new(TaskLimitedSpill(
+            spill,
+            task_resources.clone(),
+            desc,
+        )));
+    }
+    Ok(spill)
+}
+
+/// A spill wrapper which accounts written bytes to the task, writing fails
+/// once the task exceeds its max spill size.
+struct TaskLimitedSpill(Box<dyn Spill>, Arc<TaskResources>, &'static str);
+impl Spill for TaskLimitedSpill {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_buf_reader<'a>(&'a self) -> Box<dyn BufRead + Send + 'a> {
+        self.0.get_buf_reader()
+    }
+
+    fn spill_len(&self) -> usize {
+        self.0.spill_len()
+    }
+
+    fn get_buf_writer<'a>(&'a mut self) -> BufWriter<Box<dyn Write + Send + 'a>> {
+        // inner writer is already buffered
+        let task_resources = self.1.clone();
+        let desc = self.2;
+        BufWriter::with_capacity(
+            0,
+            Box::new(SpillLimitWriter(
+                self.0.get_buf_writer(),
+                task_resources,
+                desc,
+            )),
+        )
     }
 }
 
 /// A spill structure which write data to temporary files
-/// used in driver side or executor side with on-heap memory is full
-struct FileSpill(File, SpillMetrics, Option<String>);
+/// used in driver side or executor side with on-heap memory is full.
+/// disk usage and file of the spill are tracked in the task spill scope.
+struct FileSpill(File, SpillMetrics, Option<String>, Arc<TaskSpillScope>);
 impl FileSpill {
-    fn try_new(spill_metrics: &SpillMetrics) -> Result<Self> {
+    fn try_new(spill_metrics: &SpillMetrics, scope: Arc<TaskSpillScope>) -> Result<Self> {
+        // use configured spill dirs if available
+        if let Some((file, file_name)) = DiskSpillManager::get().create_spill_file()? {
+            scope.register_file(&file_name);
+            return Ok(Self(file, spill_metrics.clone(), Some(file_name), scope));
+        }
+
         if is_jni_bridge_inited() {
             let file_name = jni_get_string!(
                 jni_call_static!(JniBridge.getDirectWriteSpillToDiskFile() -> JObject)?
@@ -118,10 +208,11 @@ impl FileSpill {
                 .write(true)
                 .read(true)
                 .open(&file_name)?;
-            Ok(Self(file, spill_metrics.clone(), Some(file_name)))
+            scope.register_file(&file_name);
+            Ok(Self(file, spill_metrics.clone(), Some(file_name), scope))
         } else {
             let file = tempfile::tempfile()?;
-            Ok(Self(file, spill_metrics.clone(), None))
+            Ok(Self(file, spill_metrics.clone(), None, scope))
         }
     }
 }
@@ -135,17 +226,32 @@ impl Spill for FileSpill {
         self
     }
 
-    fn get_buf_reader<'a>(&'a self) -> BufReader<Box<dyn Read + Send + 'a>> {
+    fn get_buf_reader<'a>(&'a self) -> Box<dyn BufRead + Send + 'a> {
         let mut file_cloned = self.0.try_clone().expect("File.try_clone() returns error");
         file_cloned.sync_data().expect("error synchronizing data");
         file_cloned.rewind().expect("error rewinding");
-        BufReader::with_capacity(
+
+        // large spills are read with mmap, avoiding read syscalls of each
+        // cursor in high fan-in merging
+        let file_len = file_cloned.len();
+        if file_len > 0 && file_len >= spill_mmap_read_threshold() {
+            match MmapSpillReader::try_new(&file_cloned) {
+                Ok(mmap_reader) => return Box::new(mmap_reader),
+                Err(err) => warn!("error mapping spill file, fallback to buffered read: {err}"),
+            }
+        }
+
+        // spill is read sequentially, let the kernel read ahead aggressively
+        unsafe {
+            // safety: advices only affect caching of the valid fd
+            let fd = file_cloned.as_raw_fd();
+            libc::posix_fadvise(fd, 0, 0, libc::POSIX_FADV_SEQUENTIAL);
+            libc::posix_fadvise(fd, 0, 0, libc::POSIX_FADV_WILLNEED);
+        }
+        Box::new(BufReader::with_capacity(
             65536,
-            Box::new(IoTimeReadWrapper(
-                file_cloned,
-                self.1.mem_spill_iotime.clone(),
-            )),
-        )
+            IoTimeReadWrapper(file_cloned, self.1.mem_spill_iotime.clone()),
+        ))
     }
 
     fn get_buf_writer<'a>(&'a mut self) -> BufWriter<Box<dyn Write + Send + 'a>> {
@@ -153,11 +259,15 @@ impl Spill for FileSpill {
         BufWriter::with_capacity(
             65536,
             Box::new(IoTimeWriteWrapper(
-                file_cloned,
+                SpillQuotaWriter(file_cloned, self.3.clone()),
                 self.1.mem_spill_iotime.clone(),
             )),
         )
     }
+
+    fn spill_len(&self) -> usize {
+        self.0.len() as usize
+    }
 }
 
 impl Drop for FileSpill {
@@ -166,17 +276,78 @@ impl Drop for FileSpill {
         self.1
             .disk_spill_iotime
             .add_duration(Duration::from_nanos(self.1.mem_spill_iotime.value() as u64));
+        self.3.release(self.0.len() as usize);
         if let Some(file_path) = &self.2 {
-            if let Err(e) = fs::remove_file(file_path) {
-                warn!(
-                    "Was unable to delete spill file: {}. error: {}",
-                    file_path, e
-                );
-            }
+            self.3.remove_file(file_path);
         }
     }
 }
 
+/// reads a whole spill file through a read-only memory map, the mapped pages
+/// are used as the read buffer directly without extra copying
+struct MmapSpillReader {
+    mmap: Mmap,
+    pos: usize,
+}
+
+impl MmapSpillReader {
+    fn try_new(file: &File) -> std::io::Result<Self> {
+        // safety: spill files are no longer written once being read
+        let mmap = unsafe { MmapOptions::new().map(file)? };
+        mmap.advise(Advice::Sequential)?;
+        Ok(Self { mmap, pos: 0 })
+    }
+}
+
+impl Read for MmapSpillReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = &self.mmap[self.pos..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl BufRead for MmapSpillReader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(&self.mmap[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.mmap.len());
+    }
+}
+
+fn spill_mmap_read_threshold() -> u64 {
+    static THRESHOLD: OnceCell<u64> = OnceCell::new();
+    *THRESHOLD.get_or_init(|| {
+        if !is_jni_bridge_inited() {
+            return 4 << 20; // for testing
+        }
+        match conf::SPILL_MMAP_READ_THRESHOLD.value() {
+            Ok(threshold) if threshold > 0 => threshold as u64,
+            Ok(_) => u64::MAX, // mmap disabled
+            Err(_) => 4 << 20,
+        }
+    })
+}
+
+/// max number of spills merged at once, consumers merge spills into larger
+/// spills in multiple levels when exceeded
+pub fn spill_merge_max_fan_in() -> usize {
+    static MAX_FAN_IN: OnceCell<usize> = OnceCell::new();
+    *MAX_FAN_IN.get_or_init(|| {
+        if !is_jni_bridge_inited() {
+            return 64; // for testing
+        }
+        conf::SPILL_MERGE_MAX_FAN_IN
+            .value()
+            .map(|v| v.max(2) as usize)
+            .unwrap_or(64)
+    })
+}
+
 /// A spill structure which cooperates with BlazeOnHeapSpillManager
 /// used in executor side
 struct OnHeapSpill(Arc<RawOnHeapSpill>, SpillMetrics);
@@ -187,6 +358,7 @@ impl OnHeapSpill {
             Arc::new(RawOnHeapSpill {
                 hsm: jni_new_global_ref!(hsm.as_obj())?,
                 spill_id,
+                written_len: AtomicUsize::new(0),
             }),
             spill_metrics.clone(),
         ))
@@ -214,15 +386,19 @@ impl Spill for OnHeapSpill {
         self
     }
 
-    fn get_buf_reader<'a>(&'a self) -> BufReader<Box<dyn Read + Send + 'a>> {
+    fn get_buf_reader<'a>(&'a self) -> Box<dyn BufRead + Send + 'a> {
         let cloned = Self(self.0.clone(), self.1.clone());
-        BufReader::with_capacity(65536, Box::new(cloned))
+        Box::new(BufReader::with_capacity(65536, cloned))
     }
 
     fn get_buf_writer<'a>(&'a mut self) -> BufWriter<Box<dyn Write + Send + 'a>> {
         let cloned = Self(self.0.clone(), self.1.clone());
         BufWriter::with_capacity(1048576, Box::new(cloned))
     }
+
+    fn spill_len(&self) -> usize {
+        self.0.written_len.load(Relaxed)
+    }
 }
 
 impl Write for OnHeapSpill {
@@ -235,6 +411,7 @@ impl Write for OnHeapSpill {
             self.0.hsm.as_obj()).writeSpill(self.0.spill_id, buf.as_obj()) -> ()
         )?;
         self.1.mem_spill_size.add(write_len);
+        self.0.written_len.fetch_add(write_len, Relaxed);
         Ok(write_len)
     }
 
@@ -269,6 +446,7 @@ impl Drop for OnHeapSpill {
 struct RawOnHeapSpill {
     hsm: GlobalRef,
     spill_id: i32,
+    written_len: AtomicUsize,
 }
 
 impl Drop for RawOnHeapSpill {
@@ -278,6 +456,46 @@ impl Drop for RawOnHeapSpill {
     }
 }
 
+// reserves disk usage from the task spill scope before writing, so that spill
+// fails gracefully instead of filling up local disks
+struct SpillQuotaWriter<W: Write>(W, Arc<TaskSpillScope>);
+
+impl<W: Write> Write for SpillQuotaWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.1.try_reserve(buf.len())?;
+        match self.0.write(buf) {
+            Ok(written) => {
+                self.1.release(buf.len() - written);
+                Ok(written)
+            }
+            Err(err) => {
+                self.1.release(buf.len());
+                Err(err)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// accounts written bytes to the task, failing the spill once the max spill
+// size of the task is exceeded
+struct SpillLimitWriter<W: Write>(W, Arc<TaskResources>, &'static str);
+
+impl<W: Write> Write for SpillLimitWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.0.write(buf)?;
+        self.1.add_spilled_bytes(self.2, written)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
 struct IoTimeReadWrapper<R: Read>(R, Time);
 struct IoTimeWriteWrapper<W: Write>(W, Time);
 
@@ -300,9 +518,104 @@ impl<W: Write> Write for IoTimeWriteWrapper<W> {
     }
 }
 
+/// splits compressed spill data into frames of [u32 len][u32 crc32c][data],
+/// so that corrupted spill files are detected before being decompressed.
+/// the last frame is written when flushed, which is done in
+/// IoCompressionWriter::finish()
+pub struct SpillChecksumWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> SpillChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(SPILL_CHECKSUM_FRAME_SIZE),
+        }
+    }
+
+    fn write_frame(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            self.inner
+                .write_u32::<LittleEndian>(self.buf.len() as u32)?;
+            self.inner
+                .write_u32::<LittleEndian>(crc32c::crc32c(&self.buf))?;
+            self.inner.write_all(&self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for SpillChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(SPILL_CHECKSUM_FRAME_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() >= SPILL_CHECKSUM_FRAME_SIZE {
+            self.write_frame()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_frame()?;
+        self.inner.flush()
+    }
+}
+
+pub struct SpillChecksumReader<R: Read> {
+    inner: R,
+    buf: Cursor<Vec<u8>>,
+}
+
+impl<R: Read> SpillChecksumReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Cursor::default(),
+        }
+    }
+
+    // reads next frame into buf, returns false if reaching the end
+    fn read_frame(&mut self) -> std::io::Result<bool> {
+        let frame_len = match self.inner.read_u32::<LittleEndian>() {
+            Ok(frame_len) => frame_len as usize,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let expected_checksum = self.inner.read_u32::<LittleEndian>()?;
+
+        let buf = self.buf.get_mut();
+        buf.resize(frame_len, 0);
+        self.inner.read_exact(buf)?;
+        let checksum = crc32c::crc32c(buf);
+        if checksum != expected_checksum {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "spill frame checksum mismatch: expected {expected_checksum:#010x}, \
+                     got {checksum:#010x}, spill data may be corrupted",
+                ),
+            ));
+        }
+        self.buf.set_position(0);
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for SpillChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buf.position() as usize >= self.buf.get_ref().len() && !self.read_frame()? {
+            return Ok(0);
+        }
+        self.buf.read(buf)
+    }
+}
+
 pub struct OwnedSpillBufReader<'a> {
     spill: Box<dyn Spill>,
-    buf_reader: BufReader<Box<dyn Read + Send + 'a>>,
+    buf_reader: Box<dyn BufRead + Send + 'a>,
 }
 
 impl<'a> OwnedSpillBufReader<'a> {
@@ -322,7 +635,146 @@ impl<'a> OwnedSpillBufReader<'a> {
         &mut self.spill
     }
 
-    pub fn buf_reader(&mut self) -> &mut BufReader<Box<dyn Read + Send + 'a>> {
+    pub fn buf_reader(&mut self) -> &mut Box<dyn BufRead + Send + 'a> {
         &mut self.buf_reader
     }
 }
+
+impl Read for OwnedSpillBufReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.buf_reader.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+
+    use arrow::datatypes::Schema;
+    use datafusion::{execution::TaskContext, physical_plan::metrics::ExecutionPlanMetricsSet};
+
+    use super::*;
+    use crate::common::execution_context::TaskLimits;
+
+    #[test]
+    fn test_spill_checksum() -> Result<()> {
+        let data = (0..300000u32)
+            .flat_map(|i| (i % 1000).to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let mut spill: Vec<u8> = vec![];
+        let mut writer = spill.get_compressed_writer();
+        writer.write_all(&data)?;
+        writer.finish()?;
+
+        let mut read_data = vec![];
+        spill.get_compressed_reader().read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        // corrupted spill data is detected
+        let last = spill.len() - 1;
+        spill[last] ^= 0x01;
+        let mut read_data = vec![];
+        let err = spill
+            .get_compressed_reader()
+            .read_to_end(&mut read_data)
+            .unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_spill_read() -> Result<()> {
+        let metrics = ExecutionPlanMetricsSet::new();
+        let spill_metrics = SpillMetrics::new(&metrics, 0);
+        let scope = DiskSpillManager::get().new_task_scope();
+
+        // small spills are read with buffered reads, large spills with mmap
+        for num_values in [1000u32, 2000000u32] {
+            let data = (0..num_values)
+                .flat_map(|i| i.to_le_bytes())
+                .collect::<Vec<_>>();
+            let mut spill = FileSpill::try_new(&spill_metrics, scope.clone())?;
+            let mut writer = spill.get_buf_writer();
+            writer.write_all(&data)?;
+            writer.flush()?;
+            drop(writer);
+            assert_eq!(
+                spill.0.len() >= spill_mmap_read_threshold(),
+                num_values == 2000000
+            );
+
+            let mut read_data = vec![];
+            spill.get_buf_reader().read_to_end(&mut read_data)?;
+            assert_eq!(read_data, data);
+        }
+        scope.release_all();
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_spill_scope() -> Result<()> {
+        let task_ctx = Arc::new(TaskContext::default());
+        let schema = Arc::new(Schema::empty());
+        let metrics = ExecutionPlanMetricsSet::new();
+        let exec_ctx = ExecutionContext::new(task_ctx.clone(), 0, schema, &metrics);
+        let scope = exec_ctx.task_resources().spill_scope().clone();
+
+        // disk usage is accounted across all spills of the task
+        let mut spill1 = try_new_spill(&exec_ctx, "Test")?;
+        let mut spill2 = try_new_spill(&exec_ctx, "Test")?;
+        for spill in [&mut spill1, &mut spill2] {
+            let mut writer = spill.get_buf_writer();
+            writer.write_all(&[0u8; 6000])?;
+            writer.flush()?;
+        }
+        assert_eq!(scope.task_used(), 12000);
+
+        let mut read_data = vec![];
+        spill1.get_buf_reader().read_to_end(&mut read_data)?;
+        assert_eq!(read_data.len(), 6000);
+        drop(spill1);
+        assert_eq!(scope.task_used(), 6000);
+        drop(spill2);
+        assert_eq!(scope.task_used(), 0);
+        scope.release_all();
+        Ok(())
+    }
+    #[test]
+    fn test_spill_size_limit() -> Result<()> {
+        let task_ctx = Arc::new(TaskContext::default());
+        let schema = Arc::new(Schema::empty());
+        let metrics = ExecutionPlanMetricsSet::new();
+        let exec_ctx = ExecutionContext::new(task_ctx.clone(), 0, schema, &metrics);
+        exec_ctx.task_resources().set_limits(TaskLimits {
+            max_spill_bytes: 10000,
+            ..TaskLimits::default()
+        });
+
+        // spilled bytes are accounted across all spills of the task
+        let mut spill1 = try_new_spill(&exec_ctx, "Test1")?;
+        let mut writer = spill1.get_buf_writer();
+        writer.write_all(&[0u8; 6000])?;
+        writer.flush()?;
+        drop(writer);
+        assert_eq!(exec_ctx.task_resources().spilled_bytes(), 6000);
+
+        // error names the operator exceeding the limit
+        let mut spill2 = try_new_spill(&exec_ctx, "Test2")?;
+        let mut writer = spill2.get_buf_writer();
+        let err = writer.write_all(&[0u8; 6000]).unwrap_err();
+        assert!(
+            err.to_string().contains("Test2 exceeded max spill size"),
+            "{err}"
+        );
+        drop(writer);
+        drop(spill2);
+
+        let mut read_data = vec![];
+        spill1.get_buf_reader().read_to_end(&mut read_data)?;
+        assert_eq!(read_data.len(), 6000);
+        drop(spill1);
+        exec_ctx.task_resources().spill_scope().release_all();
+        Ok(())
+    }
+}
@@ -55,6 +55,7 @@ use parking_lot::Mutex;
 
 use crate::{
     common::execution_context::ExecutionContext,
+    memmgr::reservation::MemReservation,
     scan::{internal_file_reader::InternalFileReader, BlazeSchemaAdapterFactory},
 };
 
@@ -286,6 +287,7 @@ impl ParquetFileReaderFactory for FsReaderFactory {
         )?);
         let reader = ParquetFileReaderRef(Arc::new(ParquetFileReader {
             internal_reader,
+            mem_reservation: MemReservation::new("ParquetFileReader", 0),
             metrics: ParquetFileMetrics::new(
                 partition_index,
                 file_meta
@@ -302,6 +304,7 @@ impl ParquetFileReaderFactory for FsReaderFactory {
 
 struct ParquetFileReader {
     internal_reader: Arc<InternalFileReader>,
+    mem_reservation: MemReservation,
     metrics: ParquetFileMetrics,
 }
 
@@ -397,6 +400,11 @@ impl AsyncFileReader for ParquetFileReaderRef {
             .expect("tokio spawn_blocking error")
             .map_err(|e| ParquetError::External(Box::new(e)))?;
 
+            // fetched column chunks are held by the decoder until the next
+            // row group is fetched
+            let fetched_size = merged_ranges.iter().map(|range| range.len()).sum();
+            self.0.mem_reservation.resize(fetched_size);
+
             let merged_bytes = &*merged_bytes.lock();
             let mut sorted_range_bytes = Vec::with_capacity(num_ranges);
             let mut m = 0;
@@ -198,6 +198,7 @@ fn execute_project_with_filtering(
         .cloned()
         .collect::<Vec<PhysicalExprRef>>();
 
+    exec_ctx.register_spark_udf_metrics(&[exprs.clone(), filters.clone()].concat());
     let cached_expr_evaluator = Arc::new(CachedExprsEvaluator::try_new(
         filters,
         exprs,
@@ -0,0 +1,446 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt::{Debug, Formatter},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{Array, ArrayRef, RecordBatch, RecordBatchOptions, StructArray},
+    compute::concat_batches,
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    ffi::{from_ffi_and_data_type, FFI_ArrowArray},
+};
+use blaze_jni_bridge::{jni_call, jni_new_direct_byte_buffer, jni_new_global_ref, jni_new_object};
+use datafusion::{
+    common::{Result, Statistics},
+    execution::context::TaskContext,
+    physical_expr::{EquivalenceProperties, PhysicalExprRef},
+    physical_plan::{
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream,
+    },
+};
+use datafusion_ext_commons::{
+    arrow::{array_size::ArraySize, cast::cast},
+    df_execution_err,
+};
+use futures::StreamExt;
+use jni::objects::GlobalRef;
+use once_cell::sync::OnceCell;
+
+use crate::{common::execution_context::ExecutionContext, memmgr::reservation::MemReservation};
+
+/// Evaluates python UDFs of spark's `ArrowEvalPythonExec` (scalar pandas
+/// UDFs). The UDFs are run by spark's own `ArrowPythonRunner` in the JVM side,
+/// which handles the python worker protocol, while the arguments are
+/// evaluated natively. UDF results are appended after the input columns.
+pub struct PythonEvalExec {
+    input: Arc<dyn ExecutionPlan>,
+    serialized: Vec<u8>,
+    args: Vec<PhysicalExprRef>,
+    result_schema: SchemaRef,
+    schema: SchemaRef,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl PythonEvalExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        serialized: Vec<u8>,
+        args: Vec<PhysicalExprRef>,
+        result_schema: SchemaRef,
+    ) -> Result<Self> {
+        let input_schema = input.schema();
+        let schema = Arc::new(Schema::new(
+            input_schema
+                .fields()
+                .iter()
+                .chain(result_schema.fields())
+                .cloned()
+                .collect::<Vec<_>>(),
+        ));
+        Ok(Self {
+            input,
+            serialized,
+            args,
+            result_schema,
+            schema,
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+}
+
+impl Debug for PythonEvalExec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PythonEval")
+    }
+}
+
+impl DisplayAs for PythonEvalExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "PythonEvalExec")
+    }
+}
+
+impl ExecutionPlan for PythonEvalExec {
+    fn name(&self) -> &str {
+        "PythonEvalExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::try_new(
+            children[0].clone(),
+            self.serialized.clone(),
+            self.args.clone(),
+            self.result_schema.clone(),
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let input = exec_ctx.execute_with_input_stats(&self.input)?;
+        execute_python_eval(
+            input,
+            self.serialized.clone(),
+            self.args.clone(),
+            self.result_schema.clone(),
+            exec_ctx,
+        )
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        todo!()
+    }
+}
+
+fn execute_python_eval(
+    mut input: SendableRecordBatchStream,
+    serialized: Vec<u8>,
+    args: Vec<PhysicalExprRef>,
+    result_schema: SchemaRef,
+    exec_ctx: Arc<ExecutionContext>,
+) -> Result<SendableRecordBatchStream> {
+    let python_time = exec_ctx.register_timer_metric("python_time");
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("PythonEval", move |sender| async move {
+            sender.exclude_time(exec_ctx.baseline_metrics().elapsed_compute());
+
+            struct AutoCloseableContext(GlobalRef);
+            impl Drop for AutoCloseableContext {
+                fn drop(&mut self) {
+                    let _ = jni_call!(JavaAutoCloseable(self.0.as_obj()).close() -> ());
+                }
+            }
+            let jcontext = AutoCloseableContext({
+                let serialized_buf = jni_new_direct_byte_buffer!(&serialized)?;
+                let jcontext_local =
+                    jni_new_object!(SparkPythonEvalWrapperContext(serialized_buf.as_obj()))?;
+                jni_new_global_ref!(jcontext_local.as_obj())?
+            });
+
+            // input batches are kept until their results are returned by the
+            // python worker, results come in the same order as
+            // input rows
+            let mut pending = PendingInputs::new(input.schema());
+            let mem_reservation = MemReservation::new("PythonEval", 0);
+            let mut params_schema = None;
+
+            while let Some(batch) = input.next().await.transpose()? {
+                let output_batches = {
+                    let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+                    let params_batch = evaluate_args(&args, &batch, &mut params_schema)?;
+                    pending.push(batch);
+                    mem_reservation.resize(pending.mem_size());
+
+                    let _python_timer = python_time.timer();
+                    feed(&jcontext.0, params_batch)?;
+
+                    // the runner does not flush the worker's input for every
+                    // batch, so only results that are
+                    // already available are taken here
+                    let mut output_batches = vec![];
+                    while let Some(result) = next_result(&jcontext.0, &result_schema, false)? {
+                        output_batches.push(build_output(&exec_ctx, &mut pending, result)?);
+                    }
+                    mem_reservation.resize(pending.mem_size());
+                    output_batches
+                };
+                for output_batch in output_batches {
+                    sender.send(output_batch).await;
+                }
+            }
+
+            // finish input and wait for all remaining results
+            jni_call!(SparkPythonEvalWrapperContext(jcontext.0.as_obj()).finishInput() -> ())?;
+            loop {
+                let jcontext_obj = jcontext.0.clone();
+                let result_schema = result_schema.clone();
+                let python_time = python_time.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let _python_timer = python_time.timer();
+                    next_result(&jcontext_obj, &result_schema, true)
+                })
+                .await
+                .expect("tokio spawn_blocking error")?;
+
+                let Some(result) = result else {
+                    break;
+                };
+                let output_batch = {
+                    let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+                    let output_batch = build_output(&exec_ctx, &mut pending, result)?;
+                    mem_reservation.resize(pending.mem_size());
+                    output_batch
+                };
+                sender.send(output_batch).await;
+            }
+
+            if pending.num_rows > 0 {
+                df_execution_err!(
+                    "PythonEvalExec: python worker returned {} rows less than input",
+                    pending.num_rows,
+                )?;
+            }
+            Ok(())
+        }))
+}
+
+fn build_output(
+    exec_ctx: &ExecutionContext,
+    pending: &mut PendingInputs,
+    result: StructArray,
+) -> Result<RecordBatch> {
+    let input_batch = pending.take_rows(result.len())?;
+    let output_batch = RecordBatch::try_new_with_options(
+        exec_ctx.output_schema(),
+        [input_batch.columns(), result.columns()].concat(),
+        &RecordBatchOptions::new().with_row_count(Some(result.len())),
+    )?;
+    exec_ctx
+        .baseline_metrics()
+        .record_output(output_batch.num_rows());
+    Ok(output_batch)
+}
+
+fn evaluate_args(
+    args: &[PhysicalExprRef],
+    batch: &RecordBatch,
+    params_schema: &mut Option<SchemaRef>,
+) -> Result<RecordBatch> {
+    let batch_schema = batch.schema();
+    let params_schema = match params_schema {
+        Some(params_schema) => params_schema.clone(),
+        None => {
+            let mut param_fields = Vec::with_capacity(args.len());
+            for arg in args {
+                param_fields.push(Field::new(
+                    "",
+                    arg.data_type(&batch_schema)?,
+                    arg.nullable(&batch_schema)?,
+                ));
+            }
+            params_schema
+                .insert(Arc::new(Schema::new(param_fields)))
+                .clone()
+        }
+    };
+
+    let num_rows = batch.num_rows();
+    let params: Vec<ArrayRef> = args
+        .iter()
+        .zip(params_schema.fields())
+        .map(|(arg, field)| {
+            let param_array = arg.evaluate(batch).and_then(|r| r.into_array(num_rows))?;
+            cast(&param_array, field.data_type())
+        })
+        .collect::<Result<_>>()?;
+    Ok(RecordBatch::try_new_with_options(
+        params_schema,
+        params,
+        &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+    )?)
+}
+
+fn feed(jcontext: &GlobalRef, params_batch: RecordBatch) -> Result<()> {
+    let struct_array = StructArray::from(params_batch);
+    let mut export_ffi_array = FFI_ArrowArray::new(&struct_array.to_data());
+    jni_call!(SparkPythonEvalWrapperContext(jcontext.as_obj()).feed(
+        &mut export_ffi_array as *mut FFI_ArrowArray as i64,
+    ) -> ())?;
+    Ok(())
+}
+
+fn next_result(
+    jcontext: &GlobalRef,
+    result_schema: &SchemaRef,
+    blocking: bool,
+) -> Result<Option<StructArray>> {
+    let mut import_ffi_array = FFI_ArrowArray::empty();
+    let has_result = jni_call!(SparkPythonEvalWrapperContext(jcontext.as_obj()).nextResult(
+        &mut import_ffi_array as *mut FFI_ArrowArray as i64,
+        blocking,
+    ) -> bool)?;
+    if !has_result {
+        return Ok(None);
+    }
+    let import_data_type = DataType::Struct(result_schema.fields().clone());
+    let imported = unsafe { from_ffi_and_data_type(import_ffi_array, import_data_type)? };
+    Ok(Some(StructArray::from(imported)))
+}
+
+/// Input batches waiting for their UDF results.
+struct PendingInputs {
+    schema: SchemaRef,
+    batches: VecDeque<RecordBatch>,
+    num_rows: usize,
+}
+
+impl PendingInputs {
+    fn new(schema: SchemaRef) -> Self {
+        Self {
+            schema,
+            batches: VecDeque::new(),
+            num_rows: 0,
+        }
+    }
+
+    fn push(&mut self, batch: RecordBatch) {
+        self.num_rows += batch.num_rows();
+        self.batches.push_back(batch);
+    }
+
+    fn mem_size(&self) -> usize {
+        self.batches
+            .iter()
+            .map(|batch| batch.get_array_mem_size())
+            .sum()
+    }
+
+    /// Takes the first `num_rows` pending rows, which may span or split
+    /// input batches.
+    fn take_rows(&mut self, num_rows: usize) -> Result<RecordBatch> {
+        if num_rows > self.num_rows {
+            df_execution_err!(
+                "PythonEvalExec: python worker returned more rows than input: {num_rows} > {}",
+                self.num_rows,
+            )?;
+        }
+        let mut taken = vec![];
+        let mut remaining = num_rows;
+        while remaining > 0 {
+            let front = self.batches.front_mut().expect("pending batch");
+            if front.num_rows() <= remaining {
+                remaining -= front.num_rows();
+                taken.push(self.batches.pop_front().expect("pending batch"));
+            } else {
+                taken.push(front.slice(0, remaining));
+                *front = front.slice(remaining, front.num_rows() - remaining);
+                remaining = 0;
+            }
+        }
+        self.num_rows -= num_rows;
+
+        if taken.len() == 1 {
+            return Ok(taken.pop().expect("taken batch"));
+        }
+        Ok(concat_batches(&self.schema, &taken)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int32Array, RecordBatch},
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::{assert_batches_eq, common::Result};
+
+    use crate::python_eval_exec::PendingInputs;
+
+    #[test]
+    fn test_pending_inputs() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+        };
+
+        let mut pending = PendingInputs::new(schema.clone());
+        pending.push(batch(vec![1, 2, 3]));
+        pending.push(batch(vec![4, 5]));
+        pending.push(batch(vec![6]));
+
+        // results may split or span input batches
+        let taken = pending.take_rows(2)?;
+        assert_batches_eq!(
+            vec!["+---+", "| a |", "+---+", "| 1 |", "| 2 |", "+---+"],
+            &[taken]
+        );
+        let taken = pending.take_rows(3)?;
+        assert_batches_eq!(
+            vec!["+---+", "| a |", "+---+", "| 3 |", "| 4 |", "| 5 |", "+---+"],
+            &[taken]
+        );
+        assert_eq!(pending.num_rows, 1);
+
+        // more rows than input
+        assert!(pending.take_rows(2).is_err());
+        let taken = pending.take_rows(1)?;
+        assert_batches_eq!(vec!["+---+", "| a |", "+---+", "| 6 |", "+---+"], &[taken]);
+        assert_eq!(pending.num_rows, 0);
+        Ok(())
+    }
+}
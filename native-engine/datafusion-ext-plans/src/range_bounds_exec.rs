@@ -0,0 +1,321 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, fmt::Formatter, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, AsArray},
+    compute::concat,
+    datatypes::{Field, Float32Type, Schema, SchemaRef},
+    record_batch::RecordBatch,
+    row::{RowConverter, SortField},
+};
+use datafusion::{
+    common::Result,
+    execution::context::TaskContext,
+    physical_expr::{EquivalenceProperties, PhysicalSortExpr},
+    physical_plan::{
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream,
+    },
+};
+use datafusion_ext_commons::df_execution_err;
+use futures::StreamExt;
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+
+use crate::{
+    common::execution_context::ExecutionContext,
+    shuffle::range_bounds::{determine_bounds, take_bounds},
+};
+
+/// determines range partitioning bounds from sampled keys. input batches are
+/// outputs of RangeSampleExec, in which the last column is the weight of each
+/// sampled key. outputs a single batch of bound keys, at most
+/// partition_count-1 bounds are produced.
+#[derive(Debug)]
+pub struct RangeBoundsExec {
+    input: Arc<dyn ExecutionPlan>,
+    sort_exprs: Vec<PhysicalSortExpr>,
+    partition_count: usize,
+    schema: SchemaRef,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl RangeBoundsExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        sort_exprs: Vec<PhysicalSortExpr>,
+        partition_count: usize,
+    ) -> Result<Self> {
+        let input_schema = input.schema();
+        let fields = sort_exprs
+            .iter()
+            .enumerate()
+            .map(|(i, sort_expr)| {
+                Ok(Field::new(
+                    format!("key_{i}"),
+                    sort_expr.expr.data_type(&input_schema)?,
+                    true,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            input,
+            sort_exprs,
+            partition_count,
+            schema: Arc::new(Schema::new(fields)),
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+}
+
+impl DisplayAs for RangeBoundsExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "RangeBoundsExec: [{}], partition_count={}",
+            self.sort_exprs.iter().join(", "),
+            self.partition_count,
+        )
+    }
+}
+
+impl ExecutionPlan for RangeBoundsExec {
+    fn name(&self) -> &str {
+        "RangeBoundsExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::try_new(
+            children[0].clone(),
+            self.sort_exprs.clone(),
+            self.partition_count,
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let input = exec_ctx.execute_with_input_stats(&self.input)?;
+        execute_range_bounds(
+            input,
+            self.sort_exprs.clone(),
+            self.partition_count,
+            exec_ctx,
+        )
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+}
+
+fn execute_range_bounds(
+    mut input: SendableRecordBatchStream,
+    sort_exprs: Vec<PhysicalSortExpr>,
+    partition_count: usize,
+    exec_ctx: Arc<ExecutionContext>,
+) -> Result<SendableRecordBatchStream> {
+    let input_schema = input.schema();
+    let row_converter = RowConverter::new(
+        sort_exprs
+            .iter()
+            .map(|sort_expr| {
+                Ok(SortField::new_with_options(
+                    sort_expr.expr.data_type(&input_schema)?,
+                    sort_expr.options,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    )?;
+
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("RangeBounds", move |sender| async move {
+            let mut candidate_cols: Vec<Vec<ArrayRef>> = vec![vec![]; sort_exprs.len()];
+            let mut weights: Vec<f32> = vec![];
+
+            while let Some(batch) = input.next().await.transpose()? {
+                let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+                let Some(weight_col) = batch.columns().last() else {
+                    return df_execution_err!("RangeBoundsExec: missing weight column");
+                };
+                weights.extend(weight_col.as_primitive::<Float32Type>().values());
+                for (i, sort_expr) in sort_exprs.iter().enumerate() {
+                    let key_col = sort_expr.expr.evaluate(&batch)?;
+                    candidate_cols[i].push(key_col.into_array(batch.num_rows())?);
+                }
+            }
+
+            if weights.is_empty() {
+                return Ok(());
+            }
+            let timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+            let candidate_cols = candidate_cols
+                .iter()
+                .map(|cols| {
+                    let cols = cols.iter().map(|col| col.as_ref()).collect::<Vec<_>>();
+                    Ok(concat(&cols)?)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let candidates = row_converter.convert_columns(&candidate_cols)?;
+            let bounds = determine_bounds(&candidates, &weights, partition_count);
+            log::info!(
+                "determined {} range partitioning bounds from {} sampled keys",
+                bounds.len(),
+                candidates.num_rows(),
+            );
+
+            let bound_cols = take_bounds(&candidate_cols, &bounds)?;
+            let output_batch = RecordBatch::try_new(exec_ctx.output_schema(), bound_cols)?;
+            exec_ctx
+                .baseline_metrics()
+                .record_output(output_batch.num_rows());
+            drop(timer);
+            sender.send(output_batch).await;
+            Ok(())
+        }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Float32Array, Int32Array},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::Result,
+        physical_expr::{expressions::Column, PhysicalSortExpr},
+        physical_plan::{common, memory::MemoryExec, ExecutionPlan},
+        prelude::SessionContext,
+    };
+
+    use crate::{
+        memmgr::MemManager, range_bounds_exec::RangeBoundsExec, range_sample_exec::RangeSampleExec,
+    };
+
+    #[tokio::test]
+    async fn test_range_sample_and_bounds() -> Result<()> {
+        MemManager::init(10000);
+        let session_ctx = SessionContext::new();
+        let task_ctx = session_ctx.task_ctx();
+
+        // sample keys of each partition
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let partitions = (0..4)
+            .map(|p| {
+                let batch = RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(
+                        (0..1000).map(|i| i * 4 + p),
+                    ))],
+                )?;
+                Ok(vec![batch])
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("a", 0)),
+            options: Default::default(),
+        }];
+        let sample_exec = RangeSampleExec::try_new(input, sort_exprs, 100)?;
+        let mut samples = vec![];
+        for p in 0..4 {
+            let stream = sample_exec.execute(p, task_ctx.clone())?;
+            let batches = common::collect(stream).await?;
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0].num_rows(), 100);
+            assert_eq!(
+                batches[0].column(1).as_ref(),
+                &Float32Array::from(vec![10.0; 100]),
+            );
+            samples.extend(batches);
+        }
+
+        // determine bounds from all samples
+        let input = Arc::new(MemoryExec::try_new(&[samples], sample_exec.schema(), None)?);
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("key_0", 0)),
+            options: Default::default(),
+        }];
+        let bounds_exec = RangeBoundsExec::try_new(input, sort_exprs, 4)?;
+        let stream = bounds_exec.execute(0, task_ctx.clone())?;
+        let batches = common::collect(stream).await?;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 3);
+        let bounds = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap()
+            .values()
+            .to_vec();
+        for (bound, expected) in bounds.into_iter().zip([1000, 2000, 3000]) {
+            assert!((bound - expected).abs() < 300, "bound={bound}");
+        }
+
+        // empty input produces no samples and no bounds
+        let input = Arc::new(MemoryExec::try_new(&[vec![]], schema.clone(), None)?);
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("a", 0)),
+            options: Default::default(),
+        }];
+        let sample_exec = Arc::new(RangeSampleExec::try_new(input, sort_exprs, 100)?);
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("key_0", 0)),
+            options: Default::default(),
+        }];
+        let bounds_exec = RangeBoundsExec::try_new(sample_exec, sort_exprs, 4)?;
+        let stream = bounds_exec.execute(0, task_ctx.clone())?;
+        let batches = common::collect(stream).await?;
+        assert!(batches.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, fmt::Formatter, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, Float32Array},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    record_batch::RecordBatch,
+    row::{RowConverter, SortField},
+};
+use datafusion::{
+    common::Result,
+    execution::context::TaskContext,
+    physical_expr::{EquivalenceProperties, PhysicalSortExpr},
+    physical_plan::{
+        metrics::{ExecutionPlanMetricsSet, MetricsSet},
+        DisplayAs, DisplayFormatType, ExecutionMode, ExecutionPlan, ExecutionPlanProperties,
+        PlanProperties, SendableRecordBatchStream,
+    },
+};
+use futures::StreamExt;
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+
+use crate::{common::execution_context::ExecutionContext, shuffle::range_bounds::ReservoirSampler};
+
+/// samples range partitioning keys of each partition with reservoir sampling.
+/// outputs a single batch of sampled keys (columns key_0, key_1, ...) and a
+/// trailing weight column, the weight is number of input rows represented by
+/// each sampled key. sampled batches of all partitions are collected and
+/// passed to RangeBoundsExec to determine the partition bounds.
+#[derive(Debug)]
+pub struct RangeSampleExec {
+    input: Arc<dyn ExecutionPlan>,
+    sort_exprs: Vec<PhysicalSortExpr>,
+    sample_size: usize,
+    schema: SchemaRef,
+    metrics: ExecutionPlanMetricsSet,
+    props: OnceCell<PlanProperties>,
+}
+
+impl RangeSampleExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        sort_exprs: Vec<PhysicalSortExpr>,
+        sample_size: usize,
+    ) -> Result<Self> {
+        let input_schema = input.schema();
+        let mut fields = sort_exprs
+            .iter()
+            .enumerate()
+            .map(|(i, sort_expr)| {
+                Ok(Field::new(
+                    format!("key_{i}"),
+                    sort_expr.expr.data_type(&input_schema)?,
+                    true,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        fields.push(Field::new("weight", DataType::Float32, false));
+
+        Ok(Self {
+            input,
+            sort_exprs,
+            sample_size,
+            schema: Arc::new(Schema::new(fields)),
+            metrics: ExecutionPlanMetricsSet::new(),
+            props: OnceCell::new(),
+        })
+    }
+}
+
+impl DisplayAs for RangeSampleExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "RangeSampleExec: [{}], sample_size={}",
+            self.sort_exprs.iter().join(", "),
+            self.sample_size,
+        )
+    }
+}
+
+impl ExecutionPlan for RangeSampleExec {
+    fn name(&self) -> &str {
+        "RangeSampleExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.props.get_or_init(|| {
+            PlanProperties::new(
+                EquivalenceProperties::new(self.schema()),
+                self.input.output_partitioning().clone(),
+                ExecutionMode::Bounded,
+            )
+        })
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(Self::try_new(
+            children[0].clone(),
+            self.sort_exprs.clone(),
+            self.sample_size,
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let exec_ctx = ExecutionContext::new(context, partition, self.schema(), &self.metrics);
+        let input = exec_ctx.execute_with_input_stats(&self.input)?;
+        execute_range_sample(input, self.sort_exprs.clone(), self.sample_size, exec_ctx)
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+}
+
+fn execute_range_sample(
+    mut input: SendableRecordBatchStream,
+    sort_exprs: Vec<PhysicalSortExpr>,
+    sample_size: usize,
+    exec_ctx: Arc<ExecutionContext>,
+) -> Result<SendableRecordBatchStream> {
+    let input_schema = input.schema();
+    let row_converter = RowConverter::new(
+        sort_exprs
+            .iter()
+            .map(|sort_expr| {
+                Ok(SortField::new_with_options(
+                    sort_expr.expr.data_type(&input_schema)?,
+                    sort_expr.options,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    )?;
+
+    // use partition id as seed, so that sampling is deterministic when the
+    // task is retried
+    let mut sampler = ReservoirSampler::new(sample_size, exec_ctx.partition_id() as u64);
+
+    Ok(exec_ctx
+        .clone()
+        .output_with_sender("RangeSample", move |sender| async move {
+            while let Some(batch) = input.next().await.transpose()? {
+                let _timer = exec_ctx.baseline_metrics().elapsed_compute().timer();
+                let key_cols: Vec<ArrayRef> = sort_exprs
+                    .iter()
+                    .map(|sort_expr| {
+                        sort_expr
+                            .expr
+                            .evaluate(&batch)
+                            .and_then(|v| v.into_array(batch.num_rows()))
+                    })
+                    .collect::<Result<_>>()?;
+                sampler.insert_batch(&key_cols, &row_converter)?;
+            }
+
+            let num_rows = sampler.num_rows();
+            if num_rows == 0 {
+                return Ok(());
+            }
+            let (samples, _) = sampler.finish(&row_converter);
+            let weight = num_rows as f32 / samples.num_rows() as f32;
+            let mut cols = row_converter.convert_rows(samples.iter())?;
+            cols.push(Arc::new(Float32Array::from(vec![
+                weight;
+                samples.num_rows()
+            ])));
+
+            let output_batch = RecordBatch::try_new(exec_ctx.output_schema(), cols)?;
+            exec_ctx
+                .baseline_metrics()
+                .record_output(output_batch.num_rows());
+            sender.send(output_batch).await;
+            Ok(())
+        }))
+}
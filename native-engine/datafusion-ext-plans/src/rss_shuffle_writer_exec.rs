@@ -30,14 +30,18 @@ use datafusion::{
         SendableRecordBatchStream, Statistics,
     },
 };
+use datafusion_ext_commons::df_execution_err;
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 
 use crate::{
     common::execution_context::ExecutionContext,
     memmgr::MemManager,
     shuffle::{
+        rss::{JniRssPartitionWriter, RssBatchPusher, RssPushConfig},
         rss_single_repartitioner::RssSingleShuffleRepartitioner,
-        rss_sort_repartitioner::RssSortShuffleRepartitioner, Partitioning, ShuffleRepartitioner,
+        rss_sort_repartitioner::RssSortShuffleRepartitioner,
+        Partitioning, ShuffleRepartitioner,
     },
     sort_exec::SortExec,
 };
@@ -119,23 +123,31 @@ impl ExecutionPlan for RssShuffleWriterExec {
             JniBridge.getResource(resource_id.as_obj()) -> JObject
         )?;
         let rss_partition_writer = jni_new_global_ref!(rss_partition_writer_local.as_obj())?;
+        let rss_pusher = Arc::new(Mutex::new(RssBatchPusher::new(
+            Box::new(JniRssPartitionWriter::new(rss_partition_writer)),
+            RssPushConfig::try_from_conf()?,
+        )));
         let mut input = self.input.clone();
 
         let repartitioner: Arc<dyn ShuffleRepartitioner> = match &self.partitioning {
             p if p.partition_count() == 1 => {
-                Arc::new(RssSingleShuffleRepartitioner::new(rss_partition_writer))
+                Arc::new(RssSingleShuffleRepartitioner::new(rss_pusher))
             }
             Partitioning::HashPartitioning(..) | Partitioning::RangePartitioning(..) => {
                 let sort_time = exec_ctx.register_timer_metric("sort_time");
                 let partitioner = Arc::new(RssSortShuffleRepartitioner::new(
+                    exec_ctx.stage_id(),
                     partition,
-                    rss_partition_writer,
+                    rss_pusher,
                     self.partitioning.clone(),
                     sort_time,
                 ));
                 MemManager::register_consumer(partitioner.clone(), true);
                 partitioner
             }
+            Partitioning::SkewedHashPartitioning(..) => {
+                return df_execution_err!("hot key splitting is not supported in rss shuffle");
+            }
             Partitioning::RoundRobinPartitioning(..) => {
                 let sort_time = exec_ctx.register_timer_metric("sort_time");
                 let sort_expr: Vec<PhysicalSortExpr> = self
@@ -152,8 +164,9 @@ impl ExecutionPlan for RssShuffleWriterExec {
                 input = Arc::new(SortExec::new(self.input.clone(), sort_expr, None));
 
                 let partitioner = Arc::new(RssSortShuffleRepartitioner::new(
+                    exec_ctx.stage_id(),
                     partition,
-                    rss_partition_writer,
+                    rss_pusher,
                     self.partitioning.clone(),
                     sort_time,
                 ));
@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
 use arrow::record_batch::RecordBatch;
-use blaze_jni_bridge::{is_task_running, jni_call};
+use blaze_jni_bridge::is_task_running;
 use bytesize::ByteSize;
 use count_write::CountWrite;
 use datafusion::{common::Result, physical_plan::metrics::Time};
@@ -28,25 +28,26 @@ use datafusion_ext_commons::{
     compute_suggested_batch_size_for_output, df_execution_err,
 };
 use itertools::Itertools;
-use jni::objects::GlobalRef;
-#[cfg(test)]
 use parking_lot::Mutex;
 
 use crate::{
     common::{
-        ipc_compression::IpcCompressionWriter,
+        ipc_compression::{arrow_ipc_format_enabled, IpcCompressionWriter},
         offsetted::{Offsetted, OffsettedMergeIterator},
         timer_helper::TimerHelper,
     },
     shuffle::{
-        evaluate_hashes, evaluate_partition_ids, evaluate_range_partition_ids,
-        evaluate_robin_partition_ids, rss::RssWriter, Partitioning,
+        evaluate_output_partition_ids,
+        rss::{RssBatchPusher, RssWriter},
+        sorted_merge::{ShuffleSortKeys, SortedRun, SortedRunsMerger},
+        Partitioning,
     },
 };
 
 pub struct BufferedData {
     partition_id: usize,
     partitioning: Partitioning,
+    sort_keys: Option<Arc<ShuffleSortKeys>>,
     staging_batches: Vec<RecordBatch>,
     staging_num_rows: usize,
     staging_mem_used: usize,
@@ -58,10 +59,16 @@ pub struct BufferedData {
 }
 
 impl BufferedData {
-    pub fn new(partitioning: Partitioning, partition_id: usize, sort_time: Time) -> Self {
+    pub fn new(
+        partitioning: Partitioning,
+        sort_keys: Option<Arc<ShuffleSortKeys>>,
+        partition_id: usize,
+        sort_time: Time,
+    ) -> Self {
         Self {
             partition_id,
             partitioning,
+            sort_keys,
             staging_batches: vec![],
             staging_num_rows: 0,
             staging_mem_used: 0,
@@ -78,6 +85,7 @@ impl BufferedData {
             self,
             Self::new(
                 self.partitioning.clone(),
+                self.sort_keys.clone(),
                 self.partition_id,
                 self.sort_time.clone(),
             ),
@@ -106,6 +114,7 @@ impl BufferedData {
             sort_batches_by_partition_id(
                 staging_batches,
                 &self.partitioning,
+                self.sort_keys.as_deref(),
                 sorted_num_rows,
                 self.partition_id,
             )
@@ -133,21 +142,23 @@ impl BufferedData {
             return Ok(vec![0; self.partitioning.partition_count() + 1]);
         }
         let num_partitions = self.partitioning.partition_count();
-        let mut writer = IpcCompressionWriter::new(CountWrite::from(&mut w));
+        let mut writer = IpcCompressionWriter::new(CountWrite::from(&mut w))
+            .try_with_arrow_ipc_format(arrow_ipc_format_enabled())?;
         let mut offsets = vec![];
-        let mut iter = self.into_sorted_batches()?;
 
-        while let Some((partition_id, batch_iter)) = iter.next_partition_chunk() {
+        self.for_each_partition_chunk(|partition_id, batch_iter| {
             if !is_task_running() {
                 df_execution_err!("task completed/killed")?;
             }
 
             offsets.resize(partition_id + 1, writer.inner().count());
             for batch in batch_iter {
+                let batch = batch?;
                 writer.write_batch(batch.num_rows(), batch.columns())?;
             }
             writer.finish_current_buf()?;
-        }
+            Ok(())
+        })?;
         offsets.resize(num_partitions + 1, writer.inner().count());
 
         let compressed_size = ByteSize(offsets.last().cloned().unwrap_or_default());
@@ -156,7 +167,8 @@ impl BufferedData {
     }
 
     // write buffered data to rss, returns uncompressed size
-    pub fn write_rss(mut self, rss_partition_writer: GlobalRef) -> Result<()> {
+    // returns the number of bytes written to rss
+    pub fn write_rss(mut self, rss_pusher: &Arc<Mutex<RssBatchPusher>>) -> Result<usize> {
         if !self.staging_batches.is_empty() {
             self.flush_staging()?;
         }
@@ -165,25 +177,67 @@ impl BufferedData {
         log::info!("draining all buffered data to rss, total_mem={mem_used}");
 
         if self.num_rows == 0 {
-            return Ok(());
+            return Ok(0);
         }
-        let mut iter = self.into_sorted_batches()?;
-        let mut writer = IpcCompressionWriter::new(RssWriter::new(rss_partition_writer.clone(), 0));
+        let start_bytes_written = rss_pusher.lock().num_bytes_written();
+        let mut writer = IpcCompressionWriter::new(RssWriter::new(rss_pusher.clone(), 0));
 
-        while let Some((partition_id, batch_iter)) = iter.next_partition_chunk() {
+        self.for_each_partition_chunk(|partition_id, batch_iter| {
             if !is_task_running() {
                 df_execution_err!("task completed/killed")?;
             }
 
             // write all batches with this part id
-            writer.set_output(RssWriter::new(rss_partition_writer.clone(), partition_id));
+            writer.set_output(RssWriter::new(rss_pusher.clone(), partition_id));
             for batch in batch_iter {
+                let batch = batch?;
                 writer.write_batch(batch.num_rows(), batch.columns())?;
             }
             writer.finish_current_buf()?;
-        }
-        jni_call!(BlazeRssPartitionWriterBase(rss_partition_writer.as_obj()).flush() -> ())?;
+            Ok(())
+        })?;
+        let mut rss_pusher = rss_pusher.lock();
+        rss_pusher.flush()?;
         log::info!("all buffered data drained to rss");
+        Ok(rss_pusher.num_bytes_written() - start_bytes_written)
+    }
+
+    // calls f with batches of each non-empty partition in order of partition
+    // id. in key-sorted mode, sorted runs of the partition are merged by
+    // keys so the partition is written as one sorted run
+    fn for_each_partition_chunk(
+        self,
+        mut f: impl FnMut(usize, &mut dyn Iterator<Item = Result<RecordBatch>>) -> Result<()>,
+    ) -> Result<()> {
+        let Some(sort_keys) = self.sort_keys.clone() else {
+            let mut iter = self.into_sorted_batches()?;
+            while let Some((partition_id, batch_iter)) = iter.next_partition_chunk() {
+                f(partition_id, &mut batch_iter.map(Ok))?;
+            }
+            return Ok(());
+        };
+
+        let sub_batch_size =
+            compute_suggested_batch_size_for_output(self.mem_used(), self.num_rows);
+        for partition_id in 0..self.partitioning.partition_count() {
+            let runs = self
+                .sorted_batches
+                .iter()
+                .zip(&self.sorted_offsets)
+                .filter_map(|(batch, offsets)| {
+                    let start = offsets[partition_id] as usize;
+                    let end = offsets[partition_id + 1] as usize;
+                    (start < end).then(|| -> SortedRun {
+                        Box::new(std::iter::once(Ok(batch.slice(start, end - start))))
+                    })
+                })
+                .collect::<Vec<_>>();
+            if !runs.is_empty() {
+                let mut merger =
+                    SortedRunsMerger::try_new(sort_keys.clone(), runs, sub_batch_size)?;
+                f(partition_id, &mut merger)?;
+            }
+        }
         Ok(())
     }
 
@@ -276,6 +330,7 @@ impl<'a> PartitionedBatchesIterator<'a> {
 fn sort_batches_by_partition_id(
     batches: Vec<RecordBatch>,
     partitioning: &Partitioning,
+    sort_keys: Option<&ShuffleSortKeys>,
     current_num_rows: usize,
     partition_id: usize,
 ) -> Result<(Vec<u32>, RecordBatch)> {
@@ -284,35 +339,17 @@ fn sort_batches_by_partition_id(
         (partition_id * 1000193 + current_num_rows) % partitioning.partition_count();
 
     // compute partition indices
-    let mut partition_indices = batches
-        .iter()
-        .enumerate()
-        .flat_map(|(batch_idx, batch)| {
-            let part_ids = match partitioning {
-                Partitioning::HashPartitioning(..) => {
-                    // compute partition indices
-                    let hashes = evaluate_hashes(partitioning, &batch)
-                        .expect(&format!("error evaluating hashes with {partitioning}"));
-                    evaluate_partition_ids(hashes, partitioning.partition_count())
-                }
-                Partitioning::RoundRobinPartitioning(..) => {
-                    let part_ids =
-                        evaluate_robin_partition_ids(partitioning, &batch, round_robin_start_rows);
-                    round_robin_start_rows += batch.num_rows();
-                    round_robin_start_rows %= partitioning.partition_count();
-                    part_ids
-                }
-                Partitioning::RangePartitioning(sort_expr, _, bounds) => {
-                    evaluate_range_partition_ids(&batch, sort_expr, bounds).unwrap()
-                }
-                _ => unreachable!("unsupported partitioning: {:?}", partitioning),
-            };
+    let mut partition_indices = vec![];
+    for (batch_idx, batch) in batches.iter().enumerate() {
+        let part_ids =
+            evaluate_output_partition_ids(partitioning, batch, &mut round_robin_start_rows)?;
+        partition_indices.extend(
             part_ids
                 .into_iter()
                 .enumerate()
-                .map(move |(row_idx, part_id)| (part_id, batch_idx as u32, row_idx as u32))
-        })
-        .collect::<Vec<_>>();
+                .map(|(row_idx, part_id)| (part_id, batch_idx as u32, row_idx as u32)),
+        );
+    }
 
     // sort
     let mut part_counts = vec![0; num_partitions];
@@ -331,6 +368,23 @@ fn sort_batches_by_partition_id(
     }
     partition_offsets.push(offset);
 
+    // in key-sorted mode, also sort rows of each partition by keys
+    if let Some(sort_keys) = sort_keys {
+        let key_rows = batches
+            .iter()
+            .map(|batch| sort_keys.convert(batch))
+            .collect::<Result<Vec<_>>>()?;
+        for (&start, &end) in partition_offsets.iter().tuple_windows() {
+            partition_indices[start as usize..end as usize].sort_by(
+                |&(_, batch_idx1, row_idx1), &(_, batch_idx2, row_idx2)| {
+                    let key1 = key_rows[batch_idx1 as usize].row(row_idx1 as usize);
+                    let key2 = key_rows[batch_idx2 as usize].row(row_idx2 as usize);
+                    key1.cmp(&key2)
+                },
+            );
+        }
+    }
+
     // get sorted batch
     let batches_interleaver = create_batch_interleaver(&batches, true)?;
     let sorted_batch = batches_interleaver(
@@ -344,7 +398,7 @@ fn sort_batches_by_partition_id(
 
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
+    use std::{io::Cursor, sync::Arc};
 
     use arrow::{
         array::{ArrayRef, Int32Array},
@@ -360,6 +414,7 @@ mod test {
     };
 
     use super::*;
+    use crate::common::ipc_compression::IpcCompressionReader;
 
     fn build_table_i32(
         a: (&str, &Vec<i32>),
@@ -392,8 +447,13 @@ mod test {
         );
 
         let round_robin_partitioning = Partitioning::RoundRobinPartitioning(4);
-        let (_parts, sorted_batch) =
-            sort_batches_by_partition_id(vec![record_batch], &round_robin_partitioning, 3, 0)?;
+        let (_parts, sorted_batch) = sort_batches_by_partition_id(
+            vec![record_batch],
+            &round_robin_partitioning,
+            None,
+            3,
+            0,
+        )?;
 
         let expected = vec![
             "+----+---+---+",
@@ -447,7 +507,7 @@ mod test {
         let range_repartitioning =
             Partitioning::RangePartitioning(sort_exprs, partition_num, Arc::from(rows));
         let (_parts, sorted_batch) =
-            sort_batches_by_partition_id(vec![record_batch], &range_repartitioning, 0, 0)?;
+            sort_batches_by_partition_id(vec![record_batch], &range_repartitioning, None, 0, 0)?;
 
         let expected = vec![
             "+----+---+---+",
@@ -509,7 +569,7 @@ mod test {
         let range_repartitioning =
             Partitioning::RangePartitioning(sort_exprs, partition_num, Arc::from(rows));
         let (_parts, sorted_batch) =
-            sort_batches_by_partition_id(vec![record_batch], &range_repartitioning, 0, 0)?;
+            sort_batches_by_partition_id(vec![record_batch], &range_repartitioning, None, 0, 0)?;
 
         let expected = vec![
             "+----+---+---+",
@@ -530,4 +590,55 @@ mod test {
         assert_batches_eq!(expected, &vec![sorted_batch]);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_key_sorted_write() -> Result<()> {
+        let batch1 = build_table_i32(
+            ("a", &vec![19, 17, 15, 13, 11]),
+            ("b", &vec![0, 2, 4, 6, 8]),
+            ("c", &vec![5, 7, 9, 1, 3]),
+        );
+        let batch2 = build_table_i32(
+            ("a", &vec![18, 16, 14, 12, 10]),
+            ("b", &vec![1, 3, 5, 7, 9]),
+            ("c", &vec![6, 8, 0, 2, 4]),
+        );
+        let schema = batch1.schema();
+        let partitioning = Partitioning::HashPartitioning(vec![Arc::new(Column::new("a", 0))], 3);
+        let sort_keys = Arc::new(ShuffleSortKeys::try_new(
+            vec![PhysicalSortExpr {
+                expr: Arc::new(Column::new("c", 2)),
+                options: SortOptions {
+                    descending: true,
+                    nulls_first: false,
+                },
+            }],
+            &schema,
+        )?);
+
+        // two sorted batches are merged by keys when writing
+        let mut data = BufferedData::new(partitioning, Some(sort_keys), 0, Time::new());
+        data.add_batch(batch1)?;
+        data.flush_staging()?;
+        data.add_batch(batch2)?;
+        data.flush_staging()?;
+        let mut buf = vec![];
+        let offsets = data.write(&mut buf)?;
+        assert_eq!(offsets.len(), 4);
+
+        let mut num_rows = 0;
+        for (&start, &end) in offsets.iter().tuple_windows() {
+            let partition_data = buf[start as usize..end as usize].to_vec();
+            let mut reader = IpcCompressionReader::new(Cursor::new(partition_data));
+            let mut values = vec![];
+            while let Some((batch_num_rows, cols)) = reader.read_batch(&schema)? {
+                let col = cols[2].as_any().downcast_ref::<Int32Array>().unwrap();
+                values.extend(col.values().iter().copied());
+                num_rows += batch_num_rows;
+            }
+            assert!(values.windows(2).all(|w| w[0] >= w[1]), "{values:?}");
+        }
+        assert_eq!(num_rows, 10);
+        Ok(())
+    }
 }
@@ -0,0 +1,381 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Seek, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use blaze_jni_bridge::{conf, conf::BooleanConf, is_jni_bridge_inited};
+use datafusion::{
+    common::{DataFusionError, Result},
+    physical_plan::metrics::Time,
+};
+use datafusion_ext_commons::{
+    algorithm::rdx_sort::radix_sort_by_key, arrow::selection::take_batch, df_execution_err,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    common::{
+        execution_context::ExecutionContext,
+        ipc_compression::{
+            arrow_ipc_format_enabled, IpcCompressionWriter,
+            DEFAULT_SHUFFLE_COMPRESSION_TARGET_BUF_SIZE,
+        },
+        timer_helper::{TimedWriter, TimerHelper},
+    },
+    memmgr::reservation::MemReservation,
+    shuffle::{
+        checksum::{PartitionChecksumWriter, ShuffleChecksumOutput},
+        evaluate_output_partition_ids, Partitioning, ShuffleRepartitioner,
+    },
+};
+
+// total size of compressed blocks buffered in all partition writers
+const MAX_BUFFERED_SIZE: usize = 67108864;
+const MIN_BLOCK_SIZE: usize = 262144;
+const PARTITION_FILE_BUF_SIZE: usize = 32768;
+
+type PartitionWriter = IpcCompressionWriter<BufWriter<TimedWriter<File>>>;
+
+/// bypass-merge shuffle repartitioner, same as spark's
+/// BypassMergeSortShuffleWriter. rows are directly written into a temporary
+/// file of each output partition without buffering and sorting, and the
+/// partition files are concatenated into the output data file at last.
+/// only used with small number of output partitions since all partition
+/// files are opened at the same time.
+pub struct BypassShuffleRepartitioner {
+    exec_ctx: Arc<ExecutionContext>,
+    output_data_file: String,
+    output_index_file: String,
+    checksum_output: Option<ShuffleChecksumOutput>,
+    partitioning: Partitioning,
+    partition_writers: Mutex<PartitionWriters>,
+    block_size: usize,
+    mem_reservation: MemReservation,
+    output_io_time: Time,
+}
+
+struct PartitionWriters {
+    writers: Vec<Option<PartitionWriter>>,
+    num_rows: usize,
+}
+
+impl BypassShuffleRepartitioner {
+    pub fn new(
+        exec_ctx: Arc<ExecutionContext>,
+        output_data_file: String,
+        output_index_file: String,
+        checksum_output: Option<ShuffleChecksumOutput>,
+        partitioning: Partitioning,
+        output_io_time: Time,
+    ) -> Self {
+        let num_partitions = partitioning.partition_count();
+        let block_size = (MAX_BUFFERED_SIZE / num_partitions.max(1))
+            .clamp(MIN_BLOCK_SIZE, DEFAULT_SHUFFLE_COMPRESSION_TARGET_BUF_SIZE);
+        Self {
+            exec_ctx,
+            output_data_file,
+            output_index_file,
+            checksum_output,
+            partitioning,
+            partition_writers: Mutex::new(PartitionWriters {
+                writers: (0..num_partitions).map(|_| None).collect(),
+                num_rows: 0,
+            }),
+            block_size,
+            mem_reservation: MemReservation::new("BypassShuffleRepartitioner", 0),
+            output_io_time,
+        }
+    }
+
+    fn create_partition_writer(&self) -> Result<PartitionWriter> {
+        // partition files are created in the same directory of output file
+        let file = match Path::new(&self.output_data_file).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => tempfile::tempfile_in(dir)?,
+            _ => tempfile::tempfile()?,
+        };
+        IpcCompressionWriter::new(BufWriter::with_capacity(
+            PARTITION_FILE_BUF_SIZE,
+            self.output_io_time.wrap_writer(file),
+        ))
+        .try_with_arrow_ipc_format(arrow_ipc_format_enabled())
+    }
+}
+
+#[async_trait]
+impl ShuffleRepartitioner for BypassShuffleRepartitioner {
+    async fn insert_batch(&self, input: RecordBatch) -> Result<()> {
+        let mut partition_writers = self.partition_writers.lock().await;
+        let num_partitions = self.partitioning.partition_count();
+        let mut round_robin_start_rows =
+            (self.exec_ctx.partition_id() * 1000193 + partition_writers.num_rows) % num_partitions;
+        let part_ids =
+            evaluate_output_partition_ids(&self.partitioning, &input, &mut round_robin_start_rows)?;
+        partition_writers.num_rows += input.num_rows();
+
+        // group rows by partition ids
+        let mut indices = part_ids
+            .into_iter()
+            .enumerate()
+            .map(|(row_idx, part_id)| (part_id, row_idx as u32))
+            .collect::<Vec<_>>();
+        let mut part_counts = vec![0; num_partitions];
+        radix_sort_by_key(&mut indices, &mut part_counts, |&(part_id, _)| {
+            part_id as usize
+        });
+        let sorted_batch = take_batch(
+            input,
+            indices
+                .into_iter()
+                .map(|(_, row_idx)| row_idx)
+                .collect::<Vec<_>>(),
+        )?;
+
+        // write rows of each partition into its partition file
+        let mut offset = 0;
+        for (partition_id, part_count) in part_counts.into_iter().enumerate() {
+            if part_count == 0 {
+                continue;
+            }
+            let partition_batch = sorted_batch.slice(offset, part_count);
+            offset += part_count;
+
+            let writer = &mut partition_writers.writers[partition_id];
+            if writer.is_none() {
+                *writer = Some(self.create_partition_writer()?);
+            }
+            let writer = writer.as_mut().unwrap();
+            writer.write_batch(partition_batch.num_rows(), partition_batch.columns())?;
+            if writer.buffered_len() >= self.block_size {
+                writer.finish_current_buf()?;
+            }
+        }
+
+        // buffered blocks are not spillable, account them as reserved memory
+        let buffered_size = partition_writers
+            .writers
+            .iter()
+            .flatten()
+            .map(|writer| writer.buffered_len() + PARTITION_FILE_BUF_SIZE)
+            .sum();
+        self.mem_reservation.resize(buffered_size);
+        Ok(())
+    }
+
+    async fn shuffle_write(&self) -> Result<()> {
+        let writers = std::mem::take(&mut self.partition_writers.lock().await.writers);
+        let data_file = self.output_data_file.clone();
+        let index_file = self.output_index_file.clone();
+        let checksum_output = self.checksum_output.clone();
+        let output_io_time = self.output_io_time.clone();
+        let transfer_to = file_transfer_to_enabled()?;
+
+        tokio::task::spawn_blocking(move || {
+            // finish all partition files
+            let partition_files = writers
+                .into_iter()
+                .map(|writer| {
+                    writer
+                        .map(|mut writer| -> Result<File> {
+                            writer.finish_current_buf()?;
+                            writer.inner_mut().flush()?;
+                            Ok(writer.inner().get_ref().0.try_clone()?)
+                        })
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut output_data = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&data_file)?;
+            let mut output_index = output_io_time.wrap_writer(
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&index_file)?,
+            );
+
+            // concatenate partition files
+            let offsets = match &checksum_output {
+                Some(checksum_output) => {
+                    let mut output_data = PartitionChecksumWriter::new(
+                        output_io_time.wrap_writer(output_data),
+                        Some(checksum_output.algorithm),
+                    );
+                    let offsets = concat_partition_files(partition_files, &mut output_data)?;
+                    checksum_output.write(&output_data, &offsets)?;
+                    offsets
+                }
+                None if transfer_to => {
+                    // file-to-file copying, which is done in kernel space
+                    // (copy_file_range/sendfile) if supported
+                    output_io_time
+                        .with_timer(|| concat_partition_files(partition_files, &mut output_data))?
+                }
+                None => {
+                    let mut output_data = output_io_time.wrap_writer(output_data);
+                    concat_partition_files(partition_files, &mut output_data)?
+                }
+            };
+
+            // write index file
+            let mut offsets_data = vec![];
+            for &offset in &offsets {
+                offsets_data.extend_from_slice(&(offset as i64).to_le_bytes()[..]);
+            }
+            output_index.write_all(&offsets_data)?;
+            Ok::<(), DataFusionError>(())
+        })
+        .await
+        .or_else(|e| df_execution_err!("shuffle write error: {e:?}"))??;
+        self.mem_reservation.resize(0);
+        Ok(())
+    }
+}
+
+// concatenates compressed data of partition files into output without
+// decompressing, returns offsets of each partition in output
+fn concat_partition_files<W: Write>(
+    partition_files: Vec<Option<File>>,
+    output: &mut W,
+) -> Result<Vec<u64>> {
+    let mut offsets = vec![0];
+    let mut offset = 0;
+    for partition_file in partition_files {
+        if let Some(mut partition_file) = partition_file {
+            partition_file.rewind()?;
+            offset += std::io::copy(&mut partition_file, output)?;
+        }
+        offsets.push(offset);
+    }
+    output.flush()?;
+    Ok(offsets)
+}
+
+fn file_transfer_to_enabled() -> Result<bool> {
+    if !is_jni_bridge_inited() {
+        return Ok(true);
+    }
+    conf::SPARK_FILE_TRANSFER_TO.value()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, io::Cursor, sync::Arc};
+
+    use arrow::{
+        array::Int32Array,
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::{
+        common::Result,
+        physical_expr::expressions::Column,
+        physical_plan::metrics::{ExecutionPlanMetricsSet, Time},
+        prelude::SessionContext,
+    };
+
+    use crate::{
+        common::{execution_context::ExecutionContext, ipc_compression::IpcCompressionReader},
+        shuffle::{
+            bypass_repartitioner::BypassShuffleRepartitioner,
+            checksum::{ShuffleChecksumAlgorithm, ShuffleChecksumOutput},
+            evaluate_hashes, evaluate_partition_ids, Partitioning, ShuffleRepartitioner,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_bypass_shuffle_repartitioner() -> Result<()> {
+        let session_ctx = SessionContext::new();
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let num_partitions = 7;
+        let partitioning =
+            Partitioning::HashPartitioning(vec![Arc::new(Column::new("a", 0))], num_partitions);
+        let batches = (0..10)
+            .map(|i| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(
+                        (0..1000).map(|j| i * 1000 + j),
+                    ))],
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let dir = tempfile::tempdir()?;
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        for with_checksum in [false, true] {
+            let checksum_output = with_checksum.then(|| ShuffleChecksumOutput {
+                checksum_file: path("shuffle.checksum"),
+                algorithm: ShuffleChecksumAlgorithm::Crc32c,
+            });
+            let exec_ctx = ExecutionContext::new(
+                session_ctx.task_ctx(),
+                0,
+                schema.clone(),
+                &ExecutionPlanMetricsSet::new(),
+            );
+            let repartitioner = BypassShuffleRepartitioner::new(
+                exec_ctx,
+                path("shuffle.data"),
+                path("shuffle.index"),
+                checksum_output,
+                partitioning.clone(),
+                Time::new(),
+            );
+            for batch in &batches {
+                repartitioner.insert_batch(batch.clone()).await?;
+            }
+            repartitioner.shuffle_write().await?;
+
+            // each partition contains exactly rows with the partition id
+            let data = fs::read(path("shuffle.data"))?;
+            let offsets = fs::read(path("shuffle.index"))?
+                .chunks(8)
+                .map(|chunk| i64::from_le_bytes(chunk.try_into().unwrap()) as usize)
+                .collect::<Vec<_>>();
+            assert_eq!(offsets.len(), num_partitions + 1);
+            assert_eq!(*offsets.last().unwrap(), data.len());
+
+            let mut total_num_rows = 0;
+            for partition_id in 0..num_partitions {
+                let segment = data[offsets[partition_id]..offsets[partition_id + 1]].to_vec();
+                let mut reader = IpcCompressionReader::new(Cursor::new(segment));
+                while let Some((num_rows, cols)) = reader.read_batch(&schema)? {
+                    let batch = RecordBatch::try_new(schema.clone(), cols)?;
+                    let hashes = evaluate_hashes(&partitioning, &batch)?;
+                    let part_ids = evaluate_partition_ids(hashes, num_partitions);
+                    assert!(part_ids.iter().all(|&id| id as usize == partition_id));
+                    total_num_rows += num_rows;
+                }
+            }
+            assert_eq!(total_num_rows, 10000);
+
+            if with_checksum {
+                let checksum_data = fs::read(path("shuffle.checksum"))?;
+                assert_eq!(checksum_data.len(), num_partitions * 8);
+            }
+        }
+        Ok(())
+    }
+}
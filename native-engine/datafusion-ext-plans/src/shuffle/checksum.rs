@@ -0,0 +1,224 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fs::File, io::Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use datafusion::common::Result;
+use datafusion_ext_commons::df_execution_err;
+
+use crate::shuffle::add_shuffle_bytes_written;
+
+const ADLER32_BASE: u64 = 65521;
+
+/// checksum algorithms of spark.shuffle.checksum.algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShuffleChecksumAlgorithm {
+    Adler32,
+    Crc32,
+    Crc32c,
+}
+
+impl ShuffleChecksumAlgorithm {
+    pub fn try_from_name(name: &str) -> Result<Self> {
+        match name.to_uppercase().as_str() {
+            "ADLER32" => Ok(Self::Adler32),
+            "CRC32" => Ok(Self::Crc32),
+            "CRC32C" => Ok(Self::Crc32c),
+            _ => df_execution_err!("unsupported shuffle checksum algorithm: {name}"),
+        }
+    }
+
+    fn checksum(&self, data: &[u8]) -> u32 {
+        match self {
+            Self::Adler32 => adler2::adler32_slice(data),
+            Self::Crc32 => crc32fast::hash(data),
+            Self::Crc32c => crc32c::crc32c(data),
+        }
+    }
+
+    fn empty_checksum(&self) -> u32 {
+        self.checksum(&[])
+    }
+
+    // computes checksum(AB) from checksum(A), checksum(B) and length of B
+    fn combine(&self, checksum1: u32, checksum2: u32, len2: usize) -> u32 {
+        match self {
+            Self::Adler32 => {
+                let rem = len2 as u64 % ADLER32_BASE;
+                let sum1 = checksum1 as u64 & 0xffff;
+                let sum2 = rem * sum1 % ADLER32_BASE;
+                let high1 = checksum1 as u64 >> 16;
+                let low2 = checksum2 as u64 & 0xffff;
+                let high2 = checksum2 as u64 >> 16;
+                let sum1 = (sum1 + low2 + ADLER32_BASE - 1) % ADLER32_BASE;
+                let sum2 = (sum2 + high1 + high2 + ADLER32_BASE - rem) % ADLER32_BASE;
+                (sum1 | (sum2 << 16)) as u32
+            }
+            Self::Crc32 => {
+                let mut hasher = crc32fast::Hasher::new_with_initial(checksum1);
+                hasher.combine(&crc32fast::Hasher::new_with_initial_len(
+                    checksum2,
+                    len2 as u64,
+                ));
+                hasher.finalize()
+            }
+            Self::Crc32c => crc32c::crc32c_combine(checksum1, checksum2, len2),
+        }
+    }
+}
+
+/// checksum file of shuffle output, written if spark.shuffle.checksum.enabled
+#[derive(Debug, Clone)]
+pub struct ShuffleChecksumOutput {
+    pub checksum_file: String,
+    pub algorithm: ShuffleChecksumAlgorithm,
+}
+
+impl ShuffleChecksumOutput {
+    /// writes checksums in the format of spark shuffle checksum file, which is
+    /// read by the jvm side and committed together with data and index files
+    pub fn write<W: Write>(
+        &self,
+        writer: &PartitionChecksumWriter<W>,
+        offsets: &[u64],
+    ) -> Result<()> {
+        let checksums = writer.partition_checksums(offsets)?;
+        let mut data = Vec::with_capacity(checksums.len() * 8);
+        for checksum in checksums {
+            data.write_i64::<BigEndian>(checksum as i64)?;
+        }
+        File::create(&self.checksum_file)?.write_all(&data)?;
+        Ok(())
+    }
+}
+
+/// computes spark-compatible checksums of each partition in shuffle output.
+/// partition offsets are unknown while writing, so checksum of each write is
+/// recorded and combined by partitions after all data is written. callers must
+/// ensure that a single write never spans over two partitions, which holds for
+/// ipc compression blocks and copied partition segments
+pub struct PartitionChecksumWriter<W: Write> {
+    inner: W,
+    algorithm: Option<ShuffleChecksumAlgorithm>,
+    chunks: Vec<(u64, usize, u32)>, // (offset, len, checksum)
+    offset: u64,
+}
+
+impl<W: Write> PartitionChecksumWriter<W> {
+    /// checksums are not computed if algorithm is none
+    pub fn new(inner: W, algorithm: Option<ShuffleChecksumAlgorithm>) -> Self {
+        Self {
+            inner,
+            algorithm,
+            chunks: vec![],
+            offset: 0,
+        }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// returns checksums of partitions, offsets are from the index file
+    pub fn partition_checksums(&self, offsets: &[u64]) -> Result<Vec<u32>> {
+        let Some(algorithm) = self.algorithm else {
+            return df_execution_err!("shuffle checksum algorithm not specified");
+        };
+        let num_partitions = offsets.len().saturating_sub(1);
+        let mut checksums = vec![algorithm.empty_checksum(); num_partitions];
+        let mut chunks = self.chunks.iter().peekable();
+
+        for partition_id in 0..num_partitions {
+            let partition_range = offsets[partition_id]..offsets[partition_id + 1];
+            while let Some(&&(offset, len, checksum)) = chunks.peek() {
+                if offset >= partition_range.end {
+                    break;
+                }
+                if offset < partition_range.start || offset + len as u64 > partition_range.end {
+                    return df_execution_err!(
+                        "shuffle checksum: write [{offset}, {}) spans over partition {partition_id} \
+                         [{}, {})",
+                        offset + len as u64,
+                        partition_range.start,
+                        partition_range.end,
+                    );
+                }
+                checksums[partition_id] = algorithm.combine(checksums[partition_id], checksum, len);
+                chunks.next();
+            }
+        }
+        Ok(checksums)
+    }
+}
+
+impl<W: Write> Write for PartitionChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        add_shuffle_bytes_written(len);
+        if let Some(algorithm) = self.algorithm {
+            if len > 0 {
+                let checksum = algorithm.checksum(&buf[..len]);
+                self.chunks.push((self.offset, len, checksum));
+            }
+        }
+        self.offset += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use datafusion::common::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_partition_checksums() -> Result<()> {
+        let partitions: Vec<Vec<u8>> = vec![
+            (0..100000u32).map(|i| (i * 31 % 251) as u8).collect(),
+            vec![],
+            b"hello world".to_vec(),
+            (0..70000u32).map(|i| (i % 7) as u8).collect(),
+        ];
+
+        for algorithm in ["ADLER32", "CRC32", "CRC32C"] {
+            let algorithm = ShuffleChecksumAlgorithm::try_from_name(algorithm)?;
+            let mut writer = PartitionChecksumWriter::new(vec![], Some(algorithm));
+            let mut offsets = vec![0];
+            for partition in &partitions {
+                for chunk in partition.chunks(8192) {
+                    writer.write_all(chunk)?;
+                }
+                offsets.push(offsets.last().unwrap() + partition.len() as u64);
+            }
+            let checksums = writer.partition_checksums(&offsets)?;
+            let expected = partitions
+                .iter()
+                .map(|partition| algorithm.checksum(partition))
+                .collect::<Vec<_>>();
+            assert_eq!(checksums, expected);
+            assert_eq!(writer.inner, partitions.concat());
+
+            // a write spanning over partitions is not allowed
+            assert!(writer.partition_checksums(&[0, 100, 170011]).is_err());
+        }
+        assert!(ShuffleChecksumAlgorithm::try_from_name("MD5").is_err());
+        Ok(())
+    }
+}
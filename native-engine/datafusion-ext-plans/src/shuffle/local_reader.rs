@@ -0,0 +1,209 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fs::File, io::Read, os::unix::fs::FileExt};
+
+use blaze_jni_bridge::{conf, conf::IntConf, is_jni_bridge_inited};
+use datafusion::common::Result;
+use datafusion_ext_commons::df_execution_err;
+use memmap2::{Advice, Mmap, MmapOptions};
+
+// size of each pread call, larger reads let the kernel read ahead more data
+const PREAD_READAHEAD_SIZE: usize = 1 << 20;
+
+/// opens the byte range of partitions [start_partition, end_partition) in a
+/// local shuffle data file, the range is located with the committed index
+/// file. large segments are memory-mapped, others are read with pread.
+pub fn open_local_shuffle_segment(
+    data_file_path: &str,
+    index_file_path: &str,
+    start_partition: usize,
+    end_partition: usize,
+) -> Result<Box<dyn Read + Send>> {
+    let index_file = File::open(index_file_path)?;
+    let (offset, end) = read_partition_range(&index_file, start_partition, end_partition)?;
+    let length = end - offset;
+    if length == 0 {
+        return Ok(Box::new(std::io::empty()));
+    }
+
+    let data_file = File::open(data_file_path)?;
+    if length >= mmap_threshold()? {
+        return Ok(Box::new(MmapSegmentReader::try_new(
+            &data_file, offset, length,
+        )?));
+    }
+    Ok(Box::new(PreadSegmentReader::new(data_file, offset, length)))
+}
+
+/// reads byte range of partitions [start_partition, end_partition) from a
+/// spark shuffle index file. note that the committed index file is written by
+/// spark's IndexShuffleBlockResolver, in which offsets are big-endian.
+pub fn read_partition_range(
+    index_file: &File,
+    start_partition: usize,
+    end_partition: usize,
+) -> Result<(u64, u64)> {
+    let mut buf = [0u8; 8];
+    index_file.read_exact_at(&mut buf, start_partition as u64 * 8)?;
+    let start = i64::from_be_bytes(buf);
+    index_file.read_exact_at(&mut buf, end_partition as u64 * 8)?;
+    let end = i64::from_be_bytes(buf);
+
+    if start < 0 || end < start {
+        df_execution_err!(
+            "invalid shuffle index offsets of partitions [{start_partition}, {end_partition}): \
+            [{start}, {end})"
+        )?;
+    }
+    Ok((start as u64, end as u64))
+}
+
+fn mmap_threshold() -> Result<u64> {
+    if !is_jni_bridge_inited() {
+        return Ok(4 << 20);
+    }
+    let threshold = conf::SHUFFLE_LOCAL_READ_MMAP_THRESHOLD.value()?;
+    if threshold <= 0 {
+        return Ok(u64::MAX); // mmap disabled
+    }
+    Ok(threshold as u64)
+}
+
+struct MmapSegmentReader {
+    mmap: Mmap,
+    pos: usize,
+}
+
+impl MmapSegmentReader {
+    fn try_new(file: &File, offset: u64, length: u64) -> Result<Self> {
+        // safety: shuffle data files are immutable once committed
+        let mmap = unsafe {
+            MmapOptions::new()
+                .offset(offset)
+                .len(length as usize)
+                .map(file)?
+        };
+
+        // segment is read sequentially, let the kernel read ahead aggressively
+        mmap.advise(Advice::Sequential)?;
+        mmap.advise(Advice::WillNeed)?;
+        Ok(Self { mmap, pos: 0 })
+    }
+}
+
+impl Read for MmapSegmentReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = &self.mmap[self.pos..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+struct PreadSegmentReader {
+    file: File,
+    pos: u64,
+    end: u64,
+    buf: Vec<u8>,
+    buf_pos: usize,
+}
+
+impl PreadSegmentReader {
+    fn new(file: File, offset: u64, length: u64) -> Self {
+        Self {
+            file,
+            pos: offset,
+            end: offset + length,
+            buf: vec![],
+            buf_pos: 0,
+        }
+    }
+}
+
+impl Read for PreadSegmentReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buf_pos == self.buf.len() {
+            let read_len = PREAD_READAHEAD_SIZE.min((self.end - self.pos) as usize);
+            if read_len == 0 {
+                return Ok(0);
+            }
+            self.buf.resize(read_len, 0);
+            self.file.read_exact_at(&mut self.buf, self.pos)?;
+            self.pos += read_len as u64;
+            self.buf_pos = 0;
+        }
+        let remaining = &self.buf[self.buf_pos..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.buf_pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_local_shuffle_segment() -> Result<()> {
+        let partitions: Vec<Vec<u8>> = vec![
+            (0..100).map(|i| i as u8).collect(),
+            vec![],
+            (0..3000000).map(|i| (i % 251) as u8).collect(),
+            vec![7; 10],
+        ];
+        let mut data_file = tempfile::NamedTempFile::new()?;
+        let mut index_file = tempfile::NamedTempFile::new()?;
+        let mut offset = 0i64;
+        index_file.write_all(&offset.to_be_bytes())?;
+        for partition in &partitions {
+            data_file.write_all(partition)?;
+            offset += partition.len() as i64;
+            index_file.write_all(&offset.to_be_bytes())?;
+        }
+        let data_path = data_file.path().to_str().unwrap();
+        let index_path = index_file.path().to_str().unwrap();
+
+        for start in 0..partitions.len() {
+            for end in start..=partitions.len() {
+                let expected = partitions[start..end].concat();
+                let mut read = vec![];
+                open_local_shuffle_segment(data_path, index_path, start, end)?
+                    .read_to_end(&mut read)?;
+                assert_eq!(read, expected);
+
+                // read with both mmap and pread
+                let index_file = File::open(index_path)?;
+                let (offset, end) = read_partition_range(&index_file, start, end)?;
+                if end > offset {
+                    let data_file = File::open(data_path)?;
+                    let mut read = vec![];
+                    MmapSegmentReader::try_new(&data_file, offset, end - offset)?
+                        .read_to_end(&mut read)?;
+                    assert_eq!(read, expected);
+
+                    let mut read = vec![];
+                    PreadSegmentReader::new(data_file, offset, end - offset)
+                        .read_to_end(&mut read)?;
+                    assert_eq!(read, expected);
+                }
+            }
+        }
+        Ok(())
+    }
+}
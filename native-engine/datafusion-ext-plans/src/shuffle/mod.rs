@@ -15,7 +15,10 @@
 use std::{
     fmt,
     sync::{
-        atomic::{AtomicUsize, Ordering::SeqCst},
+        atomic::{
+            AtomicUsize,
+            Ordering::{Relaxed, SeqCst},
+        },
         Arc,
     },
 };
@@ -38,15 +41,32 @@ use datafusion_ext_commons::{arrow::array_size::ArraySize, spark_hash::create_mu
 use futures::StreamExt;
 use parking_lot::Mutex as SyncMutex;
 
-use crate::common::execution_context::ExecutionContext;
+use crate::{common::execution_context::ExecutionContext, shuffle::skew::HotKeySplitting};
 
 pub mod single_repartitioner;
 pub mod sort_repartitioner;
 
 pub mod buffered_data;
-mod rss;
+pub mod bypass_repartitioner;
+pub mod checksum;
+pub mod local_reader;
+pub mod range_bounds;
+pub mod rss;
 pub mod rss_single_repartitioner;
 pub mod rss_sort_repartitioner;
+pub mod skew;
+pub mod sorted_merge;
+
+static SHUFFLE_BYTES_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// total bytes written to shuffle outputs (local files or rss) of all tasks
+pub fn shuffle_bytes_written() -> usize {
+    SHUFFLE_BYTES_WRITTEN.load(Relaxed)
+}
+
+pub(crate) fn add_shuffle_bytes_written(num_bytes: usize) {
+    SHUFFLE_BYTES_WRITTEN.fetch_add(num_bytes, Relaxed);
+}
 
 #[async_trait]
 pub trait ShuffleRepartitioner: Send + Sync {
@@ -112,6 +132,9 @@ pub enum Partitioning {
     /// Allocate rows based on a hash of one of more expressions and the
     /// specified number of partitions
     HashPartitioning(Vec<Arc<dyn PhysicalExpr>>, usize),
+    /// Hash partitioning with rows of hot keys split into sub-partitions,
+    /// partition count is the number of sub-partitions
+    SkewedHashPartitioning(Vec<Arc<dyn PhysicalExpr>>, usize, Arc<HotKeySplitting>),
     /// Single partitioning scheme with a known number of partitions
     SinglePartitioning(),
     /// Range partitioning
@@ -124,6 +147,7 @@ impl Partitioning {
         use Partitioning::*;
         match self {
             RoundRobinPartitioning(n) | HashPartitioning(_, n) | RangePartitioning(_, n, _) => *n,
+            SkewedHashPartitioning(_, n, splitting) => *n * splitting.num_splits(),
             SinglePartitioning() => 1,
         }
     }
@@ -141,6 +165,18 @@ impl fmt::Display for Partitioning {
                     .join(", ");
                 write!(f, "Hash([{phy_exprs_str}], {size})")
             }
+            Partitioning::SkewedHashPartitioning(phy_exprs, size, splitting) => {
+                let phy_exprs_str = phy_exprs
+                    .iter()
+                    .map(|e| format!("{e}"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                let num_splits = splitting.num_splits();
+                write!(
+                    f,
+                    "SkewedHash([{phy_exprs_str}], {size}, splits={num_splits})"
+                )
+            }
             Partitioning::SinglePartitioning() => {
                 write!(f, "SinglePartitioning()")
             }
@@ -156,9 +192,40 @@ impl fmt::Display for Partitioning {
     }
 }
 
+/// evaluates output partition ids of all rows in the batch. with round-robin
+/// partitioning, round_robin_start_rows is forwarded by the number of rows
+fn evaluate_output_partition_ids(
+    partitioning: &Partitioning,
+    batch: &RecordBatch,
+    round_robin_start_rows: &mut usize,
+) -> Result<Vec<u32>> {
+    Ok(match partitioning {
+        Partitioning::HashPartitioning(..) => {
+            let hashes = evaluate_hashes(partitioning, batch)?;
+            evaluate_partition_ids(hashes, partitioning.partition_count())
+        }
+        Partitioning::SkewedHashPartitioning(_, num_partitions, splitting) => {
+            let hashes = evaluate_hashes(partitioning, batch)?;
+            splitting.evaluate_partition_ids(hashes, *num_partitions)
+        }
+        Partitioning::RoundRobinPartitioning(..) => {
+            let part_ids =
+                evaluate_robin_partition_ids(partitioning, batch, *round_robin_start_rows);
+            *round_robin_start_rows += batch.num_rows();
+            *round_robin_start_rows %= partitioning.partition_count();
+            part_ids
+        }
+        Partitioning::RangePartitioning(sort_expr, _, bounds) => {
+            evaluate_range_partition_ids(batch, sort_expr, bounds)?
+        }
+        _ => unreachable!("unsupported partitioning: {:?}", partitioning),
+    })
+}
+
 fn evaluate_hashes(partitioning: &Partitioning, batch: &RecordBatch) -> ArrowResult<Vec<i32>> {
     match partitioning {
-        Partitioning::HashPartitioning(exprs, _) => {
+        Partitioning::HashPartitioning(exprs, _)
+        | Partitioning::SkewedHashPartitioning(exprs, ..) => {
             let arrays = exprs
                 .iter()
                 .map(|expr| Ok(expr.evaluate(batch)?.into_array(batch.num_rows())?))
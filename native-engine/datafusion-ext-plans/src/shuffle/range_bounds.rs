@@ -0,0 +1,237 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use arrow::{
+    array::ArrayRef,
+    compute::take,
+    row::{OwnedRow, RowConverter, Rows},
+};
+use datafusion::common::Result;
+
+/// reservoir sampler of range partitioning keys, keys are stored in row
+/// format so that sampled keys can be compared and sorted directly
+pub struct ReservoirSampler {
+    sample_size: usize,
+    num_rows: usize,
+    samples: Vec<OwnedRow>,
+    rng: XorShiftRandom,
+}
+
+impl ReservoirSampler {
+    pub fn new(sample_size: usize, seed: u64) -> Self {
+        Self {
+            sample_size,
+            num_rows: 0,
+            samples: Vec::with_capacity(sample_size),
+            rng: XorShiftRandom::new(seed),
+        }
+    }
+
+    /// total number of rows inserted
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn insert_batch(
+        &mut self,
+        key_cols: &[ArrayRef],
+        row_converter: &RowConverter,
+    ) -> Result<()> {
+        let batch_num_rows = key_cols.first().map(|col| col.len()).unwrap_or(0);
+
+        // decide sampled rows before converting to rows format, so only
+        // selected keys are converted
+        let mut selected = vec![]; // (row_idx, slot)
+        for row_idx in 0..batch_num_rows {
+            let pos = self.num_rows + row_idx;
+            if pos < self.sample_size {
+                selected.push((row_idx as u32, pos));
+            } else {
+                let slot = (self.rng.next_u64() % (pos as u64 + 1)) as usize;
+                if slot < self.sample_size {
+                    selected.push((row_idx as u32, slot));
+                }
+            }
+        }
+        self.num_rows += batch_num_rows;
+        if selected.is_empty() {
+            return Ok(());
+        }
+
+        let indices = selected
+            .iter()
+            .map(|&(row_idx, _)| row_idx)
+            .collect::<arrow::array::UInt32Array>();
+        let selected_cols = key_cols
+            .iter()
+            .map(|col| Ok(take(col, &indices, None)?))
+            .collect::<Result<Vec<_>>>()?;
+        let selected_rows = row_converter.convert_columns(&selected_cols)?;
+
+        // later rows replace earlier ones with the same slot, as in sequential
+        // reservoir sampling
+        for (i, &(_, slot)) in selected.iter().enumerate() {
+            let row = selected_rows.row(i).owned();
+            if slot < self.samples.len() {
+                self.samples[slot] = row;
+            } else {
+                self.samples.push(row);
+            }
+        }
+        Ok(())
+    }
+
+    /// returns sampled keys and the total number of rows
+    pub fn finish(self, row_converter: &RowConverter) -> (Rows, usize) {
+        let data_size = self
+            .samples
+            .iter()
+            .map(|row| row.row().as_ref().len())
+            .sum();
+        let mut rows = row_converter.empty_rows(self.samples.len(), data_size);
+        for row in &self.samples {
+            rows.push(row.row());
+        }
+        (rows, self.num_rows)
+    }
+}
+
+/// determines range partitioning bounds from weighted candidates, returns
+/// indices of bound keys in candidates. same as spark's
+/// RangePartitioner.determineBounds, bounds are selected so that each
+/// partition gets roughly equal sum of weights, and duplicated keys are
+/// skipped
+pub fn determine_bounds(candidates: &Rows, weights: &[f32], num_partitions: usize) -> Vec<usize> {
+    let mut ordered = (0..candidates.num_rows()).collect::<Vec<_>>();
+    ordered.sort_by_key(|&i| candidates.row(i));
+
+    let num_partitions = num_partitions.min(candidates.num_rows());
+    if num_partitions <= 1 {
+        return vec![];
+    }
+    let sum_weights = weights.iter().map(|&w| w as f64).sum::<f64>();
+    let step = sum_weights / num_partitions as f64;
+    let mut cum_weight = 0.0;
+    let mut target = step;
+    let mut bounds: Vec<usize> = vec![];
+
+    for &i in &ordered {
+        if bounds.len() >= num_partitions - 1 {
+            break;
+        }
+        cum_weight += weights[i] as f64;
+        if cum_weight >= target {
+            // skip duplicated keys
+            let is_duplicated = bounds
+                .last()
+                .is_some_and(|&last| candidates.row(i) <= candidates.row(last));
+            if !is_duplicated {
+                bounds.push(i);
+                target += step;
+            }
+        }
+    }
+    bounds
+}
+
+/// takes bound keys from candidate key columns
+pub fn take_bounds(candidate_cols: &[ArrayRef], bounds: &[usize]) -> Result<Vec<ArrayRef>> {
+    let indices = bounds
+        .iter()
+        .map(|&i| i as u32)
+        .collect::<arrow::array::UInt32Array>();
+    candidate_cols
+        .iter()
+        .map(|col| Ok(take(col, &indices, None)?))
+        .collect()
+}
+
+// a simple xorshift random generator, as spark's XORShiftRandom used in
+// sampling
+struct XorShiftRandom(u64);
+
+impl XorShiftRandom {
+    fn new(seed: u64) -> Self {
+        // avoid zero state, which always generates zeros
+        Self(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Array, Int32Array},
+        datatypes::DataType,
+        row::SortField,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_reservoir_sampling_and_bounds() -> Result<()> {
+        let row_converter = RowConverter::new(vec![SortField::new(DataType::Int32)])?;
+        let mut sampler = ReservoirSampler::new(1000, 0);
+        for i in 0..100 {
+            let col: ArrayRef = Arc::new(Int32Array::from_iter_values(
+                (0..1000).map(|j| (i * 1000 + j) % 10000),
+            ));
+            sampler.insert_batch(&[col], &row_converter)?;
+        }
+        let (samples, num_rows) = sampler.finish(&row_converter);
+        assert_eq!(num_rows, 100000);
+        assert_eq!(samples.num_rows(), 1000);
+
+        // bounds of uniformly distributed keys are roughly balanced
+        let weights = vec![(num_rows / samples.num_rows()) as f32; samples.num_rows()];
+        let bounds = determine_bounds(&samples, &weights, 4);
+        assert_eq!(bounds.len(), 3);
+        let sample_cols = row_converter.convert_rows(samples.iter())?;
+        let bound_cols = take_bounds(&sample_cols, &bounds)?;
+        let bound_values = bound_cols[0]
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap()
+            .values()
+            .to_vec();
+        for (bound, expected) in bound_values.into_iter().zip([2500, 5000, 7500]) {
+            assert!((bound - expected).abs() < 500, "bound={bound}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_determine_bounds_with_duplicated_keys() -> Result<()> {
+        let row_converter = RowConverter::new(vec![SortField::new(DataType::Int32)])?;
+        let col: ArrayRef = Arc::new(Int32Array::from(vec![1, 1, 1, 1, 1, 1, 2, 3]));
+        let candidates = row_converter.convert_columns(&[col.clone()])?;
+        let bounds = determine_bounds(&candidates, &[1.0; 8], 4);
+        let bound_cols = take_bounds(&[col], &bounds)?;
+        assert_eq!(
+            bound_cols[0].as_any().downcast_ref::<Int32Array>().unwrap(),
+            &Int32Array::from(vec![1, 2, 3]),
+        );
+        Ok(())
+    }
+}
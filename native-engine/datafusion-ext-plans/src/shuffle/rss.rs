@@ -12,20 +12,173 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
-use blaze_jni_bridge::{jni_call, jni_new_direct_byte_buffer};
-use jni::objects::GlobalRef;
+use blaze_jni_bridge::{
+    conf, conf::IntConf, is_jni_bridge_inited, jni_call, jni_new_direct_byte_buffer,
+    jni_new_prim_array,
+};
+use datafusion::common::Result;
+use jni::objects::{GlobalRef, JObject};
+use parking_lot::Mutex;
 
-pub struct RssWriter {
+use crate::shuffle::add_shuffle_bytes_written;
+
+/// interface of rss partition writers, implemented by the jvm-side
+/// RssPartitionWriterBase object (and mock writers in tests). retries,
+/// deduplication and partition splits are handled by the rss clients behind
+/// the writers, unrecoverable failures are returned as errors.
+pub trait RssPartitionWriter: Send {
+    /// pushes buffers of multiple partitions in one call. data contains all
+    /// buffers concatenated, the i-th buffer belongs to partition_ids[i] and
+    /// has lengths[i] bytes.
+    fn push_batch(&mut self, partition_ids: &[i32], lengths: &[i32], data: &[u8]) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()>;
+}
+
+pub struct JniRssPartitionWriter {
     rss_partition_writer: GlobalRef,
+}
+
+impl JniRssPartitionWriter {
+    pub fn new(rss_partition_writer: GlobalRef) -> Self {
+        Self {
+            rss_partition_writer,
+        }
+    }
+}
+
+impl RssPartitionWriter for JniRssPartitionWriter {
+    fn push_batch(&mut self, partition_ids: &[i32], lengths: &[i32], data: &[u8]) -> Result<()> {
+        let partition_ids = jni_new_prim_array!(int, partition_ids)?;
+        let lengths = jni_new_prim_array!(int, lengths)?;
+        let buf = jni_new_direct_byte_buffer!(&data)?;
+        jni_call!(
+            BlazeRssPartitionWriterBase(self.rss_partition_writer.as_obj()).pushBatch(
+                partition_ids.as_obj(),
+                lengths.as_obj(),
+                buf.as_obj(),
+            ) -> ()
+        )?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        jni_call!(BlazeRssPartitionWriterBase(self.rss_partition_writer.as_obj()).flush() -> ())?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RssPushConfig {
+    pub batch_size: usize,
+}
+
+impl Default for RssPushConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 4 << 20,
+        }
+    }
+}
+
+impl RssPushConfig {
+    pub fn try_from_conf() -> Result<Self> {
+        if !is_jni_bridge_inited() {
+            return Ok(Self::default());
+        }
+        Ok(Self {
+            batch_size: conf::SHUFFLE_RSS_PUSH_BATCH_SIZE.value()?.max(1) as usize,
+        })
+    }
+}
+
+/// batches buffers of all partitions and pushes them to rss when staged data
+/// exceeds the batch size. pushes are synchronous so the producer is blocked
+/// while rss servers apply backpressure.
+pub struct RssBatchPusher {
+    writer: Box<dyn RssPartitionWriter>,
+    config: RssPushConfig,
+    staging_partition_ids: Vec<i32>,
+    staging_lengths: Vec<i32>,
+    staging_data: Vec<u8>,
+    num_pushes: usize,
+    num_bytes_written: usize,
+}
+
+impl RssBatchPusher {
+    pub fn new(writer: Box<dyn RssPartitionWriter>, config: RssPushConfig) -> Self {
+        Self {
+            writer,
+            config,
+            staging_partition_ids: vec![],
+            staging_lengths: vec![],
+            staging_data: vec![],
+            num_pushes: 0,
+            num_bytes_written: 0,
+        }
+    }
+
+    pub fn num_bytes_written(&self) -> usize {
+        self.num_bytes_written
+    }
+
+    pub fn write(&mut self, partition_id: usize, buf: &[u8]) -> Result<()> {
+        let partition_id = partition_id as i32;
+        add_shuffle_bytes_written(buf.len());
+        self.num_bytes_written += buf.len();
+
+        // merge with the last buffer if it belongs to the same partition
+        if self.staging_partition_ids.last() == Some(&partition_id) {
+            *self.staging_lengths.last_mut().unwrap() += buf.len() as i32;
+        } else {
+            self.staging_partition_ids.push(partition_id);
+            self.staging_lengths.push(buf.len() as i32);
+        }
+        self.staging_data.extend_from_slice(buf);
+
+        if self.staging_data.len() >= self.config.batch_size {
+            self.push()?;
+        }
+        Ok(())
+    }
+
+    /// pushes all staged buffers
+    pub fn push(&mut self) -> Result<()> {
+        if self.staging_partition_ids.is_empty() {
+            return Ok(());
+        }
+        self.num_pushes += 1;
+        self.writer.push_batch(
+            &self.staging_partition_ids,
+            &self.staging_lengths,
+            &self.staging_data,
+        )?;
+        self.staging_partition_ids.clear();
+        self.staging_lengths.clear();
+        self.staging_data.clear();
+        Ok(())
+    }
+
+    /// pushes all staged buffers and flushes the underlying writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.push()?;
+        self.writer.flush()?;
+        log::info!("rss pusher flushed, pushes={}", self.num_pushes);
+        Ok(())
+    }
+}
+
+pub struct RssWriter {
+    pusher: Arc<Mutex<RssBatchPusher>>,
     partition_id: usize,
 }
 
 impl RssWriter {
-    pub fn new(rss_partition_writer: GlobalRef, partition_id: usize) -> Self {
+    pub fn new(pusher: Arc<Mutex<RssBatchPusher>>, partition_id: usize) -> Self {
         Self {
-            rss_partition_writer,
+            pusher,
             partition_id,
         }
     }
@@ -33,16 +186,72 @@ impl RssWriter {
 
 impl Write for RssWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let buf_len = buf.len();
-        let buf = jni_new_direct_byte_buffer!(&buf)?;
-        jni_call!(
-            BlazeRssPartitionWriterBase(self.rss_partition_writer.as_obj())
-                .write(self.partition_id as i32, buf.as_obj()) -> ()
-        )?;
-        Ok(buf_len)
+        self.pusher.lock().write(self.partition_id, buf)?;
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // in-process mock of an rss partition writer
+    #[derive(Default)]
+    struct MockRssState {
+        num_push_calls: usize,
+        num_flushes: usize,
+        partitions: Vec<Vec<u8>>,
+    }
+
+    struct MockRssPartitionWriter(Arc<Mutex<MockRssState>>);
+
+    impl RssPartitionWriter for MockRssPartitionWriter {
+        fn push_batch(
+            &mut self,
+            partition_ids: &[i32],
+            lengths: &[i32],
+            data: &[u8],
+        ) -> Result<()> {
+            let mut state = self.0.lock();
+            state.num_push_calls += 1;
+            let mut offset = 0;
+            for (&partition_id, &len) in partition_ids.iter().zip(lengths) {
+                let buf = &data[offset..][..len as usize];
+                state.partitions[partition_id as usize].extend_from_slice(buf);
+                offset += len as usize;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.0.lock().num_flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_rss_push_batching() -> Result<()> {
+        let state = Arc::new(Mutex::new(MockRssState {
+            partitions: vec![vec![]; 3],
+            ..Default::default()
+        }));
+        let writer = Box::new(MockRssPartitionWriter(state.clone()));
+        let mut pusher = RssBatchPusher::new(writer, RssPushConfig { batch_size: 10 });
+        for i in 0..10 {
+            pusher.write(i % 3, &[i as u8; 3])?;
+        }
+        pusher.flush()?;
+
+        let state = state.lock();
+        assert_eq!(state.num_push_calls, 3);
+        assert_eq!(state.num_flushes, 1);
+        assert_eq!(state.partitions[0], [0, 0, 0, 3, 3, 3, 6, 6, 6, 9, 9, 9]);
+        assert_eq!(state.partitions[1], [1, 1, 1, 4, 4, 4, 7, 7, 7]);
+        assert_eq!(state.partitions[2], [2, 2, 2, 5, 5, 5, 8, 8, 8]);
+        Ok(())
+    }
+}
@@ -17,25 +17,29 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use datafusion::{arrow::record_batch::RecordBatch, common::Result};
 use datafusion_ext_commons::df_execution_err;
-use jni::objects::GlobalRef;
 use parking_lot::Mutex;
 
 use crate::{
     common::ipc_compression::IpcCompressionWriter,
-    shuffle::{rss::RssWriter, ShuffleRepartitioner},
+    shuffle::{
+        rss::{RssBatchPusher, RssWriter},
+        ShuffleRepartitioner,
+    },
 };
 
 pub struct RssSingleShuffleRepartitioner {
     rss_partition_writer: Arc<Mutex<IpcCompressionWriter<RssWriter>>>,
+    rss_pusher: Arc<Mutex<RssBatchPusher>>,
 }
 
 impl RssSingleShuffleRepartitioner {
-    pub fn new(rss_partition_writer: GlobalRef) -> Self {
+    pub fn new(rss_pusher: Arc<Mutex<RssBatchPusher>>) -> Self {
         Self {
             rss_partition_writer: Arc::new(Mutex::new(IpcCompressionWriter::new(RssWriter::new(
-                rss_partition_writer,
+                rss_pusher.clone(),
                 0,
             )))),
+            rss_pusher,
         }
     }
 }
@@ -55,7 +59,14 @@ impl ShuffleRepartitioner for RssSingleShuffleRepartitioner {
     }
 
     async fn shuffle_write(&self) -> Result<()> {
-        self.rss_partition_writer.lock().finish_current_buf()?;
+        let rss_partition_writer = self.rss_partition_writer.clone();
+        let rss_pusher = self.rss_pusher.clone();
+        tokio::task::spawn_blocking(move || {
+            rss_partition_writer.lock().finish_current_buf()?;
+            rss_pusher.lock().flush()
+        })
+        .await
+        .or_else(|err| df_execution_err!("{err}"))??;
         Ok(())
     }
 }
@@ -12,37 +12,49 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 
 use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use datafusion::{common::Result, physical_plan::metrics::Time};
 use datafusion_ext_commons::arrow::array_size::ArraySize;
 use futures::lock::Mutex;
-use jni::objects::GlobalRef;
+use parking_lot::Mutex as SyncMutex;
 
 use crate::{
-    memmgr::{MemConsumer, MemConsumerInfo, MemManager},
-    shuffle::{buffered_data::BufferedData, Partitioning, ShuffleRepartitioner},
+    memmgr::{policy::MEM_PRIORITY_LOW, MemConsumer, MemConsumerInfo, MemManager},
+    shuffle::{
+        buffered_data::BufferedData, rss::RssBatchPusher, Partitioning, ShuffleRepartitioner,
+    },
 };
 
 pub struct RssSortShuffleRepartitioner {
     mem_consumer_info: Option<Weak<MemConsumerInfo>>,
+    stage_id: Option<usize>,
+    partition_id: usize,
     data: Mutex<BufferedData>,
-    rss: GlobalRef,
+    rss_pusher: Arc<SyncMutex<RssBatchPusher>>,
 }
 
 impl RssSortShuffleRepartitioner {
     pub fn new(
+        stage_id: Option<usize>,
         partition_id: usize,
-        rss_partition_writer: GlobalRef,
+        rss_pusher: Arc<SyncMutex<RssBatchPusher>>,
         partitioning: Partitioning,
         sort_time: Time,
     ) -> Self {
         Self {
             mem_consumer_info: None,
-            data: Mutex::new(BufferedData::new(partitioning, partition_id, sort_time)),
-            rss: rss_partition_writer,
+            stage_id,
+            partition_id,
+            data: Mutex::new(BufferedData::new(
+                partitioning,
+                None,
+                partition_id,
+                sort_time,
+            )),
+            rss_pusher,
         }
     }
 }
@@ -63,13 +75,26 @@ impl MemConsumer for RssSortShuffleRepartitioner {
             .expect("consumer info not set")
     }
 
+    fn mem_stage_id(&self) -> Option<usize> {
+        self.stage_id
+    }
+
+    fn mem_partition_id(&self) -> Option<usize> {
+        Some(self.partition_id)
+    }
+
+    fn mem_priority(&self) -> u32 {
+        MEM_PRIORITY_LOW
+    }
+
     async fn spill(&self) -> Result<()> {
         let data = self.data.lock().await.drain();
-        let rss = self.rss.clone();
+        let rss_pusher = self.rss_pusher.clone();
 
-        tokio::task::spawn_blocking(move || data.write_rss(rss))
+        let written_len = tokio::task::spawn_blocking(move || data.write_rss(&rss_pusher))
             .await
             .expect("tokio error")?;
+        self.consumer_info().record_spill(written_len);
         self.update_mem_used(0).await?;
         Ok(())
     }
@@ -25,24 +25,38 @@ use tokio::sync::Mutex;
 
 use crate::{
     common::{
-        ipc_compression::IpcCompressionWriter,
+        ipc_compression::{
+            arrow_ipc_format_enabled, zstd_dict_training_enabled, IpcCompressionWriter,
+        },
         timer_helper::{TimedWriter, TimerHelper},
     },
-    shuffle::ShuffleRepartitioner,
+    shuffle::{
+        checksum::{PartitionChecksumWriter, ShuffleChecksumOutput},
+        ShuffleRepartitioner,
+    },
 };
 
+type OutputWriter = IpcCompressionWriter<PartitionChecksumWriter<TimedWriter<File>>>;
+
 pub struct SingleShuffleRepartitioner {
     output_data_file: String,
     output_index_file: String,
-    output_data: Arc<Mutex<Option<IpcCompressionWriter<TimedWriter<File>>>>>,
+    checksum_output: Option<ShuffleChecksumOutput>,
+    output_data: Arc<Mutex<Option<OutputWriter>>>,
     output_io_time: Time,
 }
 
 impl SingleShuffleRepartitioner {
-    pub fn new(output_data_file: String, output_index_file: String, output_io_time: Time) -> Self {
+    pub fn new(
+        output_data_file: String,
+        output_index_file: String,
+        checksum_output: Option<ShuffleChecksumOutput>,
+        output_io_time: Time,
+    ) -> Self {
         Self {
             output_data_file,
             output_index_file,
+            checksum_output,
             output_data: Arc::new(Mutex::default()),
             output_io_time,
         }
@@ -50,18 +64,25 @@ impl SingleShuffleRepartitioner {
 
     fn get_output_writer<'a>(
         &self,
-        output_data: &'a mut Option<IpcCompressionWriter<TimedWriter<File>>>,
-    ) -> Result<&'a mut IpcCompressionWriter<TimedWriter<File>>> {
+        output_data: &'a mut Option<OutputWriter>,
+    ) -> Result<&'a mut OutputWriter> {
         if output_data.is_none() {
-            *output_data = Some(IpcCompressionWriter::new(
-                self.output_io_time.wrap_writer(
-                    OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .truncate(true)
-                        .open(&self.output_data_file)?,
-                ),
-            ));
+            // the only partition is read as a whole stream, so zstd dictionary
+            // can be used
+            *output_data = Some(
+                IpcCompressionWriter::new(PartitionChecksumWriter::new(
+                    self.output_io_time.wrap_writer(
+                        OpenOptions::new()
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .open(&self.output_data_file)?,
+                    ),
+                    self.checksum_output.as_ref().map(|c| c.algorithm),
+                ))
+                .with_dictionary_training(zstd_dict_training_enabled())
+                .try_with_arrow_ipc_format(arrow_ipc_format_enabled())?,
+            );
         }
         Ok(output_data.as_mut().unwrap())
     }
@@ -89,9 +110,14 @@ impl ShuffleRepartitioner for SingleShuffleRepartitioner {
                     .open(&self.output_index_file)?,
             );
             output_writer.finish_current_buf()?;
-            let offset = output_writer.inner_mut().0.stream_position()?;
+            let offset = output_writer.inner_mut().inner_mut().0.stream_position()?;
             output_index.write_all(&[0u8; 8])?;
             output_index.write_all(&(offset as i64).to_le_bytes()[..])?;
+
+            // write checksum file
+            if let Some(checksum_output) = &self.checksum_output {
+                checksum_output.write(output_writer.inner(), &[0, offset])?;
+            }
         } else {
             // write empty data file and index file
             let _output_data = self.output_io_time.wrap_writer(
@@ -109,6 +135,13 @@ impl ShuffleRepartitioner for SingleShuffleRepartitioner {
                     .open(&self.output_index_file)?,
             );
             output_index.write_all(&[0u8; 16])?;
+
+            // write checksum file
+            if let Some(checksum_output) = &self.checksum_output {
+                let empty_writer =
+                    PartitionChecksumWriter::new(std::io::sink(), Some(checksum_output.algorithm));
+                checksum_output.write(&empty_writer, &[0, 0])?;
+            }
         }
         Ok(())
     }
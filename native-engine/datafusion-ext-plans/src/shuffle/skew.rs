@@ -0,0 +1,206 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use blaze_jni_bridge::{
+    conf,
+    conf::{DoubleConf, IntConf},
+    is_jni_bridge_inited,
+};
+use datafusion::common::Result;
+use hashbrown::HashMap;
+use itertools::Itertools;
+use parking_lot::Mutex;
+
+const DEFAULT_SAMPLE_ROWS: usize = 100000;
+const DEFAULT_HOT_KEY_MIN_RATIO: f64 = 0.05;
+const DEFAULT_MAX_HOT_KEYS: usize = 16;
+
+/// splits rows of hot keys into several sub-partitions of the same output
+/// partition (salting). rows of other keys always go to the first
+/// sub-partition. keys are identified by their partitioning hashes, hot keys
+/// are detected by sampling key frequencies of the first rows written by the
+/// task.
+///
+/// sub-partitions of a partition are written continuously and only partition
+/// offsets are written to the index file, so readers still see each partition
+/// as a single range. sub-partition offsets are not exported to map statuses
+/// yet.
+#[derive(Debug)]
+pub struct HotKeySplitting {
+    num_splits: usize,
+    sample_rows: usize,
+    hot_key_min_ratio: f64,
+    max_hot_keys: usize,
+    state: Mutex<HotKeyState>,
+}
+
+#[derive(Debug, Default)]
+struct HotKeyState {
+    sampled_rows: usize,
+    sampled_freqs: HashMap<i32, usize>,
+    sampling_finished: bool,
+    hot_keys: HashMap<i32, usize>, // hash -> next split
+}
+
+impl HotKeySplitting {
+    pub fn new(
+        num_splits: usize,
+        sample_rows: usize,
+        hot_key_min_ratio: f64,
+        max_hot_keys: usize,
+    ) -> Self {
+        let state = HotKeyState {
+            sampling_finished: sample_rows == 0,
+            ..Default::default()
+        };
+        Self {
+            num_splits: num_splits.max(1),
+            sample_rows,
+            hot_key_min_ratio,
+            max_hot_keys,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn try_new_with_conf(num_splits: usize) -> Result<Self> {
+        if !is_jni_bridge_inited() {
+            return Ok(Self::new(
+                num_splits,
+                DEFAULT_SAMPLE_ROWS,
+                DEFAULT_HOT_KEY_MIN_RATIO,
+                DEFAULT_MAX_HOT_KEYS,
+            ));
+        }
+        Ok(Self::new(
+            num_splits,
+            conf::SHUFFLE_SKEW_SAMPLE_ROWS.value()?.max(0) as usize,
+            conf::SHUFFLE_SKEW_HOT_KEY_MIN_RATIO.value()?,
+            conf::SHUFFLE_SKEW_MAX_HOT_KEYS.value()?.max(0) as usize,
+        ))
+    }
+
+    pub fn num_splits(&self) -> usize {
+        self.num_splits
+    }
+
+    /// returns hashes of all detected hot keys
+    pub fn hot_keys(&self) -> Vec<i32> {
+        self.state
+            .lock()
+            .hot_keys
+            .keys()
+            .copied()
+            .sorted()
+            .collect()
+    }
+
+    /// evaluates sub-partition ids from hashes, sub-partitions of the same
+    /// partition are numbered continuously, so the output data of a
+    /// partition is still a continuous range
+    pub fn evaluate_partition_ids(&self, hashes: Vec<i32>, num_partitions: usize) -> Vec<u32> {
+        let mut state = self.state.lock();
+        if !state.sampling_finished {
+            self.sample(&mut state, &hashes);
+        }
+
+        hashes
+            .into_iter()
+            .map(|hash| {
+                let partition_id = hash.rem_euclid(num_partitions as i32) as usize;
+                let split = match state.hot_keys.get_mut(&hash) {
+                    Some(next_split) => {
+                        let split = *next_split;
+                        *next_split = (split + 1) % self.num_splits;
+                        split
+                    }
+                    None => 0,
+                };
+                (partition_id * self.num_splits + split) as u32
+            })
+            .collect()
+    }
+
+    fn sample(&self, state: &mut HotKeyState, hashes: &[i32]) {
+        let num_sampled = hashes.len().min(self.sample_rows - state.sampled_rows);
+        for &hash in &hashes[..num_sampled] {
+            *state.sampled_freqs.entry(hash).or_default() += 1;
+        }
+        state.sampled_rows += num_sampled;
+        if state.sampled_rows < self.sample_rows {
+            return;
+        }
+
+        // sampling finished, take most frequent keys as hot keys
+        let min_freq =
+            ((state.sampled_rows as f64 * self.hot_key_min_ratio).ceil() as usize).max(2);
+        let sampled_freqs = std::mem::take(&mut state.sampled_freqs);
+        let detected_hot_keys = sampled_freqs
+            .into_iter()
+            .filter(|&(_, freq)| freq >= min_freq)
+            .sorted_by_key(|&(hash, freq)| (std::cmp::Reverse(freq), hash))
+            .take(self.max_hot_keys)
+            .collect::<Vec<_>>();
+
+        for &(hash, freq) in &detected_hot_keys {
+            log::info!(
+                "detected shuffle hot key: hash={hash}, sampled freq={freq}/{}",
+                state.sampled_rows,
+            );
+            state.hot_keys.entry(hash).or_default();
+        }
+        state.sampling_finished = true;
+    }
+
+    /// returns partition offsets for the index file from sub-partition offsets
+    pub fn partition_offsets(&self, sub_partition_offsets: &[u64]) -> Vec<u64> {
+        sub_partition_offsets
+            .iter()
+            .step_by(self.num_splits)
+            .copied()
+            .collect()
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hot_key_splitting() {
+        let num_partitions = 4;
+        let splitting = HotKeySplitting::new(3, 10, 0.3, 4);
+
+        // rows before sampling finishes are not split
+        let ids = splitting.evaluate_partition_ids(vec![7, 1, 7, 2, 7, 100], num_partitions);
+        assert_eq!(ids, vec![9, 3, 9, 6, 9, 0]);
+        assert!(splitting.hot_keys().is_empty());
+
+        // key 7 is detected as hot key in the first 10 rows
+        let ids = splitting.evaluate_partition_ids(vec![7, 3, 7, 100, 100, 5], num_partitions);
+        assert_eq!(ids, vec![9, 9, 10, 0, 0, 3]);
+        assert_eq!(splitting.hot_keys(), vec![7]);
+
+        // rows of hot keys are split round-robin into sub-partitions
+        let ids = splitting.evaluate_partition_ids(vec![7, 7, 7, 100, 2], num_partitions);
+        assert_eq!(ids, vec![11, 9, 10, 0, 6]);
+
+        // partition offsets are offsets of the first sub-partitions
+        let sub_partition_offsets = (0..=12).map(|i| i * 10).collect::<Vec<u64>>();
+        assert_eq!(
+            splitting.partition_offsets(&sub_partition_offsets),
+            vec![0, 30, 60, 90, 120],
+        );
+    }
+}
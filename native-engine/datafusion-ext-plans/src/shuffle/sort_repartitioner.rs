@@ -18,27 +18,38 @@ use std::{
     sync::{Arc, Weak},
 };
 
-use arrow::record_batch::RecordBatch;
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
 use async_trait::async_trait;
 use bytesize::ByteSize;
+use count_write::CountWrite;
 use datafusion::{
     common::{DataFusionError, Result},
     physical_plan::metrics::Time,
 };
-use datafusion_ext_commons::{arrow::array_size::ArraySize, df_execution_err};
+use datafusion_ext_commons::{arrow::array_size::ArraySize, batch_size, df_execution_err};
 use futures::lock::Mutex;
 
 use crate::{
     common::{
         execution_context::ExecutionContext,
+        ipc_compression::{arrow_ipc_format_enabled, IpcCompressionReader, IpcCompressionWriter},
         offsetted::{Offsetted, OffsettedMergeIterator},
         timer_helper::TimerHelper,
+        trace::TraceLane,
     },
     memmgr::{
+        async_spill::AsyncSpillWriter,
+        policy::MEM_PRIORITY_LOW,
         spill::{try_new_spill, OwnedSpillBufReader, Spill},
         MemConsumer, MemConsumerInfo, MemManager,
     },
-    shuffle::{buffered_data::BufferedData, Partitioning, ShuffleRepartitioner},
+    shuffle::{
+        buffered_data::BufferedData,
+        checksum::{PartitionChecksumWriter, ShuffleChecksumOutput},
+        skew::HotKeySplitting,
+        sorted_merge::{ipc_sorted_run, ShuffleSortKeys, SortedRun, SortedRunsMerger},
+        Partitioning, ShuffleRepartitioner,
+    },
 };
 
 pub struct SortShuffleRepartitioner {
@@ -46,8 +57,12 @@ pub struct SortShuffleRepartitioner {
     mem_consumer_info: Option<Weak<MemConsumerInfo>>,
     output_data_file: String,
     output_index_file: String,
+    checksum_output: Option<ShuffleChecksumOutput>,
+    hot_key_splitting: Option<Arc<HotKeySplitting>>,
     data: Mutex<BufferedData>,
     spills: Mutex<Vec<Offsetted<u64, Box<dyn Spill>>>>,
+    spill_writer: Mutex<AsyncSpillWriter<Offsetted<u64, Box<dyn Spill>>>>,
+    sort_keys: Option<Arc<ShuffleSortKeys>>,
     num_output_partitions: usize,
     output_io_time: Time,
 }
@@ -57,19 +72,38 @@ impl SortShuffleRepartitioner {
         exec_ctx: Arc<ExecutionContext>,
         output_data_file: String,
         output_index_file: String,
+        checksum_output: Option<ShuffleChecksumOutput>,
         partitioning: Partitioning,
+        sort_keys: Option<Arc<ShuffleSortKeys>>,
         output_io_time: Time,
     ) -> Self {
         let partition_id = exec_ctx.partition_id();
         let sort_time = exec_ctx.register_timer_metric("sort_time");
         let num_output_partitions = partitioning.partition_count();
+        let hot_key_splitting = match &partitioning {
+            Partitioning::SkewedHashPartitioning(_, _, splitting) => Some(splitting.clone()),
+            _ => None,
+        };
+        let spill_writer = Mutex::new(AsyncSpillWriter::new(
+            "SortShuffleRepartitioner",
+            exec_ctx.spill_metrics().clone(),
+        ));
         Self {
             exec_ctx,
             mem_consumer_info: None,
             output_data_file,
             output_index_file,
-            data: Mutex::new(BufferedData::new(partitioning, partition_id, sort_time)),
+            checksum_output,
+            hot_key_splitting,
+            data: Mutex::new(BufferedData::new(
+                partitioning,
+                sort_keys.clone(),
+                partition_id,
+                sort_time,
+            )),
             spills: Mutex::default(),
+            spill_writer,
+            sort_keys,
             num_output_partitions,
             output_io_time,
         }
@@ -92,18 +126,43 @@ impl MemConsumer for SortShuffleRepartitioner {
             .expect("consumer info not set")
     }
 
+    fn mem_stage_id(&self) -> Option<usize> {
+        self.exec_ctx.stage_id()
+    }
+
+    fn mem_partition_id(&self) -> Option<usize> {
+        Some(self.exec_ctx.partition_id())
+    }
+
+    fn mem_trace_lane(&self) -> Option<TraceLane> {
+        self.exec_ctx.trace_lane(self.name()).cloned()
+    }
+
+    fn mem_priority(&self) -> u32 {
+        MEM_PRIORITY_LOW
+    }
+
     async fn spill(&self) -> Result<()> {
         let data = self.data.lock().await.drain();
-        let spill_metrics = self.exec_ctx.spill_metrics().clone();
-        let spill = tokio::task::spawn_blocking(move || {
-            let mut spill = try_new_spill(&spill_metrics)?;
-            let offsets = data.write(spill.get_buf_writer())?;
-            Ok::<_, DataFusionError>(Offsetted::new(offsets, spill))
-        })
-        .await
-        .expect("tokio spawn_blocking error")?;
+        let exec_ctx = self.exec_ctx.clone();
+        let consumer_info = self.get_consumer_info().clone();
+        let mut spill_writer = self.spill_writer.lock().await;
+        spill_writer
+            .submit(data.mem_used(), self.mem_trace_lane(), move || {
+                let mut spill = try_new_spill(&exec_ctx, "Shuffle")?;
+                let offsets = data.write(spill.get_buf_writer())?;
+                if let Some(consumer_info) = consumer_info.upgrade() {
+                    consumer_info.record_spill(spill.spill_len());
+                }
+                Ok(Offsetted::new(offsets, spill))
+            })
+            .await;
+        let written_spills = spill_writer.take_finished().await?;
+        drop(spill_writer);
 
-        self.spills.lock().await.push(spill);
+        // drained data is written in background and accounted by the spill
+        // writer, new input can be accepted
+        self.spills.lock().await.extend(written_spills);
         self.update_mem_used(0).await?;
         Ok(())
     }
@@ -148,6 +207,7 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
     async fn shuffle_write(&self) -> Result<()> {
         self.set_spillable(false);
         let mut spills = std::mem::take(&mut *self.spills.lock().await);
+        spills.extend(self.spill_writer.lock().await.wait_all().await?);
         let data = self.data.lock().await.drain();
 
         log::info!(
@@ -163,13 +223,18 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
         // no spills - directly write current batches into final file
         if spills.is_empty() {
             let output_io_time = self.output_io_time.clone();
+            let checksum_output = self.checksum_output.clone();
+            let hot_key_splitting = self.hot_key_splitting.clone();
             tokio::task::spawn_blocking(move || {
-                let mut output_data = output_io_time.wrap_writer(
-                    OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .truncate(true)
-                        .open(&data_file)?,
+                let mut output_data = PartitionChecksumWriter::new(
+                    output_io_time.wrap_writer(
+                        OpenOptions::new()
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .open(&data_file)?,
+                    ),
+                    checksum_output.as_ref().map(|c| c.algorithm),
                 );
                 let mut output_index = output_io_time.wrap_writer(
                     OpenOptions::new()
@@ -182,13 +247,21 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
                 // write data file
                 let offsets = data.write(&mut output_data)?;
 
+                // with hot key splitting, offsets are of sub-partitions
+                let offsets = partition_offsets(hot_key_splitting.as_deref(), offsets);
+
                 // write index file
                 let mut offsets_data = vec![];
-                for offset in offsets {
+                for &offset in &offsets {
                     offsets_data.extend_from_slice(&(offset as i64).to_le_bytes()[..]);
                 }
                 output_index.write_all(&offsets_data)?;
 
+                // write checksum file
+                if let Some(checksum_output) = &checksum_output {
+                    checksum_output.write(&output_data, &offsets)?;
+                }
+
                 Ok::<(), DataFusionError>(())
             })
             .await
@@ -206,9 +279,9 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
                 self.update_mem_used(spill.len()).await?;
                 spills.push(Offsetted::new(offsets, spill));
             } else {
-                let spill_metrics = self.exec_ctx.spill_metrics().clone();
+                let exec_ctx = self.exec_ctx.clone();
                 let spill = tokio::task::spawn_blocking(move || {
-                    let mut spill = try_new_spill(&spill_metrics)?;
+                    let mut spill = try_new_spill(&exec_ctx, "Shuffle")?;
                     let offsets = data.write(spill.get_buf_writer())?;
                     Ok::<_, DataFusionError>(Offsetted::new(offsets, spill))
                 })
@@ -222,13 +295,20 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
         // append partition in each spills
         let num_output_partitions = self.num_output_partitions;
         let output_io_time = self.output_io_time.clone();
+        let sort_keys = self.sort_keys.clone();
+        let schema = self.exec_ctx.output_schema();
+        let checksum_output = self.checksum_output.clone();
+        let hot_key_splitting = self.hot_key_splitting.clone();
         tokio::task::spawn_blocking(move || {
-            let mut output_data = output_io_time.wrap_writer(
-                OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(&data_file)?,
+            let mut output_data = PartitionChecksumWriter::new(
+                output_io_time.wrap_writer(
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&data_file)?,
+                ),
+                checksum_output.as_ref().map(|c| c.algorithm),
             );
             let mut output_index = output_io_time.wrap_writer(
                 OpenOptions::new()
@@ -246,19 +326,38 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
                     .collect(),
             );
 
-            while let Some((_partition_id, reader, range)) = merge_iter.next() {
-                let mut reader = reader.buf_reader().take(range.end - range.start);
-                std::io::copy(&mut reader, &mut output_data)?;
-            }
-            let offsets = merge_iter.merged_offsets();
+            let offsets = match sort_keys {
+                Some(sort_keys) => merge_key_sorted_spills(
+                    &mut merge_iter,
+                    &mut output_data,
+                    num_output_partitions,
+                    sort_keys,
+                    schema,
+                )?,
+                None => {
+                    while let Some((_partition_id, reader, range)) = merge_iter.next() {
+                        let mut reader = reader.buf_reader().take(range.end - range.start);
+                        std::io::copy(&mut reader, &mut output_data)?;
+                    }
+                    merge_iter.merged_offsets().to_vec()
+                }
+            };
+
+            // with hot key splitting, offsets are of sub-partitions
+            let offsets = partition_offsets(hot_key_splitting.as_deref(), offsets);
 
             // write index file
             let mut offsets_data = vec![];
-            for &offset in offsets {
+            for &offset in &offsets {
                 offsets_data.extend_from_slice(&(offset as i64).to_le_bytes()[..]);
             }
             output_index.write_all(&offsets_data)?;
 
+            // write checksum file
+            if let Some(checksum_output) = &checksum_output {
+                checksum_output.write(&output_data, &offsets)?;
+            }
+
             Ok::<(), DataFusionError>(())
         })
         .await
@@ -268,3 +367,47 @@ impl ShuffleRepartitioner for SortShuffleRepartitioner {
         Ok(())
     }
 }
+
+// returns offsets of partitions for the index file, with hot key splitting,
+// offsets of sub-partitions are merged
+fn partition_offsets(hot_key_splitting: Option<&HotKeySplitting>, offsets: Vec<u64>) -> Vec<u64> {
+    match hot_key_splitting {
+        Some(hot_key_splitting) => hot_key_splitting.partition_offsets(&offsets),
+        None => offsets,
+    }
+}
+
+// partition segments in key-sorted spills are sorted runs, merges them by keys
+// so that each partition is still written as one sorted run. returns offsets of
+// each partition in output
+fn merge_key_sorted_spills<'a, W: Write>(
+    merge_iter: &mut OffsettedMergeIterator<'a, u64, OwnedSpillBufReader<'a>>,
+    output: W,
+    num_output_partitions: usize,
+    sort_keys: Arc<ShuffleSortKeys>,
+    schema: SchemaRef,
+) -> Result<Vec<u64>> {
+    let batch_size = batch_size();
+    let mut writer = IpcCompressionWriter::new(CountWrite::from(output))
+        .try_with_arrow_ipc_format(arrow_ipc_format_enabled())?;
+    let mut offsets = vec![];
+
+    while let Some((partition_id, chunk)) = merge_iter.next_partition_chunk() {
+        // segments of all spills are read in a streaming way while merging
+        let runs = chunk
+            .map(|(reader, range)| -> SortedRun {
+                let segment = reader.buf_reader().take(range.end - range.start);
+                ipc_sorted_run(IpcCompressionReader::new(segment), schema.clone())
+            })
+            .collect::<Vec<_>>();
+
+        offsets.resize(partition_id + 1, writer.inner().count());
+        for batch in SortedRunsMerger::try_new(sort_keys.clone(), runs, batch_size)? {
+            let batch = batch?;
+            writer.write_batch(batch.num_rows(), batch.columns())?;
+        }
+        writer.finish_current_buf()?;
+    }
+    offsets.resize(num_output_partitions + 1, writer.inner().count());
+    Ok(offsets)
+}
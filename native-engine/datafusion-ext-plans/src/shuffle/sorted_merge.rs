@@ -0,0 +1,444 @@
+// Copyright 2022 The Blaze Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use arrow::{
+    array::{ArrayRef, RecordBatch, RecordBatchOptions},
+    datatypes::SchemaRef,
+    row::{Row, RowConverter, Rows, SortField},
+};
+use datafusion::{common::Result, physical_expr::PhysicalSortExpr};
+use datafusion_ext_commons::{
+    algorithm::loser_tree::{ComparableForLoserTree, LoserTree},
+    arrow::{array_size::ArraySize, selection::create_batch_interleaver},
+    batch_size,
+};
+use parking_lot::Mutex as SyncMutex;
+
+use crate::{
+    common::{
+        execution_context::ExecutionContext,
+        ipc_compression::{IpcCompressionReader, IpcCompressionWriter},
+    },
+    memmgr::{
+        reservation::MemReservation,
+        spill::{spill_merge_max_fan_in, try_new_spill, OwnedSpillBufReader},
+    },
+};
+
+/// a sequence of batches sorted by shuffle sort keys
+pub type SortedRun<'a> = Box<dyn Iterator<Item = Result<RecordBatch>> + Send + 'a>;
+
+/// sort keys of key-sorted shuffle, rows of each output partition are sorted
+/// by these keys on map side and merged on reduce side
+#[derive(Debug)]
+pub struct ShuffleSortKeys {
+    exprs: Vec<PhysicalSortExpr>,
+    row_converter: SyncMutex<RowConverter>,
+}
+
+impl ShuffleSortKeys {
+    pub fn try_new(exprs: Vec<PhysicalSortExpr>, schema: &SchemaRef) -> Result<Self> {
+        let row_converter = RowConverter::new(
+            exprs
+                .iter()
+                .map(|expr| {
+                    Ok(SortField::new_with_options(
+                        expr.expr.data_type(schema)?,
+                        expr.options,
+                    ))
+                })
+                .collect::<Result<Vec<SortField>>>()?,
+        )?;
+        Ok(Self {
+            exprs,
+            row_converter: SyncMutex::new(row_converter),
+        })
+    }
+
+    pub fn exprs(&self) -> &[PhysicalSortExpr] {
+        &self.exprs
+    }
+
+    pub fn convert(&self, batch: &RecordBatch) -> Result<Rows> {
+        let key_cols: Vec<ArrayRef> = self
+            .exprs
+            .iter()
+            .map(|expr| {
+                expr.expr
+                    .evaluate(batch)
+                    .and_then(|cv| cv.into_array(batch.num_rows()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(self.row_converter.lock().convert_columns(&key_cols)?)
+    }
+}
+
+/// creates a sorted run reading all batches from an ipc compressed input
+pub fn ipc_sorted_run<'a, R: Read + Send + 'a>(
+    mut reader: IpcCompressionReader<R>,
+    schema: SchemaRef,
+) -> SortedRun<'a> {
+    Box::new(std::iter::from_fn(move || {
+        let (num_rows, cols) = match reader.read_batch(&schema) {
+            Ok(Some(batch)) => batch,
+            Ok(None) => return None,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(
+            RecordBatch::try_new_with_options(
+                schema.clone(),
+                cols,
+                &RecordBatchOptions::new().with_row_count(Some(num_rows)),
+            )
+            .map_err(Into::into),
+        )
+    }))
+}
+
+/// collects sorted runs with bounded fan-in. once a level has collected too
+/// many runs, they are merged into one spilled run of the next level, so that
+/// the final merging never opens more than max fan-in runs at the same time.
+pub struct SortedRunsCollector {
+    exec_ctx: Arc<ExecutionContext>,
+    sort_keys: Arc<ShuffleSortKeys>,
+    schema: SchemaRef,
+    max_fan_in: usize,
+    levels: Vec<Vec<SortedRun<'static>>>,
+}
+
+impl SortedRunsCollector {
+    pub fn new(
+        exec_ctx: Arc<ExecutionContext>,
+        sort_keys: Arc<ShuffleSortKeys>,
+        schema: SchemaRef,
+    ) -> Self {
+        Self {
+            exec_ctx,
+            sort_keys,
+            schema,
+            max_fan_in: spill_merge_max_fan_in().max(2),
+            levels: vec![],
+        }
+    }
+
+    pub fn num_runs(&self) -> usize {
+        self.levels.iter().map(|runs| runs.len()).sum()
+    }
+
+    pub fn push(&mut self, run: SortedRun<'static>) -> Result<()> {
+        self.push_at_level(run, 0)
+    }
+
+    fn push_at_level(&mut self, run: SortedRun<'static>, level: usize) -> Result<()> {
+        if self.levels.len() <= level {
+            self.levels.resize_with(level + 1, Vec::new);
+        }
+        self.levels[level].push(run);
+        if self.levels[level].len() >= self.max_fan_in {
+            let runs = std::mem::take(&mut self.levels[level]);
+            let merged = self.merge_into_spill(runs)?;
+            self.push_at_level(merged, level + 1)?;
+        }
+        Ok(())
+    }
+
+    /// merges lower levels until all remaining runs can be merged at once
+    pub fn finish(mut self) -> Result<SortedRunsMerger<'static>> {
+        let mut level = 0;
+        while self.num_runs() > self.max_fan_in {
+            let mut runs = std::mem::take(&mut self.levels[level]);
+            let run = match runs.len() {
+                0 => None,
+                1 => runs.pop(),
+                _ => Some(self.merge_into_spill(runs)?),
+            };
+            if let Some(run) = run {
+                self.push_at_level(run, level + 1)?;
+            }
+            level += 1;
+        }
+        let runs = self.levels.into_iter().flatten().collect();
+        SortedRunsMerger::try_new(self.sort_keys, runs, batch_size())
+    }
+
+    fn merge_into_spill(&self, runs: Vec<SortedRun<'static>>) -> Result<SortedRun<'static>> {
+        let mut spill = try_new_spill(&self.exec_ctx, "IpcReader")?;
+        let mut writer = IpcCompressionWriter::new(spill.get_buf_writer());
+        for batch in SortedRunsMerger::try_new(self.sort_keys.clone(), runs, batch_size())? {
+            let batch = batch?;
+            writer.write_batch(batch.num_rows(), batch.columns())?;
+        }
+        writer.finish_current_buf()?;
+        writer.inner_mut().flush()?;
+        drop(writer);
+
+        let reader = IpcCompressionReader::new(OwnedSpillBufReader::from(spill));
+        Ok(ipc_sorted_run(reader, self.schema.clone()))
+    }
+}
+
+/// k-way merges sorted runs into sorted batches. memory of batches currently
+/// loaded from all runs is accounted in the mem manager.
+pub struct SortedRunsMerger<'a> {
+    sort_keys: Arc<ShuffleSortKeys>,
+    cursors: LoserTree<RunCursor<'a>>,
+    staging_batches: Vec<RecordBatch>,
+    batch_size: usize,
+    mem_reservation: MemReservation,
+}
+
+impl<'a> SortedRunsMerger<'a> {
+    pub fn try_new(
+        sort_keys: Arc<ShuffleSortKeys>,
+        runs: Vec<SortedRun<'a>>,
+        batch_size: usize,
+    ) -> Result<Self> {
+        let mut staging_batches = vec![];
+        let mut cursors = vec![];
+        for run in runs {
+            let mut cursor = RunCursor {
+                run,
+                current: None,
+                row_idx: 0,
+                staging_idx: 0,
+            };
+            cursor.load_next_batch(&sort_keys, &mut staging_batches)?;
+            cursors.push(cursor);
+        }
+
+        // loser tree requires at least one cursor
+        if cursors.is_empty() {
+            cursors.push(RunCursor {
+                run: Box::new(std::iter::empty()),
+                current: None,
+                row_idx: 0,
+                staging_idx: 0,
+            });
+        }
+
+        let merger = Self {
+            sort_keys,
+            cursors: LoserTree::new(cursors),
+            staging_batches,
+            batch_size,
+            mem_reservation: MemReservation::new("SortedRunsMerger", 0),
+        };
+        merger.update_mem_reservation();
+        Ok(merger)
+    }
+
+    fn update_mem_reservation(&self) {
+        let mem_size = self
+            .cursors
+            .values()
+            .iter()
+            .flat_map(|cursor| &cursor.current)
+            .map(|(batch, keys)| batch.get_array_mem_size() + keys.size())
+            .sum();
+        self.mem_reservation.resize(mem_size);
+    }
+
+    pub fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        let mut indices = Vec::with_capacity(self.batch_size);
+        while indices.len() < self.batch_size {
+            let mut min_cursor = self.cursors.peek_mut();
+            if min_cursor.finished() {
+                break;
+            }
+            indices.push((min_cursor.staging_idx, min_cursor.row_idx));
+            min_cursor.row_idx += 1;
+            if min_cursor.row_idx >= min_cursor.num_rows() {
+                min_cursor.load_next_batch(&self.sort_keys, &mut self.staging_batches)?;
+            }
+        }
+        if indices.is_empty() {
+            return Ok(None);
+        }
+        let batch = create_batch_interleaver(&self.staging_batches, false)?(&indices)?;
+
+        // only retain batches still referenced by cursors
+        self.staging_batches.clear();
+        for cursor in self.cursors.values_mut() {
+            if let Some((batch, _)) = &cursor.current {
+                cursor.staging_idx = self.staging_batches.len();
+                self.staging_batches.push(batch.clone());
+            }
+        }
+        self.update_mem_reservation();
+        Ok(Some(batch))
+    }
+}
+
+impl Iterator for SortedRunsMerger<'_> {
+    type Item = Result<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch().transpose()
+    }
+}
+
+struct RunCursor<'a> {
+    run: SortedRun<'a>,
+    current: Option<(RecordBatch, Rows)>,
+    row_idx: usize,
+    staging_idx: usize,
+}
+
+impl RunCursor<'_> {
+    fn finished(&self) -> bool {
+        self.current.is_none()
+    }
+
+    fn num_rows(&self) -> usize {
+        self.current
+            .as_ref()
+            .map(|(batch, _)| batch.num_rows())
+            .unwrap_or(0)
+    }
+
+    fn cur_key(&self) -> Row {
+        let (_, keys) = self.current.as_ref().expect("cursor finished");
+        keys.row(self.row_idx)
+    }
+
+    fn load_next_batch(
+        &mut self,
+        sort_keys: &ShuffleSortKeys,
+        staging_batches: &mut Vec<RecordBatch>,
+    ) -> Result<()> {
+        self.current = None;
+        self.row_idx = 0;
+        while let Some(batch) = self.run.next().transpose()? {
+            if batch.num_rows() > 0 {
+                let keys = sort_keys.convert(&batch)?;
+                self.staging_idx = staging_batches.len();
+                staging_batches.push(batch.clone());
+                self.current = Some((batch, keys));
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ComparableForLoserTree for RunCursor<'_> {
+    #[inline(always)]
+    fn lt(&self, other: &Self) -> bool {
+        if self.finished() {
+            return false;
+        }
+        if other.finished() {
+            return true;
+        }
+        self.cur_key() < other.cur_key()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{Int32Array, RecordBatch, StringArray},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+    };
+    use arrow_schema::SortOptions;
+    use datafusion::{
+        assert_batches_eq,
+        common::Result,
+        physical_expr::{expressions::Column, PhysicalSortExpr},
+    };
+
+    use super::*;
+
+    fn build_batch(schema: &SchemaRef, keys: Vec<i32>, tags: Vec<&str>) -> RecordBatch {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(keys)),
+                Arc::new(StringArray::from(tags)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_sorted_runs() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int32, false),
+            Field::new("v", DataType::Utf8, false),
+        ]));
+        let sort_keys = Arc::new(ShuffleSortKeys::try_new(
+            vec![PhysicalSortExpr {
+                expr: Arc::new(Column::new("k", 0)),
+                options: SortOptions::default(),
+            }],
+            &schema,
+        )?);
+
+        let run =
+            |batches: Vec<RecordBatch>| -> SortedRun { Box::new(batches.into_iter().map(Ok)) };
+        let runs = vec![
+            run(vec![
+                build_batch(&schema, vec![1, 4], vec!["a1", "a4"]),
+                build_batch(&schema, vec![], vec![]),
+                build_batch(&schema, vec![7], vec!["a7"]),
+            ]),
+            run(vec![]),
+            run(vec![build_batch(
+                &schema,
+                vec![2, 3, 8],
+                vec!["b2", "b3", "b8"],
+            )]),
+            run(vec![
+                build_batch(&schema, vec![0], vec!["c0"]),
+                build_batch(&schema, vec![5, 6], vec!["c5", "c6"]),
+            ]),
+        ];
+        let merged =
+            SortedRunsMerger::try_new(sort_keys.clone(), runs, 4)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            merged.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![4, 4, 1]
+        );
+        assert_batches_eq!(
+            vec![
+                "+---+----+",
+                "| k | v  |",
+                "+---+----+",
+                "| 0 | c0 |",
+                "| 1 | a1 |",
+                "| 2 | b2 |",
+                "| 3 | b3 |",
+                "| 4 | a4 |",
+                "| 5 | c5 |",
+                "| 6 | c6 |",
+                "| 7 | a7 |",
+                "| 8 | b8 |",
+                "+---+----+",
+            ],
+            &merged
+        );
+
+        // no runs
+        let mut merger = SortedRunsMerger::try_new(sort_keys, vec![], 4)?;
+        assert!(merger.next_batch()?.is_none());
+        Ok(())
+    }
+}
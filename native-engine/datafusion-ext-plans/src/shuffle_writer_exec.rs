@@ -18,6 +18,7 @@ use std::{any::Any, fmt::Debug, sync::Arc};
 
 use arrow::datatypes::SchemaRef;
 use async_trait::async_trait;
+use blaze_jni_bridge::{conf, conf::IntConf, is_jni_bridge_inited};
 use datafusion::{
     error::Result,
     execution::context::TaskContext,
@@ -36,28 +37,37 @@ use crate::{
     common::execution_context::ExecutionContext,
     memmgr::MemManager,
     shuffle::{
+        bypass_repartitioner::BypassShuffleRepartitioner, checksum::ShuffleChecksumOutput,
         single_repartitioner::SingleShuffleRepartitioner,
-        sort_repartitioner::SortShuffleRepartitioner, Partitioning, ShuffleRepartitioner,
+        sort_repartitioner::SortShuffleRepartitioner, sorted_merge::ShuffleSortKeys, Partitioning,
+        ShuffleRepartitioner,
     },
     sort_exec::SortExec,
 };
 
 /// The shuffle writer operator maps each input partition to M output partitions
 /// based on a partitioning scheme. No guarantees are made about the order of
-/// the resulting partitions.
+/// the resulting partitions, unless sort expressions are given, in which case
+/// rows of each output partition are sorted by them (key-sorted shuffle).
 #[derive(Debug)]
 pub struct ShuffleWriterExec {
     input: Arc<dyn ExecutionPlan>,
     partitioning: Partitioning,
+    sort_exprs: Vec<PhysicalSortExpr>,
     output_data_file: String,
     output_index_file: String,
+    checksum_output: Option<ShuffleChecksumOutput>,
     metrics: ExecutionPlanMetricsSet,
     props: OnceCell<PlanProperties>,
 }
 
 impl DisplayAs for ShuffleWriterExec {
     fn fmt_as(&self, _t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "ShuffleWriterExec: partitioning={:?}", self.partitioning)
+        write!(f, "ShuffleWriterExec: partitioning={:?}", self.partitioning)?;
+        if !self.sort_exprs.is_empty() {
+            write!(f, ", sort_exprs={:?}", self.sort_exprs)?;
+        }
+        Ok(())
     }
 }
 
@@ -97,8 +107,10 @@ impl ExecutionPlan for ShuffleWriterExec {
             1 => Ok(Arc::new(ShuffleWriterExec::try_new(
                 children[0].clone(),
                 self.partitioning.clone(),
+                self.sort_exprs.clone(),
                 self.output_data_file.clone(),
                 self.output_index_file.clone(),
+                self.checksum_output.clone(),
             )?)),
             _ => df_execution_err!("ShuffleWriterExec wrong number of children"),
         }
@@ -115,19 +127,51 @@ impl ExecutionPlan for ShuffleWriterExec {
         let output_time = exec_ctx.register_timer_metric("output_io_time");
 
         let mut input = self.input.clone();
+        let sort_keys = if !self.sort_exprs.is_empty() {
+            Some(Arc::new(ShuffleSortKeys::try_new(
+                self.sort_exprs.clone(),
+                &self.input.schema(),
+            )?))
+        } else {
+            None
+        };
 
         let repartitioner: Arc<dyn ShuffleRepartitioner> = match &self.partitioning {
-            p if p.partition_count() == 1 => Arc::new(SingleShuffleRepartitioner::new(
-                self.output_data_file.clone(),
-                self.output_index_file.clone(),
-                output_time,
-            )),
-            Partitioning::HashPartitioning(..) | Partitioning::RangePartitioning(..) => {
+            p if p.partition_count() == 1 => {
+                // single output partition is written as is, sort it in advance
+                if !self.sort_exprs.is_empty() {
+                    input = Arc::new(SortExec::new(input, self.sort_exprs.clone(), None));
+                }
+                Arc::new(SingleShuffleRepartitioner::new(
+                    self.output_data_file.clone(),
+                    self.output_index_file.clone(),
+                    self.checksum_output.clone(),
+                    output_time,
+                ))
+            }
+            Partitioning::HashPartitioning(..) | Partitioning::RangePartitioning(..)
+                if sort_keys.is_none()
+                    && self.partitioning.partition_count() <= bypass_merge_threshold()? =>
+            {
+                Arc::new(BypassShuffleRepartitioner::new(
+                    exec_ctx.clone(),
+                    self.output_data_file.clone(),
+                    self.output_index_file.clone(),
+                    self.checksum_output.clone(),
+                    self.partitioning.clone(),
+                    output_time,
+                ))
+            }
+            Partitioning::HashPartitioning(..)
+            | Partitioning::SkewedHashPartitioning(..)
+            | Partitioning::RangePartitioning(..) => {
                 let partitioner = Arc::new(SortShuffleRepartitioner::new(
                     exec_ctx.clone(),
                     self.output_data_file.clone(),
                     self.output_index_file.clone(),
+                    self.checksum_output.clone(),
                     self.partitioning.clone(),
+                    sort_keys.clone(),
                     output_time,
                 ));
                 MemManager::register_consumer(partitioner.clone(), true);
@@ -151,7 +195,9 @@ impl ExecutionPlan for ShuffleWriterExec {
                     exec_ctx.clone(),
                     self.output_data_file.clone(),
                     self.output_index_file.clone(),
+                    self.checksum_output.clone(),
                     self.partitioning.clone(),
+                    sort_keys.clone(),
                     output_time,
                 ));
                 MemManager::register_consumer(partitioner.clone(), true);
@@ -178,16 +224,36 @@ impl ShuffleWriterExec {
     pub fn try_new(
         input: Arc<dyn ExecutionPlan>,
         partitioning: Partitioning,
+        sort_exprs: Vec<PhysicalSortExpr>,
         output_data_file: String,
         output_index_file: String,
+        checksum_output: Option<ShuffleChecksumOutput>,
     ) -> Result<Self> {
+        // sub-partitions of a partition are sorted separately, so the whole
+        // partition cannot be read as one sorted run
+        if matches!(partitioning, Partitioning::SkewedHashPartitioning(..))
+            && !sort_exprs.is_empty()
+        {
+            return df_execution_err!("hot key splitting is not supported in key-sorted shuffle");
+        }
         Ok(ShuffleWriterExec {
             input,
             partitioning,
+            sort_exprs,
             metrics: ExecutionPlanMetricsSet::new(),
             output_data_file,
             output_index_file,
+            checksum_output,
             props: OnceCell::new(),
         })
     }
 }
+
+// shuffles with no more output partitions than this threshold are written with
+// BypassShuffleRepartitioner
+fn bypass_merge_threshold() -> Result<usize> {
+    if !is_jni_bridge_inited() {
+        return Ok(0);
+    }
+    Ok(conf::SHUFFLE_BYPASS_MERGE_THRESHOLD.value()?.max(0) as usize)
+}
@@ -35,7 +35,7 @@ use arrow::{
 use async_trait::async_trait;
 use bytesize::ByteSize;
 use datafusion::{
-    common::{DataFusionError, Result, Statistics},
+    common::{Result, Statistics},
     execution::context::TaskContext,
     physical_expr::{
         expressions::Column, EquivalenceProperties, PhysicalExprRef, PhysicalSortExpr,
@@ -64,10 +64,11 @@ use crate::{
         column_pruning::ExecuteWithColumnPruning,
         execution_context::{ExecutionContext, WrappedRecordBatchSender},
         timer_helper::TimerHelper,
+        trace::TraceLane,
     },
     memmgr::{
-        metrics::SpillMetrics,
-        spill::{try_new_spill, Spill, SpillCompressedReader},
+        async_spill::AsyncSpillWriter,
+        spill::{spill_merge_max_fan_in, try_new_spill, Spill, SpillCompressedReader},
         MemConsumer, MemConsumerInfo, MemManager,
     },
 };
@@ -75,7 +76,6 @@ use crate::{
 // reserve memory for each spill
 // estimated size: bufread=64KB + lz4dec.src=64KB + lz4dec.dest=64KB
 const SPILL_OFFHEAP_MEM_COST: usize = 200000;
-const SPILL_MERGING_SIZE: usize = 32;
 
 #[derive(Debug)]
 pub struct SortExec {
@@ -200,6 +200,7 @@ struct ExternalSorter {
     limit: usize,
     data: Arc<Mutex<BufferedData>>,
     spills: Mutex<Vec<LevelSpill>>,
+    spill_writer: Mutex<AsyncSpillWriter<Box<dyn Spill>>>,
     num_total_rows: AtomicUsize,
     mem_total_size: AtomicUsize,
 }
@@ -220,6 +221,18 @@ impl MemConsumer for ExternalSorter {
             .expect("consumer info not set")
     }
 
+    fn mem_stage_id(&self) -> Option<usize> {
+        self.exec_ctx.stage_id()
+    }
+
+    fn mem_partition_id(&self) -> Option<usize> {
+        Some(self.exec_ctx.partition_id())
+    }
+
+    fn mem_trace_lane(&self) -> Option<TraceLane> {
+        self.exec_ctx.trace_lane(self.name()).cloned()
+    }
+
     async fn spill(&self) -> Result<()> {
         let data = std::mem::take(&mut *self.data.lock().await);
         let sub_batch_size = compute_suggested_batch_size_for_kway_merge(
@@ -228,45 +241,34 @@ impl MemConsumer for ExternalSorter {
         );
 
         let limit = self.limit;
-        let spill_metrics = self.exec_ctx.spill_metrics().clone();
-        let spill = tokio::task::spawn_blocking(move || {
-            let mut spill = try_new_spill(&spill_metrics)?;
-            data.try_into_spill(&mut spill, sub_batch_size, limit)?;
-            Ok::<_, DataFusionError>(spill)
-        })
-        .await
-        .expect("tokio error")?;
-
-        self.spills
-            .lock()
-            .await
-            .push(LevelSpill { spill, level: 0 });
+        let exec_ctx = self.exec_ctx.clone();
+        let consumer_info = self.get_consumer_info().clone();
+        let mut spill_writer = self.spill_writer.lock().await;
+        spill_writer
+            .submit(data.mem_used(), self.mem_trace_lane(), move || {
+                let mut spill = try_new_spill(&exec_ctx, "Sort")?;
+                data.try_into_spill(&mut spill, sub_batch_size, limit)?;
+                if let Some(consumer_info) = consumer_info.upgrade() {
+                    consumer_info.record_spill(spill.spill_len());
+                }
+                Ok(spill)
+            })
+            .await;
+        let written_spills = spill_writer.take_finished().await?;
+        drop(spill_writer);
+
+        // frozen data is being written in background and accounted by the spill
+        // writer, new input can be accepted
+        self.spills.lock().await.extend(
+            written_spills
+                .into_iter()
+                .map(|spill| LevelSpill { spill, level: 0 }),
+        );
         self.update_mem_used(0).await?;
 
         // merge if there are too many spills
         let mut spills = self.spills.lock().await;
-        let mut levels = (0..32).map(|_| vec![]).collect::<Vec<_>>();
-        for spill in std::mem::take(&mut *spills) {
-            levels[spill.level].push(spill.spill);
-        }
-        for level in 0..levels.len() {
-            if levels[level].len() >= SPILL_MERGING_SIZE {
-                let merged = merge_spills(
-                    std::mem::take(&mut levels[level]),
-                    self.exec_ctx.spill_metrics(),
-                    sub_batch_size,
-                    self.limit,
-                    self.prune_sort_keys_from_batch.pruned_schema.clone(),
-                )?;
-                levels[level + 1].push(merged);
-            } else {
-                spills.extend(
-                    std::mem::take(&mut levels[level])
-                        .into_iter()
-                        .map(|spill| LevelSpill { spill, level }),
-                )
-            }
-        }
+        *spills = self.merge_spill_levels(std::mem::take(&mut *spills), sub_batch_size, false)?;
         Ok(())
     }
 }
@@ -504,6 +506,10 @@ impl ExecuteWithColumnPruning for SortExec {
             limit: self.fetch.unwrap_or(usize::MAX),
             data: Default::default(),
             spills: Default::default(),
+            spill_writer: Mutex::new(AsyncSpillWriter::new(
+                "ExternalSorter",
+                exec_ctx.spill_metrics().clone(),
+            )),
             num_total_rows: Default::default(),
             mem_total_size: Default::default(),
         });
@@ -529,7 +535,7 @@ impl ExecuteWithColumnPruning for SortExec {
 
                 // if external merging is required, we need to spill in-mem data
                 // to free memory as soon as possible
-                let has_spill = !sorter.spills.lock().await.is_empty();
+                let has_spill = sorter.has_spill().await;
                 let in_mem_used = sorter.data.lock().await.mem_used();
                 if has_spill && in_mem_used > 0 {
                     log::info!(
@@ -553,6 +559,10 @@ impl Drop for ExternalSorter {
 }
 
 impl ExternalSorter {
+    async fn has_spill(&self) -> bool {
+        !self.spills.lock().await.is_empty() || self.spill_writer.lock().await.num_pending() > 0
+    }
+
     async fn insert_batch(self: &Arc<Self>, batch: RecordBatch) -> Result<()> {
         if batch.num_rows() == 0 {
             return Ok(());
@@ -579,7 +589,13 @@ impl ExternalSorter {
         self.set_spillable(false);
 
         let data = std::mem::take(&mut *self.data.lock().await);
-        let spills = std::mem::take(&mut *self.spills.lock().await);
+        let mut spills = std::mem::take(&mut *self.spills.lock().await);
+        let written_spills = self.spill_writer.lock().await.wait_all().await?;
+        spills.extend(
+            written_spills
+                .into_iter()
+                .map(|spill| LevelSpill { spill, level: 0 }),
+        );
         log::info!(
             "{} starts outputting ({} spills + in_mem: {})",
             self.name(),
@@ -591,7 +607,6 @@ impl ExternalSorter {
             self.mem_total_size(),
             self.num_total_rows(),
         );
-        let mut spills: Vec<Box<dyn Spill>> = spills.into_iter().map(|spill| spill.spill).collect();
 
         // no spills -- output in-mem batches
         if spills.is_empty() {
@@ -599,6 +614,7 @@ impl ExternalSorter {
                 for (key_store, pruned_batch) in
                     data.into_sorted_batches::<SimpleKeyCollector>(sub_batch_size, self.limit)?
                 {
+                    self.exec_ctx.check_cancelled()?;
                     let batch = self
                         .prune_sort_keys_from_batch
                         .restore(pruned_batch, key_store)?;
@@ -612,6 +628,14 @@ impl ExternalSorter {
             return Ok(());
         }
 
+        // too many spills -- merge into larger spills, so the number of
+        // cursors opened at once is bounded
+        let mut spills: Vec<Box<dyn Spill>> = self
+            .merge_spill_levels(spills, sub_batch_size, true)?
+            .into_iter()
+            .map(|spill| spill.spill)
+            .collect();
+
         let mut merger = ExternalMerger::<SimpleKeyCollector>::try_new(
             &mut spills,
             self.prune_sort_keys_from_batch.pruned_schema(),
@@ -619,6 +643,7 @@ impl ExternalSorter {
             self.limit,
         )?;
         while let Some((key_collector, pruned_batch)) = merger.next().transpose()? {
+            self.exec_ctx.check_cancelled()?;
             let batch = self
                 .prune_sort_keys_from_batch
                 .restore(pruned_batch, key_collector)?;
@@ -634,6 +659,64 @@ impl ExternalSorter {
         Ok(())
     }
 
+    // merges spills of the same level into one spill of the next level when
+    // the level is full. if `is_final` is set, lower levels are also merged
+    // until the total number of spills does not exceed the max fan-in.
+    fn merge_spill_levels(
+        &self,
+        spills: Vec<LevelSpill>,
+        sub_batch_size: usize,
+        is_final: bool,
+    ) -> Result<Vec<LevelSpill>> {
+        let max_fan_in = spill_merge_max_fan_in();
+        let mut num_spills = spills.len();
+        let mut levels: Vec<Vec<Box<dyn Spill>>> = vec![];
+        for spill in spills {
+            if levels.len() <= spill.level {
+                levels.resize_with(spill.level + 1, Vec::new);
+            }
+            levels[spill.level].push(spill.spill);
+        }
+
+        let mut level = 0;
+        while level < levels.len() {
+            let num_merging = if levels[level].len() >= max_fan_in {
+                max_fan_in
+            } else if is_final && num_spills > max_fan_in {
+                // merge only as many spills as needed
+                levels[level].len().min(num_spills - max_fan_in + 1)
+            } else {
+                0
+            };
+            if num_merging == 0 {
+                level += 1;
+                continue;
+            }
+            let merged = merge_spills(
+                levels[level].drain(..num_merging).collect(),
+                &self.exec_ctx,
+                sub_batch_size,
+                self.limit,
+                self.prune_sort_keys_from_batch.pruned_schema.clone(),
+            )?;
+            num_spills -= num_merging - 1;
+            if levels.len() <= level + 1 {
+                levels.push(vec![]);
+            }
+            levels[level + 1].push(merged);
+        }
+
+        Ok(levels
+            .into_iter()
+            .enumerate()
+            .flat_map(|(level, spills)| {
+                spills
+                    .into_iter()
+                    .map(move |spill| LevelSpill { spill, level })
+            })
+            .collect())
+    }
+
     fn num_total_rows(&self) -> usize {
         self.num_total_rows.load(SeqCst)
     }
@@ -904,7 +987,7 @@ impl<KC: KeyCollector> ExternalMerger<'_, KC> {
 
 fn merge_spills(
     mut spills: Vec<Box<dyn Spill>>,
-    spill_metrics: &SpillMetrics,
+    exec_ctx: &ExecutionContext,
     sub_batch_size: usize,
     limit: usize,
     pruned_schema: SchemaRef,
@@ -914,7 +997,7 @@ fn merge_spills(
         return Ok(spills.into_iter().next().unwrap());
     }
 
-    let mut output_spill = try_new_spill(spill_metrics)?;
+    let mut output_spill = try_new_spill(exec_ctx, "Sort")?;
     let mut output_writer = output_spill.get_compressed_writer();
     let mut merger = ExternalMerger::<SqueezeKeyCollector>::try_new(
         &mut spills,
@@ -924,6 +1007,7 @@ fn merge_spills(
     )?;
 
     while let Some((key_collector, pruned_batch)) = merger.next().transpose()? {
+        exec_ctx.check_cancelled()?;
         write_one_batch(
             pruned_batch.num_rows(),
             pruned_batch.columns(),
@@ -1275,10 +1359,13 @@ mod test {
         common::Result,
         physical_expr::{expressions::Column, PhysicalSortExpr},
         physical_plan::{common, memory::MemoryExec, ExecutionPlan},
-        prelude::SessionContext,
+        prelude::{SessionConfig, SessionContext},
     };
+    use futures::StreamExt;
 
-    use crate::{memmgr::MemManager, sort_exec::SortExec};
+    use crate::{
+        common::execution_context::cancel_all_tasks, memmgr::MemManager, sort_exec::SortExec,
+    };
 
     fn build_table_i32(
         a: (&str, &Vec<i32>),
@@ -1346,6 +1433,42 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_sort_cancelled() -> Result<()> {
+        MemManager::init(10000);
+        let session_ctx =
+            SessionContext::new_with_config(SessionConfig::new().with_batch_size(100));
+        let task_ctx = session_ctx.task_ctx();
+        let n = 1000000;
+        let values = (0..n).rev().collect::<Vec<i32>>();
+        let input = build_table(("a", &values), ("b", &values), ("c", &values));
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: Arc::new(Column::new("a", 0)),
+            options: SortOptions::default(),
+        }];
+
+        let sort = SortExec::new(input, sort_exprs, None);
+        let mut output = sort.execute(0, task_ctx.clone())?;
+        let first_batch = output.next().await.expect("first batch")?;
+
+        // the remaining output is interrupted promptly after cancelling
+        cancel_all_tasks(&task_ctx);
+        let mut num_output_rows = first_batch.num_rows();
+        let mut cancelled = false;
+        while let Some(batch) = output.next().await {
+            match batch {
+                Ok(batch) => num_output_rows += batch.num_rows(),
+                Err(_) => {
+                    cancelled = true;
+                    break;
+                }
+            }
+        }
+        assert!(cancelled);
+        assert!(num_output_rows < n as usize);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
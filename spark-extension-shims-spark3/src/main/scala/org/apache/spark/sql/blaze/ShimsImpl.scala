@@ -23,6 +23,8 @@ import org.apache.spark.ShuffleDependency
 import org.apache.spark.SparkEnv
 import org.apache.spark.SparkException
 import org.apache.spark.TaskContext
+import org.apache.spark.api.python.BasePythonRunner
+import org.apache.spark.api.python.ChainedPythonFunctions
 import org.apache.spark.internal.Logging
 import org.apache.spark.rdd.RDD
 import org.apache.spark.scheduler.MapStatus
@@ -41,6 +43,7 @@ import org.apache.spark.sql.catalyst.expressions.Generator
 import org.apache.spark.sql.catalyst.expressions.Like
 import org.apache.spark.sql.catalyst.expressions.Literal
 import org.apache.spark.sql.catalyst.expressions.NamedExpression
+import org.apache.spark.sql.catalyst.expressions.PythonUDF
 import org.apache.spark.sql.catalyst.expressions.SortOrder
 import org.apache.spark.sql.catalyst.expressions.StringSplit
 import org.apache.spark.sql.catalyst.expressions.TaggingExpression
@@ -104,10 +107,14 @@ import org.apache.spark.sql.execution.joins.blaze.plan.NativeBroadcastJoinExec
 import org.apache.spark.sql.execution.joins.blaze.plan.NativeShuffledHashJoinExecProvider
 import org.apache.spark.sql.execution.joins.blaze.plan.NativeSortMergeJoinExecProvider
 import org.apache.spark.sql.execution.metric.{SQLMetric, SQLShuffleReadMetricsReporter}
+import org.apache.spark.sql.execution.python.ArrowPythonRunner
 import org.apache.spark.sql.hive.execution.InsertIntoHiveTable
+import org.apache.spark.sql.internal.SQLConf
 import org.apache.spark.sql.types.DataType
 import org.apache.spark.sql.types.IntegerType
 import org.apache.spark.sql.types.StringType
+import org.apache.spark.sql.types.StructType
+import org.apache.spark.sql.vectorized.ColumnarBatch
 import org.apache.spark.storage.BlockManagerId
 import org.apache.spark.storage.FileSegment
 import org.blaze.{protobuf => pb}
@@ -235,6 +242,13 @@ class ShimsImpl extends Shims with Logging {
       child: SparkPlan): NativeGenerateBase =
     NativeGenerateExec(generator, requiredChildOutput, outer, generatorOutput, child)
 
+  override def createNativePythonEvalExec(
+      udfs: Seq[PythonUDF],
+      resultAttrs: Seq[Attribute],
+      evalType: Int,
+      child: SparkPlan): NativePythonEvalBase =
+    NativePythonEvalExec(udfs, resultAttrs, evalType, child)
+
   override def createNativeGlobalLimitExec(limit: Long, child: SparkPlan): NativeGlobalLimitBase =
     NativeGlobalLimitExec(limit, child)
 
@@ -383,10 +397,10 @@ class ShimsImpl extends Shims with Logging {
       tempDataFile: File,
       mapId: Long,
       partitionLengths: Array[Long],
+      checksums: Array[Long],
       dataSize: Long,
       context: TaskContext): MapStatus = {
 
-    val checksums = Array[Long]()
     shuffleBlockResolver.writeMetadataFileAndCommit(
       dep.shuffleId,
       mapId,
@@ -403,9 +417,11 @@ class ShimsImpl extends Shims with Logging {
       tempDataFile: File,
       mapId: Long,
       partitionLengths: Array[Long],
+      checksums: Array[Long],
       dataSize: Long,
       context: TaskContext): MapStatus = {
 
+    // checksums are not supported before spark 3.2
     shuffleBlockResolver.writeIndexFileAndCommit(
       dep.shuffleId,
       mapId,
@@ -426,13 +442,19 @@ class ShimsImpl extends Shims with Logging {
       mapId: Long): MapStatus =
     MapStatus.apply(shuffleServerId, partitionLengthMap, mapId)
 
+  override def isRssShuffleManager: Boolean =
+    SparkEnv.get.shuffleManager.isInstanceOf[BlazeCelebornShuffleManager] ||
+      SparkEnv.get.shuffleManager.isInstanceOf[BlazeUniffleShuffleManager]
+
   override def getShuffleWriteExec(
       input: pb.PhysicalPlanNode,
       nativeOutputPartitioning: pb.PhysicalRepartition.Builder): pb.PhysicalPlanNode = {
 
-    if (SparkEnv.get.shuffleManager
-        .isInstanceOf[BlazeCelebornShuffleManager] || SparkEnv.get.shuffleManager
-        .isInstanceOf[BlazeUniffleShuffleManager]) {
+    if (isRssShuffleManager) {
+      // hot key splitting is not supported in rss shuffle
+      if (nativeOutputPartitioning.hasHashRepartition) {
+        nativeOutputPartitioning.getHashRepartitionBuilder.clearHotKeySplits()
+      }
       return pb.PhysicalPlanNode
         .newBuilder()
         .setRssShuffleWriter(
@@ -855,6 +877,69 @@ class ShimsImpl extends Shims with Logging {
   @enableIf(Seq("spark-3.0", "spark-3.1").contains(System.getProperty("blaze.shim")))
   override def getSqlContext(sparkPlan: SparkPlan): SQLContext = sparkPlan.sqlContext
 
+  @enableIf(
+    Seq("spark-3.0", "spark-3.1", "spark-3.2", "spark-3.3").contains(
+      System.getProperty("blaze.shim")))
+  override def createArrowPythonRunner(
+      funcs: Seq[ChainedPythonFunctions],
+      evalType: Int,
+      argOffsets: Array[Array[Int]],
+      paramsSchema: StructType): BasePythonRunner[Iterator[InternalRow], ColumnarBatch] = {
+    val sqlConf = SQLConf.get
+    new ArrowPythonRunner(
+      funcs,
+      evalType,
+      argOffsets,
+      paramsSchema,
+      sqlConf.sessionLocalTimeZone,
+      org.apache.spark.sql.util.ArrowUtils.getPythonRunnerConfMap(sqlConf))
+  }
+
+  @enableIf(Seq("spark-3.4").contains(System.getProperty("blaze.shim")))
+  override def createArrowPythonRunner(
+      funcs: Seq[ChainedPythonFunctions],
+      evalType: Int,
+      argOffsets: Array[Array[Int]],
+      paramsSchema: StructType): BasePythonRunner[Iterator[InternalRow], ColumnarBatch] = {
+    val sqlConf = SQLConf.get
+    new ArrowPythonRunner(
+      funcs,
+      evalType,
+      argOffsets,
+      paramsSchema,
+      sqlConf.sessionLocalTimeZone,
+      org.apache.spark.sql.util.ArrowUtils.getPythonRunnerConfMap(sqlConf),
+      pythonRunnerMetrics)
+  }
+
+  @enableIf(Seq("spark-3.5").contains(System.getProperty("blaze.shim")))
+  override def createArrowPythonRunner(
+      funcs: Seq[ChainedPythonFunctions],
+      evalType: Int,
+      argOffsets: Array[Array[Int]],
+      paramsSchema: StructType): BasePythonRunner[Iterator[InternalRow], ColumnarBatch] = {
+    val sqlConf = SQLConf.get
+    new ArrowPythonRunner(
+      funcs,
+      evalType,
+      argOffsets,
+      paramsSchema,
+      sqlConf.sessionLocalTimeZone,
+      sqlConf.arrowUseLargeVarTypes,
+      ArrowPythonRunner.getPythonRunnerConfMap(sqlConf),
+      pythonRunnerMetrics,
+      None)
+  }
+
+  // metrics required by the runner since spark 3.4, they are created in executor
+  // side and not reported to the driver
+  @enableIf(Seq("spark-3.4", "spark-3.5").contains(System.getProperty("blaze.shim")))
+  private def pythonRunnerMetrics: Map[String, SQLMetric] =
+    Map(
+      "pythonDataSent" -> new SQLMetric("size"),
+      "pythonDataReceived" -> new SQLMetric("size"),
+      "pythonNumRowsReceived" -> new SQLMetric("sum"))
+
   override def createNativeExprWrapper(
       nativeExpr: pb.PhysicalExprNode,
       dataType: DataType,
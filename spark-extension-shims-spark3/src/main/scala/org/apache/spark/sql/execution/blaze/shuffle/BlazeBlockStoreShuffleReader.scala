@@ -17,6 +17,8 @@ package org.apache.spark.sql.execution.blaze.shuffle
 
 import java.io.InputStream
 
+import scala.collection.mutable.ArrayBuffer
+
 import org.apache.spark.MapOutputTracker
 import org.apache.spark.SparkEnv
 import org.apache.spark.TaskContext
@@ -24,11 +26,16 @@ import org.apache.spark.internal.Logging
 import org.apache.spark.internal.config
 import org.apache.spark.io.CompressionCodec
 import org.apache.spark.shuffle.BaseShuffleHandle
+import org.apache.spark.shuffle.FetchFailedException
+import org.apache.spark.shuffle.IndexShuffleBlockResolver
 import org.apache.spark.shuffle.ShuffleReadMetricsReporter
+import org.apache.spark.sql.blaze.BlazeConf
 import org.apache.spark.storage.BlockId
 import org.apache.spark.storage.BlockManager
 import org.apache.spark.storage.BlockManagerId
+import org.apache.spark.storage.ShuffleBlockBatchId
 import org.apache.spark.storage.ShuffleBlockFetcherIterator
+import org.apache.spark.storage.ShuffleBlockId
 
 import com.thoughtworks.enableIf
 
@@ -43,6 +50,88 @@ class BlazeBlockStoreShuffleReader[K, C](
     extends BlazeBlockStoreShuffleReaderBase[K, C](handle, context)
     with Logging {
 
+  private lazy val allBlocksByAddress = blocksByAddress.toArray
+
+  // blocks of key-sorted shuffles are merged by the native reader, each of them must be
+  // one sorted run, so blocks of continuous partitions cannot be read in batch
+  private lazy val keySorted = BlazeShuffleDependency.isKeySortedShuffle(handle)
+
+  // address and map index of each block, used for reporting fetch failures
+  private lazy val blockLocations: Map[BlockId, (BlockManagerId, Int)] =
+    allBlocksByAddress.flatMap { case (address, blocks) =>
+      blocks.map { case (blockId, _, mapIndex) => blockId -> (address, mapIndex) }
+    }.toMap
+
+  // blocks written by this executor are read natively from local shuffle files,
+  // other blocks are fetched with the block fetcher iterator
+  private lazy val (localShuffleBlocks, remoteBlocksByAddress) = {
+    if (!BlazeConf.SHUFFLE_NATIVE_LOCAL_READ_ENABLE.booleanConf()) {
+      (Seq.empty[(BlockId, Long)], allBlocksByAddress.iterator)
+    } else {
+      val localExecutorId = blockManager.blockManagerId.executorId
+      val localBlocks = ArrayBuffer[(BlockId, Long)]()
+      val remoteBlocks = allBlocksByAddress.map { case (address, blocks) =>
+        if (address.executorId == localExecutorId) {
+          val (nativeBlocks, otherBlocks) = blocks.partition {
+            case (_: ShuffleBlockId, _, _) | (_: ShuffleBlockBatchId, _, _) => true
+            case _ => false
+          }
+          localBlocks ++= nativeBlocks.map(block => (block._1, block._2))
+          (address, otherBlocks)
+        } else {
+          (address, blocks)
+        }
+      }.toArray
+      (localBlocks.toSeq, remoteBlocks.iterator.filter(_._2.nonEmpty))
+    }
+  }
+
+  override protected def readLocalShuffleBlocks(): Iterator[BlockObject] = {
+    val resolver =
+      SparkEnv.get.shuffleManager.shuffleBlockResolver.asInstanceOf[IndexShuffleBlockResolver]
+    localShuffleBlocks.iterator.flatMap {
+      case (blockId @ ShuffleBlockId(shuffleId, mapId, reduceId), size) =>
+        readMetrics.incLocalBlocksFetched(1)
+        readMetrics.incLocalBytesRead(size)
+        Iterator.single(
+          BlazeBlockStoreShuffleReaderBase.createLocalShuffleBlockObject(
+            resolver.getDataFile(shuffleId, mapId),
+            reduceId,
+            reduceId + 1,
+            message => throwFetchFailed(blockId, message)))
+      case (blockId @ ShuffleBlockBatchId(shuffleId, mapId, startReduceId, endReduceId), size) =>
+        readMetrics.incLocalBlocksFetched(1)
+        readMetrics.incLocalBytesRead(size)
+        // split the batch into one block per partition for key-sorted shuffles
+        val reduceIdRanges = if (keySorted) {
+          (startReduceId until endReduceId).iterator.map(reduceId => (reduceId, reduceId + 1))
+        } else {
+          Iterator.single((startReduceId, endReduceId))
+        }
+        reduceIdRanges.map { case (start, end) =>
+          BlazeBlockStoreShuffleReaderBase.createLocalShuffleBlockObject(
+            resolver.getDataFile(shuffleId, mapId),
+            start,
+            end,
+            message => throwFetchFailed(blockId, message))
+        }
+    }
+  }
+
+  override protected def throwFetchFailed(blockId: BlockId, message: String): Unit = {
+    val fetchFailed = (blockId, blockLocations.get(blockId)) match {
+      case (ShuffleBlockId(shuffleId, mapId, reduceId), Some((address, mapIndex))) =>
+        new FetchFailedException(address, shuffleId, mapId, mapIndex, reduceId, message)
+      case (ShuffleBlockBatchId(shuffleId, mapId, startReduceId, _), Some((address, mapIndex))) =>
+        new FetchFailedException(address, shuffleId, mapId, mapIndex, startReduceId, message)
+      case _ =>
+        return super.throwFetchFailed(blockId, message)
+    }
+    // the exception may be wrapped by native side, mark the task explicitly
+    context.setFetchFailed(fetchFailed)
+    throw fetchFailed
+  }
+
   override def readBlocks(): Iterator[(BlockId, InputStream)] = {
     @enableIf(
       Seq("spark-3.2", "spark-3.3", "spark-3.4", "spark-3.5").contains(
@@ -52,7 +141,7 @@ class BlazeBlockStoreShuffleReader[K, C](
       blockManager.blockStoreClient,
       blockManager,
       mapOutputTracker,
-      blocksByAddress,
+      remoteBlocksByAddress,
       (_, inputStream) => inputStream,
       // Note: we use getSizeAsMb when no suffix is provided for backwards compatibility
       SparkEnv.get.conf.get(config.REDUCER_MAX_SIZE_IN_FLIGHT) * 1024 * 1024,
@@ -62,8 +151,8 @@ class BlazeBlockStoreShuffleReader[K, C](
       SparkEnv.get.conf.get(config.SHUFFLE_MAX_ATTEMPTS_ON_NETTY_OOM),
       SparkEnv.get.conf.get(config.SHUFFLE_DETECT_CORRUPT),
       SparkEnv.get.conf.get(config.SHUFFLE_DETECT_CORRUPT_MEMORY),
-      false, // checksums not supported
-      "ChecksumAlgorithmsNotSupported",
+      SparkEnv.get.conf.get(config.SHUFFLE_CHECKSUM_ENABLED),
+      SparkEnv.get.conf.get(config.SHUFFLE_CHECKSUM_ALGORITHM),
       readMetrics,
       fetchContinuousBlocksInBatch).toCompletionIterator
 
@@ -72,7 +161,7 @@ class BlazeBlockStoreShuffleReader[K, C](
       context,
       blockManager.blockStoreClient,
       blockManager,
-      blocksByAddress,
+      remoteBlocksByAddress,
       (_, inputStream) => inputStream,
       // Note: we use getSizeAsMb when no suffix is provided for backwards compatibility
       SparkEnv.get.conf.get(config.REDUCER_MAX_SIZE_IN_FLIGHT) * 1024 * 1024,
@@ -100,14 +189,14 @@ class BlazeBlockStoreShuffleReader[K, C](
     val useOldFetchProtocol = conf.get(config.SHUFFLE_USE_OLD_FETCH_PROTOCOL)
 
     val doBatchFetch = shouldBatchFetch && serializerRelocatable &&
-      (!compressed || codecConcatenation) && !useOldFetchProtocol
+      (!compressed || codecConcatenation) && !useOldFetchProtocol && !keySorted
     if (shouldBatchFetch && !doBatchFetch) {
       logDebug(
         "The feature tag of continuous shuffle block fetching is set to true, but " +
           "we can not enable the feature because other conditions are not satisfied. " +
           s"Shuffle compress: $compressed, serializer relocatable: $serializerRelocatable, " +
           s"codec concatenation: $codecConcatenation, use old shuffle fetch protocol: " +
-          s"$useOldFetchProtocol.")
+          s"$useOldFetchProtocol, key-sorted shuffle: $keySorted.")
     }
     doBatchFetch
   }
@@ -24,6 +24,7 @@ import org.apache.spark.shuffle._
 import org.apache.spark.shuffle.sort.SortShuffleManager
 import org.apache.spark.shuffle.sort.SortShuffleManager.canUseBatchFetch
 import org.apache.spark.sql.execution.blaze.shuffle.BlazeShuffleDependency.isArrowShuffle
+import org.apache.spark.sql.execution.blaze.shuffle.BlazeShuffleDependency.isKeySortedShuffle
 
 import com.thoughtworks.enableIf
 
@@ -69,8 +70,10 @@ class BlazeShuffleManager(conf: SparkConf) extends ShuffleManager with Logging {
         Seq("spark-3.3", "spark-3.4", "spark-3.5").contains(System.getProperty("blaze.shim")))
       def shuffleMergeFinalized = baseShuffleHandle.dependency.isShuffleMergeFinalizedMarked
 
+      // merged blocks of push-based shuffle concatenate outputs of many maps, key-sorted
+      // shuffles read the original map outputs so that each block is one sorted run
       val (blocksByAddress, canEnableBatchFetch) =
-        if (shuffleMergeFinalized) {
+        if (shuffleMergeFinalized && !isKeySortedShuffle(handle)) {
           val res = SparkEnv.get.mapOutputTracker.getPushBasedShuffleMapSizesByExecutorId(
             handle.shuffleId,
             startMapIndex,
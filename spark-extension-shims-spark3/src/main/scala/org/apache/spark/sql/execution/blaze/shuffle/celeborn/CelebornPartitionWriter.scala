@@ -55,6 +55,37 @@ class CelebornPartitionWriter(
     mapStatusLengths(partitionId) += bytesWritten
   }
 
+  override def pushBatch(
+      partitionIds: Array[Int],
+      lengths: Array[Int],
+      buffer: ByteBuffer): Unit = {
+    val bytes = new Array[Byte](buffer.remaining())
+    buffer.get(bytes)
+
+    // buffers of all partitions are merged by worker addresses and pushed together.
+    // celeborn client assigns batch ids, retries failed pushes and revives split
+    // partitions in its push callbacks, unrecoverable failures are thrown on the next
+    // call and fail the task
+    shuffleClient.prepareForMergeData(shuffleId, mapId, encodedAttemptId)
+    var offset = 0
+    for (i <- partitionIds.indices) {
+      val bytesWritten = shuffleClient.mergeData(
+        shuffleId,
+        mapId,
+        encodedAttemptId,
+        partitionIds(i),
+        bytes,
+        offset,
+        lengths(i),
+        numMappers,
+        numPartitions)
+      metrics.incBytesWritten(bytesWritten)
+      mapStatusLengths(partitionIds(i)) += bytesWritten
+      offset += lengths(i)
+    }
+    shuffleClient.pushMergedData(shuffleId, mapId, encodedAttemptId)
+  }
+
   override def flush(): Unit = {}
 
   override def close(): Unit = {
@@ -56,6 +56,32 @@ class UnifflePartitionWriter[K, V, C](
     mapStatusLengths(partitionId) += bytesWritten
   }
 
+  override def pushBatch(
+      partitionIds: Array[Int],
+      lengths: Array[Int],
+      buffer: ByteBuffer): Unit = {
+    val bufferManager = rssShuffleWriter.getBufferManager
+    val shuffleBlockInfos = new java.util.ArrayList[ShuffleBlockInfo]()
+
+    // add buffers of all partitions and send the full blocks with one call.
+    // failed blocks are resent by the data pusher and checked when the writer
+    // is stopped
+    rssShuffleWriter.synchronized {
+      for (i <- partitionIds.indices) {
+        val bytes = new Array[Byte](lengths(i))
+        buffer.get(bytes)
+        val blocks = bufferManager.addPartitionData(partitionIds(i), bytes)
+        if (blocks != null) {
+          shuffleBlockInfos.addAll(blocks)
+        }
+        mapStatusLengths(partitionIds(i)) += lengths(i)
+      }
+      if (!shuffleBlockInfos.isEmpty) {
+        rssShuffleWriterPushBlocksMethod.invoke(rssShuffleWriter, shuffleBlockInfos)
+      }
+    }
+  }
+
   override def flush(): Unit = {}
 
   override def close(): Unit = {
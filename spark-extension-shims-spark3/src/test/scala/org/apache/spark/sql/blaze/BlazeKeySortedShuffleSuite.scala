@@ -0,0 +1,74 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.blaze
+
+import org.apache.spark.SparkConf
+
+import com.thoughtworks.enableMembersIf
+
+@enableMembersIf(Seq("spark-3.5").contains(System.getProperty("blaze.shim")))
+class BlazeKeySortedShuffleSuite
+    extends org.apache.spark.sql.QueryTest
+    with BaseBlazeSQLSuite
+    with org.apache.spark.sql.execution.adaptive.AdaptiveSparkPlanHelper {
+
+  import org.apache.spark.sql.execution.CoalescedPartitionSpec
+  import org.apache.spark.sql.execution.adaptive.AQEShuffleReadExec
+  import org.apache.spark.sql.internal.SQLConf
+  import org.apache.spark.sql.test.SQLTestData.TestData
+
+  import testImplicits._
+
+  override protected def sparkConf: SparkConf = {
+    // local blocks are fetched by the block fetcher iterator, which fetches blocks of
+    // continuous reduce partitions in batch if not disabled
+    super.sparkConf
+      .set("spark.blaze.shuffle.keySorted.enable", "true")
+      .set("spark.blaze.shuffle.nativeLocalRead.enable", "false")
+  }
+
+  test("key-sorted shuffle read with blocks of several reduce partitions") {
+    withTempView("v") {
+      withSQLConf(
+        SQLConf.ADAPTIVE_EXECUTION_ENABLED.key -> "true",
+        SQLConf.COALESCE_PARTITIONS_ENABLED.key -> "true",
+        SQLConf.SHUFFLE_PARTITIONS.key -> "5",
+        SQLConf.COALESCE_PARTITIONS_MIN_PARTITION_NUM.key -> "1",
+        SQLConf.ADVISORY_PARTITION_SIZE_IN_BYTES.key -> "10000") {
+
+        spark.sparkContext
+          .parallelize((1 to 100).map(i => TestData(i * 37 % 100, i.toString)), 4)
+          .toDF("c1", "c2")
+          .createOrReplaceTempView("v")
+
+        // all reduce partitions are coalesced into one, whose map outputs are read as
+        // several sorted runs and merged without sorting again
+        val df = sql("SELECT /*+ REPARTITION(c1) */ c1 FROM v SORT BY c1")
+        val result = df.collect().map(_.getInt(0)).toSeq
+        assert(result == (0 until 100))
+
+        val read = collect(df.queryExecution.executedPlan) { case read: AQEShuffleReadExec =>
+          read
+        }
+        assert(read.size == 1)
+        assert(read.head.partitionSpecs.exists {
+          case CoalescedPartitionSpec(start, end, _) => end - start > 1
+          case _ => false
+        })
+      }
+    }
+  }
+}
@@ -27,6 +27,9 @@ public enum BlazeConf {
     /// actual off-heap memory usage is expected to be spark.executor.memoryOverhead * fraction.
     MEMORY_FRACTION("spark.blaze.memoryFraction", 0.6),
 
+    // policy of sharing memory among spillable consumers: fair-share, priority or largest-first
+    MEMMGR_POLICY("spark.blaze.memmgr.policy", "fair-share"),
+
     /// enable converting upper/lower functions to native, special cases may provide different
     /// outputs from spark due to different unicode versions.
     CASE_CONVERT_FUNCTIONS_ENABLE("spark.blaze.enable.caseconvert.functions", true),
@@ -61,6 +64,66 @@ public enum BlazeConf {
     // spark io compression codec
     SPARK_IO_COMPRESSION_CODEC("spark.io.compression.codec", "lz4"),
 
+    // spark io compression zstd level
+    SPARK_IO_COMPRESSION_ZSTD_LEVEL("spark.io.compression.zstd.level", 1),
+
+    // shuffle/broadcast compression codec, falls back to spark.io.compression.codec if empty
+    // supported codecs: lz4, lz4_block, zstd, snappy, none
+    SHUFFLE_COMPRESSION_CODEC("spark.blaze.shuffle.compression.codec", ""),
+
+    // train zstd dictionary from first batches of shuffle/broadcast streams,
+    // improving compression ratio of small blocks
+    ZSTD_DICT_TRAINING_ENABLE("spark.blaze.io.compression.zstd.dictTraining.enable", false),
+
+    // write shuffle data in standard arrow ipc stream format (with ipc body compression). each
+    // stream is prefixed by a blaze block header, so files must be inspected with ipc_dump
+    // instead of standard arrow tools
+    SHUFFLE_ARROW_IPC_FORMAT_ENABLE("spark.blaze.shuffle.arrowIpcFormat.enable", false),
+
+    // write columns of shuffle/spill batches with adaptive encodings (dictionary, run-length,
+    // bit-packing and delta), disable to write all columns in plain format
+    COLUMN_ENCODING_ENABLE("spark.blaze.columnEncoding.enable", true),
+
+    // split rows of hot keys into this number of sub-partitions in hash shuffle, 0 to disable.
+    // sub-partitions are not exported to map statuses yet, readers see whole partitions
+    SHUFFLE_SKEW_HOT_KEY_SPLITS("spark.blaze.shuffle.skew.hotKeySplits", 0),
+
+    // number of rows sampled by each shuffle writer to detect hot keys
+    SHUFFLE_SKEW_SAMPLE_ROWS("spark.blaze.shuffle.skew.sampleRows", 100000),
+
+    // min frequency ratio of a sampled key to be considered as hot key
+    SHUFFLE_SKEW_HOT_KEY_MIN_RATIO("spark.blaze.shuffle.skew.hotKeyMinRatio", 0.05),
+
+    // max number of hot keys detected by each shuffle writer
+    SHUFFLE_SKEW_MAX_HOT_KEYS("spark.blaze.shuffle.skew.maxHotKeys", 16),
+
+    // use bypass-merge shuffle writer which writes each partition into a separated file, if
+    // number of output partitions is not greater than this threshold, 0 to disable
+    SHUFFLE_BYPASS_MERGE_THRESHOLD("spark.blaze.shuffle.bypassMergeThreshold", 0),
+
+    // sort rows of each hash/range shuffle partition by partition keys in map side, so that a
+    // following sort on the same keys is replaced with merging the sorted shuffle blocks
+    SHUFFLE_KEY_SORTED_ENABLE("spark.blaze.shuffle.keySorted.enable", false),
+
+    // concatenate shuffle partition files with file-to-file copying
+    SPARK_FILE_TRANSFER_TO("spark.file.transferTo", true),
+
+    // rss shuffle writer batches buffers of all partitions and pushes when staged data
+    // exceeds this size
+    SHUFFLE_RSS_PUSH_BATCH_SIZE("spark.blaze.shuffle.rss.pushBatchSize", 4 * 1024 * 1024),
+
+    // read local shuffle blocks natively from shuffle data/index files, instead of fetching
+    // them with jvm block iterator
+    SHUFFLE_NATIVE_LOCAL_READ_ENABLE("spark.blaze.shuffle.nativeLocalRead.enable", true),
+
+    // local shuffle segments not smaller than this size are read with mmap, others are read
+    // with pread, 0 to disable mmap
+    SHUFFLE_LOCAL_READ_MMAP_THRESHOLD("spark.blaze.shuffle.nativeLocalRead.mmapThreshold", 4 * 1024 * 1024),
+
+    // sample keys and determine bounds of range partitioning natively, instead of sampling with
+    // an extra spark job on converted rows
+    RANGE_PARTITIONING_NATIVE_SAMPLING_ENABLE("spark.blaze.shuffle.range.nativeSampling.enable", true),
+
     // tokio worker threads per cpu (spark.task.cpus), 0 for auto detection
     TOKIO_WORKER_THREADS_PER_CPU("spark.blaze.tokio.worker.threads.per.cpu", 0),
 
@@ -70,9 +133,46 @@ public enum BlazeConf {
     // replace all sort-merge join to shuffled-hash join, only used for benchmarking
     FORCE_SHUFFLED_HASH_JOIN("spark.blaze.forceShuffledHashJoin", false),
 
-    // spark spill compression codec
+    // spark spill compression codec, supports the same codecs as shuffle
     SPILL_COMPRESSION_CODEC("spark.blaze.spill.compression.codec", "lz4"),
 
+    // max number of spills being written by background io tasks, spilling consumers wait when exceeded
+    SPILL_ASYNC_MAX_INFLIGHT("spark.blaze.spill.async.maxInflight", 2),
+
+    // comma-separated local dirs for native disk spills, spark local dirs are used if empty
+    SPILL_LOCAL_DIRS("spark.blaze.spill.localDirs", ""),
+
+    // max disk spill size of each task in MB, 0 for unlimited
+    SPILL_DISK_QUOTA_PER_TASK_MB("spark.blaze.spill.diskQuotaPerTaskMB", 0),
+
+    // max disk spill size of all tasks in an executor in MB, 0 for unlimited
+    SPILL_DISK_QUOTA_PER_EXECUTOR_MB("spark.blaze.spill.diskQuotaPerExecutorMB", 0),
+
+    // spill files not smaller than this size are read back with mmap, others are read with
+    // readahead hints, 0 to disable mmap
+    SPILL_MMAP_READ_THRESHOLD("spark.blaze.spill.mmapReadThreshold", 4 * 1024 * 1024),
+
+    // max number of spills merged at once, spills are merged into larger spills in multiple
+    // levels when exceeded
+    SPILL_MERGE_MAX_FAN_IN("spark.blaze.spill.merge.maxFanIn", 64),
+
+    // max number of rows produced by a single generate or join operator in one task, exceeding
+    // operators fail the task, 0 means unlimited
+    LIMIT_MAX_OPERATOR_OUTPUT_ROWS("spark.blaze.limit.maxOperatorOutputRows", 0),
+
+    // max bytes spilled by one task in MB, including both on-heap and disk spills, 0 means
+    // unlimited
+    LIMIT_MAX_TASK_SPILL_MB("spark.blaze.limit.maxTaskSpillMB", 0),
+
+    // wall-clock deadline of native execution of one task in seconds, 0 means unlimited
+    LIMIT_TASK_DEADLINE_SECS("spark.blaze.limit.taskDeadlineSeconds", 0),
+
+    // record execution spans of native operators and write a chrome trace file for each task
+    TRACE_ENABLE("spark.blaze.trace.enable", false),
+
+    // local directory of chrome trace files, system temp dir is used if empty
+    TRACE_DIR("spark.blaze.trace.dir", ""),
+
     // enable hash join falling back to sort merge join when hash table is too big
     SMJ_FALLBACK_ENABLE("spark.blaze.smjfallback.enable", false),
 
@@ -96,7 +196,20 @@ public enum BlazeConf {
     // TypedImperativeAggregate one row mem use size
     SUGGESTED_UDAF_ROW_MEM_USAGE("spark.blaze.suggested.udaf.memUsedSize", 64),
 
-    ORC_FORCE_POSITIONAL_EVOLUTION("spark.blaze.orc.force.positional.evolution", false);
+    ORC_FORCE_POSITIONAL_EVOLUTION("spark.blaze.orc.force.positional.evolution", false),
+
+    /// evaluate all fallback UDFs in the same projection with one JNI round trip
+    UDF_FUSION_ENABLE("spark.blaze.udf.fusion.enable", true),
+
+    /// local directory of wasm UDF modules, named as <udf_name>.wasm. wasm UDFs are experimental
+    /// and only available to native plans when blaze is built with the wasm-udf feature
+    WASM_UDF_DIR("spark.blaze.udf.wasm.dir", ""),
+
+    /// max memory size in bytes of each wasm UDF instance
+    WASM_UDF_MEMORY_LIMIT("spark.blaze.udf.wasm.memoryLimit", 67108864),
+
+    /// fuel (roughly number of wasm instructions) each row can consume in a wasm UDF
+    WASM_UDF_FUEL_PER_ROW("spark.blaze.udf.wasm.fuelPerRow", 1000000);
 
     public final String key;
     private final Object defaultValue;
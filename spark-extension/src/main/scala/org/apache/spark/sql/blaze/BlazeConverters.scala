@@ -21,6 +21,7 @@ import scala.annotation.tailrec
 import scala.collection.mutable
 import org.apache.hadoop.hive.ql.io.parquet.MapredParquetOutputFormat
 import org.apache.spark.SparkEnv
+import org.apache.spark.api.python.PythonEvalType
 import org.apache.spark.broadcast.Broadcast
 import org.apache.spark.internal.Logging
 import org.apache.spark.sql.blaze.BlazeConvertStrategy.childOrderingRequiredTag
@@ -70,6 +71,7 @@ import org.apache.spark.sql.execution.aggregate.SortAggregateExec
 import org.apache.spark.sql.execution.blaze.plan.NativeBroadcastExchangeBase
 import org.apache.spark.sql.execution.GenerateExec
 import org.apache.spark.sql.execution.LocalTableScanExec
+import org.apache.spark.sql.execution.python.ArrowEvalPythonExec
 import org.apache.spark.sql.execution.UnaryExecNode
 import org.apache.spark.sql.execution.blaze.plan.BroadcastLeft
 import org.apache.spark.sql.execution.blaze.plan.BroadcastRight
@@ -118,6 +120,8 @@ object BlazeConverters extends Logging {
     SparkEnv.get.conf.getBoolean("spark.blaze.enable.window", defaultValue = true)
   val enableGenerate: Boolean =
     SparkEnv.get.conf.getBoolean("spark.blaze.enable.generate", defaultValue = true)
+  val enablePythonEval: Boolean =
+    SparkEnv.get.conf.getBoolean("spark.blaze.enable.python.eval", defaultValue = true)
   val enableLocalTableScan: Boolean =
     SparkEnv.get.conf.getBoolean("spark.blaze.enable.local.table.scan", defaultValue = true)
   val enableDataWriting: Boolean =
@@ -222,6 +226,8 @@ object BlazeConverters extends Logging {
         tryConvert(e, convertWindowExec)
       case e: GenerateExec if enableGenerate => // generate
         tryConvert(e, convertGenerateExec)
+      case e: ArrowEvalPythonExec if enablePythonEval => // pandas udf
+        tryConvert(e, convertArrowEvalPythonExec)
       case e: LocalTableScanExec if enableLocalTableScan => // local table scan
         tryConvert(e, convertLocalTableScanExec)
       case e: DataWritingCommandExec if enableDataWriting => // data writing
@@ -852,6 +858,22 @@ object BlazeConverters extends Logging {
       addRenameColumnsExec(convertToNative(exec.child)))
   }
 
+  def convertArrowEvalPythonExec(exec: ArrowEvalPythonExec): SparkPlan = {
+    logDebug(s"Converting ArrowEvalPythonExec: ${Shims.get.simpleStringWithNodeId(exec)}")
+    logDebug(s"  udfs: ${exec.udfs}")
+    logDebug(s"  resultAttrs: ${exec.resultAttrs}")
+    logDebug(s"  evalType: ${exec.evalType}")
+    assert(
+      Seq(PythonEvalType.SQL_SCALAR_PANDAS_UDF, PythonEvalType.SQL_SCALAR_PANDAS_ITER_UDF)
+        .contains(exec.evalType),
+      s"unsupported python eval type: ${exec.evalType}")
+    Shims.get.createNativePythonEvalExec(
+      exec.udfs,
+      exec.resultAttrs,
+      exec.evalType,
+      addRenameColumnsExec(convertToNative(exec.child)))
+  }
+
   def convertLocalTableScanExec(exec: LocalTableScanExec): SparkPlan = {
     convertToNative(exec)
   }
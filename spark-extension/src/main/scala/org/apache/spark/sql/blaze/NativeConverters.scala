@@ -27,7 +27,7 @@ import com.google.protobuf.ByteString
 import org.apache.spark.SparkEnv
 import org.blaze.{protobuf => pb}
 import org.apache.spark.internal.Logging
-import org.apache.spark.sql.catalyst.expressions.{Abs, Acos, Add, Alias, And, Asin, Atan, Attribute, AttributeReference, BitwiseAnd, BitwiseOr, BoundReference, CaseWhen, Cast, Ceil, CheckOverflow, Coalesce, Concat, ConcatWs, Contains, Cos, CreateArray, CreateNamedStruct, DayOfMonth, Divide, EndsWith, EqualTo, Exp, Expression, Floor, GetArrayItem, GetJsonObject, GetMapValue, GetStructField, GreaterThan, GreaterThanOrEqual, If, In, InSet, IsNotNull, IsNull, LeafExpression, Length, LessThan, LessThanOrEqual, Like, Literal, Log, Log10, Log2, Lower, MakeDecimal, Md5, Month, Multiply, Murmur3Hash, Not, NullIf, OctetLength, Or, Remainder, Sha2, ShiftLeft, ShiftRight, Signum, Sin, Sqrt, StartsWith, StringRepeat, StringSpace, StringTrim, StringTrimLeft, StringTrimRight, Substring, Subtract, Tan, TruncDate, Unevaluable, UnscaledValue, Upper, XxHash64, Year}
+import org.apache.spark.sql.catalyst.expressions.{Abs, Acos, Add, Alias, And, Asin, Atan, Attribute, AttributeReference, BitwiseAnd, BitwiseOr, BoundReference, CaseWhen, Cast, Ceil, CheckOverflow, Coalesce, Concat, ConcatWs, Contains, Cos, Crc32, CreateArray, CreateNamedStruct, DayOfMonth, Divide, EndsWith, EqualTo, Exp, Expression, Floor, GetArrayItem, GetJsonObject, GetMapValue, GetStructField, GreaterThan, GreaterThanOrEqual, HiveHash, If, In, InSet, IsNotNull, IsNull, JsonToStructs, LeafExpression, Length, LessThan, LessThanOrEqual, Like, Literal, Log, Log10, Log2, Lower, MakeDecimal, Md5, Month, Multiply, Murmur3Hash, Not, NullIf, OctetLength, Or, Remainder, SchemaOfJson, Sha1, Sha2, ShiftLeft, ShiftRight, Signum, Sin, Sqrt, StartsWith, StringRepeat, StringSpace, StringTrim, StringTrimLeft, StringTrimRight, StructsToJson, Substring, Subtract, Tan, TruncDate, Unevaluable, UnscaledValue, Upper, XxHash64, Year}
 import org.apache.spark.sql.catalyst.expressions.aggregate.{AggregateExpression, AggregateFunction, Average, CollectList, CollectSet, Count, DeclarativeAggregate, First, ImperativeAggregate, Max, Min, Sum}
 import org.apache.spark.sql.catalyst.expressions.codegen.CodegenContext
 import org.apache.spark.sql.catalyst.expressions.codegen.ExprCode
@@ -871,21 +871,19 @@ object NativeConverters extends Logging {
       case e: TruncDate =>
         buildScalarFunction(pb.ScalarFunction.DateTrunc, e.children, e.dataType)
       case Md5(_1) =>
-        buildScalarFunction(pb.ScalarFunction.MD5, Seq(unpackBinaryTypeCast(_1)), StringType)
-      case Sha2(_1, Literal(224, _)) =>
-        buildScalarFunction(pb.ScalarFunction.SHA224, Seq(unpackBinaryTypeCast(_1)), StringType)
-      case Sha2(_1, Literal(0, _)) =>
-        buildScalarFunction(pb.ScalarFunction.SHA256, Seq(unpackBinaryTypeCast(_1)), StringType)
-      case Sha2(_1, Literal(256, _)) =>
-        buildScalarFunction(pb.ScalarFunction.SHA256, Seq(unpackBinaryTypeCast(_1)), StringType)
-      case Sha2(_1, Literal(384, _)) =>
-        buildScalarFunction(pb.ScalarFunction.SHA384, Seq(unpackBinaryTypeCast(_1)), StringType)
-      case Sha2(_1, Literal(512, _)) =>
-        buildScalarFunction(pb.ScalarFunction.SHA512, Seq(unpackBinaryTypeCast(_1)), StringType)
+        buildExtScalarFunction("Md5", Seq(unpackBinaryTypeCast(_1)), StringType)
+      case Sha1(_1) =>
+        buildExtScalarFunction("Sha1", Seq(unpackBinaryTypeCast(_1)), StringType)
+      case Sha2(_1, bitLength @ Literal(_, IntegerType)) =>
+        buildExtScalarFunction("Sha2", Seq(unpackBinaryTypeCast(_1), bitLength), StringType)
+      case Crc32(_1) =>
+        buildExtScalarFunction("Crc32", Seq(unpackBinaryTypeCast(_1)), LongType)
       case Murmur3Hash(children, 42) =>
         buildExtScalarFunction("Murmur3Hash", children, IntegerType)
       case XxHash64(children, 42L) =>
         buildExtScalarFunction("XxHash64", children, LongType)
+      case HiveHash(children) =>
+        buildExtScalarFunction("HiveHash", children, IntegerType)
 
       case Year(child) => buildExtScalarFunction("Year", child :: Nil, IntegerType)
       case Month(child) => buildExtScalarFunction("Month", child :: Nil, IntegerType)
@@ -1065,6 +1063,30 @@ object NativeConverters extends Logging {
           nullable = false)
         buildExtScalarFunction("GetParsedJsonObject", parsed :: e.children(1) :: Nil, StringType)
 
+      // json functions, options are passed as key/value literal pairs
+      case e: JsonToStructs if udfJsonEnabled && e.child.dataType == StringType =>
+        val options = e.options ++ Map(
+          "columnNameOfCorruptRecord" -> e.options.getOrElse(
+            "columnNameOfCorruptRecord",
+            SQLConf.get.columnNameOfCorruptRecord),
+          "timeZone" -> e.timeZoneId.getOrElse(SQLConf.get.sessionLocalTimeZone))
+        val optionArgs = options.toSeq.flatMap { case (k, v) => Seq(Literal(k), Literal(v)) }
+        buildExtScalarFunction("FromJson", e.child +: optionArgs, e.dataType)
+
+      case e: StructsToJson if udfJsonEnabled =>
+        val options = e.options ++ Map(
+          "timeZone" -> e.timeZoneId.getOrElse(SQLConf.get.sessionLocalTimeZone))
+        val optionArgs = options.toSeq.flatMap { case (k, v) => Seq(Literal(k), Literal(v)) }
+        buildExtScalarFunction("ToJson", e.child +: optionArgs, StringType)
+
+      case e
+          if udfJsonEnabled && e.getClass.getSimpleName == "LengthOfJsonArray"
+            && e.children.map(_.dataType) == Seq(StringType) =>
+        buildExtScalarFunction("JsonArrayLength", e.children, IntegerType)
+
+      case e: SchemaOfJson if udfJsonEnabled && e.child.dataType == StringType =>
+        buildExtScalarFunction("SchemaOfJson", e.child :: Nil, StringType)
+
       // hive UDF brickhouse.array_union
       case e
           if getFunctionClassName(e).contains("brickhouse.udf.collect.ArrayUnionUDF")
@@ -15,15 +15,20 @@
  */
 package org.apache.spark.sql.blaze
 
+import scala.collection.JavaConverters._
 import scala.collection.immutable.TreeMap
 
+import com.google.protobuf.Message
+
 import org.apache.hadoop.security.UserGroupInformation
 import org.apache.spark.Partition
 import org.apache.spark.SparkConf
 import org.apache.spark.SparkContext
 import org.apache.spark.SparkEnv
 import org.apache.spark.TaskContext
+import org.blaze.protobuf.PhysicalExprNode
 import org.blaze.protobuf.PhysicalPlanNode
+import org.blaze.protobuf.PhysicalSparkUDFWrapperExprNode
 
 import org.apache.spark.internal.Logging
 import org.apache.spark.internal.config
@@ -31,6 +36,8 @@ import org.apache.spark.sql.catalyst.InternalRow
 import org.apache.spark.sql.execution.SparkPlan
 import org.apache.spark.sql.execution.metric.SQLMetric
 import org.apache.spark.sql.execution.metric.SQLMetrics
+import org.apache.spark.unsafe.Platform
+import org.apache.spark.unsafe.hash.Murmur3_x86_32
 
 object NativeHelper extends Logging {
   val currentUser: UserGroupInformation = UserGroupInformation.getCurrentUser
@@ -112,7 +119,9 @@ object NativeHelper extends Logging {
       "disk_spill_iotime" -> nanoTimingMetric("Native.disk_spill_iotime"),
       "sort_time" -> nanoTimingMetric("Native.sort_time"),
       "output_io_time" -> nanoTimingMetric("Native.output_io_time"),
-      "shuffle_read_total_time" -> nanoTimingMetric("Native.shuffle_read_total_time"))
+      "shuffle_read_total_time" -> nanoTimingMetric("Native.shuffle_read_total_time"),
+      "udf_jni_calls" -> metric("Native.udf_jni_calls"),
+      "udf_time" -> nanoTimingMetric("Native.udf_time"))
 
     if (BlazeConf.INPUT_BATCH_STATISTICS_ENABLE.booleanConf()) {
       metrics ++= TreeMap(
@@ -123,6 +132,40 @@ object NativeHelper extends Logging {
     metrics
   }
 
+  // metrics of each spark UDF, named by the same stable id as native side
+  // (SparkUDFWrapperExpr::udf_id): murmur3 hash of the serialized UDF
+  def getSparkUdfMetrics(
+      sc: SparkContext,
+      nativeExprs: Seq[PhysicalExprNode]): Map[String, SQLMetric] = {
+    def collectSparkUdfs(message: Message): Seq[PhysicalSparkUDFWrapperExprNode] = {
+      val udfs = message match {
+        case udf: PhysicalSparkUDFWrapperExprNode => Seq(udf)
+        case _ => Nil
+      }
+      udfs ++ message.getAllFields.values.asScala.flatMap {
+        case child: Message => collectSparkUdfs(child)
+        case children: java.util.List[_] =>
+          children.asScala.flatMap {
+            case child: Message => collectSparkUdfs(child)
+            case _ => Nil
+          }
+        case _ => Nil
+      }
+    }
+
+    val udfIds = nativeExprs.flatMap(collectSparkUdfs).map { udf =>
+      val bytes = udf.getSerialized.toByteArray
+      "%08x".format(
+        Murmur3_x86_32.hashUnsafeBytes(bytes, Platform.BYTE_ARRAY_OFFSET, bytes.length, 42))
+    }
+    TreeMap(udfIds.distinct.flatMap { id =>
+      Seq(
+        s"udf_${id}_calls" -> SQLMetrics.createMetric(sc, s"Native.udf_${id}_calls"),
+        s"udf_${id}_rows" -> SQLMetrics.createMetric(sc, s"Native.udf_${id}_rows"),
+        s"udf_${id}_time" -> SQLMetrics.createNanoTimingMetric(sc, s"Native.udf_${id}_time"))
+    }: _*)
+  }
+
   private def getDefaultNativeFileMetrics(sc: SparkContext): Map[String, SQLMetric] = {
     TreeMap(
       "bytes_scanned" -> SQLMetrics.createSizeMetric(sc, "Native.bytes_scanned"),
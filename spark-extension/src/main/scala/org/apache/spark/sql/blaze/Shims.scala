@@ -17,6 +17,8 @@ package org.apache.spark.sql.blaze
 
 import java.io.File
 import org.apache.spark.ShuffleDependency
+import org.apache.spark.api.python.BasePythonRunner
+import org.apache.spark.api.python.ChainedPythonFunctions
 import org.apache.spark.TaskContext
 import org.apache.spark.SparkContext
 import org.blaze.{protobuf => pb}
@@ -38,6 +40,7 @@ import org.apache.spark.sql.SQLContext
 import org.apache.spark.sql.catalyst.expressions.Attribute
 import org.apache.spark.sql.catalyst.expressions.Generator
 import org.apache.spark.sql.catalyst.expressions.NamedExpression
+import org.apache.spark.sql.catalyst.expressions.PythonUDF
 import org.apache.spark.sql.catalyst.expressions.SortOrder
 import org.apache.spark.sql.catalyst.plans.JoinType
 import org.apache.spark.sql.execution.blaze.plan.NativeBroadcastJoinBase
@@ -45,6 +48,8 @@ import org.apache.spark.sql.execution.blaze.plan.NativeSortMergeJoinBase
 import org.apache.spark.sql.execution.metric.SQLMetric
 import org.apache.spark.sql.hive.execution.InsertIntoHiveTable
 import org.apache.spark.sql.types.DataType
+import org.apache.spark.sql.types.StructType
+import org.apache.spark.sql.vectorized.ColumnarBatch
 import org.apache.spark.sql.SparkSession
 import org.apache.spark.sql.catalyst.InternalRow
 import org.apache.spark.sql.catalyst.catalog.CatalogTable
@@ -113,6 +118,12 @@ abstract class Shims {
       generatorOutput: Seq[Attribute],
       child: SparkPlan): NativeGenerateBase
 
+  def createNativePythonEvalExec(
+      udfs: Seq[PythonUDF],
+      resultAttrs: Seq[Attribute],
+      evalType: Int,
+      child: SparkPlan): NativePythonEvalBase
+
   def createNativeGlobalLimitExec(limit: Long, child: SparkPlan): NativeGlobalLimitBase
 
   def createNativeLocalLimitExec(limit: Long, child: SparkPlan): NativeLocalLimitBase
@@ -213,6 +224,7 @@ abstract class Shims {
       tempDataFile: File,
       mapId: Long,
       partitionLengths: Array[Long],
+      checksums: Array[Long],
       dataSize: Long,
       context: TaskContext): MapStatus
 
@@ -227,6 +239,8 @@ abstract class Shims {
       partitionLengthMap: Array[Long],
       mapId: Long): MapStatus
 
+  def isRssShuffleManager: Boolean
+
   def getShuffleWriteExec(
       input: pb.PhysicalPlanNode,
       nativeOutputPartitioning: pb.PhysicalRepartition.Builder): pb.PhysicalPlanNode
@@ -235,6 +249,14 @@ abstract class Shims {
 
   def getSqlContext(sparkPlan: SparkPlan): SQLContext
 
+  // creates spark's ArrowPythonRunner in executor side, conf is taken from the
+  // task's SQLConf like ArrowEvalPythonExec
+  def createArrowPythonRunner(
+      funcs: Seq[ChainedPythonFunctions],
+      evalType: Int,
+      argOffsets: Array[Array[Int]],
+      paramsSchema: StructType): BasePythonRunner[Iterator[InternalRow], ColumnarBatch]
+
   def createNativeExprWrapper(
       nativeExpr: pb.PhysicalExprNode,
       dataType: DataType,
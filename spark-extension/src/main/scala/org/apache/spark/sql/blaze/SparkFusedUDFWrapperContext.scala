@@ -0,0 +1,108 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.blaze
+
+import java.nio.ByteBuffer
+
+import scala.collection.mutable.ArrayBuffer
+
+import org.apache.arrow.c.ArrowArray
+import org.apache.arrow.c.Data
+import org.apache.arrow.vector.VectorSchemaRoot
+import org.apache.arrow.vector.dictionary.DictionaryProvider
+import org.apache.arrow.vector.dictionary.DictionaryProvider.MapDictionaryProvider
+import org.apache.spark.TaskContext
+import org.apache.spark.internal.Logging
+import org.apache.spark.sql.blaze.util.Using
+import org.apache.spark.sql.catalyst.expressions.BoundReference
+import org.apache.spark.sql.catalyst.expressions.Expression
+import org.apache.spark.sql.catalyst.expressions.GenericInternalRow
+import org.apache.spark.sql.catalyst.expressions.Nondeterministic
+import org.apache.spark.sql.execution.blaze.arrowio.util.ArrowUtils
+import org.apache.spark.sql.execution.blaze.arrowio.util.ArrowUtils.ROOT_ALLOCATOR
+import org.apache.spark.sql.execution.blaze.arrowio.util.ArrowWriter
+import org.apache.spark.sql.execution.blaze.columnar.ColumnarHelper
+import org.apache.spark.sql.types.StructField
+import org.apache.spark.sql.types.StructType
+
+/**
+ * evaluates a group of UDFs in one call. the serialized buffer contains the number of UDFs
+ * followed by (length, serialized UDF) of each UDF. params of all UDFs are concatenated into
+ * one input struct, and the output struct contains one column for each UDF.
+ */
+case class SparkFusedUDFWrapperContext(serialized: ByteBuffer) extends Logging {
+  private val (exprs, javaParamsSchema) = {
+    val numExprs = serialized.getInt()
+    val exprs = ArrayBuffer[Expression]()
+    val paramsFields = ArrayBuffer[StructField]()
+
+    for (_ <- 0 until numExprs) {
+      val bytes = new Array[Byte](serialized.getInt())
+      serialized.get(bytes)
+      val (expr, paramsSchema) =
+        NativeConverters.deserializeExpression[Expression, StructType](bytes)
+
+      // rebind params to their positions in the concatenated input
+      val paramsOffset = paramsFields.length
+      exprs += expr.transform { case ref: BoundReference =>
+        ref.copy(ordinal = ref.ordinal + paramsOffset)
+      }
+      paramsFields ++= paramsSchema.fields
+    }
+    (exprs.toArray, StructType(paramsFields.toArray))
+  }
+
+  // initialize all nondeterministic children exprs
+  exprs.foreach(_.foreach {
+    case nondeterministic: Nondeterministic =>
+      nondeterministic.initialize(TaskContext.get.partitionId())
+    case _ =>
+  })
+
+  private val dictionaryProvider: DictionaryProvider = new MapDictionaryProvider()
+  private val outputSchema = {
+    val schema = StructType(exprs.map(expr => StructField("", expr.dataType, expr.nullable)))
+    ArrowUtils.toArrowSchema(schema)
+  }
+  private val paramsSchema = ArrowUtils.toArrowSchema(javaParamsSchema)
+
+  def eval(importFFIArrayPtr: Long, exportFFIArrayPtr: Long): Unit = {
+    Using.resources(
+      VectorSchemaRoot.create(outputSchema, ROOT_ALLOCATOR),
+      VectorSchemaRoot.create(paramsSchema, ROOT_ALLOCATOR),
+      ArrowArray.wrap(importFFIArrayPtr),
+      ArrowArray.wrap(exportFFIArrayPtr)) { (outputRoot, paramsRoot, importArray, exportArray) =>
+      // import into params root
+      Data.importIntoVectorSchemaRoot(ROOT_ALLOCATOR, importArray, paramsRoot, dictionaryProvider)
+
+      // evaluate all expressions and write to output root
+      val reusedOutputRow = new GenericInternalRow(exprs.length)
+      val outputWriter = ArrowWriter.create(outputRoot)
+      for (paramsRow <- ColumnarHelper.rootRowsIter(paramsRoot)) {
+        var i = 0
+        while (i < exprs.length) {
+          reusedOutputRow.update(i, exprs(i).eval(paramsRow))
+          i += 1
+        }
+        outputWriter.write(reusedOutputRow)
+      }
+      outputWriter.finish()
+
+      // export to output using root allocator
+      Data.exportVectorSchemaRoot(ROOT_ALLOCATOR, outputRoot, dictionaryProvider, exportArray)
+    }
+  }
+}
@@ -0,0 +1,225 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.blaze
+
+import java.io.ByteArrayInputStream
+import java.io.ByteArrayOutputStream
+import java.io.ObjectInputStream
+import java.io.ObjectOutputStream
+import java.nio.ByteBuffer
+import java.util.concurrent.LinkedBlockingQueue
+
+import scala.collection.JavaConverters._
+
+import org.apache.arrow.c.ArrowArray
+import org.apache.arrow.c.Data
+import org.apache.arrow.vector.VectorSchemaRoot
+import org.apache.arrow.vector.dictionary.DictionaryProvider
+import org.apache.arrow.vector.dictionary.DictionaryProvider.MapDictionaryProvider
+import org.apache.spark.TaskContext
+import org.apache.spark.api.python.ChainedPythonFunctions
+import org.apache.spark.internal.Logging
+import org.apache.spark.sql.blaze.util.Using
+import org.apache.spark.sql.catalyst.InternalRow
+import org.apache.spark.sql.catalyst.expressions.UnsafeProjection
+import org.apache.spark.sql.execution.blaze.arrowio.util.ArrowUtils
+import org.apache.spark.sql.execution.blaze.arrowio.util.ArrowUtils.ROOT_ALLOCATOR
+import org.apache.spark.sql.execution.blaze.arrowio.util.ArrowWriter
+import org.apache.spark.sql.execution.blaze.columnar.ColumnarHelper
+import org.apache.spark.sql.types.StructType
+import org.apache.spark.util.Utils
+
+/**
+ * evaluates python UDFs of ArrowEvalPythonExec with spark's ArrowPythonRunner, which takes care
+ * of the whole python worker protocol (worker factory, task context, python includes, broadcasts
+ * and runner conf).
+ *
+ * params fed from native side are sent to the python worker by the runner's writer thread, and
+ * results are read from the worker in a separate reader thread. the runner does not flush the
+ * worker's input for every batch, so native side only takes results that are already available
+ * until the input is finished, then waits for the remaining ones.
+ */
+class SparkPythonEvalWrapperContext(serialized: ByteBuffer) extends AutoCloseable with Logging {
+  private val (funcs, evalType, argOffsets, javaParamsSchema, javaResultSchema) =
+    SparkPythonEvalWrapperContext.deserialize({
+      val bytes = new Array[Byte](serialized.remaining())
+      serialized.get(bytes)
+      bytes
+    })
+
+  private val dictionaryProvider: DictionaryProvider = new MapDictionaryProvider()
+  private val paramsSchema = ArrowUtils.toArrowSchema(javaParamsSchema)
+  private val resultSchema = ArrowUtils.toArrowSchema(javaResultSchema)
+  private val toUnsafeRow = UnsafeProjection.create(javaParamsSchema)
+
+  // None marks the end of input/results
+  private val inputQueue = new LinkedBlockingQueue[Option[Array[InternalRow]]]()
+  private val resultQueue = new LinkedBlockingQueue[Either[Throwable, Option[VectorSchemaRoot]]]()
+  private var closed = false
+
+  private val taskContext = TaskContext.get
+  private val resultIter = {
+    val inputIter = new Iterator[Iterator[InternalRow]] {
+      private var nextInput: Option[Array[InternalRow]] = _
+
+      override def hasNext: Boolean = {
+        if (nextInput == null) {
+          nextInput = inputQueue.take()
+        }
+        nextInput.isDefined
+      }
+
+      override def next(): Iterator[InternalRow] = {
+        if (!hasNext) {
+          throw new NoSuchElementException()
+        }
+        val rows = nextInput.get
+        nextInput = null
+        rows.iterator
+      }
+    }
+    Shims.get
+      .createArrowPythonRunner(funcs, evalType, argOffsets, javaParamsSchema)
+      .compute(inputIter, taskContext.partitionId(), taskContext)
+  }
+
+  private val readerThread = new Thread(s"PythonEvalReader-${taskContext.taskAttemptId()}") {
+    override def run(): Unit = {
+      TaskContext.setTaskContext(taskContext)
+      try {
+        while (!isClosed && resultIter.hasNext) {
+          // result batches are reused by the runner, copy them before handing over to native side
+          val batch = resultIter.next()
+          val root = VectorSchemaRoot.create(resultSchema, ROOT_ALLOCATOR)
+          val outputWriter = ArrowWriter.create(root)
+          for (row <- batch.rowIterator().asScala) {
+            outputWriter.write(row)
+          }
+          outputWriter.finish()
+          putResult(Right(Some(root)))
+        }
+        putResult(Right(None))
+      } catch {
+        case t: Throwable => putResult(Left(t))
+      }
+    }
+  }
+  readerThread.setDaemon(true)
+  readerThread.start()
+
+  def feed(importFFIArrayPtr: Long): Unit = {
+    Using.resources(
+      VectorSchemaRoot.create(paramsSchema, ROOT_ALLOCATOR),
+      ArrowArray.wrap(importFFIArrayPtr)) { (paramsRoot, importArray) =>
+      // import into params root
+      Data.importIntoVectorSchemaRoot(ROOT_ALLOCATOR, importArray, paramsRoot, dictionaryProvider)
+
+      // rows are consumed by the writer thread after the params root is released
+      val rows = ColumnarHelper
+        .rootRowsIter(paramsRoot)
+        .map(row => toUnsafeRow(row).copy(): InternalRow)
+        .toArray
+      inputQueue.put(Some(rows))
+    }
+  }
+
+  def finishInput(): Unit = {
+    inputQueue.put(None)
+  }
+
+  // exports the next result batch, returns false if no results are available (non-blocking)
+  // or all results are taken (blocking)
+  def nextResult(exportFFIArrayPtr: Long, blocking: Boolean): Boolean = {
+    val result = if (blocking) resultQueue.take() else resultQueue.poll()
+    result match {
+      case null => false
+      case Left(t) =>
+        resultQueue.put(result) // keep the error for later calls
+        throw t
+      case Right(None) =>
+        resultQueue.put(result) // keep the end mark for later calls
+        false
+      case Right(Some(root)) =>
+        Using.resources(root, ArrowArray.wrap(exportFFIArrayPtr)) { (root, exportArray) =>
+          Data.exportVectorSchemaRoot(ROOT_ALLOCATOR, root, dictionaryProvider, exportArray)
+        }
+        true
+    }
+  }
+
+  override def close(): Unit = synchronized {
+    if (!closed) {
+      closed = true
+      inputQueue.put(None)
+
+      // release results not taken by native side
+      var result = resultQueue.poll()
+      while (result != null) {
+        result.foreach(_.foreach(_.close()))
+        result = resultQueue.poll()
+      }
+    }
+  }
+
+  private def isClosed: Boolean = synchronized(closed)
+
+  private def putResult(result: Either[Throwable, Option[VectorSchemaRoot]]): Unit = {
+    val accepted = synchronized {
+      if (!closed) {
+        resultQueue.put(result)
+      }
+      !closed
+    }
+    if (!accepted) {
+      result.foreach(_.foreach(_.close()))
+    }
+  }
+}
+
+object SparkPythonEvalWrapperContext {
+  def serialize(
+      funcs: Seq[ChainedPythonFunctions],
+      evalType: Int,
+      argOffsets: Array[Array[Int]],
+      paramsSchema: StructType,
+      resultSchema: StructType): Array[Byte] = {
+    Utils.tryWithResource(new ByteArrayOutputStream()) { bos =>
+      Utils.tryWithResource(new ObjectOutputStream(bos)) { oos =>
+        oos.writeObject(funcs)
+        oos.writeInt(evalType)
+        oos.writeObject(argOffsets)
+        oos.writeObject(paramsSchema)
+        oos.writeObject(resultSchema)
+        null
+      }
+      bos.toByteArray
+    }
+  }
+
+  def deserialize(serialized: Array[Byte])
+      : (Seq[ChainedPythonFunctions], Int, Array[Array[Int]], StructType, StructType) = {
+    Utils.tryWithResource(new ByteArrayInputStream(serialized)) { bis =>
+      Utils.tryWithResource(new ObjectInputStream(bis)) { ois =>
+        val funcs = ois.readObject().asInstanceOf[Seq[ChainedPythonFunctions]]
+        val evalType = ois.readInt()
+        val argOffsets = ois.readObject().asInstanceOf[Array[Array[Int]]]
+        val paramsSchema = ois.readObject().asInstanceOf[StructType]
+        val resultSchema = ois.readObject().asInstanceOf[StructType]
+        (funcs, evalType, argOffsets, paramsSchema, resultSchema)
+      }
+    }
+  }
+}
@@ -54,8 +54,10 @@ abstract class NativeFilterBase(condition: Expression, override val child: Spark
           "elapsed_compute",
           "input_batch_count",
           "input_batch_mem_size",
-          "input_row_count"))
-      .toSeq: _*)
+          "input_row_count",
+          "udf_jni_calls",
+          "udf_time"))
+      .toSeq: _*) ++ NativeHelper.getSparkUdfMetrics(sparkContext, nativeFilterExprs)
 
   override def output: Seq[Attribute] = FilterExec(condition, child).output
   override def outputPartitioning: Partitioning = child.outputPartitioning
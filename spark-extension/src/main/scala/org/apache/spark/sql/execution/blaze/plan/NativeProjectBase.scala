@@ -57,8 +57,10 @@ abstract class NativeProjectBase(
           "elapsed_compute",
           "input_batch_count",
           "input_batch_mem_size",
-          "input_row_count"))
-      .toSeq: _*)
+          "input_row_count",
+          "udf_jni_calls",
+          "udf_time"))
+      .toSeq: _*) ++ NativeHelper.getSparkUdfMetrics(sparkContext, nativeProject.getExprList.asScala)
 
   override def output: Seq[Attribute] = projectList.map(_.toAttribute)
   override def outputPartitioning: Partitioning = child.outputPartitioning
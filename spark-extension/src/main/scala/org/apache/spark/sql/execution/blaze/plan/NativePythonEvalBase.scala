@@ -0,0 +1,155 @@
+/*
+ * Copyright 2022 The Blaze Authors
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package org.apache.spark.sql.execution.blaze.plan
+
+import scala.collection.JavaConverters._
+import scala.collection.immutable.SortedMap
+import scala.collection.mutable.ArrayBuffer
+
+import org.apache.spark.OneToOneDependency
+import org.apache.spark.api.python.ChainedPythonFunctions
+import org.apache.spark.api.python.PythonEvalType
+import org.apache.spark.sql.blaze.MetricNode
+import org.apache.spark.sql.blaze.NativeConverters
+import org.apache.spark.sql.blaze.NativeHelper
+import org.apache.spark.sql.blaze.NativeRDD
+import org.apache.spark.sql.blaze.NativeSupports
+import org.apache.spark.sql.blaze.SparkPythonEvalWrapperContext
+import org.apache.spark.sql.catalyst.expressions.Attribute
+import org.apache.spark.sql.catalyst.expressions.Expression
+import org.apache.spark.sql.catalyst.expressions.PythonUDF
+import org.apache.spark.sql.catalyst.plans.physical.Partitioning
+import org.apache.spark.sql.execution.SparkPlan
+import org.apache.spark.sql.execution.UnaryExecNode
+import org.apache.spark.sql.execution.metric.SQLMetric
+import org.apache.spark.sql.execution.metric.SQLMetrics
+import org.apache.spark.sql.types.StructField
+import org.apache.spark.sql.types.StructType
+import org.blaze.{protobuf => pb}
+import org.blaze.protobuf.PhysicalPlanNode
+
+import com.google.protobuf.ByteString
+
+abstract class NativePythonEvalBase(
+    udfs: Seq[PythonUDF],
+    resultAttrs: Seq[Attribute],
+    evalType: Int,
+    override val child: SparkPlan)
+    extends UnaryExecNode
+    with NativeSupports {
+
+  override lazy val metrics: Map[String, SQLMetric] = SortedMap[String, SQLMetric]() ++ Map(
+    NativeHelper
+      .getDefaultNativeMetrics(sparkContext)
+      .filterKeys(
+        Set(
+          "stage_id",
+          "output_rows",
+          "elapsed_compute",
+          "input_batch_count",
+          "input_batch_mem_size",
+          "input_row_count"))
+      .toSeq: _*) ++ Map(
+    "python_time" -> SQLMetrics.createNanoTimingMetric(sparkContext, "Native.python_time"))
+
+  override def output: Seq[Attribute] = child.output ++ resultAttrs
+  override def outputPartitioning: Partitioning = child.outputPartitioning
+
+  // only UDFs returning one row for each input row are supported
+  assert(
+    Seq(PythonEvalType.SQL_SCALAR_PANDAS_UDF, PythonEvalType.SQL_SCALAR_PANDAS_ITER_UDF)
+      .contains(evalType),
+    s"unsupported python eval type: $evalType")
+
+  // chained python functions and deduplicated arguments, same as spark's EvalPythonExec
+  private val (pythonFuncs, args, argOffsets) = {
+    def collectFunctions(udf: PythonUDF): (ChainedPythonFunctions, Seq[Expression]) = {
+      udf.children match {
+        case Seq(u: PythonUDF) =>
+          val (chained, children) = collectFunctions(u)
+          (ChainedPythonFunctions(chained.funcs ++ Seq(udf.func)), children)
+        case children =>
+          assert(
+            children.forall(!_.exists(_.isInstanceOf[PythonUDF])),
+            "python UDFs cannot be mixed with other expressions")
+          (ChainedPythonFunctions(Seq(udf.func)), udf.children)
+      }
+    }
+    val (funcs, inputs) = udfs.map(collectFunctions).unzip
+    val allInputs = ArrayBuffer[Expression]()
+    val argOffsets = inputs.map { input =>
+      input.map { e =>
+        allInputs.indexWhere(_.semanticEquals(e)) match {
+          case -1 =>
+            allInputs += e
+            allInputs.length - 1
+          case i => i
+        }
+      }.toArray
+    }.toArray
+    (funcs, allInputs.toSeq, argOffsets)
+  }
+
+  private def nativeArgs = args.map(NativeConverters.convertExpr)
+
+  private def nativeResultSchema = Util.getNativeSchema(resultAttrs)
+
+  private def serialized = {
+    val paramsSchema = StructType(args.zipWithIndex.map { case (arg, i) =>
+      StructField(s"_$i", arg.dataType, arg.nullable)
+    })
+    val resultSchema = StructType(resultAttrs.map { attr =>
+      StructField(attr.name, attr.dataType, attr.nullable)
+    })
+    SparkPythonEvalWrapperContext.serialize(
+      pythonFuncs,
+      evalType,
+      argOffsets,
+      paramsSchema,
+      resultSchema)
+  }
+
+  // check whether native converting is supported
+  nativeArgs
+  nativeResultSchema
+
+  override def doExecuteNative(): NativeRDD = {
+    val inputRDD = NativeHelper.executeNative(child)
+    val nativeMetrics = MetricNode(metrics, inputRDD.metrics :: Nil)
+    val nativeArgs = this.nativeArgs
+    val nativeResultSchema = this.nativeResultSchema
+    val serialized = ByteString.copyFrom(this.serialized)
+
+    new NativeRDD(
+      sparkContext,
+      nativeMetrics,
+      rddPartitions = inputRDD.partitions,
+      rddDependencies = new OneToOneDependency(inputRDD) :: Nil,
+      inputRDD.isShuffleReadFull,
+      (partition, taskContext) => {
+        val inputPartition = inputRDD.partitions(partition.index)
+        val nativePythonEvalExec = pb.PythonEvalExecNode
+          .newBuilder()
+          .setInput(inputRDD.nativePlan(inputPartition, taskContext))
+          .setSerialized(serialized)
+          .addAllArg(nativeArgs.asJava)
+          .setResultSchema(nativeResultSchema)
+          .build()
+        PhysicalPlanNode.newBuilder().setPythonEval(nativePythonEvalExec).build()
+      },
+      friendlyName = "NativeRDD.PythonEval")
+  }
+}
@@ -15,13 +15,16 @@
  */
 package org.apache.spark.sql.execution.blaze.plan
 
+import java.io.ByteArrayOutputStream
+import java.nio.ByteBuffer
 import java.util.UUID
 import scala.collection.JavaConverters._
-import org.apache.spark.{OneToOneDependency, Partitioner, RangePartitioner, ShuffleDependency, SparkEnv, TaskContext}
-import org.blaze.protobuf.{IpcReaderExecNode, PhysicalExprNode, PhysicalHashRepartition, PhysicalPlanNode, PhysicalRangeRepartition, PhysicalRepartition, PhysicalRoundRobinRepartition, PhysicalSingleRepartition, PhysicalSortExprNode, Schema, SortExecNode}
+import org.apache.spark.{OneToOneDependency, Partition, Partitioner, RangePartitioner, ShuffleDependency, SparkEnv, TaskContext}
+import org.blaze.protobuf.{IpcReaderExecNode, IpcWriterExecNode, PhysicalExprNode, PhysicalHashRepartition, PhysicalPlanNode, PhysicalRangeRepartition, PhysicalRepartition, PhysicalRoundRobinRepartition, PhysicalSingleRepartition, PhysicalSortExprNode, RangeBoundsExecNode, RangeSampleExecNode, Schema, SortExecNode}
 import org.apache.spark.rdd.{PartitionPruningRDD, RDD}
 import org.apache.spark.serializer.Serializer
 import org.apache.spark.shuffle.ShuffleWriteProcessor
+import org.apache.spark.sql.blaze.BlazeConf
 import org.apache.spark.sql.blaze.JniBridge
 import org.apache.spark.sql.blaze.MetricNode
 import org.apache.spark.sql.blaze.NativeConverters
@@ -31,17 +34,18 @@ import org.apache.spark.sql.blaze.NativeSupports
 import org.apache.spark.sql.blaze.Shims
 import org.apache.spark.sql.catalyst.plans.physical.{HashPartitioning, Partitioning, RangePartitioning, RoundRobinPartitioning, SinglePartition}
 import org.apache.spark.sql.catalyst.InternalRow
-import org.apache.spark.sql.catalyst.expressions.{Ascending, Attribute, BoundReference, NullsFirst, UnsafeProjection}
+import org.apache.spark.sql.catalyst.expressions.{Ascending, Attribute, BoundReference, NullsFirst, SortOrder, UnsafeProjection}
 import org.apache.spark.sql.execution.exchange.ShuffleExchangeLike
 import org.apache.spark.sql.execution.metric.{SQLMetric, SQLMetrics, SQLShuffleReadMetricsReporter, SQLShuffleWriteMetricsReporter}
 import org.apache.spark.sql.execution.{SQLExecution, SparkPlan, UnsafeRowSerializer}
 import org.apache.spark.sql.execution.blaze.shuffle.BlazeBlockStoreShuffleReaderBase
+import org.apache.spark.sql.execution.blaze.shuffle.BlockObject
 import org.apache.spark.sql.execution.blaze.shuffle.BlazeShuffleDependency
 import org.apache.spark.util.{CompletionIterator, MutablePair}
 import org.apache.spark.sql.catalyst.expressions.codegen.LazilyGeneratedOrdering
 import org.apache.spark.sql.catalyst.util.ArrayData
 import org.apache.spark.sql.internal.SQLConf
-import org.apache.spark.sql.types.ArrayType
+import org.apache.spark.sql.types.{ArrayType, FloatType, StructField, StructType}
 
 import scala.collection.mutable
 import scala.collection.mutable.ArrayBuffer
@@ -110,10 +114,35 @@ abstract class NativeShuffleExchangeBase(
     case _ => null
   }
 
+  // rows of each output partition are sorted by these keys in map side, so that the reduce
+  // side can merge the sorted blocks instead of sorting them again
+  val keySortOrder: Seq[SortOrder] = outputPartitioning match {
+    case _ if !BlazeConf.SHUFFLE_KEY_SORTED_ENABLE.booleanConf() => Nil
+    case _ if Shims.get.isRssShuffleManager => Nil
+    case p if p.numPartitions <= 1 => Nil
+    case HashPartitioning(expressions, _) => expressions.map(SortOrder(_, Ascending))
+    case RangePartitioning(ordering, _) => ordering
+    case _ => Nil
+  }
+
+  def nativeKeySortExprs: Seq[PhysicalExprNode] = keySortOrder.map { sortOrder =>
+    PhysicalExprNode
+      .newBuilder()
+      .setSort(
+        PhysicalSortExprNode
+          .newBuilder()
+          .setExpr(NativeConverters.convertExpr(sortOrder.child))
+          .setAsc(sortOrder.direction == Ascending)
+          .setNullsFirst(sortOrder.nullOrdering == NullsFirst)
+          .build())
+      .build()
+  }
+
   // check whether native converting is supported
   nativeSchema
   nativeHashExprs
   nativeSortExecNode
+  nativeKeySortExprs
 
   protected def doExecuteNonNative(): RDD[InternalRow]
 
@@ -200,31 +229,39 @@ abstract class NativeShuffleExchangeBase(
           new SQLShuffleWriteMetricsReporter(shuffleWriteMetrics, metrics).incWriteTime(v)
         case _ =>
       }))
+    val nativeKeySortExprs = this.nativeKeySortExprs
     var numPartitionsRest = numPartitions;
     // if RangePartitioning => sample and find bounds
     val nativeBounds = outputPartitioning match {
       case RangePartitioning(sortingExpressions, numPartitions) =>
-        // Extract only fields used for sorting to avoid collecting large fields that does not
-        // affect sorting result when deciding partition bounds in RangePartitioner
-        val rddForSampling = rdd.mapPartitionsInternal { iter =>
-          val projection =
-            UnsafeProjection.create(sortingExpressions.map(_.child), outputAttributes)
-          val mutablePair = new MutablePair[InternalRow, Null]()
-          // Internally, RangePartitioner runs a job on the RDD that samples keys to compute
-          // partition bounds. To get accurate samples, we need to copy the mutable keys.
-          iter.map(row => mutablePair.update(projection(row).copy(), null))
-        }
-        // Construct ordering on extracted sort key.
-        val orderingAttributes = sortingExpressions.zipWithIndex.map { case (ord, i) =>
-          ord.copy(child = BoundReference(i, ord.dataType, ord.nullable))
-        }
-        implicit val ordering = new LazilyGeneratedOrdering(orderingAttributes)
+        val bounds = if (BlazeConf.RANGE_PARTITIONING_NATIVE_SAMPLING_ENABLE.booleanConf()) {
+          nativeRangePartitioningBound(
+            numPartitions,
+            nativeInputRDD,
+            sortingExpressions,
+            samplePointsPerPartitionHint = SQLConf.get.rangeExchangeSampleSizePerPartition)
+        } else {
+          // Extract only fields used for sorting to avoid collecting large fields that does not
+          // affect sorting result when deciding partition bounds in RangePartitioner
+          val rddForSampling = rdd.mapPartitionsInternal { iter =>
+            val projection =
+              UnsafeProjection.create(sortingExpressions.map(_.child), outputAttributes)
+            val mutablePair = new MutablePair[InternalRow, Null]()
+            // Internally, RangePartitioner runs a job on the RDD that samples keys to compute
+            // partition bounds. To get accurate samples, we need to copy the mutable keys.
+            iter.map(row => mutablePair.update(projection(row).copy(), null))
+          }
+          // Construct ordering on extracted sort key.
+          val orderingAttributes = sortingExpressions.zipWithIndex.map { case (ord, i) =>
+            ord.copy(child = BoundReference(i, ord.dataType, ord.nullable))
+          }
+          implicit val ordering = new LazilyGeneratedOrdering(orderingAttributes)
 
-        // TODO: complete Native SampleExec
-        val bounds = rangePartitioningBound(
-          numPartitions,
-          rddForSampling,
-          samplePointsPerPartitionHint = SQLConf.get.rangeExchangeSampleSizePerPartition)
+          rangePartitioningBound(
+            numPartitions,
+            rddForSampling,
+            samplePointsPerPartitionHint = SQLConf.get.rangeExchangeSampleSizePerPartition)
+        }
         numPartitionsRest = bounds.length + 1
         sortingExpressions.zipWithIndex.map { case (field, index) =>
           val valueList = bounds.map { internal_row =>
@@ -259,7 +296,13 @@ abstract class NativeShuffleExchangeBase(
                 PhysicalHashRepartition
                   .newBuilder()
                   .setPartitionCount(numPartitions)
-                  .addAllHashExpr(nativeHashExprs.asJava))
+                  .addAllHashExpr(nativeHashExprs.asJava)
+                  // hot key splitting is not supported in key-sorted shuffle
+                  .setHotKeySplits(if (nativeKeySortExprs.isEmpty) {
+                    BlazeConf.SHUFFLE_SKEW_HOT_KEY_SPLITS.intConf()
+                  } else {
+                    0
+                  }))
           case RoundRobinPartitioning(_) =>
             repartitionBuilder
               .setRoundRobinRepartition(
@@ -281,7 +324,15 @@ abstract class NativeShuffleExchangeBase(
         val input = nativeInputRDD.nativePlan(nativeInputPartition, taskContext)
         val nativeShuffleWriteExec =
           Shims.get.getShuffleWriteExec(input, nativeOutputPartitioning)
-        nativeShuffleWriteExec
+        if (nativeKeySortExprs.nonEmpty && nativeShuffleWriteExec.hasShuffleWriter) {
+          nativeShuffleWriteExec.toBuilder
+            .setShuffleWriter(
+              nativeShuffleWriteExec.getShuffleWriter.toBuilder
+                .addAllSortExpr(nativeKeySortExprs.asJava))
+            .build()
+        } else {
+          nativeShuffleWriteExec
+        }
       },
       friendlyName = "NativeRDD.ShuffleWrite")
 
@@ -294,13 +345,128 @@ abstract class NativeShuffleExchangeBase(
 
         override def getPartition(key: Any): Int = key.asInstanceOf[Int]
       },
-      schema = Util.getSchema(outputAttributes, useExprId = false))
+      schema = Util.getSchema(outputAttributes, useExprId = false),
+      keySorted = keySortOrder.nonEmpty)
     metrics("numPartitions").set(numPartitionsRest)
     val executionId = sparkContext.getLocalProperty(SQLExecution.EXECUTION_ID_KEY)
     SQLMetrics.postDriverMetricUpdates(sparkContext, executionId, metrics("numPartitions") :: Nil)
     dependency
   }
 
+  // samples keys with native reservoir sampling and determines bounds on the driver, same as
+  // rangePartitioningBound() but without converting input batches to rows for sampling.
+  private def nativeRangePartitioningBound(
+      partitions: Int,
+      nativeInputRDD: NativeRDD,
+      sortingExpressions: Seq[SortOrder],
+      samplePointsPerPartitionHint: Int): Array[InternalRow] = {
+    if (partitions <= 1) {
+      return Array.empty
+    }
+    // This is the sample size we need to have roughly balanced output partitions, capped at 1M.
+    val sampleSize = math.min(samplePointsPerPartitionHint.toDouble * partitions, 1e6)
+    // Assume the input partitions are roughly balanced and over-sample a little bit.
+    val sampleSizePerPartition =
+      math.ceil(3.0 * sampleSize / nativeInputRDD.partitions.length).toInt
+    val nativeSortExprs = nativeSortExecNode.getExprList
+
+    // sample keys of each partition
+    val sampleRDD =
+      new RDD[Array[Byte]](
+        nativeInputRDD.sparkContext,
+        new OneToOneDependency(nativeInputRDD) :: Nil) {
+        setName("NativeRDD.RangeSample")
+        Shims.get.setRDDShuffleReadFull(this, nativeInputRDD.isShuffleReadFull)
+
+        override protected def getPartitions: Array[Partition] = nativeInputRDD.partitions
+
+        override def compute(split: Partition, context: TaskContext): Iterator[Array[Byte]] = {
+          val resourceId = s"NativeRangeSample.output:${UUID.randomUUID()}"
+          val bos = new ByteArrayOutputStream()
+          JniBridge.resourcesMap.put(
+            resourceId,
+            (byteBuffer: ByteBuffer) => {
+              val byteArray = new Array[Byte](byteBuffer.capacity())
+              byteBuffer.get(byteArray)
+              bos.write(byteArray)
+            })
+
+          val input = nativeInputRDD.nativePlan(nativeInputRDD.partitions(split.index), context)
+          val nativeSampleExec = RangeSampleExecNode
+            .newBuilder()
+            .setInput(input)
+            .addAllSortExpr(nativeSortExprs)
+            .setSampleSize(sampleSizePerPartition)
+          val nativeIpcWriterExec = PhysicalPlanNode
+            .newBuilder()
+            .setIpcWriter(
+              IpcWriterExecNode
+                .newBuilder()
+                .setInput(PhysicalPlanNode.newBuilder().setRangeSample(nativeSampleExec))
+                .setIpcConsumerResourceId(resourceId))
+            .build()
+          val iter = NativeHelper.executeNativePlan(
+            nativeIpcWriterExec,
+            MetricNode(Map(), Nil),
+            split,
+            Some(context))
+          assert(iter.isEmpty)
+          Iterator.single(bos.toByteArray)
+        }
+      }
+    val collectedData = sampleRDD.collect()
+
+    // determine bounds from sampled keys on the driver
+    val sampleSchema = StructType(sortingExpressions.zipWithIndex.map { case (ord, i) =>
+      StructField(s"key_$i", ord.dataType, nullable = true)
+    } :+ StructField("weight", FloatType, nullable = false))
+    val readerIpcProviderResourceId = s"NativeRangeBounds.input:${UUID.randomUUID()}"
+    val readerExec = IpcReaderExecNode
+      .newBuilder()
+      .setSchema(NativeConverters.convertSchema(sampleSchema))
+      .setNumPartitions(1)
+      .setIpcProviderResourceId(readerIpcProviderResourceId)
+    val boundsSortExprs = sortingExpressions.zipWithIndex.map { case (ord, i) =>
+      PhysicalExprNode
+        .newBuilder()
+        .setSort(
+          PhysicalSortExprNode
+            .newBuilder()
+            .setExpr(NativeConverters.convertExpr(BoundReference(i, ord.dataType, nullable = true)))
+            .setAsc(ord.direction == Ascending)
+            .setNullsFirst(ord.nullOrdering == NullsFirst)
+            .build())
+        .build()
+    }
+    val boundsExec = PhysicalPlanNode
+      .newBuilder()
+      .setRangeBounds(
+        RangeBoundsExecNode
+          .newBuilder()
+          .setInput(PhysicalPlanNode.newBuilder().setIpcReader(readerExec))
+          .addAllSortExpr(boundsSortExprs.asJava)
+          .setPartitionCount(partitions))
+      .build()
+
+    val provideIpcIterator = () => {
+      collectedData.iterator.map(bytes =>
+        new BlockObject {
+          override def hasByteBuffer: Boolean = true
+          override def getByteBuffer: ByteBuffer = ByteBuffer.wrap(bytes)
+          override def close(): Unit = {}
+        })
+    }
+    JniBridge.resourcesMap.put(readerIpcProviderResourceId, () => provideIpcIterator())
+
+    val singlePartition = new Partition {
+      override def index: Int = 0
+    }
+    NativeHelper
+      .executeNativePlan(boundsExec, MetricNode(Map(), Nil), singlePartition, None)
+      .map(_.copy())
+      .toArray
+  }
+
   private def rangePartitioningBound[K: Ordering: ClassTag, V](
       partitions: Int,
       rdd: RDD[_ <: Product2[K, V]],
@@ -18,12 +18,14 @@ package org.apache.spark.sql.execution.blaze.plan
 import scala.collection.JavaConverters._
 import scala.collection.immutable.SortedMap
 
+import org.apache.spark.sql.blaze.BlazeConverters.ForceNativeExecutionWrapperBase
 import org.apache.spark.sql.blaze.MetricNode
 import org.apache.spark.sql.blaze.NativeConverters
 import org.apache.spark.sql.blaze.NativeRDD
 import org.apache.spark.sql.blaze.NativeHelper
 import org.apache.spark.sql.catalyst.expressions.Ascending
 import org.apache.spark.sql.catalyst.expressions.Attribute
+import org.apache.spark.sql.catalyst.expressions.Expression
 import org.apache.spark.sql.catalyst.expressions.NullsFirst
 import org.apache.spark.sql.catalyst.expressions.SortOrder
 import org.apache.spark.sql.catalyst.plans.physical.Distribution
@@ -91,10 +93,64 @@ abstract class NativeSortBase(
   // check whether native converting is supported
   nativeSortExprs
 
+  // input shuffle whose blocks are already sorted by this ordering (or a longer one starting
+  // with it), in which case sorting is done by merging the blocks in the shuffle reader
+  @transient
+  private lazy val keySortedShuffle: Option[NativeShuffleExchangeBase] = {
+    def findShuffle(plan: SparkPlan): Option[NativeShuffleExchangeBase] = plan match {
+      case exchange: NativeShuffleExchangeBase => Some(exchange)
+      case rename: NativeRenameColumnsBase => findShuffle(rename.child)
+      case wrapper: ForceNativeExecutionWrapperBase => findShuffle(wrapper.child)
+      case _: NativeSupports => None
+      case p if NativeHelper.isNative(p) => findShuffle(NativeHelper.getUnderlyingNativePlan(p))
+      case _ => None
+    }
+
+    // column positions are kept from shuffle output to this input, so attributes are matched
+    // by positions (they may have different exprIds when the shuffle is reused)
+    def keyIndex(expr: Expression, output: Seq[Attribute]): Int = expr match {
+      case attr: Attribute => output.indexWhere(_.exprId == attr.exprId)
+      case _ => -1
+    }
+
+    findShuffle(child).filter { exchange =>
+      val keySortOrder = exchange.keySortOrder
+      sortOrder.length <= keySortOrder.length && sortOrder.zip(keySortOrder).forall {
+        case (order, keyOrder) =>
+          val index = keyIndex(order.child, child.output)
+          index >= 0 &&
+          index == keyIndex(keyOrder.child, exchange.output) &&
+          order.direction == keyOrder.direction &&
+          order.nullOrdering == keyOrder.nullOrdering
+      }
+    }
+  }
+
   override def doExecuteNative(): NativeRDD = {
     val inputRDD = NativeHelper.executeNative(child)
     val nativeMetrics = MetricNode(metrics, inputRDD.metrics :: Nil)
     val nativeSortExprs = this.nativeSortExprs
+    val nativeKeySortExprs = keySortedShuffle.map(_.nativeKeySortExprs)
+
+    // replaces the sort with merging sorted blocks in shuffle reader
+    def mergeSortedBlocks(input: PhysicalPlanNode): Option[PhysicalPlanNode] = {
+      nativeKeySortExprs.flatMap { keySortExprs =>
+        if (input.hasIpcReader) {
+          Some(
+            input.toBuilder
+              .setIpcReader(input.getIpcReader.toBuilder.addAllSortExpr(keySortExprs.asJava))
+              .build())
+        } else if (input.hasRenameColumns) {
+          mergeSortedBlocks(input.getRenameColumns.getInput).map { mergedInput =>
+            input.toBuilder
+              .setRenameColumns(input.getRenameColumns.toBuilder.setInput(mergedInput))
+              .build()
+          }
+        } else {
+          None
+        }
+      }
+    }
 
     new NativeRDD(
       sparkContext,
@@ -104,12 +160,15 @@ abstract class NativeSortBase(
       inputRDD.isShuffleReadFull,
       (partition, taskContext) => {
         val inputPartition = inputRDD.partitions(partition.index)
-        val nativeSortExec = SortExecNode
-          .newBuilder()
-          .setInput(inputRDD.nativePlan(inputPartition, taskContext))
-          .addAllExpr(nativeSortExprs.asJava)
-          .build()
-        PhysicalPlanNode.newBuilder().setSort(nativeSortExec).build()
+        val input = inputRDD.nativePlan(inputPartition, taskContext)
+        mergeSortedBlocks(input).getOrElse {
+          val nativeSortExec = SortExecNode
+            .newBuilder()
+            .setInput(input)
+            .addAllExpr(nativeSortExprs.asJava)
+            .build()
+          PhysicalPlanNode.newBuilder().setSort(nativeSortExec).build()
+        }
       },
       friendlyName = "NativeRDD.Sort")
   }
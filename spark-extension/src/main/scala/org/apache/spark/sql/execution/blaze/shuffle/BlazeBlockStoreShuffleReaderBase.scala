@@ -15,7 +15,9 @@
  */
 package org.apache.spark.sql.execution.blaze.shuffle
 
+import java.io.File
 import java.io.FileInputStream
+import java.io.IOException
 import java.io.InputStream
 import java.nio.channels.Channels
 import java.nio.channels.ReadableByteChannel
@@ -44,9 +46,18 @@ abstract class BlazeBlockStoreShuffleReaderBase[K, C](
   protected val dep: ShuffleDependency[K, _, C] = handle.dependency
   protected def readBlocks(): Iterator[(BlockId, InputStream)]
 
+  // local shuffle blocks which are read natively from shuffle data/index files,
+  // these blocks must be excluded from readBlocks()
+  protected def readLocalShuffleBlocks(): Iterator[BlockObject] = Iterator.empty
+
+  // called by native side when a block is found corrupted
+  protected def throwFetchFailed(blockId: BlockId, message: String): Unit =
+    throw new IOException(s"corrupted shuffle block $blockId: $message")
+
   def readIpc(): Iterator[BlockObject] = {
-    val ipcIterator = readBlocks().map { case (_, inputStream) =>
-      createBlockObject(inputStream)
+    val ipcIterator = readLocalShuffleBlocks() ++ readBlocks().map {
+      case (blockId, inputStream) =>
+        createBlockObject(inputStream, message => throwFetchFailed(blockId, message))
     }
 
     // An interruptible iterator must be used here in order to support task cancellation
@@ -60,7 +71,25 @@ abstract class BlazeBlockStoreShuffleReaderBase[K, C](
 }
 
 object BlazeBlockStoreShuffleReaderBase extends Logging {
-  def createBlockObject(in: InputStream): BlockObject = {
+  def createLocalShuffleBlockObject(
+      dataFile: File,
+      startPartition: Int,
+      endPartition: Int,
+      onCorrupted: String => Unit): BlockObject = {
+    // committed index file is located next to the data file
+    val indexFilePath = dataFile.getPath.stripSuffix(".data") + ".index"
+    new BlockObject {
+      override def hasShuffleFiles: Boolean = true
+      override def getDataFilePath: String = dataFile.getPath
+      override def getIndexFilePath: String = indexFilePath
+      override def getStartPartition: Int = startPartition
+      override def getEndPartition: Int = endPartition
+      override def throwFetchFailed(message: String): Unit = onCorrupted(message)
+      override def close(): Unit = {}
+    }
+  }
+
+  def createBlockObject(in: InputStream, onCorrupted: String => Unit): BlockObject = {
     getFileSegmentFromInputStream(in) match {
       case Some((path, offset, limit)) =>
         return new BlockObject {
@@ -68,6 +97,7 @@ object BlazeBlockStoreShuffleReaderBase extends Logging {
           override def getFilePath: String = path
           override def getFileOffset: Long = offset
           override def getFileLength: Long = limit
+          override def throwFetchFailed(message: String): Unit = onCorrupted(message)
           override def close(): Unit = in.close()
         }
       case None =>
@@ -78,6 +108,7 @@ object BlazeBlockStoreShuffleReaderBase extends Logging {
         return new BlockObject {
           override def hasByteBuffer: Boolean = true
           override def getByteBuffer: ByteBuffer = buf
+          override def throwFetchFailed(message: String): Unit = onCorrupted(message)
           override def close(): Unit = in.close()
         }
       case None =>
@@ -86,6 +117,7 @@ object BlazeBlockStoreShuffleReaderBase extends Logging {
     val channel = Channels.newChannel(in)
     new BlockObject {
       override def getChannel: ReadableByteChannel = channel
+      override def throwFetchFailed(message: String): Unit = onCorrupted(message)
       override def close(): Unit = channel.close()
     }
   }
@@ -147,4 +179,10 @@ trait BlockObject extends AutoCloseable {
   def getFileLength: Long = throw new UnsupportedOperationException
   def getByteBuffer: ByteBuffer = throw new UnsupportedOperationException
   def getChannel: ReadableByteChannel = throw new UnsupportedOperationException
+  def hasShuffleFiles: Boolean = false
+  def getDataFilePath: String = throw new UnsupportedOperationException
+  def getIndexFilePath: String = throw new UnsupportedOperationException
+  def getStartPartition: Int = throw new UnsupportedOperationException
+  def getEndPartition: Int = throw new UnsupportedOperationException
+  def throwFetchFailed(message: String): Unit = throw new IOException(message)
 }
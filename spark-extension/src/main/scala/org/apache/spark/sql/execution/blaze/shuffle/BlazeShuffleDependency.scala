@@ -38,7 +38,9 @@ class BlazeShuffleDependency[K: ClassTag, V: ClassTag, C: ClassTag](
     override val aggregator: Option[Aggregator[K, V, C]] = None,
     override val mapSideCombine: Boolean = false,
     override val shuffleWriterProcessor: ShuffleWriteProcessor = new ShuffleWriteProcessor,
-    val schema: StructType)
+    val schema: StructType,
+    // rows of each output partition are sorted by partitioning keys in map side
+    val keySorted: Boolean = false)
     extends ShuffleDependency[K, V, C](
       _rdd,
       partitioner,
@@ -54,4 +56,12 @@ object BlazeShuffleDependency extends Logging {
     val dep = base.dependency
     dep.isInstanceOf[BlazeShuffleDependency[_, _, _]]
   }
+
+  def isKeySortedShuffle(handle: ShuffleHandle): Boolean = {
+    val base = handle.asInstanceOf[BaseShuffleHandle[_, _, _]]
+    base.dependency match {
+      case dep: BlazeShuffleDependency[_, _, _] => dep.keySorted
+      case _ => false
+    }
+  }
 }
@@ -62,17 +62,29 @@ abstract class BlazeShuffleWriterBase[K, V](metrics: ShuffleWriteMetricsReporter
     val dataFile = shuffleBlockResolver.getDataFile(dep.shuffleId, mapId)
     val tempDataFilename = dataFile.getPath.replace(".data", ".data.tmp")
     val tempIndexFilename = dataFile.getPath.replace(".data", ".index.tmp")
+    val tempChecksumFilename = dataFile.getPath.replace(".data", ".checksum.tmp")
     val tempDataFilePath = Paths.get(tempDataFilename)
     val tempIndexFilePath = Paths.get(tempIndexFilename)
+    val tempChecksumFilePath = Paths.get(tempChecksumFilename)
+
+    // write spark-compatible partition checksums if spark.shuffle.checksum.enabled,
+    // shuffle checksums are not supported before spark 3.2
+    val conf = SparkEnv.get.conf
+    val checksumEnabled = Shims.get.shimVersion >= "spark-3.2" &&
+      conf.getBoolean("spark.shuffle.checksum.enabled", defaultValue = true)
+    val shuffleWriterNode = ShuffleWriterExecNode
+      .newBuilder(nativeShuffleRDD.nativePlan(partition, context).getShuffleWriter)
+      .setOutputDataFile(tempDataFilename)
+      .setOutputIndexFile(tempIndexFilename)
+    if (checksumEnabled) {
+      shuffleWriterNode
+        .setOutputChecksumFile(tempChecksumFilename)
+        .setChecksumAlgorithm(conf.get("spark.shuffle.checksum.algorithm", "ADLER32"))
+    }
 
     val nativeShuffleWriterExec = PhysicalPlanNode
       .newBuilder()
-      .setShuffleWriter(
-        ShuffleWriterExecNode
-          .newBuilder(nativeShuffleRDD.nativePlan(partition, context).getShuffleWriter)
-          .setOutputDataFile(tempDataFilename)
-          .setOutputIndexFile(tempIndexFilename)
-          .build())
+      .setShuffleWriter(shuffleWriterNode.build())
       .build()
     val iterator = NativeHelper.executeNativePlan(
       nativeShuffleWriterExec,
@@ -96,6 +108,15 @@ abstract class BlazeShuffleWriterBase[K, V](metrics: ShuffleWriteMetricsReporter
       })
       .toArray
 
+    // get partition checksums from shuffle write output checksum file
+    val checksums = if (checksumEnabled) {
+      val checksumBuffer = ByteBuffer.wrap(Files.readAllBytes(tempChecksumFilePath))
+      Files.deleteIfExists(tempChecksumFilePath)
+      Array.fill(checksumBuffer.remaining() / 8)(checksumBuffer.getLong)
+    } else {
+      Array[Long]()
+    }
+
     // update metrics
     val dataSize = Files.size(tempDataFilePath)
     metrics.incBytesWritten(dataSize)
@@ -107,6 +128,7 @@ abstract class BlazeShuffleWriterBase[K, V](metrics: ShuffleWriteMetricsReporter
         tempDataFilePath.toFile,
         mapId,
         partitionLengths,
+        checksums,
         dataSize,
         context))
   }
@@ -19,6 +19,27 @@ import java.nio.ByteBuffer
 
 trait RssPartitionWriterBase {
   def write(partitionId: Int, buffer: ByteBuffer): Unit
+
+  /**
+   * Pushes buffers of multiple partitions in one call. The buffer contains all data
+   * concatenated, the i-th chunk belongs to partitionIds(i) and has lengths(i) bytes.
+   *
+   * Retries, deduplication and partition splits are handled by the rss clients, failures
+   * they cannot recover from are thrown and fail the task.
+   *
+   * The default implementation writes each chunk with write().
+   */
+  def pushBatch(partitionIds: Array[Int], lengths: Array[Int], buffer: ByteBuffer): Unit = {
+    var offset = buffer.position()
+    for (i <- partitionIds.indices) {
+      val chunk = buffer.duplicate()
+      chunk.position(offset)
+      chunk.limit(offset + lengths(i))
+      write(partitionIds(i), chunk.slice())
+      offset += lengths(i)
+    }
+  }
+
   def flush(): Unit
   def close(): Unit
   def getPartitionLengthMap: Array[Long]